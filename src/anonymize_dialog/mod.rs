@@ -0,0 +1,153 @@
+mod profile;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{editing::History, ui};
+use profile::{AnonymizeOptions, AnonymizedElement};
+
+/// A dialog for anonymizing the data set. The profile options are chosen first, and once applied
+/// the dialog shows a summary of every data element that was removed, replaced, cleaned, or
+/// flagged.
+///
+/// The anonymization is recorded in the undo history so it can be undone.
+///
+#[component]
+pub fn AnonymizeDialog(
+    data_set: Signal<DataSet>,
+    history: Signal<History>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut options = use_signal(AnonymizeOptions::default);
+    let mut summary = use_signal(|| None::<Vec<AnonymizedElement>>);
+
+    let on_apply = move |_| {
+        let result = match profile::anonymize(&data_set.read(), &options.read()) {
+            Ok(result) => result,
+            Err(e) => {
+                ui::toasts::add_error(format!("Anonymization failed: {}", e));
+                return;
+            }
+        };
+
+        if let Err(e) =
+            history
+                .write()
+                .apply(&mut data_set.write(), result.description(), result.edits)
+        {
+            ui::toasts::add_error(format!("Anonymization failed: {}", e));
+            return;
+        }
+
+        summary.set(Some(result.summary));
+    };
+
+    let option_checkbox = move |label: &'static str,
+                                hint: &'static str,
+                                get: fn(&AnonymizeOptions) -> bool,
+                                set: fn(&mut AnonymizeOptions, bool)| {
+        rsx! {
+            label {
+                class: "anonymize-option",
+
+                input {
+                    r#type: "checkbox",
+                    checked: get(&options.read()),
+                    onchange: move |event| set(&mut options.write(), event.checked()),
+                }
+                div {
+                    div { {label} }
+                    div { class: "hint", {hint} }
+                }
+            }
+        }
+    };
+
+    rsx! {
+        ui::Dialog {
+            title: "Anonymize",
+            on_close,
+
+            if let Some(summary) = summary() {
+                AnonymizeSummary { summary }
+
+                div {
+                    class: "dialog-buttons",
+
+                    button { onclick: move |_| on_close.call(()), "Close" }
+                }
+            } else {
+                p {
+                    "Applies the DICOM Basic Application Level Confidentiality Profile. Identifying "
+                    "data elements are removed or replaced with empty values, and instance UIDs are "
+                    "replaced with new UIDs."
+                }
+
+                {option_checkbox(
+                    "Retain UIDs",
+                    "Keep all UIDs unchanged",
+                    |o| o.retain_uids,
+                    |o, v| o.retain_uids = v,
+                )}
+                {option_checkbox(
+                    "Retain dates",
+                    "Keep all dates and times unchanged",
+                    |o| o.retain_dates,
+                    |o, v| o.retain_dates = v,
+                )}
+                {option_checkbox(
+                    "Clean descriptors",
+                    "Keep descriptions and comments, masking out patient and staff names and IDs",
+                    |o| o.clean_descriptors,
+                    |o, v| o.clean_descriptors = v,
+                )}
+                {option_checkbox(
+                    "Retain device identity",
+                    "Keep station name, device serial number, and other device identifiers",
+                    |o| o.retain_device_identity,
+                    |o, v| o.retain_device_identity = v,
+                )}
+                {option_checkbox(
+                    "Remove private tags",
+                    "Remove all private data elements",
+                    |o| o.remove_private_tags,
+                    |o, v| o.remove_private_tags = v,
+                )}
+
+                div {
+                    class: "dialog-buttons",
+
+                    button { onclick: move |_| on_close.call(()), "Cancel" }
+                    button { class: "primary", onclick: on_apply, "Anonymize" }
+                }
+            }
+        }
+    }
+}
+
+/// Lists every data element affected by anonymization along with its original value.
+///
+#[component]
+fn AnonymizeSummary(summary: Vec<AnonymizedElement>) -> Element {
+    rsx! {
+        p { "{summary.len()} data elements were affected." }
+
+        div {
+            class: "anonymize-summary",
+
+            div { class: "header", "Data element" }
+            div { class: "header", "Original value" }
+            div { class: "header", "Action" }
+
+            for element in summary.iter() {
+                div {
+                    title: "{element.path}",
+
+                    "{element.tag} {dictionary::tag_name(element.tag, None)}"
+                }
+                div { class: "original-value", "{element.original_value}" }
+                div { {element.action.description()} }
+            }
+        }
+    }
+}
@@ -0,0 +1,813 @@
+//! Applies the DICOM PS3.15 Basic Application Level Confidentiality Profile to a data set, with
+//! support for a subset of its options.
+//!
+//! Whether a data element is identifying is decided by [`dcmfx::anonymize::filter_tag`]. On top of
+//! that, type 1 and 2 identifying attributes that must remain present are replaced with empty
+//! values rather than being removed, instance UIDs are replaced with new UIDs, and the selected
+//! options are used to retain data elements that would otherwise be removed.
+//!
+//! The Clean Descriptors option keeps descriptions and comments but removes any occurrences of the
+//! patient's and the staff's identifying values from them. Identifying text that isn't one of
+//! those values can't be detected, so cleaned descriptors are still listed in the summary.
+
+use std::collections::HashMap;
+
+use dcmfx::core::*;
+
+use crate::{editing::value_text, utils};
+
+/// The profile options that can be selected when anonymizing.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnonymizeOptions {
+    /// Retain UIDs Option: UIDs are kept as-is instead of being replaced or removed.
+    pub retain_uids: bool,
+
+    /// Retain Longitudinal Temporal Information with Full Dates Option: dates and times are kept.
+    pub retain_dates: bool,
+
+    /// Clean Descriptors Option: descriptions and comments are kept with any identifying values
+    /// found in them masked out.
+    pub clean_descriptors: bool,
+
+    /// Retain Device Identity Option: data elements identifying the device are kept.
+    pub retain_device_identity: bool,
+
+    /// Whether to remove all private data elements.
+    pub remove_private_tags: bool,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        Self {
+            retain_uids: false,
+            retain_dates: false,
+            clean_descriptors: false,
+            retain_device_identity: false,
+            remove_private_tags: true,
+        }
+    }
+}
+
+/// What was done to a data element during anonymization.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnonymizeAction {
+    Removed,
+    Emptied,
+    ReplacedUid(String),
+    Cleaned(String),
+    Added(String),
+    Retained(&'static str),
+}
+
+impl AnonymizeAction {
+    pub fn description(&self) -> String {
+        match self {
+            Self::Removed => "Removed".to_string(),
+            Self::Emptied => "Replaced with empty value".to_string(),
+            Self::ReplacedUid(uid) => format!("Replaced with {}", uid),
+            Self::Cleaned(value) => format!("Cleaned to \"{}\"", value),
+            Self::Added(value) => format!("Added with value \"{}\"", value),
+            Self::Retained(reason) => format!("Retained: {}", reason),
+        }
+    }
+}
+
+/// A data element that was changed or flagged during anonymization.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnonymizedElement {
+    pub path: DataSetPath,
+    pub tag: DataElementTag,
+    pub original_value: String,
+    pub action: AnonymizeAction,
+}
+
+/// The result of anonymizing a data set: the edits to make to it, and a summary of every data
+/// element that was affected.
+///
+pub struct AnonymizeResult {
+    pub edits: Vec<(DataSetPath, Option<DataElementValue>)>,
+    pub summary: Vec<AnonymizedElement>,
+}
+
+impl AnonymizeResult {
+    /// Returns a short description of the result for display in the undo history.
+    ///
+    pub fn description(&self) -> String {
+        let count =
+            |f: fn(&AnonymizeAction) -> bool| self.summary.iter().filter(|e| f(&e.action)).count();
+
+        format!(
+            "Anonymized: removed {}, emptied {}, replaced {} UIDs, cleaned {}",
+            count(|a| *a == AnonymizeAction::Removed),
+            count(|a| *a == AnonymizeAction::Emptied),
+            count(|a| matches!(a, AnonymizeAction::ReplacedUid(_))),
+            count(|a| matches!(a, AnonymizeAction::Cleaned(_))),
+        )
+    }
+}
+
+/// Type 1 and 2 identifying attributes that are replaced with an empty value rather than removed.
+///
+const EMPTIED_TAGS: [DataElementTag; 9] = [
+    dictionary::PATIENT_NAME.tag,
+    dictionary::PATIENT_ID.tag,
+    dictionary::PATIENT_BIRTH_DATE.tag,
+    dictionary::PATIENT_SEX.tag,
+    dictionary::STUDY_DATE.tag,
+    dictionary::STUDY_TIME.tag,
+    dictionary::ACCESSION_NUMBER.tag,
+    dictionary::REFERRING_PHYSICIAN_NAME.tag,
+    dictionary::STUDY_ID.tag,
+];
+
+/// Instance UIDs that are replaced with new UIDs. The same original UID is always replaced with
+/// the same new UID, so references between instances remain intact.
+///
+const REPLACED_UID_TAGS: [DataElementTag; 11] = [
+    dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag,
+    dictionary::SOP_INSTANCE_UID.tag,
+    dictionary::STUDY_INSTANCE_UID.tag,
+    dictionary::SERIES_INSTANCE_UID.tag,
+    dictionary::FRAME_OF_REFERENCE_UID.tag,
+    dictionary::REFERENCED_SOP_INSTANCE_UID.tag,
+    dictionary::REFERENCED_FRAME_OF_REFERENCE_UID.tag,
+    dictionary::SYNCHRONIZATION_FRAME_OF_REFERENCE_UID.tag,
+    dictionary::IRRADIATION_EVENT_UID.tag,
+    dictionary::DIMENSION_ORGANIZATION_UID.tag,
+    dictionary::CONCATENATION_UID.tag,
+];
+
+/// Descriptors kept and cleaned by the Clean Descriptors option.
+///
+const DESCRIPTOR_TAGS: [DataElementTag; 9] = [
+    dictionary::STUDY_DESCRIPTION.tag,
+    dictionary::SERIES_DESCRIPTION.tag,
+    dictionary::IMAGE_COMMENTS.tag,
+    dictionary::PROTOCOL_NAME.tag,
+    dictionary::PERFORMED_PROCEDURE_STEP_DESCRIPTION.tag,
+    dictionary::REQUESTED_PROCEDURE_DESCRIPTION.tag,
+    dictionary::ADDITIONAL_PATIENT_HISTORY.tag,
+    dictionary::ADMITTING_DIAGNOSES_DESCRIPTION.tag,
+    dictionary::DERIVATION_DESCRIPTION.tag,
+];
+
+/// Data elements in the root data set whose values are masked out of descriptors by the Clean
+/// Descriptors option.
+///
+const DESCRIPTOR_IDENTIFIER_TAGS: [DataElementTag; 9] = [
+    dictionary::PATIENT_NAME.tag,
+    dictionary::PATIENT_ID.tag,
+    dictionary::PATIENT_BIRTH_DATE.tag,
+    dictionary::ACCESSION_NUMBER.tag,
+    dictionary::REFERRING_PHYSICIAN_NAME.tag,
+    dictionary::PERFORMING_PHYSICIAN_NAME.tag,
+    dictionary::OPERATORS_NAME.tag,
+    dictionary::INSTITUTION_NAME.tag,
+    dictionary::STUDY_ID.tag,
+];
+
+/// Identifying values shorter than this aren't masked out of descriptors, as doing so would mask
+/// out parts of unrelated words.
+///
+const MIN_DESCRIPTOR_IDENTIFIER_LENGTH: usize = 3;
+
+/// Data elements identifying the device kept by the Retain Device Identity option.
+///
+const DEVICE_IDENTITY_TAGS: [DataElementTag; 8] = [
+    dictionary::STATION_NAME.tag,
+    dictionary::DEVICE_SERIAL_NUMBER.tag,
+    dictionary::DEVICE_UID.tag,
+    dictionary::PLATE_ID.tag,
+    dictionary::GENERATOR_ID.tag,
+    dictionary::CASSETTE_ID.tag,
+    dictionary::GANTRY_ID.tag,
+    dictionary::DETECTOR_ID.tag,
+];
+
+/// Works out the edits needed to anonymize the data set with the given options. The data set
+/// itself isn't modified.
+///
+pub fn anonymize(
+    data_set: &DataSet,
+    options: &AnonymizeOptions,
+) -> Result<AnonymizeResult, String> {
+    let mut context = Context {
+        options,
+        new_uids: HashMap::new(),
+        descriptor_identifiers: descriptor_identifiers(data_set),
+        result: AnonymizeResult {
+            edits: vec![],
+            summary: vec![],
+        },
+    };
+
+    anonymize_data_set(data_set, &DataSetPath::new(), &mut context)?;
+
+    // Record that the patient's identity has been removed, and how. Each option used is a
+    // separate value of De-identification Method.
+    let mut method = "Basic Application Confidentiality Profile".to_string();
+    for (is_selected, name) in [
+        (options.retain_uids, "Retain UIDs"),
+        (options.retain_dates, "Retain Full Dates"),
+        (options.clean_descriptors, "Clean Descriptors"),
+        (options.retain_device_identity, "Retain Device Identity"),
+    ] {
+        if is_selected {
+            method.push('\\');
+            method.push_str(name);
+        }
+    }
+
+    for (tag, vr, value) in [
+        (
+            dictionary::PATIENT_IDENTITY_REMOVED.tag,
+            "CS",
+            "YES".to_string(),
+        ),
+        (dictionary::DEIDENTIFICATION_METHOD.tag, "LO", method),
+    ] {
+        let path = child_path(&DataSetPath::new(), tag)?;
+        let original_value = data_set
+            .get_value(tag)
+            .map(|v| v.to_string(tag, 80))
+            .unwrap_or_default();
+
+        context
+            .result
+            .edits
+            .push((path.clone(), Some(value_text::text_to_value(vr, &value)?)));
+        context.result.summary.push(AnonymizedElement {
+            path,
+            tag,
+            original_value,
+            action: AnonymizeAction::Added(value),
+        });
+    }
+
+    Ok(context.result)
+}
+
+struct Context<'a> {
+    options: &'a AnonymizeOptions,
+    new_uids: HashMap<String, String>,
+    descriptor_identifiers: Vec<String>,
+    result: AnonymizeResult,
+}
+
+fn anonymize_data_set(
+    data_set: &DataSet,
+    path: &DataSetPath,
+    context: &mut Context,
+) -> Result<(), String> {
+    let options = context.options;
+
+    for (tag, value) in data_set.iter() {
+        let tag = *tag;
+        let element_path = child_path(path, tag)?;
+        let vr = value.value_representation().to_string();
+        let is_date = matches!(vr.as_str(), "DA" | "DT" | "TM");
+
+        let action = if tag.is_private() {
+            options
+                .remove_private_tags
+                .then_some(AnonymizeAction::Removed)
+        } else if REPLACED_UID_TAGS.contains(&tag) {
+            if options.retain_uids {
+                None
+            } else {
+                let uids = value_text::value_to_text(value).unwrap_or_default();
+                let new_uids = uids
+                    .split('\\')
+                    .map(|uid| new_uid(&mut context.new_uids, uid))
+                    .collect::<Vec<_>>()
+                    .join("\\");
+
+                Some(AnonymizeAction::ReplacedUid(new_uids))
+            }
+        } else if EMPTIED_TAGS.contains(&tag) {
+            if options.retain_dates && is_date {
+                None
+            } else {
+                Some(AnonymizeAction::Emptied)
+            }
+        } else if options.clean_descriptors && DESCRIPTOR_TAGS.contains(&tag) {
+            let text = value_text::value_to_text(value).unwrap_or_default();
+            let cleaned = clean_descriptor(&text, &context.descriptor_identifiers);
+
+            Some(match cleaned {
+                Some(cleaned) => AnonymizeAction::Cleaned(cleaned),
+                None => AnonymizeAction::Retained(
+                    "no identifying values found, check this descriptor by hand",
+                ),
+            })
+        } else if (options.retain_device_identity && DEVICE_IDENTITY_TAGS.contains(&tag))
+            || (options.retain_dates && is_date)
+            || (options.retain_uids && vr == "UI")
+        {
+            None
+        } else if !dcmfx::anonymize::filter_tag(tag, value.value_representation()) {
+            Some(AnonymizeAction::Removed)
+        } else {
+            None
+        };
+
+        let Some(action) = action else {
+            // Anonymize the items of retained sequences
+            if let Ok(items) = value.sequence_items() {
+                for (index, item) in items.iter().enumerate() {
+                    let mut item_path = element_path.clone();
+                    item_path
+                        .add_sequence_item(index)
+                        .map_err(|_| "Invalid data set path".to_string())?;
+
+                    anonymize_data_set(item, &item_path, context)?;
+                }
+            }
+
+            continue;
+        };
+
+        let edit = match &action {
+            AnonymizeAction::Removed => Some(None),
+            AnonymizeAction::Emptied => Some(Some(value_text::text_to_value(&vr, "")?)),
+            AnonymizeAction::ReplacedUid(uids) => Some(Some(value_text::text_to_value(&vr, uids)?)),
+            AnonymizeAction::Cleaned(text) => {
+                Some(Some(value_text::text_to_value_unvalidated(&vr, text)?))
+            }
+            AnonymizeAction::Added(_) | AnonymizeAction::Retained(_) => None,
+        };
+
+        if let Some(new_value) = edit {
+            context.result.edits.push((element_path.clone(), new_value));
+        }
+
+        context.result.summary.push(AnonymizedElement {
+            path: element_path,
+            tag,
+            original_value: value.to_string(tag, 80),
+            action,
+        });
+    }
+
+    Ok(())
+}
+
+fn child_path(path: &DataSetPath, tag: DataElementTag) -> Result<DataSetPath, String> {
+    let mut path = path.clone();
+    path.add_data_element(tag)
+        .map_err(|_| "Invalid data set path".to_string())?;
+
+    Ok(path)
+}
+
+/// Returns the identifying values that are masked out of descriptors by the Clean Descriptors
+/// option. Person names are split into their individual name components, and longer values are
+/// returned first so that they're masked out before any values they contain.
+///
+fn descriptor_identifiers(data_set: &DataSet) -> Vec<String> {
+    let mut identifiers = vec![];
+
+    for tag in DESCRIPTOR_IDENTIFIER_TAGS {
+        let Some(text) = data_set
+            .get_value(tag)
+            .ok()
+            .and_then(value_text::value_to_text)
+        else {
+            continue;
+        };
+
+        for identifier in text.split(['\\', '^', '=']) {
+            let identifier = identifier.trim();
+            if identifier.len() >= MIN_DESCRIPTOR_IDENTIFIER_LENGTH {
+                identifiers.push(identifier.to_string());
+            }
+        }
+    }
+
+    identifiers.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    identifiers.dedup();
+
+    identifiers
+}
+
+/// Masks every case-insensitive occurrence of the identifying values in a descriptor with 'X'
+/// characters, which keeps the value's length unchanged. Returns `None` if the descriptor doesn't
+/// contain any of them.
+///
+fn clean_descriptor(text: &str, identifiers: &[String]) -> Option<String> {
+    // ASCII lowercasing doesn't change byte offsets, so matches found in the lowercased text can
+    // be masked in the original text
+    let lowercase_text = text.to_ascii_lowercase();
+    let mut is_masked = vec![false; text.len()];
+
+    for identifier in identifiers {
+        let identifier = identifier.to_ascii_lowercase();
+
+        for (start, _) in lowercase_text.match_indices(&identifier) {
+            is_masked[start..start + identifier.len()].fill(true);
+        }
+    }
+
+    if !is_masked.contains(&true) {
+        return None;
+    }
+
+    let cleaned = text
+        .char_indices()
+        .map(|(index, c)| if is_masked[index] { 'X' } else { c })
+        .collect();
+
+    Some(cleaned)
+}
+
+/// Returns the new UID that replaces the given UID, generating a new random one in the "2.25"
+/// UUID-derived root if the UID hasn't been seen before.
+///
+fn new_uid(new_uids: &mut HashMap<String, String>, uid: &str) -> String {
+    if uid.is_empty() {
+        return String::new();
+    }
+
+    new_uids
+        .entry(uid.to_string())
+        .or_insert_with(utils::random_uid)
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::insert;
+
+    const PRIVATE_CREATOR_TAG: DataElementTag = DataElementTag {
+        group: 0x0009,
+        element: 0x0010,
+    };
+
+    /// Returns a data set with identifying values, UIDs that are referenced from inside nested
+    /// sequences, a descriptor, and private data elements.
+    ///
+    fn test_data_set() -> DataSet {
+        let mut referenced_frame = DataSet::new();
+        insert(
+            &mut referenced_frame,
+            dictionary::REFERENCED_FRAME_OF_REFERENCE_UID.tag,
+            "UI",
+            "1.2.3.7",
+        );
+
+        let mut first_frame = DataSet::new();
+        insert(
+            &mut first_frame,
+            dictionary::REFERENCED_SOP_INSTANCE_UID.tag,
+            "UI",
+            "1.2.3.9",
+        );
+        insert(&mut first_frame, PRIVATE_CREATOR_TAG, "LO", "ACME");
+
+        let mut second_frame = DataSet::new();
+        second_frame.insert(
+            dictionary::FRAME_VOI_LUT_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![referenced_frame]),
+        );
+
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::PATIENT_NAME.tag,
+            "PN",
+            "Doe^Jane",
+        );
+        insert(&mut data_set, dictionary::PATIENT_ID.tag, "LO", "12345");
+        insert(&mut data_set, dictionary::STUDY_DATE.tag, "DA", "20200101");
+        insert(
+            &mut data_set,
+            dictionary::ACQUISITION_DATE.tag,
+            "DA",
+            "20200102",
+        );
+        insert(&mut data_set, dictionary::STATION_NAME.tag, "SH", "CT01");
+        insert(
+            &mut data_set,
+            dictionary::SERIES_DESCRIPTION.tag,
+            "LO",
+            "Scan of Jane DOE, MRN 12345",
+        );
+        insert(
+            &mut data_set,
+            dictionary::STUDY_DESCRIPTION.tag,
+            "LO",
+            "Chest",
+        );
+        insert(
+            &mut data_set,
+            dictionary::SOP_CLASS_UID.tag,
+            "UI",
+            "1.2.840.10008.5.1.4.1.1.2",
+        );
+        insert(
+            &mut data_set,
+            dictionary::SOP_INSTANCE_UID.tag,
+            "UI",
+            "1.2.3.9",
+        );
+        insert(
+            &mut data_set,
+            dictionary::STUDY_INSTANCE_UID.tag,
+            "UI",
+            "1.2.3.1",
+        );
+        insert(
+            &mut data_set,
+            dictionary::FRAME_OF_REFERENCE_UID.tag,
+            "UI",
+            "1.2.3.7",
+        );
+        insert(&mut data_set, PRIVATE_CREATOR_TAG, "LO", "ACME");
+        insert(
+            &mut data_set,
+            DataElementTag::new(0x0009, 0x1001),
+            "LO",
+            "Doe",
+        );
+        data_set.insert(
+            dictionary::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![first_frame, second_frame]),
+        );
+
+        data_set
+    }
+
+    fn root_path(tag: DataElementTag) -> DataSetPath {
+        child_path(&DataSetPath::new(), tag).unwrap()
+    }
+
+    /// Returns the path to a data element in an item of the per-frame sequence.
+    ///
+    fn frame_path(item_index: usize, tag: DataElementTag) -> DataSetPath {
+        let mut path = root_path(dictionary::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE.tag);
+        path.add_sequence_item(item_index).unwrap();
+        child_path(&path, tag).unwrap()
+    }
+
+    fn action<'a>(result: &'a AnonymizeResult, path: &DataSetPath) -> Option<&'a AnonymizeAction> {
+        result
+            .summary
+            .iter()
+            .find(|element| element.path == *path)
+            .map(|element| &element.action)
+    }
+
+    fn edited_text(result: &AnonymizeResult, path: &DataSetPath) -> Option<String> {
+        result
+            .edits
+            .iter()
+            .find(|(edit_path, _)| edit_path == path)
+            .and_then(|(_, value)| value.as_ref())
+            .and_then(value_text::value_to_text)
+    }
+
+    fn replaced_uid(result: &AnonymizeResult, path: &DataSetPath) -> String {
+        match action(result, path) {
+            Some(AnonymizeAction::ReplacedUid(uid)) => uid.clone(),
+            action => panic!("{} wasn't replaced: {:?}", path, action),
+        }
+    }
+
+    #[test]
+    fn identifying_values_are_emptied_and_uids_replaced() {
+        let data_set = test_data_set();
+        let result = anonymize(&data_set, &AnonymizeOptions::default()).unwrap();
+
+        for tag in [
+            dictionary::PATIENT_NAME.tag,
+            dictionary::PATIENT_ID.tag,
+            dictionary::STUDY_DATE.tag,
+        ] {
+            assert_eq!(
+                action(&result, &root_path(tag)),
+                Some(&AnonymizeAction::Emptied)
+            );
+            assert_eq!(edited_text(&result, &root_path(tag)), Some(String::new()));
+        }
+
+        let study_instance_uid =
+            replaced_uid(&result, &root_path(dictionary::STUDY_INSTANCE_UID.tag));
+        assert!(study_instance_uid.starts_with("2.25."));
+        assert_eq!(
+            edited_text(&result, &root_path(dictionary::STUDY_INSTANCE_UID.tag)),
+            Some(study_instance_uid)
+        );
+
+        // UIDs that don't identify an instance are kept
+        assert_eq!(
+            action(&result, &root_path(dictionary::SOP_CLASS_UID.tag)),
+            None
+        );
+
+        // Identifying data elements that aren't required are removed
+        assert_eq!(
+            action(&result, &root_path(dictionary::ACQUISITION_DATE.tag)),
+            Some(&AnonymizeAction::Removed)
+        );
+        assert_eq!(
+            action(&result, &root_path(dictionary::STATION_NAME.tag)),
+            Some(&AnonymizeAction::Removed)
+        );
+    }
+
+    #[test]
+    fn private_tags_are_removed_including_in_sequences() {
+        let data_set = test_data_set();
+        let result = anonymize(&data_set, &AnonymizeOptions::default()).unwrap();
+
+        assert_eq!(
+            action(&result, &root_path(PRIVATE_CREATOR_TAG)),
+            Some(&AnonymizeAction::Removed)
+        );
+        assert_eq!(
+            action(&result, &root_path(DataElementTag::new(0x0009, 0x1001))),
+            Some(&AnonymizeAction::Removed)
+        );
+        assert_eq!(
+            action(&result, &frame_path(0, PRIVATE_CREATOR_TAG)),
+            Some(&AnonymizeAction::Removed)
+        );
+
+        // Keeping private tags takes precedence over everything else
+        let options = AnonymizeOptions {
+            remove_private_tags: false,
+            ..AnonymizeOptions::default()
+        };
+        let result = anonymize(&data_set, &options).unwrap();
+
+        assert!(
+            result
+                .summary
+                .iter()
+                .all(|element| !element.tag.is_private())
+        );
+    }
+
+    #[test]
+    fn options_retain_data_elements() {
+        let data_set = test_data_set();
+
+        let options = AnonymizeOptions {
+            retain_uids: true,
+            retain_dates: true,
+            retain_device_identity: true,
+            ..AnonymizeOptions::default()
+        };
+        let result = anonymize(&data_set, &options).unwrap();
+
+        for tag in [
+            dictionary::STUDY_DATE.tag,
+            dictionary::ACQUISITION_DATE.tag,
+            dictionary::STATION_NAME.tag,
+            dictionary::STUDY_INSTANCE_UID.tag,
+            dictionary::SOP_INSTANCE_UID.tag,
+        ] {
+            assert_eq!(action(&result, &root_path(tag)), None);
+        }
+        assert_eq!(
+            action(
+                &result,
+                &frame_path(0, dictionary::REFERENCED_SOP_INSTANCE_UID.tag)
+            ),
+            None
+        );
+
+        // Retaining dates doesn't retain other identifying values that must stay present
+        assert_eq!(
+            action(&result, &root_path(dictionary::PATIENT_NAME.tag)),
+            Some(&AnonymizeAction::Emptied)
+        );
+    }
+
+    #[test]
+    fn uids_are_replaced_consistently_across_sequences() {
+        let data_set = test_data_set();
+        let result = anonymize(&data_set, &AnonymizeOptions::default()).unwrap();
+
+        let sop_instance_uid = replaced_uid(&result, &root_path(dictionary::SOP_INSTANCE_UID.tag));
+        let frame_of_reference_uid =
+            replaced_uid(&result, &root_path(dictionary::FRAME_OF_REFERENCE_UID.tag));
+
+        assert_eq!(
+            replaced_uid(
+                &result,
+                &frame_path(0, dictionary::REFERENCED_SOP_INSTANCE_UID.tag)
+            ),
+            sop_instance_uid
+        );
+
+        // The reference in the nested sequence
+        let mut nested_path = frame_path(1, dictionary::FRAME_VOI_LUT_SEQUENCE.tag);
+        nested_path.add_sequence_item(0).unwrap();
+        let nested_path = child_path(
+            &nested_path,
+            dictionary::REFERENCED_FRAME_OF_REFERENCE_UID.tag,
+        )
+        .unwrap();
+        assert_eq!(replaced_uid(&result, &nested_path), frame_of_reference_uid);
+
+        // Different UIDs are replaced with different UIDs
+        assert_ne!(sop_instance_uid, frame_of_reference_uid);
+        assert_ne!(
+            replaced_uid(&result, &root_path(dictionary::STUDY_INSTANCE_UID.tag)),
+            sop_instance_uid
+        );
+    }
+
+    #[test]
+    fn clean_descriptors_masks_identifying_values() {
+        let data_set = test_data_set();
+
+        let options = AnonymizeOptions {
+            clean_descriptors: true,
+            ..AnonymizeOptions::default()
+        };
+        let result = anonymize(&data_set, &options).unwrap();
+
+        let series_description = root_path(dictionary::SERIES_DESCRIPTION.tag);
+        assert_eq!(
+            action(&result, &series_description),
+            Some(&AnonymizeAction::Cleaned(
+                "Scan of XXXX XXX, MRN XXXXX".into()
+            ))
+        );
+        assert_eq!(
+            edited_text(&result, &series_description),
+            Some("Scan of XXXX XXX, MRN XXXXX".into())
+        );
+
+        // Descriptors without identifying values are kept but flagged for checking
+        assert!(matches!(
+            action(&result, &root_path(dictionary::STUDY_DESCRIPTION.tag)),
+            Some(AnonymizeAction::Retained(_))
+        ));
+
+        // Without the option, descriptors are removed
+        let result = anonymize(&data_set, &AnonymizeOptions::default()).unwrap();
+        assert_eq!(
+            action(&result, &series_description),
+            Some(&AnonymizeAction::Removed)
+        );
+    }
+
+    #[test]
+    fn clean_descriptor_masks_longest_identifiers_first() {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::PATIENT_NAME.tag,
+            "PN",
+            "Smith^Jo=Smithson",
+        );
+
+        // Name components shorter than the minimum length aren't used
+        let identifiers = descriptor_identifiers(&data_set);
+        assert_eq!(identifiers, vec!["Smithson", "Smith"]);
+
+        assert_eq!(
+            clean_descriptor("smithson and Smith, Jo", &identifiers),
+            Some("XXXXXXXX and XXXXX, Jo".into())
+        );
+        assert_eq!(clean_descriptor("Nothing here", &identifiers), None);
+    }
+
+    #[test]
+    fn deidentification_method_lists_the_options() {
+        let data_set = test_data_set();
+
+        let result = anonymize(&data_set, &AnonymizeOptions::default()).unwrap();
+        assert_eq!(
+            edited_text(&result, &root_path(dictionary::DEIDENTIFICATION_METHOD.tag)),
+            Some("Basic Application Confidentiality Profile".into())
+        );
+        assert_eq!(
+            edited_text(
+                &result,
+                &root_path(dictionary::PATIENT_IDENTITY_REMOVED.tag)
+            ),
+            Some("YES".into())
+        );
+
+        let options = AnonymizeOptions {
+            retain_uids: true,
+            clean_descriptors: true,
+            ..AnonymizeOptions::default()
+        };
+        let result = anonymize(&data_set, &options).unwrap();
+        assert_eq!(
+            edited_text(&result, &root_path(dictionary::DEIDENTIFICATION_METHOD.tag)),
+            Some(
+                "Basic Application Confidentiality Profile\\Retain UIDs\\Clean Descriptors".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use dcmfx::core::*;
+
+/// The expansion state of the data set grid, which is the set of sequences, sequence items, and
+/// encapsulated pixel data elements that are expanded. These are keyed by the string form of their
+/// [`DataSetPath`].
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GridExpansion {
+    expanded_paths: HashSet<String>,
+}
+
+impl GridExpansion {
+    /// Returns whether the sequence, sequence item, or encapsulated pixel data at the given path is
+    /// expanded.
+    ///
+    pub fn is_expanded(&self, path: &str) -> bool {
+        self.expanded_paths.contains(path)
+    }
+
+    /// Toggles whether the given path is expanded.
+    ///
+    pub fn toggle(&mut self, path: String) {
+        if !self.expanded_paths.remove(&path) {
+            self.expanded_paths.insert(path);
+        }
+    }
+
+    /// Expands all of the given paths.
+    ///
+    pub fn expand(&mut self, paths: impl IntoIterator<Item = String>) {
+        self.expanded_paths.extend(paths);
+    }
+
+    /// Collapses everything.
+    ///
+    pub fn collapse_all(&mut self) {
+        self.expanded_paths.clear();
+    }
+
+    /// Expands every sequence and sequence item in the data set. Encapsulated pixel data isn't
+    /// expanded because it can have a very large number of items.
+    ///
+    pub fn expand_all(&mut self, data_set: &DataSet) {
+        self.expand_to_depth(data_set, usize::MAX);
+    }
+
+    /// Expands sequences and their items up to the given depth and collapses everything deeper.
+    /// A depth of one expands the sequences in the root data set, a depth of two also expands the
+    /// sequences directly inside their items, and so on. Encapsulated pixel data is never
+    /// expanded.
+    ///
+    pub fn expand_to_depth(&mut self, data_set: &DataSet, depth: usize) {
+        self.expanded_paths.clear();
+
+        if depth > 0 {
+            self.expand_data_set(data_set, &DataSetPath::new(), depth);
+        }
+    }
+
+    fn expand_data_set(
+        &mut self,
+        data_set: &DataSet,
+        path_to_data_set: &DataSetPath,
+        depth: usize,
+    ) {
+        for (tag, value) in data_set.iter() {
+            let Ok(items) = value.sequence_items() else {
+                continue;
+            };
+
+            let mut path = path_to_data_set.clone();
+            path.add_data_element(*tag).unwrap();
+            self.expanded_paths.insert(path.to_string());
+
+            for (item_index, item) in items.iter().enumerate() {
+                let mut item_path = path.clone();
+                item_path.add_sequence_item(item_index).unwrap();
+                self.expanded_paths.insert(item_path.to_string());
+
+                if depth > 1 {
+                    self.expand_data_set(item, &item_path, depth.saturating_sub(1));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use dcmfx::core::*;
+
+use crate::editing::value_text;
+
+/// The search text and filters applied to the data set grid.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GridFilter {
+    pub search_text: String,
+    pub private_tags_only: bool,
+    pub hide_file_meta_information: bool,
+    pub value_representation: Option<String>,
+}
+
+impl GridFilter {
+    /// Returns whether any filters are active that hide data elements. The search text doesn't
+    /// hide data elements, it only highlights them.
+    ///
+    pub fn has_active_filters(&self) -> bool {
+        self.private_tags_only
+            || self.hide_file_meta_information
+            || self.value_representation.is_some()
+    }
+
+    /// Returns whether a data element passes the active filters.
+    ///
+    fn passes_filters(&self, tag: DataElementTag, value: &DataElementValue) -> bool {
+        if self.private_tags_only && !tag.is_private() {
+            return false;
+        }
+
+        if self.hide_file_meta_information && tag.group == 0x0002 {
+            return false;
+        }
+
+        if let Some(vr) = &self.value_representation
+            && value.value_representation().to_string() != *vr
+        {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// A parsed search query. The search text is matched against tags, data element names and
+/// keywords, and data element values.
+///
+struct SearchQuery {
+    tag: Option<DataElementTag>,
+    normalized_name: String,
+    lowercase_text: String,
+}
+
+impl SearchQuery {
+    fn new(search_text: &str) -> Option<Self> {
+        let search_text = search_text.trim();
+        if search_text.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            tag: value_text::parse_tag(search_text).ok(),
+            normalized_name: normalize_name(search_text),
+            lowercase_text: search_text.to_lowercase(),
+        })
+    }
+
+    fn matches(&self, data_set: &DataSet, tag: DataElementTag, value: &DataElementValue) -> bool {
+        if self.tag == Some(tag) {
+            return true;
+        }
+
+        if !self.normalized_name.is_empty()
+            && normalize_name(&data_set.tag_name(tag)).contains(&self.normalized_name)
+        {
+            return true;
+        }
+
+        if value.bytes().is_ok() {
+            return value
+                .to_string(tag, 1000)
+                .to_lowercase()
+                .contains(&self.lowercase_text);
+        }
+
+        false
+    }
+}
+
+/// The result of applying a [`GridFilter`] to a data set. Data set paths are stored in their
+/// string form.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GridFilterResult {
+    /// The paths of the data elements and sequence items that are visible. This is `None` when no
+    /// filters are active, i.e. everything is visible.
+    pub visible_paths: Option<HashSet<String>>,
+
+    /// The paths of the data elements that match the search text, in the order they appear in
+    /// the grid.
+    pub search_matches: Vec<DataSetPath>,
+
+    /// The paths in `search_matches` in string form, for fast lookup.
+    pub search_match_paths: HashSet<String>,
+
+    /// The paths of the sequences and sequence items that contain a search match, and so need to
+    /// be expanded for the match to be seen.
+    pub search_match_ancestor_paths: HashSet<String>,
+}
+
+impl GridFilterResult {
+    /// Returns whether the data element or sequence item at the given path is visible.
+    ///
+    pub fn is_visible(&self, path: &str) -> bool {
+        self.visible_paths
+            .as_ref()
+            .is_none_or(|visible_paths| visible_paths.contains(path))
+    }
+}
+
+/// Applies a grid filter to a data set.
+///
+pub fn apply(data_set: &DataSet, filter: &GridFilter) -> GridFilterResult {
+    let mut result = GridFilterResult::default();
+
+    let query = SearchQuery::new(&filter.search_text);
+    if query.is_none() && !filter.has_active_filters() {
+        return result;
+    }
+
+    let mut visible_paths = HashSet::new();
+
+    apply_to_data_set(
+        data_set,
+        &DataSetPath::new(),
+        &mut vec![],
+        filter,
+        query.as_ref(),
+        &mut visible_paths,
+        &mut result,
+    );
+
+    if filter.has_active_filters() {
+        result.visible_paths = Some(visible_paths);
+    }
+
+    result
+}
+
+/// Recursively applies a filter to a data set, returning whether anything in it is visible.
+///
+fn apply_to_data_set(
+    data_set: &DataSet,
+    path_to_data_set: &DataSetPath,
+    ancestor_paths: &mut Vec<String>,
+    filter: &GridFilter,
+    query: Option<&SearchQuery>,
+    visible_paths: &mut HashSet<String>,
+    result: &mut GridFilterResult,
+) -> bool {
+    let mut is_anything_visible = false;
+
+    for (tag, value) in data_set.iter() {
+        let mut path = path_to_data_set.clone();
+        path.add_data_element(*tag).unwrap();
+        let path_string = path.to_string();
+
+        if query.is_some_and(|query| query.matches(data_set, *tag, value)) {
+            result.search_match_paths.insert(path_string.clone());
+            result.search_matches.push(path.clone());
+            result
+                .search_match_ancestor_paths
+                .extend(ancestor_paths.iter().cloned());
+        }
+
+        let mut is_visible = filter.passes_filters(*tag, value);
+
+        if let Ok(items) = value.sequence_items() {
+            ancestor_paths.push(path_string.clone());
+
+            for (i, item) in items.iter().enumerate() {
+                let mut item_path = path.clone();
+                item_path.add_sequence_item(i).unwrap();
+                let item_path_string = item_path.to_string();
+
+                ancestor_paths.push(item_path_string.clone());
+
+                if apply_to_data_set(
+                    item,
+                    &item_path,
+                    ancestor_paths,
+                    filter,
+                    query,
+                    visible_paths,
+                    result,
+                ) {
+                    visible_paths.insert(item_path_string);
+                    is_visible = true;
+                }
+
+                ancestor_paths.pop();
+            }
+
+            ancestor_paths.pop();
+        }
+
+        if is_visible {
+            visible_paths.insert(path_string);
+            is_anything_visible = true;
+        }
+    }
+
+    is_anything_visible
+}
+
+/// Normalizes a data element name so that it can be compared against keywords, e.g. both
+/// "Patient's Name" and "PatientName" normalize to "patientname".
+///
+fn normalize_name(name: &str) -> String {
+    name.replace("'s ", " ")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,1124 @@
+mod expansion;
+mod filter;
+mod rows;
+
+use std::rc::Rc;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+use web_sys::HtmlElement;
+
+use crate::{
+    editing::{self, History, value_text},
+    hex_dump_dialog::{self, HexDumpDialog},
+    jobs::{OmittedValue, WorkerDataSet},
+    raw_token_view::{self, P10Structure},
+    ui::{self, FontAwesomeIcon},
+    utils,
+};
+pub use expansion::GridExpansion;
+use filter::{GridFilter, GridFilterResult};
+use rows::{GridRow, GridRowKind};
+
+/// The value representations that can be selected in the VR filter.
+///
+const FILTERABLE_VALUE_REPRESENTATIONS: [&str; 34] = [
+    "AE", "AS", "AT", "CS", "DA", "DS", "DT", "FD", "FL", "IS", "LO", "LT", "OB", "OD", "OF", "OL",
+    "OV", "OW", "PN", "SH", "SL", "SQ", "SS", "ST", "SV", "TM", "UC", "UI", "UL", "UN", "UR", "US",
+    "UT", "UV",
+];
+
+/// The height in pixels of the grid's header row and of each of its rows. These must match the
+/// heights set in the stylesheet because only the rows that are in view are rendered, and the
+/// space taken up by the other rows is calculated from these values.
+///
+const HEADER_HEIGHT: f64 = 30.0;
+const ROW_HEIGHT: f64 = 22.0;
+
+/// The number of extra rows rendered above and below the visible rows, which avoids blank space
+/// appearing at the edges when scrolling quickly.
+///
+const OVERSCAN_ROWS: usize = 10;
+
+/// The depths offered in the grid's "Expand to depth" selector.
+///
+const EXPAND_TO_DEPTH_OPTIONS: [usize; 5] = [1, 2, 3, 4, 5];
+
+#[component]
+pub fn DataSetGrid(
+    main_data_set: Signal<DataSet>,
+    worker_data_set: ReadSignal<Option<Rc<WorkerDataSet>>>,
+    expansion: Signal<GridExpansion>,
+    history: Signal<History>,
+    show_offsets: Signal<bool>,
+    p10_structure: Signal<Option<Result<P10Structure, String>>>,
+    jump_to_read_failure: Signal<bool>,
+) -> Element {
+    let filter = use_signal(GridFilter::default);
+    let filter_result = use_memo(move || filter::apply(&main_data_set.read(), &filter.read()));
+    let mut current_match = use_signal(|| None::<usize>);
+
+    let read_failure = use_memo(move || {
+        worker_data_set
+            .read()
+            .as_ref()
+            .and_then(|worker_data_set| worker_data_set.read_failure().cloned())
+    });
+
+    let rows = use_memo(move || {
+        let mut rows = rows::flatten(
+            &main_data_set.read(),
+            &expansion.read(),
+            &filter_result.read(),
+        );
+
+        // Reading stopped after everything that was read, so the failure's marker row goes last.
+        // It's only shown when the sequences and items it's inside of are expanded.
+        if let Some(failure) = &*read_failure.read()
+            && failure
+                .open_paths
+                .iter()
+                .all(|path| expansion.read().is_expanded(path))
+        {
+            rows.push(GridRow::read_failure(failure));
+        }
+
+        rows
+    });
+
+    let mut editing_key = use_signal(|| None::<String>);
+    let mut add_element_target = use_signal(|| None::<DataSetPath>);
+    let mut hex_dump_path = use_signal(|| None::<DataSetPath>);
+
+    let mut grid_element = use_signal(|| None::<HtmlElement>);
+    let mut scroll_top = use_signal(|| 0.0);
+    let mut viewport_height = use_signal(|| 0.0);
+
+    // Move to the first search match whenever the search text changes, and expand the sequences
+    // and sequence items that contain matches so that they can be seen. When only the data set
+    // changes, e.g. due to an edit, the current match is kept and the expansion is left alone so
+    // that sequences the user collapsed stay collapsed.
+    let mut searched_text = use_signal(String::new);
+
+    use_effect(move || {
+        let filter_result = filter_result.read();
+        let search_text = filter.read().search_text.clone();
+        let match_count = filter_result.search_matches.len();
+
+        if *searched_text.peek() != search_text {
+            searched_text.set(search_text);
+
+            current_match.set(if match_count > 0 { Some(0) } else { None });
+
+            if !filter_result.search_match_ancestor_paths.is_empty() {
+                expansion
+                    .write()
+                    .expand(filter_result.search_match_ancestor_paths.iter().cloned());
+            }
+        } else {
+            let index = *current_match.peek();
+            current_match.set(match index {
+                _ if match_count == 0 => None,
+                Some(index) => Some(index.min(match_count - 1)),
+                None => Some(0),
+            });
+        }
+    });
+
+    let current_match_path = use_memo(move || {
+        current_match().and_then(|index| {
+            filter_result
+                .read()
+                .search_matches
+                .get(index)
+                .map(|path| path.to_string())
+        })
+    });
+
+    // Scroll the current search match into view if it isn't already visible
+    use_effect(move || {
+        let Some(path) = current_match_path() else {
+            return;
+        };
+
+        let Some(index) = rows.read().iter().position(|row| row.key == path) else {
+            return;
+        };
+
+        if let Some(element) = grid_element.peek().as_ref() {
+            scroll_row_into_view(element, index);
+        }
+    });
+
+    // Jumping to the read failure expands the sequences and items it's inside of, and then scrolls
+    // to its marker row once that row is present
+    let mut scroll_to_read_failure = use_signal(|| false);
+
+    use_effect(move || {
+        if !jump_to_read_failure() {
+            return;
+        }
+
+        if let Some(failure) = &*read_failure.read() {
+            expansion.write().expand(failure.open_paths.iter().cloned());
+            scroll_to_read_failure.set(true);
+        }
+
+        jump_to_read_failure.set(false);
+    });
+
+    use_effect(move || {
+        if !scroll_to_read_failure() {
+            return;
+        }
+
+        let Some(index) = rows
+            .read()
+            .iter()
+            .position(|row| row.key == rows::READ_FAILURE_ROW_KEY)
+        else {
+            return;
+        };
+
+        if let Some(element) = grid_element.read().as_ref() {
+            scroll_row_into_view(element, index);
+            scroll_to_read_failure.set(false);
+        }
+    });
+
+    let mut update_scroll_state = move || {
+        if let Some(element) = grid_element.peek().as_ref() {
+            scroll_top.set(f64::from(element.scroll_top()));
+            viewport_height.set(f64::from(element.client_height()));
+        }
+    };
+
+    let on_toggle_expanded = move |key: String| expansion.write().toggle(key);
+
+    // All edits to the data set made in the grid go through this function so that they're
+    // recorded in the undo history. Changes that don't alter the current value are ignored.
+    let mut change_value = move |(path, value): (DataSetPath, Option<DataElementValue>)| {
+        let before = editing::get_value(&main_data_set.peek(), &path);
+        if before == value {
+            return;
+        }
+
+        let description = editing::history::describe_change(&path, before.as_ref(), value.as_ref());
+
+        if let Err(e) =
+            history
+                .write()
+                .apply(&mut main_data_set.write(), description, vec![(path, value)])
+        {
+            ui::toasts::add_error(e);
+        }
+    };
+
+    let on_add_sequence_item = move |path: DataSetPath| {
+        let Some(sequence) = editing::get_value(&main_data_set.peek(), &path) else {
+            return;
+        };
+
+        match editing::sequence_with_item_added(&sequence, usize::MAX) {
+            Ok(value) => {
+                change_value((path.clone(), Some(value)));
+                expansion.write().expand([path.to_string()]);
+            }
+            Err(e) => ui::toasts::add_error(e),
+        }
+    };
+
+    let on_delete = move |path: DataSetPath| {
+        // Deleting a sequence item replaces the value of its sequence
+        if let Ok((sequence_path, index)) = editing::split_sequence_item_path(&path) {
+            let Some(sequence) = editing::get_value(&main_data_set.peek(), &sequence_path) else {
+                return;
+            };
+
+            match editing::sequence_with_item_removed(&sequence, index) {
+                Ok(value) => change_value((sequence_path, Some(value))),
+                Err(e) => ui::toasts::add_error(e),
+            }
+        } else {
+            change_value((path, None));
+        }
+    };
+
+    // Keyboard shortcuts for expanding and collapsing: '+' expands all, '-' collapses all, and
+    // the digits 1-9 expand to that depth
+    let on_key_down = move |event: KeyboardEvent| {
+        let modifiers = event.modifiers();
+        if modifiers.contains(Modifiers::CONTROL)
+            || modifiers.contains(Modifiers::META)
+            || modifiers.contains(Modifiers::ALT)
+        {
+            return;
+        }
+
+        let Key::Character(character) = event.key() else {
+            return;
+        };
+
+        match character.as_str() {
+            "+" | "=" => expansion.write().expand_all(&main_data_set.read()),
+            "-" => expansion.write().collapse_all(),
+            c => match c.parse::<usize>() {
+                Ok(depth) if depth > 0 => expansion
+                    .write()
+                    .expand_to_depth(&main_data_set.read(), depth),
+                _ => return,
+            },
+        }
+
+        event.prevent_default();
+    };
+
+    // The offsets shown are those of the data elements in the file as it was read, so edited data
+    // elements keep their original offset and added ones have none
+    let element_offset = move |key: &str| match &*p10_structure.read() {
+        Some(Ok(structure)) => structure
+            .offsets
+            .get(key)
+            .map(raw_token_view::format_offset)
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+
+    // Values that were left out of the data set's header are shown with their size in the job
+    // worker's copy of the data set
+    let omitted_value = move |row: &GridRow| {
+        worker_data_set
+            .read()
+            .as_ref()?
+            .omitted_value(&main_data_set.read(), &row.path)
+            .cloned()
+    };
+
+    // Determine the range of rows to render
+    let row_count = rows.read().len();
+    let first_row = (((scroll_top() - HEADER_HEIGHT) / ROW_HEIGHT).max(0.0) as usize)
+        .saturating_sub(OVERSCAN_ROWS)
+        .min(row_count);
+    let last_row =
+        (first_row + (viewport_height() / ROW_HEIGHT).ceil() as usize + OVERSCAN_ROWS * 2)
+            .min(row_count);
+
+    rsx! {
+        div {
+            class: "data-set-grid-container",
+
+            DataSetGridToolbar {
+                main_data_set,
+                filter,
+                filter_result,
+                current_match,
+                expansion,
+                show_offsets,
+                on_add_element: move |_| add_element_target.set(Some(DataSetPath::new())),
+            }
+
+            if let Some(data_set_path) = add_element_target() {
+                AddElementForm {
+                    main_data_set,
+                    data_set_path,
+                    on_add: move |(path, value)| {
+                        change_value((path, Some(value)));
+                        add_element_target.set(None);
+                    },
+                    on_close: move |_| add_element_target.set(None),
+                }
+            }
+
+            div {
+                class: "data-set-grid",
+                class: if show_offsets() { "with-offsets" },
+                tabindex: 0,
+
+                onmounted: move |ev| {
+                    grid_element.set(utils::get_element::<HtmlElement>(ev));
+                    update_scroll_state();
+                },
+                onscroll: move |_| update_scroll_state(),
+                onresize: move |_| update_scroll_state(),
+                onkeydown: on_key_down,
+
+                if !main_data_set.read().is_empty() {
+                    div {
+                        class: "data-element-value-row header",
+
+                        div { "Tag" }
+                        div { "Name" }
+                        div { "VR" }
+                        div { "Length" }
+                        if show_offsets() {
+                            div { title: "Offset in the file in hex, and header length", "Offset" }
+                        }
+                        div { "Value" }
+                        div {}
+                    }
+
+                    div {
+                        class: "data-set-grid-spacer",
+                        height: format!("{}px", first_row as f64 * ROW_HEIGHT),
+                    }
+
+                    for row in rows.read()[first_row..last_row].iter().cloned() {
+                        DataSetGridRow {
+                            key: "{row.key}",
+                            main_data_set,
+                            is_expanded: expansion.read().is_expanded(&row.key),
+                            is_search_match: filter_result.read().search_match_paths.contains(&row.key),
+                            is_current_search_match: current_match_path.read().as_ref() == Some(&row.key),
+                            is_editing: editing_key.read().as_ref() == Some(&row.key),
+                            offset: show_offsets().then(|| element_offset(&row.key)),
+                            omitted_value: omitted_value(&row),
+                            is_partial: read_failure
+                                .read()
+                                .as_ref()
+                                .is_some_and(|failure| failure.is_partial(&row.key)),
+                            on_toggle_expanded,
+                            on_set_editing: move |key| editing_key.set(key),
+                            on_change_value: change_value,
+                            on_add_sequence_item,
+                            on_add_element: move |path| add_element_target.set(Some(path)),
+                            on_delete,
+                            on_view_bytes: move |path| hex_dump_path.set(Some(path)),
+                            row: row.clone(),
+                        }
+                    }
+
+                    div {
+                        class: "data-set-grid-spacer",
+                        height: format!("{}px", (row_count - last_row) as f64 * ROW_HEIGHT),
+                    }
+                }
+            }
+
+            if let Some(path) = hex_dump_path() {
+                HexDumpDialog {
+                    data_set: main_data_set,
+                    worker_data_set,
+                    path,
+                    on_close: move |_| hex_dump_path.set(None),
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn DataSetGridToolbar(
+    main_data_set: Signal<DataSet>,
+    filter: Signal<GridFilter>,
+    filter_result: Memo<GridFilterResult>,
+    current_match: Signal<Option<usize>>,
+    expansion: Signal<GridExpansion>,
+    show_offsets: Signal<bool>,
+    on_add_element: EventHandler<()>,
+) -> Element {
+    let match_count = filter_result.read().search_matches.len();
+
+    let mut step_match = move |delta: isize| {
+        if match_count == 0 {
+            return;
+        }
+
+        let index = current_match().map_or(0, |index| index as isize + delta);
+        current_match.set(Some(index.rem_euclid(match_count as isize) as usize));
+    };
+
+    rsx! {
+        div {
+            class: "data-set-grid-toolbar",
+
+            div {
+                class: "search-box",
+
+                FontAwesomeIcon { icon: "magnifying-glass", style: "solid" }
+
+                input {
+                    r#type: "search",
+                    placeholder: "Search tags, names and values",
+                    value: "{filter.read().search_text}",
+                    oninput: move |event| filter.write().search_text = event.value(),
+                    onkeydown: move |event| {
+                        if event.key() == Key::Enter {
+                            let is_shift = event.modifiers().contains(Modifiers::SHIFT);
+                            step_match(if is_shift { -1 } else { 1 });
+                        }
+                    },
+                }
+            }
+
+            if !filter.read().search_text.trim().is_empty() {
+                span {
+                    class: "search-match-count",
+
+                    if let Some(index) = current_match() {
+                        "{index + 1} of {match_count}"
+                    } else {
+                        "No matches"
+                    }
+                }
+
+                button {
+                    title: "Previous match",
+                    disabled: match_count == 0,
+                    onclick: move |_| step_match(-1),
+
+                    FontAwesomeIcon { icon: "chevron-up", style: "solid" }
+                }
+                button {
+                    title: "Next match",
+                    disabled: match_count == 0,
+                    onclick: move |_| step_match(1),
+
+                    FontAwesomeIcon { icon: "chevron-down", style: "solid" }
+                }
+            }
+
+            div { class: "toolbar-spacer" }
+
+            button {
+                title: "Expand all sequences (+)",
+                onclick: move |_| expansion.write().expand_all(&main_data_set.read()),
+
+                FontAwesomeIcon { icon: "angles-down", style: "solid" }
+                " Expand all"
+            }
+            button {
+                title: "Collapse all sequences (-)",
+                onclick: move |_| expansion.write().collapse_all(),
+
+                FontAwesomeIcon { icon: "angles-up", style: "solid" }
+                " Collapse all"
+            }
+            select {
+                title: "Expand sequences to a given depth (1-9)",
+                onchange: move |event| {
+                    if let Ok(depth) = event.value().parse::<usize>() {
+                        expansion.write().expand_to_depth(&main_data_set.read(), depth);
+                    }
+                },
+
+                option { value: "", selected: true, disabled: true, "Expand to depth…" }
+
+                for depth in EXPAND_TO_DEPTH_OPTIONS {
+                    option { value: "{depth}", "Depth {depth}" }
+                }
+            }
+
+            div { class: "vertical-divider" }
+
+            button {
+                title: "Add a data element to the root data set",
+                onclick: move |_| on_add_element.call(()),
+
+                FontAwesomeIcon { icon: "plus", style: "solid" }
+                " Add element"
+            }
+
+            div { class: "vertical-divider" }
+
+            label {
+                input {
+                    r#type: "checkbox",
+                    checked: filter.read().private_tags_only,
+                    onchange: move |event| filter.write().private_tags_only = event.checked(),
+                }
+                "Private tags only"
+            }
+
+            label {
+                input {
+                    r#type: "checkbox",
+                    checked: filter.read().hide_file_meta_information,
+                    onchange: move |event| {
+                        filter.write().hide_file_meta_information = event.checked()
+                    },
+                }
+                "Hide group 0002"
+            }
+
+            label {
+                title: "Show where each data element is in the file, for files read as DICOM P10",
+
+                input {
+                    r#type: "checkbox",
+                    checked: show_offsets(),
+                    onchange: move |event| show_offsets.set(event.checked()),
+                }
+                "File offsets"
+            }
+
+            label {
+                "VR"
+
+                select {
+                    onchange: move |event| {
+                        let vr = event.value();
+                        filter.write().value_representation = if vr.is_empty() { None } else { Some(vr) };
+                    },
+
+                    option { value: "", selected: filter.read().value_representation.is_none(), "All" }
+
+                    for vr in FILTERABLE_VALUE_REPRESENTATIONS {
+                        option {
+                            value: vr,
+                            selected: filter.read().value_representation.as_deref() == Some(vr),
+
+                            {vr}
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders a single row of the data set grid. The data element value for the row is looked up by
+/// path in the main data set.
+///
+#[component]
+fn DataSetGridRow(
+    main_data_set: Signal<DataSet>,
+    row: GridRow,
+    is_expanded: bool,
+    is_search_match: bool,
+    is_current_search_match: bool,
+    is_editing: bool,
+    offset: Option<String>,
+    omitted_value: Option<OmittedValue>,
+    is_partial: bool,
+    on_toggle_expanded: EventHandler<String>,
+    on_set_editing: EventHandler<Option<String>>,
+    on_change_value: EventHandler<(DataSetPath, Option<DataElementValue>)>,
+    on_add_sequence_item: EventHandler<DataSetPath>,
+    on_add_element: EventHandler<DataSetPath>,
+    on_delete: EventHandler<DataSetPath>,
+    on_view_bytes: EventHandler<DataSetPath>,
+) -> Element {
+    let main_data_set = main_data_set.read();
+
+    let Ok(data_set) = main_data_set.get_data_set_at_path(&row.parent_path) else {
+        return rsx! {};
+    };
+
+    let expanded = if row.is_expandable() {
+        Some(is_expanded)
+    } else {
+        None
+    };
+
+    let key = row.key.clone();
+    let on_toggle = move |_: MouseEvent| on_toggle_expanded.call(key.clone());
+
+    let path = row.path.clone();
+    let delete = EventHandler::new(move |_| on_delete.call(path.clone()));
+
+    match row.kind {
+        GridRowKind::DataElement { tag } => {
+            let Ok(value) = data_set.get_value(tag) else {
+                return rsx! {};
+            };
+
+            let vr = value.value_representation().to_string();
+            let editable_text = if value_text::is_editable(&vr) {
+                value_text::value_to_text(value)
+            } else {
+                None
+            };
+
+            let key = row.key.clone();
+            let start_edit = editable_text
+                .is_some()
+                .then(|| EventHandler::new(move |_| on_set_editing.call(Some(key.clone()))));
+
+            let path = row.path.clone();
+            let commit_edit = {
+                let vr = vr.clone();
+                move |text: String| match value_text::text_to_value(&vr, &text) {
+                    Ok(value) => {
+                        on_change_value.call((path.clone(), Some(value)));
+                        on_set_editing.call(None);
+                    }
+                    Err(e) => ui::toasts::add_error(e),
+                }
+            };
+
+            // Clicking a binary value opens it in the hex dump
+            let path = row.path.clone();
+            let view_bytes = hex_dump_dialog::is_viewable(value)
+                .then(|| EventHandler::new(move |_| on_view_bytes.call(path.clone())));
+
+            rsx! {
+                DataElementValueRow {
+                    indent: row.indent,
+                    tag: tag.to_string(),
+                    name: data_set.tag_name(tag),
+                    vr,
+                    offset,
+                    is_partial,
+                    length: match &omitted_value {
+                        Some(omitted_value) => omitted_value.length.to_string(),
+                        None => value.bytes().map(|bytes| bytes.len().to_string()).unwrap_or_default(),
+                    },
+                    value: match &omitted_value {
+                        Some(omitted_value) => {
+                            format!("{}, view bytes to load", utils::format_size(omitted_value.length as u64))
+                        }
+                        None => value.to_string(tag, 1000),
+                    },
+                    is_search_match,
+                    is_current_search_match,
+                    editing_text: if is_editing { editable_text } else { None },
+                    on_start_edit: start_edit,
+                    on_commit_edit: commit_edit,
+                    on_cancel_edit: move |_| on_set_editing.call(None),
+                    onclick: view_bytes.map(|view_bytes| {
+                        EventHandler::new(move |_: MouseEvent| view_bytes.call(()))
+                    }),
+                    on_view_bytes: view_bytes,
+                    on_delete: delete,
+                }
+            }
+        }
+
+        GridRowKind::Sequence { tag, item_count } => {
+            let path = row.path.clone();
+
+            rsx! {
+                DataElementValueRow {
+                    indent: row.indent,
+                    expanded,
+                    tag: tag.to_string(),
+                    name: data_set.tag_name(tag),
+                    vr: "SQ",
+                    offset,
+                    is_partial,
+                    value: format!(
+                        "{} item{}",
+                        item_count,
+                        if item_count == 1 { "" } else { "s" }
+                    ),
+                    is_search_match,
+                    is_current_search_match,
+                    onclick: on_toggle,
+                    on_add_item: move |_| on_add_sequence_item.call(path.clone()),
+                    on_delete: delete,
+                }
+            }
+        }
+
+        GridRowKind::SequenceItem { item_index } => {
+            let path = row.path.clone();
+
+            rsx! {
+                DataElementValueRow {
+                    indent: row.indent,
+                    expanded,
+                    tag: format!("Item {}", item_index + 1),
+                    offset,
+                    is_partial,
+                    onclick: on_toggle,
+                    on_add_element: move |_| on_add_element.call(path.clone()),
+                    on_delete: delete,
+                }
+            }
+        }
+
+        GridRowKind::EncapsulatedPixelData { tag, item_count } => rsx! {
+            DataElementValueRow {
+                indent: row.indent,
+                expanded,
+                tag: dictionary::tag_name(tag, None),
+                vr: data_set
+                    .get_value(tag)
+                    .map(|value| value.value_representation().to_string())
+                    .unwrap_or_default(),
+                length: omitted_value
+                    .and_then(|omitted_value| omitted_value.item_count)
+                    .unwrap_or(item_count)
+                    .to_string(),
+                offset,
+                is_partial,
+                is_search_match,
+                is_current_search_match,
+                onclick: on_toggle,
+                on_delete: delete,
+            }
+        },
+
+        GridRowKind::EncapsulatedPixelDataItem { item_index, length } => rsx! {
+            DataElementValueRow {
+                indent: row.indent,
+                tag: format!("Item {}", item_index),
+                length: length.to_string(),
+                offset,
+            }
+        },
+
+        GridRowKind::ReadFailure { description } => rsx! {
+            DataElementValueRow {
+                indent: row.indent,
+                tag: "Read error",
+                offset,
+                value: description,
+                is_read_failure: true,
+            }
+        },
+    }
+}
+
+#[component]
+pub fn DataElementValueRow(
+    indent: usize,
+    expanded: Option<bool>,
+    #[props(into, default)] tag: String,
+    #[props(into, default)] name: String,
+    #[props(into, default)] vr: String,
+    #[props(into, default)] length: String,
+
+    // When set, the offset column is shown with this text
+    offset: Option<String>,
+
+    #[props(into, default)] value: String,
+    #[props(default)] is_search_match: bool,
+    #[props(default)] is_current_search_match: bool,
+
+    // Whether reading the file stopped inside this row's data element or sequence item, and
+    // whether this row marks where reading stopped
+    #[props(default)] is_partial: bool,
+    #[props(default)] is_read_failure: bool,
+
+    onclick: Option<EventHandler<MouseEvent>>,
+
+    // When set, the value cell shows an editor that starts with this text
+    editing_text: Option<String>,
+
+    on_start_edit: Option<EventHandler<()>>,
+    on_commit_edit: Option<EventHandler<String>>,
+    on_cancel_edit: Option<EventHandler<()>>,
+    on_add_item: Option<EventHandler<()>>,
+    on_add_element: Option<EventHandler<()>>,
+    on_view_bytes: Option<EventHandler<()>>,
+    on_delete: Option<EventHandler<()>>,
+) -> Element {
+    let is_sequence = vr == "SQ";
+
+    let icon = if expanded == Some(true) {
+        "minus"
+    } else {
+        "plus"
+    };
+
+    rsx! {
+        div {
+            class: "data-element-value-row",
+            class: if onclick.is_some() { "interactive" },
+            class: if is_search_match { "search-match" },
+            class: if is_current_search_match { "current-search-match" },
+            class: if editing_text.is_some() { "editing" },
+            class: if is_partial { "partial" },
+            class: if is_read_failure { "read-failure" },
+
+            onclick: move |event| {
+                if let Some(onclick) = onclick {
+                    onclick.call(event);
+                }
+            },
+
+            div {
+                display: "flex",
+                align_items: "center",
+
+                div { min_width: format!("{}px", indent * 16) }
+                div {
+                    margin_right: "0.5em",
+                    line_height: "1em",
+                    visibility: if expanded.is_some() { "visible" } else { "hidden" },
+
+                    FontAwesomeIcon { icon, style: "solid", size: "xs" }
+                }
+
+                {tag.to_string()}
+
+                if is_partial {
+                    span {
+                        class: "partial-icon",
+                        title: "Incomplete because reading the file stopped inside this",
+
+                        FontAwesomeIcon { icon: "triangle-exclamation", style: "solid", size: "xs" }
+                    }
+                }
+            }
+            div { {name} }
+            div { {vr} }
+            div { {length} }
+            if let Some(offset) = offset {
+                div { class: "offset-cell", {offset} }
+            }
+            div {
+                class: "value-cell",
+                class: if is_sequence { "sequence" },
+                class: if on_start_edit.is_some() { "editable" },
+
+                ondoubleclick: move |_| {
+                    if let Some(on_start_edit) = on_start_edit {
+                        on_start_edit.call(());
+                    }
+                },
+
+                if let Some(text) = editing_text {
+                    ValueEditor {
+                        initial_text: text,
+                        on_commit: move |text| {
+                            if let Some(on_commit_edit) = on_commit_edit {
+                                on_commit_edit.call(text);
+                            }
+                        },
+                        on_cancel: move |_| {
+                            if let Some(on_cancel_edit) = on_cancel_edit {
+                                on_cancel_edit.call(());
+                            }
+                        },
+                    }
+                } else {
+                    {value}
+                }
+            }
+            div {
+                class: "actions-cell",
+
+                if let Some(on_start_edit) = on_start_edit {
+                    RowActionButton {
+                        title: "Edit value",
+                        icon: "pen",
+                        onclick: move |_| on_start_edit.call(()),
+                    }
+                }
+                if let Some(on_add_item) = on_add_item {
+                    RowActionButton {
+                        title: "Add sequence item",
+                        icon: "plus",
+                        onclick: move |_| on_add_item.call(()),
+                    }
+                }
+                if let Some(on_add_element) = on_add_element {
+                    RowActionButton {
+                        title: "Add data element to this item",
+                        icon: "plus",
+                        onclick: move |_| on_add_element.call(()),
+                    }
+                }
+                if let Some(on_view_bytes) = on_view_bytes {
+                    RowActionButton {
+                        title: "View bytes",
+                        icon: "eye",
+                        onclick: move |_| on_view_bytes.call(()),
+                    }
+                }
+                if let Some(on_delete) = on_delete {
+                    RowActionButton {
+                        title: "Delete",
+                        icon: "trash-can",
+                        onclick: move |_| on_delete.call(()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Scrolls the grid so that the row at the given index is in view, if it isn't already.
+///
+fn scroll_row_into_view(element: &HtmlElement, index: usize) {
+    let row_top = HEADER_HEIGHT + index as f64 * ROW_HEIGHT;
+    let view_top = f64::from(element.scroll_top());
+    let view_height = f64::from(element.client_height());
+
+    if row_top < view_top + HEADER_HEIGHT || row_top + ROW_HEIGHT > view_top + view_height {
+        element.set_scroll_top((row_top - view_height * 0.5).max(0.0) as i32);
+    }
+}
+
+#[component]
+fn RowActionButton(title: String, icon: String, onclick: EventHandler<()>) -> Element {
+    rsx! {
+        button {
+            title,
+            onclick: move |event| {
+                // Don't also toggle the row's expansion
+                event.stop_propagation();
+                onclick.call(());
+            },
+
+            FontAwesomeIcon { icon, style: "solid", size: "xs" }
+        }
+    }
+}
+
+/// A text input for editing a data element's value. Enter commits the edit and Escape cancels it.
+/// Moving focus elsewhere commits the edit if the text was changed.
+///
+#[component]
+fn ValueEditor(
+    initial_text: String,
+    on_commit: EventHandler<String>,
+    on_cancel: EventHandler<()>,
+) -> Element {
+    let mut text = use_signal(|| initial_text.clone());
+    let mut is_cancelled = use_signal(|| false);
+
+    rsx! {
+        input {
+            class: "value-editor",
+            r#type: "text",
+            value: "{text}",
+
+            onmounted: move |event| async move {
+                let _ = event.set_focus(true).await;
+            },
+            oninput: move |event| text.set(event.value()),
+            onkeydown: move |event| {
+                // Stop the grid's keyboard shortcuts from acting on keys typed into the editor
+                event.stop_propagation();
+
+                match event.key() {
+                    Key::Enter => on_commit.call(text()),
+                    Key::Escape => {
+                        is_cancelled.set(true);
+                        on_cancel.call(());
+                    }
+                    _ => {}
+                }
+            },
+            onblur: move |_| {
+                if is_cancelled() {
+                    return;
+                }
+
+                if text() == initial_text {
+                    on_cancel.call(());
+                } else {
+                    on_commit.call(text());
+                }
+            },
+            onclick: move |event| event.stop_propagation(),
+            ondoubleclick: move |event| event.stop_propagation(),
+        }
+    }
+}
+
+/// A form for adding a new data element to the data set at the given path.
+///
+#[component]
+fn AddElementForm(
+    main_data_set: Signal<DataSet>,
+    data_set_path: DataSetPath,
+    on_add: EventHandler<(DataSetPath, DataElementValue)>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut tag_text = use_signal(String::new);
+    let mut vr = use_signal(|| "LO".to_string());
+    let mut value_input = use_signal(String::new);
+
+    let tag = value_text::parse_tag(&tag_text());
+    let tag_name = tag
+        .as_ref()
+        .map(|tag| dictionary::tag_name(*tag, None))
+        .unwrap_or_default();
+
+    let location = if data_set_path.is_empty() {
+        "the root data set".to_string()
+    } else {
+        data_set_path.to_string()
+    };
+
+    let path = data_set_path.clone();
+    let add = move |_| {
+        let tag = match value_text::parse_tag(&tag_text()) {
+            Ok(tag) => tag,
+            Err(e) => return ui::toasts::add_error(e),
+        };
+
+        let already_exists = main_data_set
+            .peek()
+            .get_data_set_at_path(&path)
+            .is_ok_and(|data_set| data_set.get_value(tag).is_ok());
+        if already_exists {
+            return ui::toasts::add_error(format!("Data element {} already exists", tag));
+        }
+
+        let value = if vr() == "SQ" {
+            Ok(DataElementValue::new_sequence(vec![]))
+        } else {
+            value_text::text_to_value(&vr(), &value_input())
+        };
+
+        let mut element_path = path.clone();
+        if element_path.add_data_element(tag).is_err() {
+            return ui::toasts::add_error("Invalid data element path".to_string());
+        }
+
+        match value {
+            Ok(value) => on_add.call((element_path, value)),
+            Err(e) => ui::toasts::add_error(e),
+        }
+    };
+
+    rsx! {
+        div {
+            class: "data-set-grid-add-element",
+
+            span { "Add to {location}:" }
+
+            input {
+                r#type: "text",
+                class: "tag-input",
+                placeholder: "(gggg,eeee)",
+                value: "{tag_text}",
+                oninput: move |event| tag_text.set(event.value()),
+            }
+
+            span { class: "tag-name", {tag_name} }
+
+            select {
+                onchange: move |event| vr.set(event.value()),
+
+                for option_vr in FILTERABLE_VALUE_REPRESENTATIONS
+                    .into_iter()
+                    .filter(|option_vr| value_text::is_editable(option_vr) || *option_vr == "SQ")
+                {
+                    option {
+                        value: option_vr,
+                        selected: vr() == option_vr,
+
+                        {option_vr}
+                    }
+                }
+            }
+
+            input {
+                r#type: "text",
+                class: "value-input",
+                placeholder: if vr() == "SQ" { "Sequences start empty" } else { "Value" },
+                disabled: vr() == "SQ",
+                value: "{value_input}",
+                oninput: move |event| value_input.set(event.value()),
+            }
+
+            button {
+                disabled: tag.is_err(),
+                onclick: add,
+
+                "Add"
+            }
+            button {
+                onclick: move |_| on_close.call(()),
+
+                "Cancel"
+            }
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use dcmfx::core::*;
+
+use super::{expansion::GridExpansion, filter::GridFilterResult};
+use crate::raw_token_view::ReadFailure;
+
+/// The key of the row that marks where reading the file stopped.
+///
+pub const READ_FAILURE_ROW_KEY: &str = "read-failure";
+
+/// A single row in the data set grid. Rows don't hold any data element values, these are looked
+/// up by path when the row is rendered so that only rows that are on screen do that work.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridRow {
+    pub kind: GridRowKind,
+
+    /// The path to the data element or sequence item this row displays.
+    pub path: DataSetPath,
+
+    /// The path to the data set containing this row's data element or sequence item.
+    pub parent_path: DataSetPath,
+
+    /// The string form of `path`, used as the row's key.
+    pub key: String,
+
+    pub indent: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GridRowKind {
+    DataElement {
+        tag: DataElementTag,
+    },
+    Sequence {
+        tag: DataElementTag,
+        item_count: usize,
+    },
+    SequenceItem {
+        item_index: usize,
+    },
+    EncapsulatedPixelData {
+        tag: DataElementTag,
+        item_count: usize,
+    },
+    EncapsulatedPixelDataItem {
+        item_index: usize,
+        length: usize,
+    },
+    ReadFailure {
+        description: String,
+    },
+}
+
+impl GridRow {
+    /// Returns the row that marks where reading the file stopped, which is indented to the depth
+    /// of the sequence item it's in.
+    ///
+    pub fn read_failure(failure: &ReadFailure) -> Self {
+        Self {
+            kind: GridRowKind::ReadFailure {
+                description: failure.description.clone(),
+            },
+            path: DataSetPath::new(),
+            parent_path: DataSetPath::new(),
+            key: READ_FAILURE_ROW_KEY.to_string(),
+            indent: failure.open_paths.len(),
+        }
+    }
+
+    /// Returns whether this row can be expanded, i.e. it's a non-empty sequence, a sequence item,
+    /// or encapsulated pixel data with at least one item.
+    ///
+    pub fn is_expandable(&self) -> bool {
+        match self.kind {
+            GridRowKind::Sequence { item_count, .. } => item_count > 0,
+            GridRowKind::SequenceItem { .. } => true,
+            GridRowKind::EncapsulatedPixelData { item_count, .. } => item_count > 0,
+            _ => false,
+        }
+    }
+}
+
+/// Flattens the data set into the list of rows that are visible in the grid, given the grid's
+/// expansion state and the current filter result.
+///
+pub fn flatten(
+    data_set: &DataSet,
+    expansion: &GridExpansion,
+    filter_result: &GridFilterResult,
+) -> Vec<GridRow> {
+    let mut rows = vec![];
+
+    flatten_data_set(
+        data_set,
+        &DataSetPath::new(),
+        expansion,
+        filter_result,
+        &mut rows,
+    );
+
+    rows
+}
+
+fn flatten_data_set(
+    data_set: &DataSet,
+    path_to_data_set: &DataSetPath,
+    expansion: &GridExpansion,
+    filter_result: &GridFilterResult,
+    rows: &mut Vec<GridRow>,
+) {
+    let indent = path_to_data_set.len();
+
+    for (tag, value) in data_set.iter() {
+        let mut path = path_to_data_set.clone();
+        path.add_data_element(*tag).unwrap();
+        let key = path.to_string();
+
+        if !filter_result.is_visible(&key) {
+            continue;
+        }
+
+        let is_expanded = expansion.is_expanded(&key);
+
+        if let Ok(items) = value.sequence_items() {
+            rows.push(GridRow {
+                kind: GridRowKind::Sequence {
+                    tag: *tag,
+                    item_count: items.len(),
+                },
+                path: path.clone(),
+                parent_path: path_to_data_set.clone(),
+                key,
+                indent,
+            });
+
+            if !is_expanded {
+                continue;
+            }
+
+            for (item_index, item) in items.iter().enumerate() {
+                let mut item_path = path.clone();
+                item_path.add_sequence_item(item_index).unwrap();
+                let item_key = item_path.to_string();
+
+                if !filter_result.is_visible(&item_key) {
+                    continue;
+                }
+
+                let is_item_expanded = expansion.is_expanded(&item_key);
+
+                rows.push(GridRow {
+                    kind: GridRowKind::SequenceItem { item_index },
+                    path: item_path.clone(),
+                    parent_path: path_to_data_set.clone(),
+                    key: item_key,
+                    indent: indent + 1,
+                });
+
+                if is_item_expanded {
+                    flatten_data_set(item, &item_path, expansion, filter_result, rows);
+                }
+            }
+        } else if let Ok(items) = value.encapsulated_pixel_data() {
+            rows.push(GridRow {
+                kind: GridRowKind::EncapsulatedPixelData {
+                    tag: *tag,
+                    item_count: items.len(),
+                },
+                path: path.clone(),
+                parent_path: path_to_data_set.clone(),
+                key: key.clone(),
+                indent,
+            });
+
+            if !is_expanded {
+                continue;
+            }
+
+            for (item_index, item) in items.iter().enumerate() {
+                rows.push(GridRow {
+                    kind: GridRowKind::EncapsulatedPixelDataItem {
+                        item_index,
+                        length: item.len(),
+                    },
+                    path: path.clone(),
+                    parent_path: path_to_data_set.clone(),
+                    key: format!("{}/{}", key, item_index),
+                    indent: indent + 1,
+                });
+            }
+        } else if value.bytes().is_ok() {
+            rows.push(GridRow {
+                kind: GridRowKind::DataElement { tag: *tag },
+                path,
+                parent_path: path_to_data_set.clone(),
+                key,
+                indent,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_set_grid::filter::{self, GridFilter};
+    use crate::test_utils::test_data_set;
+
+    /// The index of the sequence's row among the root rows of [`test_data_set()`].
+    const SEQUENCE_ROW_INDEX: usize = 4;
+
+    fn summary(rows: &[GridRow]) -> Vec<(GridRowKind, usize)> {
+        rows.iter()
+            .map(|row| (row.kind.clone(), row.indent))
+            .collect()
+    }
+
+    fn sequence_row(item_count: usize) -> (GridRowKind, usize) {
+        (
+            GridRowKind::Sequence {
+                tag: dictionary::ANATOMIC_REGION_SEQUENCE.tag,
+                item_count,
+            },
+            0,
+        )
+    }
+
+    fn element_row(tag: DataElementTag, indent: usize) -> (GridRowKind, usize) {
+        (GridRowKind::DataElement { tag }, indent)
+    }
+
+    fn pixel_data_row() -> (GridRowKind, usize) {
+        (
+            GridRowKind::EncapsulatedPixelData {
+                tag: dictionary::PIXEL_DATA.tag,
+                item_count: 2,
+            },
+            0,
+        )
+    }
+
+    #[test]
+    fn collapsed_rows_are_the_root_data_elements() {
+        let data_set = test_data_set();
+
+        let rows = flatten(
+            &data_set,
+            &GridExpansion::default(),
+            &GridFilterResult::default(),
+        );
+
+        assert_eq!(rows.len(), data_set.iter().count());
+        assert!(rows.iter().all(|row| row.indent == 0));
+
+        let summary = summary(&rows);
+        assert_eq!(summary[SEQUENCE_ROW_INDEX], sequence_row(2));
+        assert_eq!(
+            summary[SEQUENCE_ROW_INDEX + 1],
+            element_row(DataElementTag::new(0x0009, 0x0010), 0)
+        );
+        assert_eq!(summary[rows.len() - 1], pixel_data_row());
+
+        assert!(rows[SEQUENCE_ROW_INDEX].is_expandable());
+        assert!(!rows[SEQUENCE_ROW_INDEX + 1].is_expandable());
+        assert!(rows[rows.len() - 1].is_expandable());
+    }
+
+    #[test]
+    fn expanded_sequences_show_their_items() {
+        let data_set = test_data_set();
+
+        let collapsed_rows = flatten(
+            &data_set,
+            &GridExpansion::default(),
+            &GridFilterResult::default(),
+        );
+
+        // Expanding the sequence shows its items, but not their contents
+        let mut expansion = GridExpansion::default();
+        expansion.toggle(collapsed_rows[SEQUENCE_ROW_INDEX].key.clone());
+
+        let rows = flatten(&data_set, &expansion, &GridFilterResult::default());
+
+        assert_eq!(rows.len(), collapsed_rows.len() + 2);
+        assert_eq!(
+            summary(&rows)[SEQUENCE_ROW_INDEX..SEQUENCE_ROW_INDEX + 4],
+            [
+                sequence_row(2),
+                (GridRowKind::SequenceItem { item_index: 0 }, 1),
+                (GridRowKind::SequenceItem { item_index: 1 }, 1),
+                element_row(DataElementTag::new(0x0009, 0x0010), 0),
+            ]
+        );
+
+        // Expanding the second item shows its data elements
+        expansion.toggle(rows[SEQUENCE_ROW_INDEX + 2].key.clone());
+
+        let rows = flatten(&data_set, &expansion, &GridFilterResult::default());
+
+        assert_eq!(rows.len(), collapsed_rows.len() + 4);
+        assert_eq!(
+            summary(&rows)[SEQUENCE_ROW_INDEX..SEQUENCE_ROW_INDEX + 6],
+            [
+                sequence_row(2),
+                (GridRowKind::SequenceItem { item_index: 0 }, 1),
+                (GridRowKind::SequenceItem { item_index: 1 }, 1),
+                element_row(dictionary::CODE_VALUE.tag, 2),
+                element_row(dictionary::CODE_MEANING.tag, 2),
+                element_row(DataElementTag::new(0x0009, 0x0010), 0),
+            ]
+        );
+
+        let row = &rows[SEQUENCE_ROW_INDEX + 3];
+
+        let mut expected_path = DataSetPath::new();
+        expected_path
+            .add_data_element(dictionary::ANATOMIC_REGION_SEQUENCE.tag)
+            .unwrap();
+        expected_path.add_sequence_item(1).unwrap();
+        assert_eq!(row.parent_path, expected_path);
+
+        expected_path
+            .add_data_element(dictionary::CODE_VALUE.tag)
+            .unwrap();
+        assert_eq!(row.path, expected_path);
+        assert_eq!(row.key, expected_path.to_string());
+    }
+
+    #[test]
+    fn expanded_encapsulated_pixel_data_shows_its_items() {
+        let data_set = test_data_set();
+
+        let collapsed_rows = flatten(
+            &data_set,
+            &GridExpansion::default(),
+            &GridFilterResult::default(),
+        );
+        let pixel_data_index = collapsed_rows.len() - 1;
+
+        let mut expansion = GridExpansion::default();
+        expansion.toggle(collapsed_rows[pixel_data_index].key.clone());
+
+        let rows = flatten(&data_set, &expansion, &GridFilterResult::default());
+
+        assert_eq!(rows.len(), collapsed_rows.len() + 2);
+        assert_eq!(
+            rows[pixel_data_index + 1].kind,
+            GridRowKind::EncapsulatedPixelDataItem {
+                item_index: 0,
+                length: 0
+            }
+        );
+        assert_eq!(
+            rows[pixel_data_index + 2].kind,
+            GridRowKind::EncapsulatedPixelDataItem {
+                item_index: 1,
+                length: 4
+            }
+        );
+        assert_eq!(rows[pixel_data_index + 2].indent, 1);
+
+        // Item rows point at the pixel data element but need unique keys
+        assert_eq!(
+            rows[pixel_data_index + 2].path,
+            collapsed_rows[pixel_data_index].path
+        );
+        assert_ne!(
+            rows[pixel_data_index + 1].key,
+            rows[pixel_data_index + 2].key
+        );
+    }
+
+    #[test]
+    fn filtered_out_rows_are_hidden() {
+        let data_set = test_data_set();
+
+        let mut expansion = GridExpansion::default();
+        expansion.expand_all(&data_set);
+
+        let filter_result = filter::apply(
+            &data_set,
+            &GridFilter {
+                value_representation: Some("SH".to_string()),
+                ..GridFilter::default()
+            },
+        );
+
+        let rows = flatten(&data_set, &expansion, &filter_result);
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                sequence_row(2),
+                (GridRowKind::SequenceItem { item_index: 0 }, 1),
+                element_row(dictionary::CODE_VALUE.tag, 2),
+                (GridRowKind::SequenceItem { item_index: 1 }, 1),
+                element_row(dictionary::CODE_VALUE.tag, 2),
+            ]
+        );
+    }
+}
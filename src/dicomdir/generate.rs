@@ -0,0 +1,874 @@
+//! Generation of a DICOMDIR that references a set of files, laid out in the directory structure
+//! required for DICOM media by PS3.10 and PS3.12.
+
+use std::{collections::HashMap, rc::Rc};
+
+use dcmfx::{core::*, p10::*};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{MEDIA_STORAGE_DIRECTORY_STORAGE_UID, record_offsets};
+use crate::{
+    editing::value_text,
+    file_list::{self, OpenedFile, ReadStatus},
+    jobs::{self, JobError, JobState, WorkerDataSet},
+    study_browser,
+    utils::{self, zip::ZipWriter},
+};
+
+/// The transfer syntax that DICOMDIR files are required to use.
+///
+const EXPLICIT_VR_LITTLE_ENDIAN_UID: &str = "1.2.840.10008.1.2.1";
+
+/// Writes the given files into a ZIP of a media set, along with a DICOMDIR that references them.
+/// The ZIP is written by the job worker from the files' data sets held there, so that any edits
+/// are included. Files that haven't been read are read first. The active file's data set is
+/// passed in because it's held in the app's signals rather than in the file. Returns the ZIP as
+/// blob parts and the number of files in it, excluding the DICOMDIR.
+///
+pub async fn generate_media_set(
+    files: Signal<Vec<OpenedFile>>,
+    active_file: Option<(u64, Rc<WorkerDataSet>)>,
+    state: Signal<JobState>,
+) -> Result<(js_sys::Array, usize), JobError> {
+    let layout = media_layout(&files.read()).map_err(JobError::Failed)?;
+
+    let file_ids = layout.instances().map(|instance| instance.file_id);
+
+    let mut data_sets = vec![];
+    for file_id in file_ids {
+        data_sets.push((
+            file_id,
+            instance_data_set(files, file_id, &active_file)
+                .await
+                .map_err(JobError::Failed)?,
+        ));
+    }
+
+    if data_sets.is_empty() {
+        return Err(JobError::Failed(
+            "None of the opened files are DICOM instances".into(),
+        ));
+    }
+
+    let blob_parts = jobs::generate_media_set(
+        &layout,
+        &data_sets
+            .iter()
+            .map(|(file_id, data_set)| (*file_id, data_set.as_ref()))
+            .collect::<Vec<_>>(),
+        state,
+    )
+    .await?;
+
+    Ok((blob_parts, data_sets.len()))
+}
+
+/// Returns the worker's data set for an opened file, reading the file in full if it hasn't been
+/// viewed.
+///
+async fn instance_data_set(
+    files: Signal<Vec<OpenedFile>>,
+    file_id: u64,
+    active_file: &Option<(u64, Rc<WorkerDataSet>)>,
+) -> Result<Rc<WorkerDataSet>, String> {
+    if let Some((active_file_id, data_set)) = active_file
+        && *active_file_id == file_id
+    {
+        return Ok(data_set.clone());
+    }
+
+    let file_data = {
+        let files = files.read();
+        let Some(file) = files.iter().find(|file| file.id == file_id) else {
+            return Err("A file was closed while generating the media set".into());
+        };
+
+        if file.read_status == ReadStatus::Read {
+            if !file.state.error_lines.is_empty() {
+                return Err(format!("Reading '{}' failed", file.filename));
+            }
+
+            if let Some(worker_data_set) = &file.state.worker_data_set {
+                return Ok(worker_data_set.clone());
+            }
+        }
+
+        file.file_data.clone()
+    };
+
+    let (filename, _, state) = file_list::read_file(&file_data, |_| (), || false).await;
+    if !state.error_lines.is_empty() {
+        return Err(format!("Reading '{}' failed", filename));
+    }
+
+    state
+        .worker_data_set
+        .ok_or_else(|| format!("Reading '{}' failed", filename))
+}
+
+/// Writes a media set with the given layout into a ZIP, along with a DICOMDIR that references its
+/// files. Each file is written from its data set, which is looked up by its file ID. This is run
+/// by the job worker.
+///
+pub async fn write_media_set<'a, W: IoWrite>(
+    layout: &MediaLayout,
+    data_sets: &HashMap<u64, &'a DataSet>,
+    writer: W,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<W, String> {
+    let data_set = |file_id: u64| {
+        data_sets
+            .get(&file_id)
+            .copied()
+            .ok_or_else(|| "A file's data set is missing from the media set".to_string())
+    };
+
+    let mut zip_writer = ZipWriter::new(writer);
+    let mut instance_records = vec![];
+
+    for instance in layout.instances() {
+        let data_set = data_set(instance.file_id)?;
+
+        zip_writer.start_file(&instance.zip_path())?;
+        data_set
+            .write_p10_stream(&mut zip_writer, None)
+            .map_err(|e| e.to_lines("writing P10 file").join(", "))?;
+
+        instance_records.push(instance_record(data_set, &instance.path)?);
+
+        checkpoint().await?;
+    }
+
+    let dicomdir = build_dicomdir(
+        layout,
+        data_set,
+        instance_records.into_iter(),
+        &utils::random_uid(),
+    )?;
+    zip_writer.add_file("DICOMDIR", &dicomdir)?;
+
+    zip_writer.finish()
+}
+
+/// The layout of a media set, i.e. the path that each file is written to, organized by patient,
+/// study, and series.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MediaLayout {
+    patients: Vec<MediaNode<MediaNode<MediaNode<MediaInstance>>>>,
+}
+
+impl MediaLayout {
+    /// Returns the instances in the layout, series by series.
+    ///
+    fn instances(&self) -> impl Iterator<Item = &MediaInstance> {
+        self.patients
+            .iter()
+            .flat_map(|patient| patient.children.iter())
+            .flat_map(|study| study.children.iter())
+            .flat_map(|series| series.children.iter())
+    }
+}
+
+/// A patient, study, or series in a media layout, along with the file ID of its first instance
+/// from whose data set the attributes of its directory record are taken.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct MediaNode<T> {
+    first_file_id: u64,
+    children: Vec<T>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct MediaInstance {
+    file_id: u64,
+
+    /// The components of the instance's File ID, which are at most eight characters long and
+    /// contain only uppercase letters, digits, and underscores, as PS3.10 requires.
+    path: Vec<String>,
+}
+
+impl MediaInstance {
+    /// Returns the path of the instance's file in the media set, with `/` as the separator.
+    ///
+    fn zip_path(&self) -> String {
+        self.path.join("/")
+    }
+}
+
+/// Lays out the given files into a `DICOM/PATnnnnn/STUnnnnn/SERnnnnn/IMGnnnnn` directory
+/// structure. Files whose header couldn't be read are left out.
+///
+fn media_layout(files: &[OpenedFile]) -> Result<MediaLayout, String> {
+    let mut patients = vec![];
+
+    for (patient_index, patient) in study_browser::build_study_tree(files).iter().enumerate() {
+        let mut studies = vec![];
+
+        for (study_index, study) in patient.studies.iter().enumerate() {
+            let mut series_nodes = vec![];
+
+            for (series_index, series) in study.series.iter().enumerate() {
+                let instances = series
+                    .instances
+                    .iter()
+                    .enumerate()
+                    .map(|(instance_index, instance)| {
+                        Ok(MediaInstance {
+                            file_id: instance.file_id,
+                            path: vec![
+                                "DICOM".to_string(),
+                                path_component("PAT", patient_index)?,
+                                path_component("STU", study_index)?,
+                                path_component("SER", series_index)?,
+                                path_component("IMG", instance_index)?,
+                            ],
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                series_nodes.push(MediaNode {
+                    first_file_id: instances[0].file_id,
+                    children: instances,
+                });
+            }
+
+            studies.push(MediaNode {
+                first_file_id: series_nodes[0].first_file_id,
+                children: series_nodes,
+            });
+        }
+
+        patients.push(MediaNode {
+            first_file_id: studies[0].first_file_id,
+            children: studies,
+        });
+    }
+
+    Ok(MediaLayout { patients })
+}
+
+/// Returns a File ID component made up of the given prefix followed by a five digit number.
+///
+fn path_component(prefix: &str, index: usize) -> Result<String, String> {
+    if index >= 99_999 {
+        return Err("Media sets are limited to 99,999 entries at each level".into());
+    }
+
+    Ok(format!("{}{:05}", prefix, index + 1))
+}
+
+/// Creates the directory record for an instance from the data set that was written to the media
+/// set, which may differ from its header if it has been edited.
+///
+fn instance_record(data_set: &DataSet, path: &[String]) -> Result<DataSet, String> {
+    let modality = data_set
+        .get_string(dictionary::MODALITY.tag)
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let record_type = match modality.as_str() {
+        "PR" => "PRESENTATION",
+        "SR" => "SR DOCUMENT",
+        "KO" => "KEY OBJECT DOC",
+        "DOC" => "ENCAP DOC",
+        "RTDOSE" => "RT DOSE",
+        "RTSTRUCT" => "RT STRUCTURE SET",
+        "RTPLAN" => "RT PLAN",
+        _ => "IMAGE",
+    };
+
+    let mut record = new_record(record_type)?;
+    copy_attributes(
+        &mut record,
+        data_set,
+        &[
+            (dictionary::INSTANCE_NUMBER.tag, "IS"),
+            (dictionary::CONTENT_DATE.tag, "DA"),
+            (dictionary::CONTENT_TIME.tag, "TM"),
+        ],
+    )?;
+
+    let uid = |tag: DataElementTag, fallback_tag: DataElementTag| {
+        data_set
+            .get_string(tag)
+            .or_else(|_| data_set.get_string(fallback_tag))
+            .map(|uid| uid.trim_end_matches('\0').to_string())
+            .unwrap_or_default()
+    };
+
+    let transfer_syntax_uid = data_set
+        .get_string(dictionary::TRANSFER_SYNTAX_UID.tag)
+        .map(|uid| uid.trim_end_matches('\0').to_string())
+        .unwrap_or_else(|_| "1.2.840.10008.1.2".to_string());
+
+    insert(
+        &mut record,
+        dictionary::REFERENCED_FILE_ID.tag,
+        "CS",
+        &path.join("\\"),
+    )?;
+    insert(
+        &mut record,
+        dictionary::REFERENCED_SOP_CLASS_UID_IN_FILE.tag,
+        "UI",
+        &uid(
+            dictionary::SOP_CLASS_UID.tag,
+            dictionary::MEDIA_STORAGE_SOP_CLASS_UID.tag,
+        ),
+    )?;
+    insert(
+        &mut record,
+        dictionary::REFERENCED_SOP_INSTANCE_UID_IN_FILE.tag,
+        "UI",
+        &uid(
+            dictionary::SOP_INSTANCE_UID.tag,
+            dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag,
+        ),
+    )?;
+    insert(
+        &mut record,
+        dictionary::REFERENCED_TRANSFER_SYNTAX_UID_IN_FILE.tag,
+        "UI",
+        &transfer_syntax_uid,
+    )?;
+
+    Ok(record)
+}
+
+/// Builds a DICOMDIR for the given media layout and returns its DICOM P10 bytes. The attributes of
+/// patient, study, and series records are taken from the data set of their first instance, which
+/// is looked up by file ID. The directory records for instances are passed in series by series in
+/// the order of the layout, and the DICOMDIR is given the passed SOP Instance UID.
+///
+/// The records are linked by their byte offsets in the file, so the DICOMDIR is first written with
+/// placeholder offsets to find where each record ends up, and then written again with the offsets
+/// filled in. The offsets are fixed-length values so this doesn't move any of the records.
+///
+fn build_dicomdir<'a>(
+    layout: &MediaLayout,
+    data_set: impl Fn(u64) -> Result<&'a DataSet, String>,
+    mut instance_records: impl Iterator<Item = DataSet>,
+    sop_instance_uid: &str,
+) -> Result<Vec<u8>, String> {
+    let mut records = vec![];
+    let mut links = vec![];
+
+    // Records are stored depth-first, with each record's lower-level entity following it
+    let root_entity = add_entity(&mut records, &mut links, &layout.patients, |patient| {
+        let mut record = new_record("PATIENT")?;
+        copy_attributes(
+            &mut record,
+            data_set(patient.first_file_id)?,
+            &[
+                (dictionary::PATIENT_NAME.tag, "PN"),
+                (dictionary::PATIENT_ID.tag, "LO"),
+            ],
+        )?;
+        Ok(record)
+    })?;
+
+    for (patient, patient_index) in layout.patients.iter().zip(root_entity.iter().copied()) {
+        let study_entity = add_entity(&mut records, &mut links, &patient.children, |study| {
+            let mut record = new_record("STUDY")?;
+            copy_attributes(
+                &mut record,
+                data_set(study.first_file_id)?,
+                &[
+                    (dictionary::STUDY_DATE.tag, "DA"),
+                    (dictionary::STUDY_TIME.tag, "TM"),
+                    (dictionary::ACCESSION_NUMBER.tag, "SH"),
+                    (dictionary::STUDY_DESCRIPTION.tag, "LO"),
+                    (dictionary::STUDY_INSTANCE_UID.tag, "UI"),
+                    (dictionary::STUDY_ID.tag, "SH"),
+                ],
+            )?;
+            Ok(record)
+        })?;
+        links[patient_index].lower = study_entity.first().copied();
+
+        for (study, study_index) in patient.children.iter().zip(study_entity.iter().copied()) {
+            let series_entity = add_entity(&mut records, &mut links, &study.children, |series| {
+                let mut record = new_record("SERIES")?;
+                copy_attributes(
+                    &mut record,
+                    data_set(series.first_file_id)?,
+                    &[
+                        (dictionary::MODALITY.tag, "CS"),
+                        (dictionary::SERIES_INSTANCE_UID.tag, "UI"),
+                        (dictionary::SERIES_NUMBER.tag, "IS"),
+                        (dictionary::SERIES_DESCRIPTION.tag, "LO"),
+                    ],
+                )?;
+                Ok(record)
+            })?;
+            links[study_index].lower = series_entity.first().copied();
+
+            for (series, series_index) in study.children.iter().zip(series_entity.iter().copied()) {
+                let instance_entity =
+                    add_entity(&mut records, &mut links, &series.children, |_| {
+                        instance_records
+                            .next()
+                            .ok_or_else(|| "Missing directory record for instance".to_string())
+                    })?;
+                links[series_index].lower = instance_entity.first().copied();
+            }
+        }
+    }
+
+    let mut data_set = DataSet::new();
+    insert(
+        &mut data_set,
+        dictionary::MEDIA_STORAGE_SOP_CLASS_UID.tag,
+        "UI",
+        MEDIA_STORAGE_DIRECTORY_STORAGE_UID,
+    )?;
+    insert(
+        &mut data_set,
+        dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag,
+        "UI",
+        sop_instance_uid,
+    )?;
+    insert(
+        &mut data_set,
+        dictionary::TRANSFER_SYNTAX_UID.tag,
+        "UI",
+        EXPLICIT_VR_LITTLE_ENDIAN_UID,
+    )?;
+    insert(
+        &mut data_set,
+        dictionary::SPECIFIC_CHARACTER_SET.tag,
+        "CS",
+        "ISO_IR 192",
+    )?;
+    insert(&mut data_set, dictionary::FILE_SET_ID.tag, "CS", "")?;
+    insert(
+        &mut data_set,
+        dictionary::FILE_SET_CONSISTENCY_FLAG.tag,
+        "US",
+        "0",
+    )?;
+
+    // Write with placeholder offsets to find the offset of each record
+    set_offsets(&mut data_set, records.clone(), &links, &root_entity, &[])?;
+    let record_offsets = record_offsets::directory_record_offsets(&write_p10(&data_set)?)?;
+
+    if record_offsets.len() != records.len() {
+        return Err("Failed locating directory records in the generated DICOMDIR".into());
+    }
+
+    set_offsets(
+        &mut data_set,
+        records,
+        &links,
+        &root_entity,
+        &record_offsets,
+    )?;
+    write_p10(&data_set)
+}
+
+/// The links from a directory record to others, given as indexes into the list of records.
+///
+#[derive(Clone, Copy, Default)]
+struct RecordLinks {
+    next: Option<usize>,
+    lower: Option<usize>,
+}
+
+/// Adds the records for a directory entity, linking each to the next, and returns their indexes.
+///
+fn add_entity<T>(
+    records: &mut Vec<DataSet>,
+    links: &mut Vec<RecordLinks>,
+    nodes: &[T],
+    mut create_record: impl FnMut(&T) -> Result<DataSet, String>,
+) -> Result<Vec<usize>, String> {
+    let mut indexes = vec![];
+
+    for node in nodes {
+        if let Some(previous) = indexes.last() {
+            links[*previous].next = Some(records.len());
+        }
+
+        indexes.push(records.len());
+        records.push(create_record(node)?);
+        links.push(RecordLinks::default());
+    }
+
+    Ok(indexes)
+}
+
+/// Sets the offsets that link the directory records together and stores the records in the
+/// DICOMDIR's Directory Record Sequence. All offsets are zero if no record offsets are given.
+///
+fn set_offsets(
+    data_set: &mut DataSet,
+    mut records: Vec<DataSet>,
+    links: &[RecordLinks],
+    root_entity: &[usize],
+    record_offsets: &[u32],
+) -> Result<(), String> {
+    let offset = |index: Option<usize>| {
+        index
+            .and_then(|index| record_offsets.get(index))
+            .copied()
+            .unwrap_or(0)
+            .to_string()
+    };
+
+    insert(
+        data_set,
+        dictionary::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY.tag,
+        "UL",
+        &offset(root_entity.first().copied()),
+    )?;
+    insert(
+        data_set,
+        dictionary::OFFSET_OF_THE_LAST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY.tag,
+        "UL",
+        &offset(root_entity.last().copied()),
+    )?;
+
+    for (record, links) in records.iter_mut().zip(links) {
+        insert(
+            record,
+            dictionary::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD.tag,
+            "UL",
+            &offset(links.next),
+        )?;
+        insert(
+            record,
+            dictionary::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY.tag,
+            "UL",
+            &offset(links.lower),
+        )?;
+    }
+
+    data_set.insert(
+        dictionary::DIRECTORY_RECORD_SEQUENCE.tag,
+        DataElementValue::new_sequence(records),
+    );
+
+    Ok(())
+}
+
+/// Creates a directory record of the given type that has its offsets still to be set.
+///
+fn new_record(record_type: &str) -> Result<DataSet, String> {
+    let mut record = DataSet::new();
+    insert(
+        &mut record,
+        dictionary::RECORD_IN_USE_FLAG.tag,
+        "US",
+        "65535",
+    )?;
+    insert(
+        &mut record,
+        dictionary::DIRECTORY_RECORD_TYPE.tag,
+        "CS",
+        record_type,
+    )?;
+
+    Ok(record)
+}
+
+/// Copies the given attributes into a directory record. Attributes missing from the source data
+/// set are added with an empty value.
+///
+fn copy_attributes(
+    record: &mut DataSet,
+    source: &DataSet,
+    attributes: &[(DataElementTag, &str)],
+) -> Result<(), String> {
+    for (tag, vr) in attributes {
+        match source.get_value(*tag) {
+            Ok(value) => record.insert(*tag, value.clone()),
+            Err(_) => insert(record, *tag, vr, "")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn insert(data_set: &mut DataSet, tag: DataElementTag, vr: &str, text: &str) -> Result<(), String> {
+    data_set.insert(tag, value_text::text_to_value_unvalidated(vr, text)?);
+    Ok(())
+}
+
+/// Writes a data set as DICOM P10 into memory. This is only used for the DICOMDIR, whose bytes are
+/// needed to find the offsets of its directory records.
+///
+fn write_p10(data_set: &DataSet) -> Result<Vec<u8>, String> {
+    let mut writer = utils::BytesWriter::default();
+
+    data_set
+        .write_p10_stream(&mut writer, None)
+        .map_err(|e| e.to_lines("writing P10 file").join(", "))?;
+
+    Ok(writer.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_type::DataSetSourceType;
+
+    fn data_set(attributes: &[(DataElementTag, &str, &str)]) -> DataSet {
+        let mut data_set = DataSet::new();
+        for (tag, vr, text) in attributes {
+            insert(&mut data_set, *tag, vr, text).unwrap();
+        }
+
+        data_set
+    }
+
+    fn get_string(data_set: &DataSet, tag: DataElementTag) -> String {
+        value_text::value_to_text(data_set.get_value(tag).unwrap()).unwrap()
+    }
+
+    fn instance(path: &[&str]) -> MediaInstance {
+        MediaInstance {
+            file_id: 0,
+            path: path.iter().map(|component| component.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn file_id_components_are_numbered_from_one() {
+        assert_eq!(path_component("PAT", 0), Ok("PAT00001".into()));
+        assert_eq!(path_component("IMG", 41), Ok("IMG00042".into()));
+        assert_eq!(path_component("SER", 99_998), Ok("SER99999".into()));
+        assert!(path_component("SER", 99_999).is_err());
+    }
+
+    #[test]
+    fn file_id_components_are_valid_for_media() {
+        for index in [0, 9, 99_998] {
+            for prefix in ["PAT", "STU", "SER", "IMG"] {
+                let component = path_component(prefix, index).unwrap();
+
+                assert!(component.len() <= 8);
+                assert!(
+                    component
+                        .chars()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+                );
+            }
+        }
+
+        let instance = instance(&["DICOM", "PAT00001", "STU00001", "SER00002", "IMG00003"]);
+        assert_eq!(
+            instance.zip_path(),
+            "DICOM/PAT00001/STU00001/SER00002/IMG00003"
+        );
+    }
+
+    #[test]
+    fn instance_records_reference_their_file() {
+        let path = ["DICOM", "PAT00001", "STU00001", "SER00001", "IMG00001"].map(String::from);
+
+        let record = instance_record(
+            &data_set(&[
+                (dictionary::MODALITY.tag, "CS", "SR"),
+                (
+                    dictionary::SOP_CLASS_UID.tag,
+                    "UI",
+                    "1.2.840.10008.5.1.4.1.1.88.11",
+                ),
+                (dictionary::SOP_INSTANCE_UID.tag, "UI", "1.2.3"),
+                (
+                    dictionary::TRANSFER_SYNTAX_UID.tag,
+                    "UI",
+                    "1.2.840.10008.1.2.1",
+                ),
+                (dictionary::INSTANCE_NUMBER.tag, "IS", "7"),
+            ]),
+            &path,
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_string(&record, dictionary::DIRECTORY_RECORD_TYPE.tag),
+            "SR DOCUMENT"
+        );
+        assert_eq!(
+            get_string(&record, dictionary::REFERENCED_FILE_ID.tag),
+            "DICOM\\PAT00001\\STU00001\\SER00001\\IMG00001"
+        );
+        assert_eq!(
+            get_string(&record, dictionary::REFERENCED_SOP_CLASS_UID_IN_FILE.tag),
+            "1.2.840.10008.5.1.4.1.1.88.11"
+        );
+        assert_eq!(
+            get_string(&record, dictionary::REFERENCED_SOP_INSTANCE_UID_IN_FILE.tag),
+            "1.2.3"
+        );
+        assert_eq!(
+            get_string(
+                &record,
+                dictionary::REFERENCED_TRANSFER_SYNTAX_UID_IN_FILE.tag
+            ),
+            "1.2.840.10008.1.2.1"
+        );
+        assert_eq!(get_string(&record, dictionary::INSTANCE_NUMBER.tag), "7");
+
+        // Content Date and Time are required in the record even when they're missing
+        assert_eq!(get_string(&record, dictionary::CONTENT_DATE.tag), "");
+    }
+
+    #[test]
+    fn instance_records_fall_back_to_the_file_meta_information() {
+        let path = ["DICOM".to_string()];
+
+        let record = instance_record(
+            &data_set(&[
+                (dictionary::MEDIA_STORAGE_SOP_CLASS_UID.tag, "UI", "1.2.4"),
+                (
+                    dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag,
+                    "UI",
+                    "1.2.5",
+                ),
+            ]),
+            &path,
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_string(&record, dictionary::DIRECTORY_RECORD_TYPE.tag),
+            "IMAGE"
+        );
+        assert_eq!(
+            get_string(&record, dictionary::REFERENCED_SOP_CLASS_UID_IN_FILE.tag),
+            "1.2.4"
+        );
+        assert_eq!(
+            get_string(&record, dictionary::REFERENCED_SOP_INSTANCE_UID_IN_FILE.tag),
+            "1.2.5"
+        );
+        assert_eq!(
+            get_string(
+                &record,
+                dictionary::REFERENCED_TRANSFER_SYNTAX_UID_IN_FILE.tag
+            ),
+            "1.2.840.10008.1.2"
+        );
+    }
+
+    #[test]
+    fn dicomdir_records_are_linked_by_their_offsets() {
+        // The first instance's data set provides the attributes of the patient, study, and series
+        let first_data_set = data_set(&[(dictionary::PATIENT_NAME.tag, "PN", "Doe^Jane")]);
+
+        // One patient with one study that has a series of one instance and a series of two
+        let layout = MediaLayout {
+            patients: vec![MediaNode {
+                first_file_id: 0,
+                children: vec![MediaNode {
+                    first_file_id: 0,
+                    children: vec![
+                        MediaNode {
+                            first_file_id: 0,
+                            children: vec![instance(&["IMG00001"])],
+                        },
+                        MediaNode {
+                            first_file_id: 0,
+                            children: vec![instance(&["IMG00001"]), instance(&["IMG00002"])],
+                        },
+                    ],
+                }],
+            }],
+        };
+
+        let instance_records = (1..=3).map(|number| {
+            let mut record = new_record("IMAGE").unwrap();
+            insert(
+                &mut record,
+                dictionary::INSTANCE_NUMBER.tag,
+                "IS",
+                &number.to_string(),
+            )
+            .unwrap();
+            record
+        });
+
+        let bytes =
+            build_dicomdir(&layout, |_| Ok(&first_data_set), instance_records, "1.2.3").unwrap();
+
+        let offsets = record_offsets::directory_record_offsets(&bytes).unwrap();
+        assert_eq!(offsets.len(), 7);
+
+        let dicomdir = DataSetSourceType::P10.read(&bytes).unwrap();
+        assert!(super::super::is_dicomdir(&dicomdir));
+
+        let offset =
+            |data_set: &DataSet, tag: DataElementTag| data_set.get_int::<u32>(tag).unwrap();
+
+        assert_eq!(
+            offset(
+                &dicomdir,
+                dictionary::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY.tag
+            ),
+            offsets[0]
+        );
+        assert_eq!(
+            offset(
+                &dicomdir,
+                dictionary::OFFSET_OF_THE_LAST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY.tag
+            ),
+            offsets[0]
+        );
+
+        let records = dicomdir
+            .get_value(dictionary::DIRECTORY_RECORD_SEQUENCE.tag)
+            .and_then(|value| value.sequence_items())
+            .unwrap();
+
+        // Records are stored depth-first: patient, study, series 1, its instance, series 2, and
+        // its two instances
+        let record_types = records
+            .iter()
+            .map(|record| get_string(record, dictionary::DIRECTORY_RECORD_TYPE.tag))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            record_types,
+            [
+                "PATIENT", "STUDY", "SERIES", "IMAGE", "SERIES", "IMAGE", "IMAGE"
+            ]
+        );
+        assert_eq!(
+            get_string(&records[0], dictionary::PATIENT_NAME.tag),
+            "Doe^Jane"
+        );
+
+        let expected_links = [
+            (0, offsets[1]),
+            (0, offsets[2]),
+            (offsets[4], offsets[3]),
+            (0, 0),
+            (0, offsets[5]),
+            (offsets[6], 0),
+            (0, 0),
+        ];
+
+        for (record, (next, lower)) in records.iter().zip(expected_links) {
+            assert_eq!(
+                offset(record, dictionary::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD.tag),
+                next
+            );
+            assert_eq!(
+                offset(
+                    record,
+                    dictionary::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY.tag
+                ),
+                lower
+            );
+        }
+
+        assert_eq!(
+            get_string(&records[6], dictionary::INSTANCE_NUMBER.tag),
+            "3"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Navigation and generation of DICOMDIR files, which index the DICOM files on a piece of media
+//! using a hierarchy of directory records linked together by byte offsets.
+
+mod generate;
+mod record_offsets;
+mod tree;
+
+use std::collections::HashSet;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{
+    file_list::{self, OpenedFile},
+    source_type::DataSetSourceType,
+    ui,
+};
+pub use generate::{MediaLayout, generate_media_set, write_media_set};
+use tree::DirectoryRow;
+
+/// The UID of the Media Storage Directory Storage SOP Class, which is used by DICOMDIR files.
+///
+const MEDIA_STORAGE_DIRECTORY_STORAGE_UID: &str = "1.2.840.10008.1.3.10";
+
+/// Returns whether the data set is a DICOMDIR.
+///
+pub fn is_dicomdir(data_set: &DataSet) -> bool {
+    data_set.has(dictionary::DIRECTORY_RECORD_SEQUENCE.tag)
+        || data_set
+            .get_string(dictionary::MEDIA_STORAGE_SOP_CLASS_UID.tag)
+            .is_ok_and(|uid| uid.trim_end_matches('\0') == MEDIA_STORAGE_DIRECTORY_STORAGE_UID)
+}
+
+/// Shows the directory records of a DICOMDIR as a tree of patients, studies, series, and images.
+/// Records that reference a file that was opened alongside the DICOMDIR open that file when
+/// selected.
+///
+#[component]
+pub fn DicomdirView(
+    data_set: Signal<DataSet>,
+    files: Signal<Vec<OpenedFile>>,
+    active_file_id: Option<u64>,
+    on_select_file: EventHandler<u64>,
+) -> Element {
+    let mut collapsed = use_signal(HashSet::<u32>::new);
+
+    // The record offsets come from the bytes the DICOMDIR was read from rather than its data set,
+    // so the file is read again. They're `None` while that's happening.
+    let mut record_offsets = use_signal(|| None::<Result<Vec<u32>, String>>);
+    let mut record_offsets_task = use_signal(|| None::<Task>);
+
+    // Only whether the file has been read is tracked, so that changes to other files don't cause
+    // it to be read again
+    let source_type = use_memo(use_reactive!(|active_file_id| {
+        files
+            .read()
+            .iter()
+            .find(|file| Some(file.id) == active_file_id)
+            .and_then(|file| Some(file.sniffed_file.as_ref()?.source_type))
+    }));
+
+    use_effect(use_reactive!(|active_file_id| {
+        if let Some(task) = record_offsets_task.write().take() {
+            task.cancel();
+        }
+
+        let Some(source_type) = source_type() else {
+            record_offsets.set(Some(Err("The DICOMDIR file hasn't been read".into())));
+            return;
+        };
+
+        if source_type != DataSetSourceType::P10 {
+            record_offsets.set(Some(Err(
+                "Directory records can only be navigated when read as DICOM P10".into(),
+            )));
+            return;
+        }
+
+        let file = files
+            .peek()
+            .iter()
+            .find(|file| Some(file.id) == active_file_id)
+            .and_then(|file| Some((file.file_data.clone(), file.sniffed_file.clone()?)));
+
+        let Some((file_data, sniffed_file)) = file else {
+            record_offsets.set(Some(Err("The DICOMDIR file hasn't been read".into())));
+            return;
+        };
+
+        record_offsets.set(None);
+
+        let task = spawn(async move {
+            let result = file_list::read_file_content(&file_data, &sniffed_file)
+                .await
+                .and_then(|bytes| record_offsets::directory_record_offsets(&bytes));
+
+            record_offsets.set(Some(result));
+        });
+        record_offsets_task.set(Some(task));
+    }));
+
+    let records = use_memo(move || match &*record_offsets.read() {
+        Some(Ok(offsets)) => Some(tree::build(&data_set.read(), offsets)),
+        Some(Err(e)) => Some(Err(e.clone())),
+        None => None,
+    });
+
+    let records = records.read();
+    let rows = match &*records {
+        Some(Ok(records)) => Some(Ok(tree::flatten(
+            records,
+            &collapsed.read(),
+            &files.read(),
+            active_file_id,
+        ))),
+        Some(Err(e)) => Some(Err(e.clone())),
+        None => None,
+    };
+
+    rsx! {
+        div {
+            class: "dicomdir-view",
+
+            match rows {
+                None => rsx! {
+                    div { class: "dicomdir-message", "Reading the directory records…" }
+                },
+
+                Some(Ok(rows)) if rows.is_empty() => rsx! {
+                    div { class: "dicomdir-message", "The DICOMDIR has no directory records." }
+                },
+
+                Some(Ok(rows)) => rsx! {
+                    for row in rows {
+                        div {
+                            key: "{row.offset}",
+                            class: "dicomdir-row",
+                            class: if row.file_id.is_some() { "openable" },
+                            padding_left: "calc(0.5em + {row.depth * 1.25}em)",
+                            title: row_title(&row),
+
+                            onclick: move |_| {
+                                if let Some(file_id) = row.file_id {
+                                    on_select_file.call(file_id);
+                                }
+                            },
+
+                            span {
+                                class: "expand-icon",
+                                onclick: move |event| {
+                                    event.stop_propagation();
+
+                                    let mut collapsed = collapsed.write();
+                                    if !collapsed.remove(&row.offset) {
+                                        collapsed.insert(row.offset);
+                                    }
+                                },
+
+                                if row.has_children {
+                                    ui::FontAwesomeIcon {
+                                        icon: if row.is_expanded { "chevron-down" } else { "chevron-right" },
+                                        style: "solid",
+                                    }
+                                }
+                            }
+
+                            span { class: "label", {row.label.clone()} }
+                            span { class: "details", {row.details.clone()} }
+
+                            if let Some(path) = &row.referenced_file_id {
+                                span {
+                                    class: "referenced-file",
+                                    class: if row.file_id.is_none() { "missing" },
+
+                                    if row.file_id.is_some() {
+                                        ui::FontAwesomeIcon { icon: "arrow-up-right-from-square", style: "solid" }
+                                    }
+
+                                    {path.clone()}
+                                }
+                            }
+                        }
+                    }
+                },
+
+                Some(Err(e)) => rsx! {
+                    div { class: "dicomdir-message", "Unable to read the directory records. {e}" }
+                },
+            }
+        }
+    }
+}
+
+fn row_title(row: &DirectoryRow) -> String {
+    match (&row.referenced_file_id, row.file_id) {
+        (Some(path), Some(_)) => format!("Open {}", path),
+        (Some(path), None) => format!("{} hasn't been opened alongside the DICOMDIR", path),
+        (None, _) => row.label.clone(),
+    }
+}
@@ -0,0 +1,318 @@
+//! Locates the items of a DICOMDIR's Directory Record Sequence in its raw bytes. The links between
+//! directory records are byte offsets into the file, which aren't retained when the file is read
+//! into a data set, so the file is walked to find the offset of each record.
+//!
+//! DICOMDIR files are always encoded using the Explicit VR Little Endian transfer syntax, which is
+//! the only one supported here.
+
+const DIRECTORY_RECORD_SEQUENCE: (u16, u16) = (0x0004, 0x1220);
+const ITEM: (u16, u16) = (0xFFFE, 0xE000);
+const ITEM_DELIMITATION: (u16, u16) = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: (u16, u16) = (0xFFFE, 0xE0DD);
+
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+
+/// Returns the byte offset of each item in the Directory Record Sequence, in the order the items
+/// appear. Offsets are from the start of the file, i.e. the first byte of the File Preamble.
+///
+pub fn directory_record_offsets(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    let mut offset = if bytes.get(128..132) == Some(b"DICM") {
+        132
+    } else if bytes.get(0..4) == Some(b"DICM") {
+        4
+    } else {
+        0
+    };
+
+    while offset < bytes.len() {
+        let (tag, header_length, length) = read_element_header(bytes, offset)?;
+
+        if tag == DIRECTORY_RECORD_SEQUENCE {
+            return sequence_item_offsets(bytes, offset + header_length, length);
+        }
+
+        offset = skip_element(bytes, offset)?;
+    }
+
+    Err("The file has no Directory Record Sequence".into())
+}
+
+/// Returns the offsets of the items in the sequence whose value starts at the given offset.
+///
+fn sequence_item_offsets(bytes: &[u8], start: usize, length: u32) -> Result<Vec<u32>, String> {
+    let end = if length == UNDEFINED_LENGTH {
+        usize::MAX
+    } else {
+        start + length as usize
+    };
+
+    let mut item_offsets = vec![];
+    let mut offset = start;
+
+    while offset < end && offset < bytes.len() {
+        let (tag, item_length) = read_item_header(bytes, offset)?;
+
+        match tag {
+            ITEM => {
+                item_offsets.push(
+                    u32::try_from(offset)
+                        .map_err(|_| "Directory record offset is too large".to_string())?,
+                );
+
+                offset = skip_item(bytes, offset, item_length)?;
+            }
+
+            SEQUENCE_DELIMITATION => break,
+
+            _ => return Err(format!("Unexpected tag {} in sequence", tag_string(tag))),
+        }
+    }
+
+    Ok(item_offsets)
+}
+
+/// Returns the offset following the item that starts at the given offset.
+///
+fn skip_item(bytes: &[u8], offset: usize, item_length: u32) -> Result<usize, String> {
+    if item_length != UNDEFINED_LENGTH {
+        return Ok(offset + 8 + item_length as usize);
+    }
+
+    let mut offset = offset + 8;
+    loop {
+        if read_item_header(bytes, offset)?.0 == ITEM_DELIMITATION {
+            return Ok(offset + 8);
+        }
+
+        offset = skip_element(bytes, offset)?;
+    }
+}
+
+/// Returns the offset following the data element that starts at the given offset.
+///
+fn skip_element(bytes: &[u8], offset: usize) -> Result<usize, String> {
+    let (_, header_length, length) = read_element_header(bytes, offset)?;
+
+    if length != UNDEFINED_LENGTH {
+        return Ok(offset + header_length + length as usize);
+    }
+
+    // Values with an undefined length are made up of items followed by a sequence delimiter
+    let mut offset = offset + header_length;
+    loop {
+        let (tag, item_length) = read_item_header(bytes, offset)?;
+
+        match tag {
+            ITEM => offset = skip_item(bytes, offset, item_length)?,
+            SEQUENCE_DELIMITATION => return Ok(offset + 8),
+            _ => return Err(format!("Unexpected tag {} in sequence", tag_string(tag))),
+        }
+    }
+}
+
+/// Reads the Explicit VR Little Endian data element header at the given offset, returning its tag,
+/// the length of the header, and the length of its value.
+///
+fn read_element_header(bytes: &[u8], offset: usize) -> Result<((u16, u16), usize, u32), String> {
+    let tag = read_tag(bytes, offset)?;
+    let vr = bytes
+        .get(offset + 4..offset + 6)
+        .ok_or_else(unexpected_end)?;
+
+    match vr {
+        b"OB" | b"OD" | b"OF" | b"OL" | b"OV" | b"OW" | b"SQ" | b"SV" | b"UC" | b"UN" | b"UR"
+        | b"UT" | b"UV" => Ok((tag, 12, read_u32(bytes, offset + 8)?)),
+
+        _ => Ok((tag, 8, u32::from(read_u16(bytes, offset + 6)?))),
+    }
+}
+
+/// Reads the item or delimiter header at the given offset, returning its tag and length.
+///
+fn read_item_header(bytes: &[u8], offset: usize) -> Result<((u16, u16), u32), String> {
+    Ok((read_tag(bytes, offset)?, read_u32(bytes, offset + 4)?))
+}
+
+fn read_tag(bytes: &[u8], offset: usize) -> Result<(u16, u16), String> {
+    Ok((read_u16(bytes, offset)?, read_u16(bytes, offset + 2)?))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(unexpected_end)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(unexpected_end)
+}
+
+fn unexpected_end() -> String {
+    "Unexpected end of file while locating directory records".into()
+}
+
+fn tag_string((group, element): (u16, u16)) -> String {
+    format!("({:04X},{:04X})", group, element)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_bytes((group, element): (u16, u16)) -> Vec<u8> {
+        [group.to_le_bytes(), element.to_le_bytes()].concat()
+    }
+
+    /// Returns an Explicit VR Little Endian data element with a short-form header.
+    ///
+    fn element(tag: (u16, u16), vr: &[u8; 2], value: &[u8]) -> Vec<u8> {
+        let mut bytes = tag_bytes(tag);
+        bytes.extend_from_slice(vr);
+        bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    /// Returns a sequence holding the given items, with a sequence delimiter if it has an undefined
+    /// length.
+    ///
+    fn sequence(tag: (u16, u16), items: &[Vec<u8>], is_undefined_length: bool) -> Vec<u8> {
+        let content = items.concat();
+
+        let mut bytes = tag_bytes(tag);
+        bytes.extend_from_slice(b"SQ\0\0");
+
+        if is_undefined_length {
+            bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+            bytes.extend(content);
+            bytes.extend(tag_bytes(SEQUENCE_DELIMITATION));
+            bytes.extend_from_slice(&[0, 0, 0, 0]);
+        } else {
+            bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            bytes.extend(content);
+        }
+
+        bytes
+    }
+
+    fn item(content: &[u8], is_undefined_length: bool) -> Vec<u8> {
+        let mut bytes = tag_bytes(ITEM);
+
+        if is_undefined_length {
+            bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+            bytes.extend_from_slice(content);
+            bytes.extend(tag_bytes(ITEM_DELIMITATION));
+            bytes.extend_from_slice(&[0, 0, 0, 0]);
+        } else {
+            bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(content);
+        }
+
+        bytes
+    }
+
+    fn record(record_type: &[u8]) -> Vec<u8> {
+        [
+            element((0x0004, 0x1430), b"CS", record_type),
+            element((0x0004, 0x1400), b"UL", &[0, 0, 0, 0]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn finds_records_after_the_preamble() {
+        let mut bytes = vec![0; 128];
+        bytes.extend_from_slice(b"DICM");
+        bytes.extend(element((0x0002, 0x0010), b"UI", b"1.2.840.10008.1.2.1\0"));
+
+        // An earlier sequence of undefined length, with an item of undefined length, is skipped
+        bytes.extend(sequence(
+            (0x0009, 0x1010),
+            &[item(&element((0x0009, 0x1011), b"LO", b"AB"), true)],
+            true,
+        ));
+        bytes.extend(element((0x0004, 0x1130), b"CS", b""));
+
+        let items = [
+            item(&record(b"PATIENT "), false),
+            item(&record(b"STUDY "), true),
+            item(&record(b"SERIES"), false),
+        ];
+
+        let sequence_offset = bytes.len();
+        bytes.extend(sequence(DIRECTORY_RECORD_SEQUENCE, &items, true));
+
+        let first_item = sequence_offset + 12;
+        let expected = [
+            first_item,
+            first_item + items[0].len(),
+            first_item + items[0].len() + items[1].len(),
+        ]
+        .map(|offset| offset as u32);
+
+        assert_eq!(directory_record_offsets(&bytes), Ok(expected.to_vec()));
+    }
+
+    #[test]
+    fn finds_records_in_a_sequence_of_defined_length() {
+        let items = [
+            item(&record(b"PATIENT "), false),
+            item(&record(b"IMAGE "), true),
+        ];
+
+        let mut bytes = b"DICM".to_vec();
+        bytes.extend(sequence(DIRECTORY_RECORD_SEQUENCE, &items, false));
+
+        // Data elements after the sequence aren't part of it
+        bytes.extend(element((0x0009, 0x0010), b"LO", b"AB"));
+
+        assert_eq!(
+            directory_record_offsets(&bytes),
+            Ok(vec![16, 16 + items[0].len() as u32])
+        );
+    }
+
+    #[test]
+    fn finds_no_records_in_an_empty_sequence() {
+        let bytes = sequence(DIRECTORY_RECORD_SEQUENCE, &[], true);
+
+        assert_eq!(directory_record_offsets(&bytes), Ok(vec![]));
+    }
+
+    #[test]
+    fn missing_sequence_is_an_error() {
+        let bytes = element((0x0004, 0x1130), b"CS", b"");
+
+        assert_eq!(
+            directory_record_offsets(&bytes),
+            Err("The file has no Directory Record Sequence".into())
+        );
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let bytes = sequence(
+            DIRECTORY_RECORD_SEQUENCE,
+            &[item(&record(b"PATIENT "), true)],
+            true,
+        );
+
+        assert_eq!(
+            directory_record_offsets(&bytes[..bytes.len() - 20]),
+            Err(unexpected_end())
+        );
+    }
+
+    #[test]
+    fn unexpected_tag_in_sequence_is_an_error() {
+        let mut bytes = sequence(DIRECTORY_RECORD_SEQUENCE, &[], true);
+        bytes.truncate(12);
+        bytes.extend(element((0x0004, 0x1430), b"CS", b"IMAGE "));
+
+        assert!(directory_record_offsets(&bytes).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use dcmfx::core::*;
+
+use crate::file_list::OpenedFile;
+
+/// A record in a DICOMDIR's directory, along with the records in its lower-level directory entity.
+///
+pub struct DirectoryRecord {
+    /// The offset of the record in the DICOMDIR file, which uniquely identifies it.
+    pub offset: u32,
+
+    pub record_type: String,
+    pub label: String,
+    pub details: String,
+
+    /// The path of the referenced file, with its components separated by `/`.
+    pub referenced_file_id: Option<String>,
+    pub referenced_sop_instance_uid: Option<String>,
+
+    pub children: Vec<DirectoryRecord>,
+}
+
+/// Builds the hierarchy of directory records by following the offsets that link them, starting at
+/// the root directory entity. The offset of each item in the Directory Record Sequence must be
+/// passed in, as these aren't stored in the data set.
+///
+pub fn build(data_set: &DataSet, record_offsets: &[u32]) -> Result<Vec<DirectoryRecord>, String> {
+    let records = data_set
+        .get_value(dictionary::DIRECTORY_RECORD_SEQUENCE.tag)
+        .and_then(|value| value.sequence_items())
+        .map_err(|_| "The data set has no Directory Record Sequence".to_string())?;
+
+    if records.len() != record_offsets.len() {
+        return Err(format!(
+            "The Directory Record Sequence has {} items but {} were found in the file",
+            records.len(),
+            record_offsets.len()
+        ));
+    }
+
+    let records_by_offset = record_offsets
+        .iter()
+        .copied()
+        .zip(records.iter())
+        .collect::<HashMap<_, _>>();
+
+    let root_offset = get_offset(
+        data_set,
+        dictionary::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY.tag,
+    );
+
+    let mut visited = HashSet::new();
+    directory_entity(&records_by_offset, root_offset, &mut visited)
+}
+
+/// Returns the records in the directory entity that starts at the given offset. Each record is
+/// visited at most once so that offsets that link back to an earlier record don't cause a cycle.
+///
+fn directory_entity(
+    records_by_offset: &HashMap<u32, &DataSet>,
+    first_offset: Option<u32>,
+    visited: &mut HashSet<u32>,
+) -> Result<Vec<DirectoryRecord>, String> {
+    let mut entity = vec![];
+    let mut next_offset = first_offset;
+
+    while let Some(offset) = next_offset {
+        if !visited.insert(offset) {
+            return Err(format!(
+                "Directory record at offset {} is referenced more than once",
+                offset
+            ));
+        }
+
+        let Some(record) = records_by_offset.get(&offset) else {
+            return Err(format!("No directory record found at offset {}", offset));
+        };
+
+        let lower_level_offset = get_offset(
+            record,
+            dictionary::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY.tag,
+        );
+
+        let (label, details) = record_description(record);
+
+        entity.push(DirectoryRecord {
+            offset,
+            record_type: get_string(record, dictionary::DIRECTORY_RECORD_TYPE.tag)
+                .unwrap_or_default(),
+            label,
+            details,
+            referenced_file_id: record
+                .get_strings(dictionary::REFERENCED_FILE_ID.tag)
+                .ok()
+                .map(|components| {
+                    components
+                        .iter()
+                        .map(|component| component.trim())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                }),
+            referenced_sop_instance_uid: get_string(
+                record,
+                dictionary::REFERENCED_SOP_INSTANCE_UID_IN_FILE.tag,
+            ),
+            children: directory_entity(records_by_offset, lower_level_offset, visited)?,
+        });
+
+        next_offset = get_offset(record, dictionary::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD.tag);
+    }
+
+    Ok(entity)
+}
+
+/// Returns the label and details shown for a directory record, based on its type.
+///
+fn record_description(record: &DataSet) -> (String, String) {
+    let record_type = get_string(record, dictionary::DIRECTORY_RECORD_TYPE.tag).unwrap_or_default();
+    let string = |tag| get_string(record, tag).unwrap_or_default();
+
+    let (label, details) = match record_type.as_str() {
+        "PATIENT" => (
+            string(dictionary::PATIENT_NAME.tag),
+            string(dictionary::PATIENT_ID.tag),
+        ),
+        "STUDY" => (
+            string(dictionary::STUDY_DESCRIPTION.tag),
+            string(dictionary::STUDY_DATE.tag),
+        ),
+        "SERIES" => (
+            string(dictionary::SERIES_DESCRIPTION.tag),
+            [
+                string(dictionary::MODALITY.tag),
+                get_string(record, dictionary::SERIES_NUMBER.tag)
+                    .map(|number| format!("#{}", number))
+                    .unwrap_or_default(),
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+        ),
+        _ => (
+            get_string(record, dictionary::INSTANCE_NUMBER.tag)
+                .map(|number| format!("#{}", number))
+                .unwrap_or_default(),
+            String::new(),
+        ),
+    };
+
+    let label = if label.is_empty() {
+        title_case(&record_type)
+    } else {
+        format!("{} {}", title_case(&record_type), label)
+    };
+
+    (label, details)
+}
+
+/// Converts a Directory Record Type such as "RT DOSE" to "Rt dose" for display.
+///
+fn title_case(record_type: &str) -> String {
+    let lowercase = record_type.to_lowercase();
+    let mut chars = lowercase.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "Record".into(),
+    }
+}
+
+fn get_offset(data_set: &DataSet, tag: DataElementTag) -> Option<u32> {
+    data_set
+        .get_int::<u32>(tag)
+        .ok()
+        .filter(|offset| *offset != 0)
+}
+
+fn get_string(data_set: &DataSet, tag: DataElementTag) -> Option<String> {
+    data_set
+        .get_string(tag)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the ID of the opened file that a directory record references, if that file was opened
+/// alongside the DICOMDIR. Files are matched on their SOP Instance UID where possible, and
+/// otherwise on their filename.
+///
+pub fn referenced_file(
+    record: &DirectoryRecord,
+    files: &[OpenedFile],
+    dicomdir_file_id: Option<u64>,
+) -> Option<u64> {
+    let files = files
+        .iter()
+        .filter(|file| Some(file.id) != dicomdir_file_id);
+
+    if let Some(uid) = &record.referenced_sop_instance_uid {
+        let file = files.clone().find(|file| {
+            file.header
+                .as_ref()
+                .and_then(|header| header.as_ref().ok())
+                .and_then(|header| get_string(header, dictionary::SOP_INSTANCE_UID.tag))
+                .as_ref()
+                == Some(uid)
+        });
+
+        if let Some(file) = file {
+            return Some(file.id);
+        }
+    }
+
+    let filename = record.referenced_file_id.as_ref()?.rsplit('/').next()?;
+
+    files
+        .clone()
+        .find(|file| file.filename.eq_ignore_ascii_case(filename))
+        .map(|file| file.id)
+}
+
+/// A row in the flattened directory record tree.
+///
+#[derive(Clone, PartialEq)]
+pub struct DirectoryRow {
+    pub depth: usize,
+    pub offset: u32,
+    pub label: String,
+    pub details: String,
+    pub has_children: bool,
+    pub is_expanded: bool,
+    pub referenced_file_id: Option<String>,
+
+    /// The ID of the opened file the record references, if any.
+    pub file_id: Option<u64>,
+}
+
+/// Flattens the directory record tree into the rows that are shown, leaving out the children of
+/// collapsed records.
+///
+pub fn flatten(
+    records: &[DirectoryRecord],
+    collapsed: &HashSet<u32>,
+    files: &[OpenedFile],
+    dicomdir_file_id: Option<u64>,
+) -> Vec<DirectoryRow> {
+    let mut rows = vec![];
+    flatten_into(&mut rows, records, 0, collapsed, files, dicomdir_file_id);
+    rows
+}
+
+fn flatten_into(
+    rows: &mut Vec<DirectoryRow>,
+    records: &[DirectoryRecord],
+    depth: usize,
+    collapsed: &HashSet<u32>,
+    files: &[OpenedFile],
+    dicomdir_file_id: Option<u64>,
+) {
+    for record in records {
+        let is_expanded = !collapsed.contains(&record.offset);
+
+        rows.push(DirectoryRow {
+            depth,
+            offset: record.offset,
+            label: record.label.clone(),
+            details: record.details.clone(),
+            has_children: !record.children.is_empty(),
+            is_expanded,
+            referenced_file_id: record.referenced_file_id.clone(),
+            file_id: referenced_file(record, files, dicomdir_file_id),
+        });
+
+        if is_expanded {
+            flatten_into(
+                rows,
+                &record.children,
+                depth + 1,
+                collapsed,
+                files,
+                dicomdir_file_id,
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+mod rle;
+mod transcode;
+
+use std::{borrow::Cow, rc::Rc};
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{
+    jobs::{JobError, JobState, TranscodeTarget, WorkerDataSet},
+    ui, utils,
+};
+use transcode::TARGET_TRANSFER_SYNTAXES;
+
+/// The default quality used when encoding JPEG Baseline.
+///
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Returns the data set transcoded to the target transfer syntax with the given UID. This is run by
+/// the job worker, which passes a checkpoint that lets it cancel transcoding between frames.
+///
+pub async fn transcode_to<'a>(
+    data_set: &'a DataSet,
+    uid: &str,
+    jpeg_quality: u8,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<Cow<'a, DataSet>, String> {
+    let target = TARGET_TRANSFER_SYNTAXES
+        .iter()
+        .find(|ts| ts.uid == uid)
+        .ok_or_else(|| format!("Transcoding to {} isn't supported", uid))?;
+
+    transcode::transcode(data_set, target, jpeg_quality, checkpoint).await
+}
+
+/// A dialog for choosing the transfer syntax of a downloaded DICOM P10 file. The job worker's copy
+/// of the data set is transcoded to the chosen transfer syntax and written as DICOM P10, and the
+/// written blob parts are passed to `on_download`. Neither copy of the data set is changed.
+///
+#[component]
+pub fn DownloadP10Dialog(
+    data_set: Signal<DataSet>,
+    worker_data_set: ReadSignal<Option<Rc<WorkerDataSet>>>,
+    on_download: EventHandler<js_sys::Array>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let source_uid = use_memo(move || transcode::current_transfer_syntax_uid(&data_set.read()));
+
+    let mut target_uid = use_signal(|| source_uid.peek().clone());
+    let mut jpeg_quality = use_signal(|| DEFAULT_JPEG_QUALITY);
+    let mut error = use_signal(|| None::<String>);
+    let write_state = use_signal(JobState::default);
+
+    let target = TARGET_TRANSFER_SYNTAXES
+        .iter()
+        .find(|ts| ts.uid == target_uid())
+        .copied();
+
+    // Writing is cancelled if the dialog is closed before it completes
+    let on_confirm = move |_| {
+        // Keeping the current transfer syntax needs no transcoding, which means files in transfer
+        // syntaxes that aren't in the list of targets can still be downloaded
+        let transfer_syntax = if target_uid() == source_uid() {
+            None
+        } else {
+            let Some(target) = target else {
+                return;
+            };
+
+            Some(TranscodeTarget {
+                uid: target.uid.to_string(),
+                jpeg_quality: jpeg_quality(),
+            })
+        };
+
+        let Some(worker_data_set) = worker_data_set.peek().clone() else {
+            error.set(Some("The file is still being read".into()));
+            return;
+        };
+        error.set(None);
+
+        spawn(async move {
+            match worker_data_set
+                .write_p10(transfer_syntax, write_state)
+                .await
+            {
+                Ok(blob_parts) => on_download.call(blob_parts),
+                Err(JobError::Failed(e)) => error.set(Some(e)),
+                Err(JobError::Cancelled) => (),
+            }
+        });
+    };
+
+    let is_source_a_target = TARGET_TRANSFER_SYNTAXES
+        .iter()
+        .any(|ts| ts.uid == source_uid());
+
+    rsx! {
+        ui::Dialog {
+            title: "Download as .dcm",
+            on_close,
+
+            p {
+                "Current transfer syntax: "
+                b { {transcode::transfer_syntax_name(&source_uid())} }
+            }
+
+            div {
+                class: "transfer-syntax-list",
+
+                if !is_source_a_target {
+                    label {
+                        input {
+                            r#type: "radio",
+                            name: "transfer-syntax",
+                            checked: target_uid() == source_uid(),
+                            onchange: move |_| {
+                                target_uid.set(source_uid());
+                                error.set(None);
+                            },
+                        }
+                        "Keep current transfer syntax"
+                    }
+                }
+
+                for ts in TARGET_TRANSFER_SYNTAXES {
+                    label {
+                        input {
+                            r#type: "radio",
+                            name: "transfer-syntax",
+                            checked: target_uid() == ts.uid,
+                            onchange: move |_| {
+                                target_uid.set(ts.uid.to_string());
+                                error.set(None);
+                            },
+                        }
+                        {ts.name}
+                        if ts.uid == source_uid() {
+                            span { class: "hint", " (current)" }
+                        }
+                        if ts.is_lossy {
+                            span { class: "hint", " (lossy)" }
+                        }
+                    }
+                }
+            }
+
+            if target.is_some_and(|ts| ts.is_lossy) && target_uid() != source_uid() {
+                label {
+                    class: "jpeg-quality",
+
+                    "Quality"
+                    input {
+                        r#type: "range",
+                        min: 1,
+                        max: 100,
+                        value: "{jpeg_quality}",
+                        oninput: move |event| {
+                            if let Ok(quality) = event.value().parse() {
+                                jpeg_quality.set(quality);
+                            }
+                        },
+                    }
+                    span { "{jpeg_quality}" }
+                }
+            }
+
+            if let Some(error) = error() {
+                div { class: "dialog-error", {error} }
+            }
+
+            div {
+                class: "dialog-buttons",
+
+                button { onclick: move |_| on_close.call(()), "Cancel" }
+                button {
+                    class: "primary",
+                    disabled: write_state().is_running(),
+                    onclick: on_confirm,
+
+                    match write_state() {
+                        JobState::Running { bytes_received } => {
+                            rsx! { "Writing… {utils::format_size(bytes_received)}" }
+                        }
+                        JobState::Idle => rsx! { "Download" },
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Encoding of frames in the RLE Lossless transfer syntax, as defined in DICOM PS3.5
+//! Annex G.
+//!
+//! Each frame is made up of a header followed by up to 15 segments. Each segment holds one byte of
+//! one sample for every pixel, most significant byte first, and is compressed with the PackBits
+//! algorithm.
+
+/// The layout of the native pixel data for a frame.
+///
+pub struct FrameLayout {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub bytes_per_sample: usize,
+    pub is_planar: bool,
+}
+
+impl FrameLayout {
+    /// Returns the size in bytes of a frame of native pixel data with this layout.
+    ///
+    pub fn frame_size(&self) -> usize {
+        self.width * self.height * self.samples_per_pixel * self.bytes_per_sample
+    }
+
+    fn segment_count(&self) -> usize {
+        self.samples_per_pixel * self.bytes_per_sample
+    }
+
+    /// Returns the offset into a frame of native pixel data of the given byte of a pixel's sample.
+    /// Byte zero is the most significant byte.
+    ///
+    fn byte_offset(&self, pixel: usize, sample: usize, byte: usize) -> usize {
+        let sample_index = if self.is_planar {
+            sample * self.width * self.height + pixel
+        } else {
+            pixel * self.samples_per_pixel + sample
+        };
+
+        // Native pixel data is little endian
+        sample_index * self.bytes_per_sample + (self.bytes_per_sample - 1 - byte)
+    }
+}
+
+/// Encodes a frame of native pixel data as RLE Lossless.
+///
+pub fn encode_frame(frame: &[u8], layout: &FrameLayout) -> Result<Vec<u8>, String> {
+    let segment_count = layout.segment_count();
+    if segment_count > 15 {
+        return Err(format!(
+            "RLE Lossless supports at most 15 segments, but this pixel data needs {}",
+            segment_count
+        ));
+    }
+
+    if frame.len() < layout.frame_size() {
+        return Err("Pixel data is too short".into());
+    }
+
+    let mut header = vec![0u32; 16];
+    header[0] = segment_count as u32;
+
+    let mut segments = vec![];
+    let mut row = Vec::with_capacity(layout.width);
+
+    for sample in 0..layout.samples_per_pixel {
+        for byte in 0..layout.bytes_per_sample {
+            header[1 + sample * layout.bytes_per_sample + byte] = (64 + segments.len()) as u32;
+
+            // Rows are encoded separately so that runs don't cross row boundaries
+            for y in 0..layout.height {
+                row.clear();
+                row.extend(
+                    (0..layout.width)
+                        .map(|x| frame[layout.byte_offset(y * layout.width + x, sample, byte)]),
+                );
+
+                encode_packbits(&row, &mut segments);
+            }
+
+            if segments.len() % 2 == 1 {
+                segments.push(0);
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(64 + segments.len());
+    for value in header {
+        result.extend_from_slice(&value.to_le_bytes());
+    }
+    result.extend(segments);
+
+    Ok(result)
+}
+
+/// Compresses bytes with PackBits, appending the result to `output`.
+///
+fn encode_packbits(input: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+
+    while i < input.len() {
+        let mut run_length = 1;
+        while i + run_length < input.len() && run_length < 128 && input[i + run_length] == input[i]
+        {
+            run_length += 1;
+        }
+
+        if run_length > 1 {
+            // Replicate run
+            output.push((257 - run_length) as u8);
+            output.push(input[i]);
+            i += run_length;
+        } else {
+            // Literal run, which continues until the next replicate run
+            let start = i;
+            i += 1;
+            while i < input.len()
+                && i - start < 128
+                && !(i + 1 < input.len() && input[i] == input[i + 1])
+            {
+                i += 1;
+            }
+
+            output.push((i - start - 1) as u8);
+            output.extend_from_slice(&input[start..i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a frame of RLE Lossless pixel data into native pixel data, used to check that
+    /// encoded frames round trip.
+    ///
+    fn decode_frame(data: &[u8], layout: &FrameLayout) -> Result<Vec<u8>, String> {
+        let read_u32 = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .ok_or_else(|| "RLE header is truncated".to_string())
+        };
+
+        let segment_count = read_u32(0)?;
+        if segment_count != layout.segment_count() {
+            return Err(format!(
+                "RLE data has {} segments but {} were expected",
+                segment_count,
+                layout.segment_count()
+            ));
+        }
+
+        let pixel_count = layout.width * layout.height;
+        let mut frame = vec![0u8; layout.frame_size()];
+
+        for segment in 0..segment_count {
+            let start = read_u32(4 + segment * 4)?;
+            let end = if segment + 1 < segment_count {
+                read_u32(8 + segment * 4)?
+            } else {
+                data.len()
+            };
+
+            let Some(segment_data) = data.get(start..end) else {
+                return Err("RLE segment offset is invalid".into());
+            };
+
+            let decoded = decode_packbits(segment_data, pixel_count);
+            if decoded.len() < pixel_count {
+                return Err("RLE segment is too short".into());
+            }
+
+            let sample = segment / layout.bytes_per_sample;
+            let byte = segment % layout.bytes_per_sample;
+
+            for (pixel, value) in decoded.into_iter().take(pixel_count).enumerate() {
+                frame[layout.byte_offset(pixel, sample, byte)] = value;
+            }
+        }
+
+        Ok(frame)
+    }
+
+    /// Decompresses PackBits data, stopping once the expected number of bytes have been produced.
+    ///
+    fn decode_packbits(input: &[u8], expected_length: usize) -> Vec<u8> {
+        let mut output = Vec::with_capacity(expected_length);
+        let mut i = 0;
+
+        while i < input.len() && output.len() < expected_length {
+            let header = input[i] as i8;
+            i += 1;
+
+            if header >= 0 {
+                let length = header as usize + 1;
+                let end = (i + length).min(input.len());
+                output.extend_from_slice(&input[i..end]);
+                i = end;
+            } else if header != -128 {
+                let Some(value) = input.get(i) else {
+                    break;
+                };
+
+                let length = 1 - header as isize;
+                output.extend(std::iter::repeat_n(*value, length as usize));
+                i += 1;
+            }
+        }
+
+        output
+    }
+
+    fn layout(samples_per_pixel: usize, bytes_per_sample: usize, is_planar: bool) -> FrameLayout {
+        FrameLayout {
+            width: 5,
+            height: 3,
+            samples_per_pixel,
+            bytes_per_sample,
+            is_planar,
+        }
+    }
+
+    /// Returns a frame with runs and literals that cross row boundaries.
+    ///
+    fn test_frame(layout: &FrameLayout) -> Vec<u8> {
+        (0..layout.frame_size())
+            .map(|i| if i % 7 < 4 { 0xAA } else { i as u8 })
+            .collect()
+    }
+
+    fn packbits(input: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        encode_packbits(input, &mut output);
+        output
+    }
+
+    #[test]
+    fn packbits_matches_reference_encoding() {
+        let input = [
+            0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0xAA, 0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0x22,
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+        ];
+        let encoded = [
+            0xFE, 0xAA, 0x02, 0x80, 0x00, 0x2A, 0xFD, 0xAA, 0x03, 0x80, 0x00, 0x2A, 0x22, 0xF7,
+            0xAA,
+        ];
+
+        assert_eq!(packbits(&input), encoded);
+        assert_eq!(decode_packbits(&encoded, input.len()), input);
+    }
+
+    #[test]
+    fn packbits_round_trips_long_runs_and_literals() {
+        let mut input = vec![7u8; 300];
+        input.extend((0..300).map(|i| i as u8));
+        input.push(1);
+
+        let encoded = packbits(&input);
+
+        assert_eq!(decode_packbits(&encoded, input.len()), input);
+    }
+
+    #[test]
+    fn packbits_decoding_skips_no_op_headers_and_stops_at_expected_length() {
+        assert_eq!(decode_packbits(&[0x80, 0xFF, 0x05], 2), [5, 5]);
+        assert_eq!(decode_packbits(&[0x01, 0x01, 0x02, 0x00, 0x03], 2), [1, 2]);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        for layout in [
+            layout(1, 1, false),
+            layout(1, 2, false),
+            layout(3, 1, false),
+            layout(3, 1, true),
+            layout(3, 2, true),
+            layout(1, 4, false),
+        ] {
+            let frame = test_frame(&layout);
+
+            let encoded = encode_frame(&frame, &layout).unwrap();
+            assert_eq!(encoded.len() % 2, 0);
+
+            assert_eq!(decode_frame(&encoded, &layout).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn segments_hold_the_most_significant_byte_first() {
+        let layout = FrameLayout {
+            width: 2,
+            height: 1,
+            samples_per_pixel: 1,
+            bytes_per_sample: 2,
+            is_planar: false,
+        };
+
+        let encoded = encode_frame(&[0x34, 0x12, 0x78, 0x56], &layout).unwrap();
+
+        // The header holds the segment count and the offsets of the two segments
+        assert_eq!(encoded[0..4], [2, 0, 0, 0]);
+        assert_eq!(encoded[4..8], [64, 0, 0, 0]);
+        assert_eq!(encoded[8..12], [68, 0, 0, 0]);
+
+        // Each segment is a literal run of two bytes, padded to an even length
+        assert_eq!(encoded[64..68], [0x01, 0x12, 0x56, 0x00]);
+        assert_eq!(encoded[68..72], [0x01, 0x34, 0x78, 0x00]);
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        assert!(encode_frame(&[0; 10], &layout(1, 1, false)).is_err());
+        assert!(encode_frame(&[0; 15 * 16], &layout(4, 4, false)).is_err());
+
+        let mono = layout(1, 1, false);
+        let encoded = encode_frame(&test_frame(&mono), &mono).unwrap();
+
+        assert!(decode_frame(&encoded, &layout(3, 1, false)).is_err());
+        assert!(decode_frame(&encoded[..40], &mono).is_err());
+        assert!(decode_frame(&encoded[..66], &mono).is_err());
+    }
+}
@@ -0,0 +1,701 @@
+//! Conversion of a data set to a different transfer syntax prior to writing it as DICOM P10.
+//!
+//! Changing between the native transfer syntaxes only requires updating the Transfer Syntax UID,
+//! as the P10 writer takes care of VR encoding, byte order, and deflate compression. Changing the
+//! encoding of pixel data is supported from any transfer syntax that dcmfx can decode, e.g. RLE
+//! Lossless, JPEG, JPEG-LS, and JPEG 2000, to native pixel data, RLE Lossless, and JPEG Baseline.
+//! Decoding is done by dcmfx, and only the encoders are implemented here.
+
+use std::borrow::Cow;
+
+use dcmfx::{
+    core::*,
+    pixel_data::{
+        ColorImage, ColorImageData, ColorSpace, DataSetPixelDataExtensions, MonochromeImage,
+        MonochromeImageData, PixelDataRenderer,
+    },
+};
+use image::{ExtendedColorType, ImageEncoder, codecs::jpeg::JpegEncoder};
+
+use super::rle::{self, FrameLayout};
+use crate::{editing::value_text, utils};
+
+/// How a transfer syntax stores pixel data.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelDataEncoding {
+    Native,
+    RleLossless,
+    JpegBaseline,
+}
+
+/// A transfer syntax that can be chosen when downloading.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetTransferSyntax {
+    pub uid: &'static str,
+    pub name: &'static str,
+    pub encoding: PixelDataEncoding,
+    pub is_lossy: bool,
+}
+
+pub const TARGET_TRANSFER_SYNTAXES: [TargetTransferSyntax; 6] = [
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2.1",
+        name: "Explicit VR Little Endian",
+        encoding: PixelDataEncoding::Native,
+        is_lossy: false,
+    },
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2",
+        name: "Implicit VR Little Endian",
+        encoding: PixelDataEncoding::Native,
+        is_lossy: false,
+    },
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2.1.99",
+        name: "Deflated Explicit VR Little Endian",
+        encoding: PixelDataEncoding::Native,
+        is_lossy: false,
+    },
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2.2",
+        name: "Explicit VR Big Endian",
+        encoding: PixelDataEncoding::Native,
+        is_lossy: false,
+    },
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2.5",
+        name: "RLE Lossless",
+        encoding: PixelDataEncoding::RleLossless,
+        is_lossy: false,
+    },
+    TargetTransferSyntax {
+        uid: "1.2.840.10008.1.2.4.50",
+        name: "JPEG Baseline (Process 1)",
+        encoding: PixelDataEncoding::JpegBaseline,
+        is_lossy: true,
+    },
+];
+
+/// Returns the UID of the data set's current transfer syntax. Data sets without one, e.g. those
+/// read from DICOM JSON, are treated as Explicit VR Little Endian.
+///
+pub fn current_transfer_syntax_uid(data_set: &DataSet) -> String {
+    data_set
+        .get_string(dictionary::TRANSFER_SYNTAX_UID.tag)
+        .map(|uid| uid.trim_end_matches('\0').trim().to_string())
+        .unwrap_or_else(|_| TARGET_TRANSFER_SYNTAXES[0].uid.to_string())
+}
+
+/// Returns a display name for a transfer syntax UID.
+///
+pub fn transfer_syntax_name(uid: &str) -> String {
+    TARGET_TRANSFER_SYNTAXES
+        .iter()
+        .find(|ts| ts.uid == uid)
+        .map(|ts| ts.name.to_string())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+/// Returns the data set converted to the target transfer syntax, transcoding its pixel data if
+/// needed. `jpeg_quality` is used when the target is JPEG Baseline. The data set is returned as-is
+/// if it's already in the target transfer syntax.
+///
+/// `checkpoint` is awaited before each frame is decoded or encoded, and transcoding stops with its
+/// error if it returns one.
+///
+pub async fn transcode<'a>(
+    data_set: &'a DataSet,
+    target: &TargetTransferSyntax,
+    jpeg_quality: u8,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<Cow<'a, DataSet>, String> {
+    let source_uid = current_transfer_syntax_uid(data_set);
+    if source_uid == target.uid {
+        return Ok(Cow::Borrowed(data_set));
+    }
+
+    let mut replacements = vec![];
+
+    let has_pixel_data = data_set.get_value(dictionary::PIXEL_DATA.tag).is_ok();
+    if has_pixel_data {
+        replacements.extend(
+            transcode_pixel_data(data_set, &source_uid, target, jpeg_quality, checkpoint).await?,
+        );
+    }
+
+    replacements.push((
+        dictionary::TRANSFER_SYNTAX_UID.tag,
+        value_text::text_to_value("UI", target.uid)?,
+    ));
+
+    // Build the new data set from the data elements that aren't being replaced, so that values
+    // such as the original pixel data are never copied only to then be replaced
+    let mut transcoded = DataSet::new();
+    for (tag, value) in data_set.iter() {
+        if !replacements
+            .iter()
+            .any(|(replaced_tag, _)| replaced_tag == tag)
+        {
+            transcoded.insert(*tag, value.clone());
+        }
+    }
+
+    for (tag, value) in replacements {
+        transcoded.insert(tag, value);
+    }
+
+    Ok(Cow::Owned(transcoded))
+}
+
+async fn transcode_pixel_data(
+    data_set: &DataSet,
+    source_uid: &str,
+    target: &TargetTransferSyntax,
+    jpeg_quality: u8,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<Vec<(DataElementTag, DataElementValue)>, String> {
+    let source_encoding = TARGET_TRANSFER_SYNTAXES
+        .iter()
+        .find(|ts| ts.uid == source_uid)
+        .map(|ts| ts.encoding);
+
+    if source_encoding == Some(target.encoding) && target.encoding == PixelDataEncoding::Native {
+        return Ok(vec![]);
+    }
+
+    let conversion_error = |reason: &str| {
+        format!(
+            "Can't convert from {} to {}: {}",
+            transfer_syntax_name(source_uid),
+            target.name,
+            reason
+        )
+    };
+
+    let mut layout = frame_layout(data_set).map_err(|e| conversion_error(&e))?;
+    let mut photometric_interpretation = data_set
+        .get_string(dictionary::PHOTOMETRIC_INTERPRETATION.tag)
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    // Get the frames as native pixel data
+    let frames = if source_encoding == Some(PixelDataEncoding::Native) {
+        native_frames(data_set, &layout).map_err(|e| conversion_error(&e))?
+    } else {
+        let (frames, decoded_photometric_interpretation) =
+            decode_frames(data_set, &layout, checkpoint)
+                .await
+                .map_err(|e| conversion_error(&e))?;
+
+        // Decoded color frames are always interleaved, and may have been converted to RGB
+        if let Some(decoded_photometric_interpretation) = decoded_photometric_interpretation {
+            photometric_interpretation = decoded_photometric_interpretation.to_string();
+            layout.is_planar = false;
+        }
+
+        frames
+    };
+
+    let mut replacements = vec![];
+
+    let pixel_data_value = match target.encoding {
+        PixelDataEncoding::Native => {
+            let vr = if layout.bytes_per_sample > 1 {
+                ValueRepresentation::OtherWordString
+            } else {
+                ValueRepresentation::OtherByteString
+            };
+
+            let mut bytes = frames.concat();
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+
+            DataElementValue::new_binary(vr, bytes.into()).map_err(|e| e.to_string())?
+        }
+
+        PixelDataEncoding::RleLossless => {
+            let mut items = vec![vec![]];
+            for frame in frames.iter() {
+                checkpoint().await?;
+                items.push(rle::encode_frame(frame, &layout).map_err(|e| conversion_error(&e))?);
+            }
+
+            new_encapsulated_pixel_data(items)?
+        }
+
+        PixelDataEncoding::JpegBaseline => {
+            let (items, jpeg_photometric_interpretation) = encode_jpeg_baseline(
+                data_set,
+                &photometric_interpretation,
+                &frames,
+                &layout,
+                jpeg_quality,
+                checkpoint,
+            )
+            .await
+            .map_err(|e| conversion_error(&e))?;
+            photometric_interpretation = jpeg_photometric_interpretation.to_string();
+
+            let uncompressed_size = frames.iter().map(Vec::len).sum::<usize>();
+            let compressed_size = items.iter().map(Vec::len).sum::<usize>().max(1);
+            let ratio = uncompressed_size as f64 / compressed_size as f64;
+
+            // Lossy compression creates a new instance, and the ratio and method of this
+            // compression are appended to those of any lossy compression previously applied
+            let sop_instance_uid = utils::random_uid();
+
+            for (tag, vr, value) in [
+                (dictionary::LOSSY_IMAGE_COMPRESSION.tag, "CS", "01".into()),
+                (
+                    dictionary::LOSSY_IMAGE_COMPRESSION_RATIO.tag,
+                    "DS",
+                    appended_value(
+                        data_set,
+                        dictionary::LOSSY_IMAGE_COMPRESSION_RATIO.tag,
+                        &format!("{:.2}", ratio),
+                    ),
+                ),
+                (
+                    dictionary::LOSSY_IMAGE_COMPRESSION_METHOD.tag,
+                    "CS",
+                    appended_value(
+                        data_set,
+                        dictionary::LOSSY_IMAGE_COMPRESSION_METHOD.tag,
+                        "ISO_10918_1",
+                    ),
+                ),
+                (
+                    dictionary::SOP_INSTANCE_UID.tag,
+                    "UI",
+                    sop_instance_uid.clone(),
+                ),
+            ] {
+                replacements.push((tag, value_text::text_to_value_unvalidated(vr, &value)?));
+            }
+
+            if data_set.has(dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag) {
+                replacements.push((
+                    dictionary::MEDIA_STORAGE_SOP_INSTANCE_UID.tag,
+                    value_text::text_to_value("UI", &sop_instance_uid)?,
+                ));
+            }
+
+            layout.is_planar = false;
+
+            new_encapsulated_pixel_data(std::iter::once(vec![]).chain(items).collect())?
+        }
+    };
+
+    // Decoding and encoding can change the color space and planar configuration of color frames
+    replacements.push((
+        dictionary::PHOTOMETRIC_INTERPRETATION.tag,
+        value_text::text_to_value("CS", &photometric_interpretation)?,
+    ));
+    if layout.samples_per_pixel > 1 {
+        replacements.push((
+            dictionary::PLANAR_CONFIGURATION.tag,
+            value_text::text_to_value("US", if layout.is_planar { "1" } else { "0" })?,
+        ));
+    }
+
+    replacements.push((dictionary::PIXEL_DATA.tag, pixel_data_value));
+
+    Ok(replacements)
+}
+
+/// Returns the frames of native pixel data.
+///
+fn native_frames(data_set: &DataSet, layout: &FrameLayout) -> Result<Vec<Vec<u8>>, String> {
+    let bytes = data_set
+        .get_value(dictionary::PIXEL_DATA.tag)
+        .and_then(|value| value.bytes())
+        .map_err(|e| e.to_string())?;
+
+    let frame_count = data_set
+        .get_int::<u32>(dictionary::NUMBER_OF_FRAMES.tag)
+        .map_or(1, |count| count as usize)
+        .max(1);
+
+    let frame_size = layout.frame_size();
+    if frame_size == 0 || bytes.len() < frame_size * frame_count {
+        return Err("the pixel data is shorter than expected".into());
+    }
+
+    Ok(bytes
+        .chunks_exact(frame_size)
+        .take(frame_count)
+        .map(|frame| frame.to_vec())
+        .collect())
+}
+
+/// Decodes the frames of encapsulated pixel data to native pixel data using dcmfx. For color
+/// pixel data, the photometric interpretation of the decoded frames is also returned, as decoders
+/// may convert to RGB, and decoded frames are always interleaved.
+///
+async fn decode_frames(
+    data_set: &DataSet,
+    layout: &FrameLayout,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<(Vec<Vec<u8>>, Option<&'static str>), String> {
+    let renderer = PixelDataRenderer::from_data_set(data_set)
+        .map_err(|_| "the Image Pixel module is invalid".to_string())?;
+
+    let mut frames = data_set
+        .get_pixel_data_frames()
+        .map_err(|e| e.to_string())?;
+
+    let mut decoded_frames = vec![];
+    let mut photometric_interpretation = None;
+
+    for frame in frames.iter_mut() {
+        checkpoint().await?;
+
+        let decoded_frame = if layout.samples_per_pixel == 1 {
+            let image = renderer
+                .decode_monochrome_frame(frame)
+                .map_err(|e| e.to_string())?;
+
+            monochrome_frame_bytes(&image, layout)?
+        } else {
+            let image = renderer
+                .decode_color_frame(frame)
+                .map_err(|e| e.to_string())?;
+
+            let (bytes, color_space) = color_frame_bytes(&image, layout)?;
+            photometric_interpretation = Some(color_space);
+
+            bytes
+        };
+
+        decoded_frames.push(decoded_frame);
+    }
+
+    Ok((decoded_frames, photometric_interpretation))
+}
+
+/// Returns the native pixel data for a decoded monochrome frame.
+///
+fn monochrome_frame_bytes(
+    image: &MonochromeImage,
+    layout: &FrameLayout,
+) -> Result<Vec<u8>, String> {
+    let samples: Vec<u32> = match image.data() {
+        MonochromeImageData::Bitmap { .. } => {
+            return Err("decoding 1-bit pixel data isn't supported".into());
+        }
+        MonochromeImageData::I8(data) => data.iter().map(|v| *v as u32).collect(),
+        MonochromeImageData::U8(data) => data.iter().map(|v| u32::from(*v)).collect(),
+        MonochromeImageData::I16(data) => data.iter().map(|v| *v as u32).collect(),
+        MonochromeImageData::U16(data) => data.iter().map(|v| u32::from(*v)).collect(),
+        MonochromeImageData::I32(data) => data.iter().map(|v| *v as u32).collect(),
+        MonochromeImageData::U32(data) => data.clone(),
+    };
+
+    Ok(samples_to_bytes(&samples, layout))
+}
+
+/// Returns the native pixel data for a decoded color frame, along with the photometric
+/// interpretation of its color space.
+///
+fn color_frame_bytes(
+    image: &ColorImage,
+    layout: &FrameLayout,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let (samples, color_space): (Vec<u32>, _) = match image.data() {
+        ColorImageData::U8 { data, color_space } => {
+            (data.iter().map(|v| u32::from(*v)).collect(), color_space)
+        }
+        ColorImageData::U16 { data, color_space } => {
+            (data.iter().map(|v| u32::from(*v)).collect(), color_space)
+        }
+        ColorImageData::U32 { data, color_space } => (data.clone(), color_space),
+        _ => return Err("decoding palette color pixel data isn't supported".into()),
+    };
+
+    let photometric_interpretation = match color_space {
+        ColorSpace::Rgb => "RGB",
+        ColorSpace::Ybr { .. } => "YBR_FULL",
+    };
+
+    Ok((
+        samples_to_bytes(&samples, layout),
+        photometric_interpretation,
+    ))
+}
+
+/// Writes samples as little endian native pixel data using the layout's bytes per sample.
+///
+fn samples_to_bytes(samples: &[u32], layout: &FrameLayout) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(samples.len() * layout.bytes_per_sample);
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes()[..layout.bytes_per_sample]);
+    }
+
+    bytes
+}
+
+/// Returns the text of a multi-valued attribute with the given value appended to its existing
+/// values, if it has any.
+///
+fn appended_value(data_set: &DataSet, tag: DataElementTag, value: &str) -> String {
+    let existing = data_set
+        .get_value(tag)
+        .ok()
+        .and_then(value_text::value_to_text)
+        .unwrap_or_default();
+
+    if existing.trim().is_empty() {
+        value.to_string()
+    } else {
+        format!("{}\\{}", existing.trim(), value)
+    }
+}
+
+/// Reads the layout of a single frame of native pixel data from the Image Pixel module.
+///
+fn frame_layout(data_set: &DataSet) -> Result<FrameLayout, String> {
+    let get_u16 = |tag: DataElementTag| {
+        data_set
+            .get_int::<u16>(tag)
+            .map(usize::from)
+            .map_err(|_| format!("{} is missing or invalid", dictionary::tag_name(tag, None)))
+    };
+
+    let bits_allocated = get_u16(dictionary::BITS_ALLOCATED.tag)?;
+    if !matches!(bits_allocated, 8 | 16 | 32) {
+        return Err(format!(
+            "Bits Allocated of {} isn't supported",
+            bits_allocated
+        ));
+    }
+
+    let samples_per_pixel = get_u16(dictionary::SAMPLES_PER_PIXEL.tag)?.max(1);
+
+    Ok(FrameLayout {
+        width: get_u16(dictionary::COLUMNS.tag)?,
+        height: get_u16(dictionary::ROWS.tag)?,
+        samples_per_pixel,
+        bytes_per_sample: bits_allocated / 8,
+        is_planar: samples_per_pixel > 1
+            && get_u16(dictionary::PLANAR_CONFIGURATION.tag).unwrap_or(0) == 1,
+    })
+}
+
+/// Encodes frames of native pixel data as JPEG Baseline. Returns the encoded frames and the
+/// photometric interpretation of the result.
+///
+async fn encode_jpeg_baseline(
+    data_set: &DataSet,
+    photometric_interpretation: &str,
+    frames: &[Vec<u8>],
+    layout: &FrameLayout,
+    quality: u8,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<(Vec<Vec<u8>>, &'static str), String> {
+    if layout.bytes_per_sample != 1 {
+        return Err("JPEG Baseline only supports 8-bit pixel data".into());
+    }
+
+    if data_set
+        .get_int::<u16>(dictionary::PIXEL_REPRESENTATION.tag)
+        .unwrap_or(0)
+        != 0
+    {
+        return Err("JPEG Baseline doesn't support signed pixel data".into());
+    }
+
+    let color_type = match (layout.samples_per_pixel, photometric_interpretation) {
+        (1, "MONOCHROME1" | "MONOCHROME2") => ExtendedColorType::L8,
+        (3, "RGB") => ExtendedColorType::Rgb8,
+        _ => {
+            return Err(format!(
+                "JPEG Baseline encoding of {} pixel data isn't supported",
+                photometric_interpretation
+            ));
+        }
+    };
+
+    let pixel_count = layout.width * layout.height;
+
+    let mut items = vec![];
+    for frame in frames {
+        checkpoint().await?;
+
+        // The encoder requires interleaved samples
+        let interleaved = if layout.is_planar {
+            Cow::Owned(
+                (0..frame.len())
+                    .map(|i| frame[(i % 3) * pixel_count + i / 3])
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            Cow::Borrowed(frame)
+        };
+
+        let mut bytes = vec![];
+        JpegEncoder::new_with_quality(&mut bytes, quality)
+            .write_image(
+                &interleaved,
+                layout.width as u32,
+                layout.height as u32,
+                color_type,
+            )
+            .map_err(|e| e.to_string())?;
+
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+
+        items.push(bytes);
+    }
+
+    // The encoder converts RGB to YCbCr, and whether the chroma components are subsampled is
+    // determined by the sampling factors it actually wrote
+    let result_photometric_interpretation = if color_type == ExtendedColorType::Rgb8 {
+        match items.first().map(|item| jpeg_chroma_is_subsampled(item)) {
+            Some(Ok(true)) => "YBR_FULL_422",
+            Some(Ok(false)) | None => "YBR_FULL",
+            Some(Err(e)) => return Err(e),
+        }
+    } else {
+        match photometric_interpretation {
+            "MONOCHROME1" => "MONOCHROME1",
+            _ => "MONOCHROME2",
+        }
+    };
+
+    Ok((items, result_photometric_interpretation))
+}
+
+/// Returns whether the chroma components of a JPEG image are subsampled relative to its luma
+/// component, based on the sampling factors in its start of frame segment.
+///
+fn jpeg_chroma_is_subsampled(jpeg: &[u8]) -> Result<bool, String> {
+    let error = || "The encoded JPEG data is invalid".to_string();
+
+    if jpeg.get(0..2) != Some(&[0xFF, 0xD8]) {
+        return Err(error());
+    }
+
+    let mut offset = 2;
+    loop {
+        let (Some(0xFF), Some(&marker)) = (jpeg.get(offset), jpeg.get(offset + 1)) else {
+            return Err(error());
+        };
+
+        let length = jpeg
+            .get(offset + 2..offset + 4)
+            .map(|bytes| usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+            .ok_or_else(error)?;
+        let segment = jpeg
+            .get(offset + 4..offset + 2 + length)
+            .ok_or_else(error)?;
+
+        // Start of frame segments for the baseline, extended, and progressive processes
+        if matches!(marker, 0xC0..=0xC2) {
+            let component_count = usize::from(*segment.get(5).ok_or_else(error)?);
+            let sampling_factors = (0..component_count)
+                .map(|i| segment.get(6 + i * 3 + 1).copied().ok_or_else(error))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let Some((luma, chroma)) = sampling_factors.split_first() else {
+                return Err(error());
+            };
+
+            return Ok(chroma.iter().any(|sampling_factor| sampling_factor != luma));
+        }
+
+        offset += 2 + length;
+    }
+}
+
+fn new_encapsulated_pixel_data(items: Vec<Vec<u8>>) -> Result<DataElementValue, String> {
+    DataElementValue::new_encapsulated_pixel_data(
+        ValueRepresentation::OtherByteString,
+        items.into_iter().map(Into::into).collect(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a minimal JPEG stream holding an APP0 segment and a baseline start of frame segment
+    /// for three components with the given sampling factors.
+    ///
+    fn jpeg_with_sampling_factors(sampling_factors: [u8; 3]) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+
+        jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x10, 0x00, 0x10, 0x03]);
+        for (i, sampling_factor) in sampling_factors.into_iter().enumerate() {
+            jpeg.extend([i as u8 + 1, sampling_factor, 0x00]);
+        }
+
+        jpeg
+    }
+
+    #[test]
+    fn jpeg_chroma_subsampling_is_detected() {
+        assert_eq!(
+            jpeg_chroma_is_subsampled(&jpeg_with_sampling_factors([0x11, 0x11, 0x11])),
+            Ok(false)
+        );
+        assert_eq!(
+            jpeg_chroma_is_subsampled(&jpeg_with_sampling_factors([0x21, 0x11, 0x11])),
+            Ok(true)
+        );
+        assert_eq!(
+            jpeg_chroma_is_subsampled(&jpeg_with_sampling_factors([0x22, 0x11, 0x11])),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn invalid_jpeg_data_is_rejected() {
+        assert!(jpeg_chroma_is_subsampled(&[]).is_err());
+        assert!(jpeg_chroma_is_subsampled(&[0xFF, 0xD8, 0xFF, 0xC0, 0x00]).is_err());
+    }
+
+    #[test]
+    fn lossy_compression_values_are_appended() {
+        let mut data_set = DataSet::new();
+        data_set.insert(
+            dictionary::LOSSY_IMAGE_COMPRESSION_METHOD.tag,
+            value_text::text_to_value("CS", "ISO_10918_1").unwrap(),
+        );
+
+        assert_eq!(
+            appended_value(
+                &data_set,
+                dictionary::LOSSY_IMAGE_COMPRESSION_METHOD.tag,
+                "ISO_10918_1"
+            ),
+            "ISO_10918_1\\ISO_10918_1"
+        );
+        assert_eq!(
+            appended_value(
+                &data_set,
+                dictionary::LOSSY_IMAGE_COMPRESSION_RATIO.tag,
+                "10.00"
+            ),
+            "10.00"
+        );
+    }
+
+    #[test]
+    fn encoded_rgb_jpeg_has_a_start_of_frame() {
+        let rgb = vec![128u8; 16 * 16 * 3];
+
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_quality(&mut jpeg, 90)
+            .write_image(&rgb, 16, 16, ExtendedColorType::Rgb8)
+            .unwrap();
+
+        assert!(jpeg_chroma_is_subsampled(&jpeg).is_ok());
+    }
+}
@@ -4,7 +4,7 @@ use dioxus_elements::FileData;
 #[component]
 pub fn DropArea(
     is_file_dragged_over: Signal<bool>,
-    on_select_input_file: EventHandler<Option<FileData>>,
+    on_select_input_files: EventHandler<Vec<FileData>>,
 ) -> Element {
     rsx! {
         div {
@@ -16,7 +16,7 @@ pub fn DropArea(
 
                 i { class: "fa-solid fa-file-medical fa-3x" }
 
-                span { "Drop a DICOM or DICOM JSON file here, or click to browse for a file." }
+                span { "Drop DICOM, DICOM JSON, or DICOM XML files here, or click to browse for files." }
                 span {
                     font_size: "0.75em",
                     color: "#AAA",
@@ -28,7 +28,20 @@ pub fn DropArea(
                 input {
                     id: "dicom-file-input",
                     r#type: "file",
-                    onchange: move |event| on_select_input_file(event.files().into_iter().next()),
+                    multiple: true,
+                    onchange: move |event| on_select_input_files(event.files()),
+                }
+            }
+
+            label {
+                class: "drop-area-folder",
+
+                "Or open a whole folder"
+
+                input {
+                    r#type: "file",
+                    directory: true,
+                    onchange: move |event| on_select_input_files(event.files()),
                 }
             }
         }
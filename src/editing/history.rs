@@ -0,0 +1,428 @@
+use dcmfx::core::*;
+
+use super::{path_from_entries, replace_value, split_data_element_path};
+
+/// The maximum number of entries kept in the undo history. The oldest entries are discarded once
+/// this is exceeded.
+///
+const MAX_UNDO_ENTRIES: usize = 200;
+
+/// A change to the value of a single data element. A value of `None` means the data element
+/// doesn't exist.
+///
+/// Data element values are reference counted, so holding on to them here doesn't copy their
+/// bytes, which keeps the history small even for very large data sets.
+///
+#[derive(Clone, Debug)]
+pub struct DataSetChange {
+    pub path: DataSetPath,
+    pub before: Option<DataElementValue>,
+    pub after: Option<DataElementValue>,
+}
+
+/// A single undoable modification made to a data set, which may change many data elements.
+///
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub description: String,
+    pub changes: Vec<DataSetChange>,
+}
+
+/// The undo/redo history of modifications made to a data set.
+///
+/// Every value set by applying, undoing, or redoing a modification is also recorded as an edit
+/// until it's taken by [`History::take_edits()`], which is how the copy of the data set held in the
+/// job worker is kept in sync.
+///
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    undo_entries: Vec<HistoryEntry>,
+    redo_entries: Vec<HistoryEntry>,
+    edits: Vec<(DataSetPath, Option<DataElementValue>)>,
+}
+
+impl History {
+    /// Returns the entries that can be undone, oldest first.
+    ///
+    pub fn undo_entries(&self) -> &[HistoryEntry] {
+        &self.undo_entries
+    }
+
+    /// Returns the entries that can be redone, with the next one to be redone last.
+    ///
+    pub fn redo_entries(&self) -> &[HistoryEntry] {
+        &self.redo_entries
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_entries.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_entries.is_empty()
+    }
+
+    pub fn has_edits(&self) -> bool {
+        !self.edits.is_empty()
+    }
+
+    /// Returns the values set in the data set since this was last called, in the order they were
+    /// set.
+    ///
+    pub fn take_edits(&mut self) -> Vec<(DataSetPath, Option<DataElementValue>)> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Applies a modification to the data set and records it in the history so it can be undone.
+    /// Each edit sets the value of the data element at a path, with `None` removing it.
+    ///
+    /// If any edit fails then the edits already made are reverted and the data set is left
+    /// unchanged. Edits that don't change anything aren't recorded, and if no edits change
+    /// anything then no history entry is added.
+    ///
+    pub fn apply(
+        &mut self,
+        data_set: &mut DataSet,
+        description: String,
+        edits: Vec<(DataSetPath, Option<DataElementValue>)>,
+    ) -> Result<(), String> {
+        let mut changes = vec![];
+
+        for (path, value) in edits {
+            match replace_value(data_set, &path, value.clone()) {
+                Ok(before) => {
+                    if before != value {
+                        changes.push(DataSetChange {
+                            path,
+                            before,
+                            after: value,
+                        });
+                    }
+                }
+
+                Err(e) => {
+                    revert_changes(data_set, &changes)?;
+                    return Err(e);
+                }
+            }
+        }
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.edits.extend(
+            changes
+                .iter()
+                .map(|change| (change.path.clone(), change.after.clone())),
+        );
+
+        self.undo_entries.push(HistoryEntry {
+            description,
+            changes,
+        });
+        self.redo_entries.clear();
+
+        if self.undo_entries.len() > MAX_UNDO_ENTRIES {
+            self.undo_entries.remove(0);
+        }
+
+        Ok(())
+    }
+
+    /// Undoes the most recent modification, returning its description. Returns `None` if there is
+    /// nothing to undo.
+    ///
+    pub fn undo(&mut self, data_set: &mut DataSet) -> Result<Option<String>, String> {
+        let Some(entry) = self.undo_entries.pop() else {
+            return Ok(None);
+        };
+
+        if let Err(e) = revert_changes(data_set, &entry.changes) {
+            self.undo_entries.push(entry);
+            return Err(e);
+        }
+
+        self.edits.extend(
+            entry
+                .changes
+                .iter()
+                .rev()
+                .map(|change| (change.path.clone(), change.before.clone())),
+        );
+
+        let description = entry.description.clone();
+        self.redo_entries.push(entry);
+
+        Ok(Some(description))
+    }
+
+    /// Redoes the most recently undone modification, returning its description. Returns `None` if
+    /// there is nothing to redo.
+    ///
+    pub fn redo(&mut self, data_set: &mut DataSet) -> Result<Option<String>, String> {
+        let Some(entry) = self.redo_entries.pop() else {
+            return Ok(None);
+        };
+
+        for change in entry.changes.iter() {
+            if let Err(e) = replace_value(data_set, &change.path, change.after.clone()) {
+                self.redo_entries.push(entry);
+                return Err(e);
+            }
+        }
+
+        self.edits.extend(
+            entry
+                .changes
+                .iter()
+                .map(|change| (change.path.clone(), change.after.clone())),
+        );
+
+        let description = entry.description.clone();
+        self.undo_entries.push(entry);
+
+        Ok(Some(description))
+    }
+}
+
+/// Reverts changes by restoring their previous values in reverse order.
+///
+fn revert_changes(data_set: &mut DataSet, changes: &[DataSetChange]) -> Result<(), String> {
+    for change in changes.iter().rev() {
+        replace_value(data_set, &change.path, change.before.clone())?;
+    }
+
+    Ok(())
+}
+
+/// Returns a description of a change to a single data element that's suitable for display in the
+/// history list, e.g. "Set (0010,0010) Patient's Name".
+///
+pub fn describe_change(
+    path: &DataSetPath,
+    before: Option<&DataElementValue>,
+    after: Option<&DataElementValue>,
+) -> String {
+    let data_element = describe_data_element(path);
+
+    match (before, after) {
+        (None, Some(_)) => format!("Added {}", data_element),
+        (Some(_), None) => format!("Deleted {}", data_element),
+        (Some(before), Some(after)) => match (before.sequence_items(), after.sequence_items()) {
+            (Ok(before), Ok(after)) if after.len() > before.len() => {
+                format!("Added item to {}", data_element)
+            }
+            (Ok(before), Ok(after)) if after.len() < before.len() => {
+                format!("Removed item from {}", data_element)
+            }
+            _ => format!("Set {}", data_element),
+        },
+        (None, None) => format!("Set {}", data_element),
+    }
+}
+
+/// Returns the tag and name of the data element at the given path, followed by the path to the
+/// data set that contains it if that isn't the root data set.
+///
+pub fn describe_data_element(path: &DataSetPath) -> String {
+    let Ok((entries, tag)) = split_data_element_path(path) else {
+        return path.to_string();
+    };
+
+    let description = format!("{} {}", tag, dictionary::tag_name(tag, None));
+
+    match path_from_entries(entries) {
+        Ok(parent_path) if !parent_path.is_empty() => {
+            format!("{} in {}", description, parent_path)
+        }
+        _ => description,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editing::{get_value, value_text};
+
+    fn path(tag: DataElementTag) -> DataSetPath {
+        path_from_entries(&[DataSetPathEntry::DataElement { tag }]).unwrap()
+    }
+
+    fn value(text: &str) -> Option<DataElementValue> {
+        Some(value_text::text_to_value_unvalidated("LO", text).unwrap())
+    }
+
+    fn set(
+        history: &mut History,
+        data_set: &mut DataSet,
+        tag: DataElementTag,
+        text: &str,
+    ) -> Result<(), String> {
+        history.apply(
+            data_set,
+            format!("Set {}", text),
+            vec![(path(tag), value(text))],
+        )
+    }
+
+    const TAG: DataElementTag = dictionary::PATIENT_NAME.tag;
+    const OTHER_TAG: DataElementTag = dictionary::PATIENT_ID.tag;
+
+    #[test]
+    fn undo_and_redo_restore_values() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        set(&mut history, &mut data_set, TAG, "A").unwrap();
+        set(&mut history, &mut data_set, TAG, "B").unwrap();
+        assert_eq!(get_value(&data_set, &path(TAG)), value("B"));
+
+        assert_eq!(history.undo(&mut data_set), Ok(Some("Set B".into())));
+        assert_eq!(get_value(&data_set, &path(TAG)), value("A"));
+
+        assert_eq!(history.undo(&mut data_set), Ok(Some("Set A".into())));
+        assert_eq!(get_value(&data_set, &path(TAG)), None);
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(&mut data_set), Ok(None));
+
+        assert_eq!(history.redo(&mut data_set), Ok(Some("Set A".into())));
+        assert_eq!(history.redo(&mut data_set), Ok(Some("Set B".into())));
+        assert_eq!(get_value(&data_set, &path(TAG)), value("B"));
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(&mut data_set), Ok(None));
+    }
+
+    #[test]
+    fn applying_clears_the_redo_history() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        set(&mut history, &mut data_set, TAG, "A").unwrap();
+        history.undo(&mut data_set).unwrap();
+        assert!(history.can_redo());
+
+        set(&mut history, &mut data_set, TAG, "B").unwrap();
+        assert!(!history.can_redo());
+        assert_eq!(history.undo_entries().len(), 1);
+    }
+
+    #[test]
+    fn changes_in_an_entry_are_undone_together() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        history
+            .apply(
+                &mut data_set,
+                "Set both".into(),
+                vec![(path(TAG), value("A")), (path(OTHER_TAG), value("B"))],
+            )
+            .unwrap();
+
+        history.undo(&mut data_set).unwrap();
+
+        assert!(data_set.is_empty());
+    }
+
+    #[test]
+    fn unchanged_values_are_not_recorded() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        set(&mut history, &mut data_set, TAG, "A").unwrap();
+        set(&mut history, &mut data_set, TAG, "A").unwrap();
+        history
+            .apply(
+                &mut data_set,
+                "Delete".into(),
+                vec![(path(OTHER_TAG), None)],
+            )
+            .unwrap();
+
+        assert_eq!(history.undo_entries().len(), 1);
+    }
+
+    #[test]
+    fn failed_modifications_leave_the_data_set_unchanged() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        // A path into a sequence item that doesn't exist can't be modified
+        let invalid_path = path_from_entries(&[
+            DataSetPathEntry::DataElement {
+                tag: dictionary::REFERENCED_IMAGE_SEQUENCE.tag,
+            },
+            DataSetPathEntry::SequenceItem { index: 0 },
+            DataSetPathEntry::DataElement { tag: TAG },
+        ])
+        .unwrap();
+
+        let result = history.apply(
+            &mut data_set,
+            "Set".into(),
+            vec![(path(TAG), value("A")), (invalid_path, value("B"))],
+        );
+
+        assert!(result.is_err());
+        assert!(data_set.is_empty());
+        assert!(!history.can_undo());
+        assert!(!history.has_edits());
+    }
+
+    #[test]
+    fn undo_history_is_capped() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        for i in 0..MAX_UNDO_ENTRIES + 10 {
+            set(&mut history, &mut data_set, TAG, &i.to_string()).unwrap();
+        }
+
+        let undo_entries = history.undo_entries();
+        assert_eq!(undo_entries.len(), MAX_UNDO_ENTRIES);
+        assert_eq!(undo_entries[0].description, "Set 10");
+        assert_eq!(
+            undo_entries.last().unwrap().description,
+            format!("Set {}", MAX_UNDO_ENTRIES + 9)
+        );
+
+        while history.undo(&mut data_set).unwrap().is_some() {}
+
+        // The oldest modifications can no longer be undone
+        assert_eq!(get_value(&data_set, &path(TAG)), value("9"));
+    }
+
+    #[test]
+    fn edits_record_values_set_by_apply_undo_and_redo() {
+        let mut data_set = DataSet::new();
+        let mut history = History::default();
+
+        history
+            .apply(
+                &mut data_set,
+                "Set both".into(),
+                vec![(path(TAG), value("A")), (path(OTHER_TAG), value("B"))],
+            )
+            .unwrap();
+        assert!(history.has_edits());
+        assert_eq!(
+            history.take_edits(),
+            vec![(path(TAG), value("A")), (path(OTHER_TAG), value("B"))]
+        );
+        assert!(!history.has_edits());
+
+        // Undoing restores values in reverse order
+        history.undo(&mut data_set).unwrap();
+        assert_eq!(
+            history.take_edits(),
+            vec![(path(OTHER_TAG), None), (path(TAG), None)]
+        );
+
+        history.redo(&mut data_set).unwrap();
+        assert_eq!(
+            history.take_edits(),
+            vec![(path(TAG), value("A")), (path(OTHER_TAG), value("B"))]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Modification of data sets in place, addressing data elements by their [`DataSetPath`].
+//!
+//! All modifications are expressed as replacing the value of individual data elements. Adding or
+//! removing a data element replaces a missing value or replaces a value with a missing one, and
+//! adding or removing a sequence item replaces the value of its sequence. This is what allows
+//! [`History`] to record modifications as small diffs.
+
+pub mod history;
+pub mod value_text;
+
+use dcmfx::core::*;
+
+pub use history::History;
+
+/// Replaces the value of the data element at the given path. Passing `None` removes the data
+/// element. Returns the data element's previous value, which is `None` if it didn't exist.
+///
+/// Sequences containing the data element are rebuilt along the way. Their items are cloned, but
+/// data element values are reference counted so their bytes aren't copied.
+///
+pub fn replace_value(
+    data_set: &mut DataSet,
+    path: &DataSetPath,
+    value: Option<DataElementValue>,
+) -> Result<Option<DataElementValue>, String> {
+    let (entries, tag) = split_data_element_path(path)?;
+
+    modify_data_set(data_set, entries, move |data_set| {
+        let previous_value = data_set.get_value(tag).ok().cloned();
+
+        match value {
+            Some(value) => data_set.insert(tag, value),
+            None => data_set.delete(tag),
+        }
+
+        Ok(previous_value)
+    })
+}
+
+/// Returns the value of the data element at the given path, if it exists.
+///
+pub fn get_value(data_set: &DataSet, path: &DataSetPath) -> Option<DataElementValue> {
+    data_set.get_value_at_path(path).ok().cloned()
+}
+
+/// Returns the sequence value that results from inserting a new empty item into a sequence at the
+/// given index.
+///
+pub fn sequence_with_item_added(
+    sequence: &DataElementValue,
+    index: usize,
+) -> Result<DataElementValue, String> {
+    let mut items = sequence
+        .sequence_items()
+        .map_err(|_| "Data element isn't a sequence".to_string())?
+        .clone();
+
+    items.insert(index.min(items.len()), DataSet::new());
+
+    Ok(DataElementValue::new_sequence(items))
+}
+
+/// Returns the sequence value that results from removing the item at the given index from a
+/// sequence.
+///
+pub fn sequence_with_item_removed(
+    sequence: &DataElementValue,
+    index: usize,
+) -> Result<DataElementValue, String> {
+    let mut items = sequence
+        .sequence_items()
+        .map_err(|_| "Data element isn't a sequence".to_string())?
+        .clone();
+
+    if index >= items.len() {
+        return Err(format!("Sequence item {} doesn't exist", index + 1));
+    }
+
+    items.remove(index);
+
+    Ok(DataElementValue::new_sequence(items))
+}
+
+/// Splits a path to a data element into the path to the data set containing it and the data
+/// element's tag.
+///
+pub fn split_data_element_path(
+    path: &DataSetPath,
+) -> Result<(&[DataSetPathEntry], DataElementTag), String> {
+    match path.entries().split_last() {
+        Some((DataSetPathEntry::DataElement { tag }, entries)) => Ok((entries, *tag)),
+        _ => Err(format!("Path '{}' doesn't refer to a data element", path)),
+    }
+}
+
+/// Splits a path to a sequence item into the path to its sequence and the item's index.
+///
+pub fn split_sequence_item_path(path: &DataSetPath) -> Result<(DataSetPath, usize), String> {
+    let Some((DataSetPathEntry::SequenceItem { index }, entries)) = path.entries().split_last()
+    else {
+        return Err(format!("Path '{}' doesn't refer to a sequence item", path));
+    };
+
+    Ok((path_from_entries(entries)?, *index))
+}
+
+/// Creates a data set path from a list of entries.
+///
+pub fn path_from_entries(entries: &[DataSetPathEntry]) -> Result<DataSetPath, String> {
+    let mut path = DataSetPath::new();
+
+    for entry in entries {
+        let result = match entry {
+            DataSetPathEntry::DataElement { tag } => path.add_data_element(*tag),
+            DataSetPathEntry::SequenceItem { index } => path.add_sequence_item(*index),
+        };
+
+        result.map_err(|_| "Invalid data set path".to_string())?;
+    }
+
+    Ok(path)
+}
+
+/// Calls the given function with the data set at the given path, rebuilding the sequences that
+/// lead to it afterwards.
+///
+fn modify_data_set<R>(
+    data_set: &mut DataSet,
+    entries: &[DataSetPathEntry],
+    f: impl FnOnce(&mut DataSet) -> Result<R, String>,
+) -> Result<R, String> {
+    match entries {
+        [] => f(data_set),
+
+        [
+            DataSetPathEntry::DataElement { tag },
+            DataSetPathEntry::SequenceItem { index },
+            rest @ ..,
+        ] => {
+            let mut items = data_set
+                .get_value(*tag)
+                .and_then(|value| value.sequence_items())
+                .map_err(|_| format!("Sequence {} doesn't exist", tag))?
+                .clone();
+
+            let item = items
+                .get_mut(*index)
+                .ok_or_else(|| format!("Sequence item {} doesn't exist", index + 1))?;
+
+            let result = modify_data_set(item, rest, f)?;
+
+            data_set.insert(*tag, DataElementValue::new_sequence(items));
+
+            Ok(result)
+        }
+
+        _ => Err("Data set path is invalid".into()),
+    }
+}
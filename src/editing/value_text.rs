@@ -0,0 +1,755 @@
+//! Conversion of data element values to and from the text that's shown when editing them in the
+//! data set grid. Text entered by the user is validated against the rules for its value
+//! representation before being converted into a value.
+//!
+//! Multiple values are separated by a backslash, which is the same delimiter DICOM uses.
+
+use dcmfx::core::*;
+
+/// Returns whether values with the given VR can be edited as text.
+///
+pub fn is_editable(vr: &str) -> bool {
+    is_string_vr(vr) || binary_value_size(vr).is_some() || vr == "AT"
+}
+
+/// Returns the text for a value that's shown when editing it. Returns `None` if the value can't
+/// be edited as text.
+///
+pub fn value_to_text(value: &DataElementValue) -> Option<String> {
+    let vr = value.value_representation().to_string();
+    let bytes = value.bytes().ok()?;
+
+    if is_string_vr(&vr) {
+        let text = String::from_utf8_lossy(bytes);
+        return Some(text.trim_end_matches(['\0', ' ']).to_string());
+    }
+
+    if vr == "AT" {
+        let values = bytes
+            .chunks_exact(4)
+            .map(|chunk| {
+                let group = u16::from_le_bytes([chunk[0], chunk[1]]);
+                let element = u16::from_le_bytes([chunk[2], chunk[3]]);
+                DataElementTag::new(group, element).to_string()
+            })
+            .collect::<Vec<_>>();
+
+        return Some(values.join("\\"));
+    }
+
+    let size = binary_value_size(&vr)?;
+    let values = bytes
+        .chunks_exact(size)
+        .map(|chunk| binary_value_to_string(&vr, chunk))
+        .collect::<Vec<_>>();
+
+    Some(values.join("\\"))
+}
+
+/// Parses and validates text entered for a value of the given VR, returning the new value.
+///
+pub fn text_to_value(vr: &str, text: &str) -> Result<DataElementValue, String> {
+    text_to_value_impl(vr, text, true)
+}
+
+/// Parses text for a value of the given VR without validating string values against the rules
+/// for their VR. This is used when importing values that were already stored in a data set, which
+/// aren't always conformant.
+///
+pub fn text_to_value_unvalidated(vr: &str, text: &str) -> Result<DataElementValue, String> {
+    text_to_value_impl(vr, text, false)
+}
+
+fn text_to_value_impl(vr: &str, text: &str, validate: bool) -> Result<DataElementValue, String> {
+    let value_representation = ValueRepresentation::from_bytes(vr.as_bytes())
+        .map_err(|_| format!("Unknown value representation '{}'", vr))?;
+
+    let bytes = text_to_bytes(vr, text, validate)?;
+
+    DataElementValue::new_binary(value_representation, bytes.into()).map_err(|e| e.to_string())
+}
+
+fn text_to_bytes(vr: &str, text: &str, validate: bool) -> Result<Vec<u8>, String> {
+    if is_string_vr(vr) {
+        if validate {
+            validate_string(vr, text)?;
+        }
+
+        let mut bytes = text.as_bytes().to_vec();
+        if bytes.len() % 2 == 1 {
+            bytes.push(if vr == "UI" { 0 } else { b' ' });
+        }
+
+        return Ok(bytes);
+    }
+
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut bytes = vec![];
+
+    for value in text.split('\\').map(str::trim) {
+        if vr == "AT" {
+            let tag = parse_tag(value)?;
+            bytes.extend_from_slice(&tag.group.to_le_bytes());
+            bytes.extend_from_slice(&tag.element.to_le_bytes());
+        } else {
+            bytes.extend(parse_binary_value(vr, value)?);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Returns whether the given VR stores its values as text.
+///
+pub fn is_string_vr(vr: &str) -> bool {
+    matches!(
+        vr,
+        "AE" | "AS"
+            | "CS"
+            | "DA"
+            | "DS"
+            | "DT"
+            | "IS"
+            | "LO"
+            | "LT"
+            | "PN"
+            | "SH"
+            | "ST"
+            | "TM"
+            | "UC"
+            | "UI"
+            | "UR"
+            | "UT"
+    )
+}
+
+/// Returns the size in bytes of a single value of the given binary numeric VR.
+///
+pub fn binary_value_size(vr: &str) -> Option<usize> {
+    match vr {
+        "SS" | "US" => Some(2),
+        "FL" | "SL" | "UL" => Some(4),
+        "FD" | "SV" | "UV" => Some(8),
+        _ => None,
+    }
+}
+
+fn binary_value_to_string(vr: &str, chunk: &[u8]) -> String {
+    match vr {
+        "SS" => i16::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "US" => u16::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "SL" => i32::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "UL" => u32::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "SV" => i64::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "UV" => u64::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "FL" => f32::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        "FD" => f64::from_le_bytes(chunk.try_into().unwrap()).to_string(),
+        _ => String::new(),
+    }
+}
+
+fn parse_binary_value(vr: &str, value: &str) -> Result<Vec<u8>, String> {
+    fn parse<T: std::str::FromStr>(vr: &str, value: &str) -> Result<T, String> {
+        value
+            .parse::<T>()
+            .map_err(|_| format!("'{}' isn't a valid {} value", value, vr))
+    }
+
+    Ok(match vr {
+        "SS" => parse::<i16>(vr, value)?.to_le_bytes().to_vec(),
+        "US" => parse::<u16>(vr, value)?.to_le_bytes().to_vec(),
+        "SL" => parse::<i32>(vr, value)?.to_le_bytes().to_vec(),
+        "UL" => parse::<u32>(vr, value)?.to_le_bytes().to_vec(),
+        "SV" => parse::<i64>(vr, value)?.to_le_bytes().to_vec(),
+        "UV" => parse::<u64>(vr, value)?.to_le_bytes().to_vec(),
+        "FL" => parse::<f32>(vr, value)?.to_le_bytes().to_vec(),
+        "FD" => parse::<f64>(vr, value)?.to_le_bytes().to_vec(),
+        _ => return Err(format!("{} values can't be edited", vr)),
+    })
+}
+
+/// Parses a data element tag in any of the forms "(gggg,eeee)", "gggg,eeee", or "ggggeeee".
+///
+pub fn parse_tag(text: &str) -> Result<DataElementTag, String> {
+    let hex: String = text
+        .chars()
+        .filter(|c| !matches!(c, '(' | ')' | ',') && !c.is_whitespace())
+        .collect();
+
+    if hex.len() != 8 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' isn't a valid tag", text));
+    }
+
+    let group = u16::from_str_radix(&hex[0..4], 16).unwrap();
+    let element = u16::from_str_radix(&hex[4..8], 16).unwrap();
+
+    Ok(DataElementTag::new(group, element))
+}
+
+/// Validates text for a string VR. Each value is checked against the VR's maximum length and
+/// allowed characters, and dates, times, person names, UIDs and numeric strings are checked
+/// against their required formats.
+///
+fn validate_string(vr: &str, text: &str) -> Result<(), String> {
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0C'))
+    {
+        return Err("Value contains control characters".into());
+    }
+
+    // Text VRs hold a single value, and may contain backslashes
+    if matches!(vr, "LT" | "ST" | "UR" | "UT") {
+        let max_length = match vr {
+            "LT" => 10240,
+            "ST" => 1024,
+            _ => u32::MAX as usize - 2,
+        };
+
+        return check_length(vr, text, max_length);
+    }
+
+    for (index, value) in text.split('\\').enumerate() {
+        validate_string_value(vr, value).map_err(|e| {
+            if text.contains('\\') {
+                format!("Value {}: {}", index + 1, e)
+            } else {
+                e
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+fn validate_string_value(vr: &str, value: &str) -> Result<(), String> {
+    if !matches!(vr, "LO" | "LT" | "PN" | "SH" | "ST" | "UC" | "UT")
+        && value.chars().any(|c| !c.is_ascii() || c.is_ascii_control())
+    {
+        return Err(format!("{} values may only contain ASCII characters", vr));
+    }
+
+    match vr {
+        "AE" => check_length(vr, value, 16),
+        "AS" => validate_age_string(value),
+        "CS" => {
+            check_length(vr, value, 16)?;
+
+            if value
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == ' ' || c == '_')
+            {
+                Ok(())
+            } else {
+                Err("CS values may only contain A-Z, 0-9, space and underscore".into())
+            }
+        }
+        "DA" => validate_date(value),
+        "DS" => validate_decimal_string(value),
+        "DT" => validate_date_time(value),
+        "IS" => validate_integer_string(value),
+        "LO" => check_length(vr, value, 64),
+        "PN" => validate_person_name(value),
+        "SH" => check_length(vr, value, 16),
+        "TM" => validate_time(value),
+        "UI" => validate_uid(value),
+        _ => Ok(()),
+    }
+}
+
+fn check_length(vr: &str, value: &str, max_length: usize) -> Result<(), String> {
+    let length = value.chars().count();
+
+    if length > max_length {
+        Err(format!(
+            "{} values can't be longer than {} characters, this value has {}",
+            vr, max_length, length
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn validate_age_string(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let is_valid =
+        value.len() == 4 && is_digits(&value[0..3]) && matches!(&value[3..], "D" | "W" | "M" | "Y");
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' isn't a valid age, use the format nnnD/W/M/Y, e.g. 045Y",
+            value
+        ))
+    }
+}
+
+fn validate_date(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    // Date ranges are allowed when querying, and so also appear in stored data
+    if let Some((start, end)) = value.split_once('-') {
+        if !start.is_empty() {
+            validate_date(start)?;
+        }
+        if !end.is_empty() {
+            validate_date(end)?;
+        }
+
+        return Ok(());
+    }
+
+    let error = || format!("'{}' isn't a valid date, use the format YYYYMMDD", value);
+
+    if value.len() != 8 || !is_digits(value) {
+        return Err(error());
+    }
+
+    let month: u32 = value[4..6].parse().unwrap();
+    let day: u32 = value[6..8].parse().unwrap();
+    let year: u32 = value[0..4].parse().unwrap();
+
+    let is_leap_year = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year => 29,
+        2 => 28,
+        _ => return Err(error()),
+    };
+
+    if day == 0 || day > days_in_month {
+        return Err(error());
+    }
+
+    Ok(())
+}
+
+fn validate_time(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let error = || {
+        format!(
+            "'{}' isn't a valid time, use the format HHMMSS.FFFFFF",
+            value
+        )
+    };
+
+    let (hhmmss, fraction) = match value.split_once('.') {
+        Some((hhmmss, fraction)) => (hhmmss, Some(fraction)),
+        None => (value, None),
+    };
+
+    if !matches!(hhmmss.len(), 2 | 4 | 6) || !is_digits(hhmmss) {
+        return Err(error());
+    }
+
+    if let Some(fraction) = fraction
+        && (hhmmss.len() != 6 || fraction.is_empty() || fraction.len() > 6 || !is_digits(fraction))
+    {
+        return Err(error());
+    }
+
+    let hours: u32 = hhmmss[0..2].parse().unwrap();
+    let minutes: u32 = hhmmss.get(2..4).map_or(0, |s| s.parse().unwrap());
+    let seconds: u32 = hhmmss.get(4..6).map_or(0, |s| s.parse().unwrap());
+
+    // A seconds value of 60 is allowed for leap seconds
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return Err(error());
+    }
+
+    Ok(())
+}
+
+fn validate_date_time(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let error = || {
+        format!(
+            "'{}' isn't a valid date time, use the format YYYYMMDDHHMMSS.FFFFFF&ZZXX",
+            value
+        )
+    };
+
+    // Split off the UTC offset suffix
+    let (date_time, offset) = match value.find(['+', '-']) {
+        Some(index) => (&value[..index], Some(&value[index + 1..])),
+        None => (value, None),
+    };
+
+    if let Some(offset) = offset
+        && (offset.len() != 4 || !is_digits(offset))
+    {
+        return Err(error());
+    }
+
+    let digits = date_time.split('.').next().unwrap_or_default();
+    if !matches!(digits.len(), 4 | 6 | 8 | 10 | 12 | 14) || !is_digits(digits) {
+        return Err(error());
+    }
+
+    // Validate the date and time parts, filling in any omitted trailing date components
+    let date = format!(
+        "{}{}",
+        &digits[..digits.len().min(8)],
+        &"0101"[..8 - digits.len().min(8)]
+    );
+    validate_date(&date).map_err(|_| error())?;
+
+    if digits.len() > 8 {
+        validate_time(&date_time[8..]).map_err(|_| error())?;
+    } else if date_time.contains('.') {
+        return Err(error());
+    }
+
+    Ok(())
+}
+
+fn validate_decimal_string(value: &str) -> Result<(), String> {
+    check_length("DS", value.trim(), 16)?;
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let is_valid = value
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'))
+        && value.parse::<f64>().is_ok_and(|f| f.is_finite());
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' isn't a valid decimal number", value))
+    }
+}
+
+fn validate_integer_string(value: &str) -> Result<(), String> {
+    check_length("IS", value.trim(), 12)?;
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    match value.parse::<i64>() {
+        Ok(i) if i32::try_from(i).is_ok() => Ok(()),
+        Ok(_) => Err(format!("'{}' is outside the range of an IS value", value)),
+        Err(_) => Err(format!("'{}' isn't a valid integer", value)),
+    }
+}
+
+fn validate_person_name(value: &str) -> Result<(), String> {
+    let groups = value.split('=').collect::<Vec<_>>();
+    if groups.len() > 3 {
+        return Err(
+            "Person names can have at most three component groups: alphabetic, ideographic, and \
+             phonetic"
+                .into(),
+        );
+    }
+
+    for group in groups {
+        check_length("PN", group, 64)?;
+
+        if group.split('^').count() > 5 {
+            return Err(
+                "Person names can have at most five components: family name, given name, \
+                 middle name, prefix, and suffix"
+                    .into(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_uid(value: &str) -> Result<(), String> {
+    check_length("UI", value, 64)?;
+
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    for component in value.split('.') {
+        let is_valid = !component.is_empty()
+            && is_digits(component)
+            && (component == "0" || !component.starts_with('0'));
+
+        if !is_valid {
+            return Err(format!(
+                "'{}' isn't a valid UID, it must be digits separated by periods with no leading \
+                 zeros",
+                value
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(vr: &str, text: &str) -> Option<String> {
+        value_to_text(&text_to_value(vr, text).unwrap())
+    }
+
+    #[test]
+    fn string_values_are_padded_to_an_even_length() {
+        let value = text_to_value("CS", "ABC").unwrap();
+        assert_eq!(value.bytes().unwrap().to_vec(), b"ABC ");
+
+        let value = text_to_value("UI", "1.2.3").unwrap();
+        assert_eq!(value.bytes().unwrap().to_vec(), b"1.2.3\0");
+
+        assert_eq!(round_trip("CS", "ABC"), Some("ABC".into()));
+        assert_eq!(round_trip("UI", "1.2.3"), Some("1.2.3".into()));
+    }
+
+    #[test]
+    fn binary_values_round_trip() {
+        assert_eq!(round_trip("US", "1\\65535"), Some("1\\65535".into()));
+        assert_eq!(round_trip("SS", "-32768"), Some("-32768".into()));
+        assert_eq!(round_trip("SL", "-5\\7"), Some("-5\\7".into()));
+        assert_eq!(
+            round_trip("UV", "18446744073709551615"),
+            Some("18446744073709551615".into())
+        );
+        assert_eq!(round_trip("FL", "0.5"), Some("0.5".into()));
+        assert_eq!(round_trip("FD", "-1.25"), Some("-1.25".into()));
+        assert_eq!(round_trip("US", ""), Some("".into()));
+
+        let value = text_to_value("US", "258").unwrap();
+        assert_eq!(value.bytes().unwrap().to_vec(), [2, 1]);
+    }
+
+    #[test]
+    fn invalid_binary_values_are_rejected() {
+        assert!(text_to_value("US", "65536").is_err());
+        assert!(text_to_value("US", "-1").is_err());
+        assert!(text_to_value("SS", "1.5").is_err());
+        assert!(text_to_value("FL", "abc").is_err());
+        assert!(text_to_value("OB", "1").is_err());
+    }
+
+    #[test]
+    fn attribute_tags_round_trip() {
+        assert_eq!(
+            round_trip("AT", "(0010,0010)\\00200013"),
+            Some("(0010,0010)\\(0020,0013)".into())
+        );
+
+        let value = text_to_value("AT", "(0010,0020)").unwrap();
+        assert_eq!(value.bytes().unwrap().to_vec(), [0x10, 0x00, 0x20, 0x00]);
+
+        assert!(text_to_value("AT", "(0010,001)").is_err());
+        assert!(text_to_value("AT", "(0010,001G)").is_err());
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(
+            parse_tag("(7FE0,0010)"),
+            Ok(DataElementTag::new(0x7FE0, 0x0010))
+        );
+        assert_eq!(
+            parse_tag(" 7fe00010 "),
+            Ok(DataElementTag::new(0x7FE0, 0x0010))
+        );
+        assert_eq!(
+            parse_tag("7FE0, 0010"),
+            Ok(DataElementTag::new(0x7FE0, 0x0010))
+        );
+        assert!(parse_tag("7FE0001").is_err());
+        assert!(parse_tag("").is_err());
+    }
+
+    #[test]
+    fn only_known_vrs_are_editable() {
+        assert!(is_editable("PN"));
+        assert!(is_editable("FD"));
+        assert!(is_editable("AT"));
+        assert!(!is_editable("OB"));
+        assert!(!is_editable("SQ"));
+        assert!(!is_editable("UN"));
+    }
+
+    #[test]
+    fn unknown_vrs_are_rejected() {
+        assert!(text_to_value("XX", "1").is_err());
+    }
+
+    #[test]
+    fn validates_lengths() {
+        assert!(text_to_value("SH", &"A".repeat(16)).is_ok());
+        assert!(text_to_value("SH", &"A".repeat(17)).is_err());
+        assert!(text_to_value("LO", &"é".repeat(64)).is_ok());
+        assert!(text_to_value("LO", &"é".repeat(65)).is_err());
+        assert!(text_to_value("AE", &"A".repeat(17)).is_err());
+    }
+
+    #[test]
+    fn validates_each_of_multiple_values() {
+        assert!(text_to_value("CS", "ORIGINAL\\PRIMARY").is_ok());
+        assert_eq!(
+            text_to_value("CS", "ORIGINAL\\primary").unwrap_err(),
+            "Value 2: CS values may only contain A-Z, 0-9, space and underscore"
+        );
+    }
+
+    #[test]
+    fn text_vrs_allow_backslashes() {
+        assert!(text_to_value("LT", "a\\b").is_ok());
+        assert_eq!(round_trip("ST", "a\\b"), Some("a\\b".into()));
+        assert!(text_to_value("ST", &"a".repeat(1025)).is_err());
+    }
+
+    #[test]
+    fn control_characters_are_rejected() {
+        assert!(text_to_value("LO", "a\u{1}b").is_err());
+        assert!(text_to_value("LT", "line 1\r\nline 2\ttab").is_ok());
+    }
+
+    #[test]
+    fn non_ascii_characters_are_only_allowed_in_some_vrs() {
+        assert!(text_to_value("LO", "Müller").is_ok());
+        assert!(text_to_value("PN", "Müller^Jürgen").is_ok());
+        assert!(text_to_value("SH", "Müller").is_ok());
+        assert!(text_to_value("CS", "MÜLLER").is_err());
+        assert!(text_to_value("AE", "Müller").is_err());
+    }
+
+    #[test]
+    fn validates_code_strings() {
+        assert!(text_to_value("CS", "CT").is_ok());
+        assert!(text_to_value("CS", "DERIVED_2 A").is_ok());
+        assert!(text_to_value("CS", "ct").is_err());
+        assert!(text_to_value("CS", "A-B").is_err());
+    }
+
+    #[test]
+    fn validates_age_strings() {
+        assert!(text_to_value("AS", "045Y").is_ok());
+        assert!(text_to_value("AS", "003D").is_ok());
+        assert!(text_to_value("AS", "").is_ok());
+        assert!(text_to_value("AS", "45Y").is_err());
+        assert!(text_to_value("AS", "045X").is_err());
+    }
+
+    #[test]
+    fn validates_dates() {
+        assert!(text_to_value("DA", "20240229").is_ok());
+        assert!(text_to_value("DA", "20000229").is_ok());
+        assert!(text_to_value("DA", "20230229").is_err());
+        assert!(text_to_value("DA", "19000229").is_err());
+        assert!(text_to_value("DA", "20241301").is_err());
+        assert!(text_to_value("DA", "20240431").is_err());
+        assert!(text_to_value("DA", "20240100").is_err());
+        assert!(text_to_value("DA", "2024-01-01").is_err());
+        assert!(text_to_value("DA", "20240101-20241231").is_ok());
+        assert!(text_to_value("DA", "-20241231").is_ok());
+        assert!(text_to_value("DA", "20240101-").is_ok());
+        assert!(text_to_value("DA", "").is_ok());
+    }
+
+    #[test]
+    fn validates_times() {
+        assert!(text_to_value("TM", "23").is_ok());
+        assert!(text_to_value("TM", "2359").is_ok());
+        assert!(text_to_value("TM", "235960").is_ok());
+        assert!(text_to_value("TM", "235959.123456").is_ok());
+        assert!(text_to_value("TM", "240000").is_err());
+        assert!(text_to_value("TM", "2360").is_err());
+        assert!(text_to_value("TM", "235").is_err());
+        assert!(text_to_value("TM", "2359.5").is_err());
+        assert!(text_to_value("TM", "235959.").is_err());
+        assert!(text_to_value("TM", "235959.1234567").is_err());
+    }
+
+    #[test]
+    fn validates_date_times() {
+        assert!(text_to_value("DT", "2024").is_ok());
+        assert!(text_to_value("DT", "202402").is_ok());
+        assert!(text_to_value("DT", "20240229123000.5").is_ok());
+        assert!(text_to_value("DT", "20240229123000+1000").is_ok());
+        assert!(text_to_value("DT", "20240229123000-0500").is_ok());
+        assert!(text_to_value("DT", "20240229123000+10").is_err());
+        assert!(text_to_value("DT", "202413").is_err());
+        assert!(text_to_value("DT", "2024022925").is_err());
+        assert!(text_to_value("DT", "20240229.5").is_err());
+        assert!(text_to_value("DT", "20240").is_err());
+    }
+
+    #[test]
+    fn validates_decimal_strings() {
+        assert!(text_to_value("DS", "1.5").is_ok());
+        assert!(text_to_value("DS", "-1.5e-3").is_ok());
+        assert!(text_to_value("DS", " 12 ").is_ok());
+        assert!(text_to_value("DS", "1,5").is_err());
+        assert!(text_to_value("DS", "inf").is_err());
+        assert!(text_to_value("DS", "1e999").is_err());
+        assert!(text_to_value("DS", "12345678901234567").is_err());
+    }
+
+    #[test]
+    fn validates_integer_strings() {
+        assert!(text_to_value("IS", "-2147483648").is_ok());
+        assert!(text_to_value("IS", "+12").is_ok());
+        assert_eq!(
+            text_to_value("IS", "2147483648").unwrap_err(),
+            "'2147483648' is outside the range of an IS value"
+        );
+        assert!(text_to_value("IS", "1.0").is_err());
+    }
+
+    #[test]
+    fn validates_person_names() {
+        assert!(text_to_value("PN", "Doe^Jane^^Dr^PhD").is_ok());
+        assert!(text_to_value("PN", "Doe^Jane=Ideo=Phon").is_ok());
+        assert!(text_to_value("PN", "A=B=C=D").is_err());
+        assert!(text_to_value("PN", "A^B^C^D^E^F").is_err());
+        assert!(text_to_value("PN", &"A".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn validates_uids() {
+        assert!(text_to_value("UI", "1.2.840.10008.1.2").is_ok());
+        assert!(text_to_value("UI", "1.0.3").is_ok());
+        assert!(text_to_value("UI", "1.02.3").is_err());
+        assert!(text_to_value("UI", "1..3").is_err());
+        assert!(text_to_value("UI", "1.2.").is_err());
+        assert!(text_to_value("UI", "1.a").is_err());
+        assert!(text_to_value("UI", &format!("1.{}", "2".repeat(63))).is_err());
+    }
+
+    #[test]
+    fn unvalidated_values_skip_validation() {
+        assert!(text_to_value("DA", "not a date").is_err());
+
+        let value = text_to_value_unvalidated("DA", "not a date").unwrap();
+        assert_eq!(value_to_text(&value), Some("not a date".into()));
+
+        assert!(text_to_value_unvalidated("US", "abc").is_err());
+    }
+}
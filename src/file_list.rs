@@ -0,0 +1,636 @@
+use std::{rc::Rc, time::Duration};
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+use dioxus_elements::FileData;
+use futures_util::StreamExt;
+
+use crate::{
+    ViewMode,
+    data_set_grid::GridExpansion,
+    dicomdir,
+    editing::History,
+    jobs::{self, JobError, WorkerDataSet},
+    source_type::{self, *},
+    study_browser::*,
+    ui, utils,
+};
+
+/// A file that has been opened. The active file's state is held in the app's signals while it's
+/// being viewed, and is moved back into its entry here when a different file is selected.
+///
+pub struct OpenedFile {
+    /// A unique ID for the file that doesn't change when other files are closed.
+    pub id: u64,
+
+    pub filename: String,
+
+    /// Identifies the file by its name and size, and is used to remember the file's grid
+    /// expansion if it's closed and opened again.
+    pub key: String,
+
+    /// The file as provided by the browser. Its content is only read in full when it's viewed.
+    pub file_data: FileData,
+
+    /// The file's data set without its pixel data, which is read for every opened file so that it
+    /// can be shown in the file list and study browser. This is `None` while it's being read.
+    pub header: Option<Result<DataSet, String>>,
+
+    /// The file's detected format, which is set once it has been read in full. Its content isn't
+    /// kept, and is read again from `file_data` when it's needed.
+    pub sniffed_file: Option<SniffedFile>,
+    pub read_status: ReadStatus,
+
+    pub state: FileState,
+}
+
+/// Whether an opened file's content has been read in full.
+///
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReadStatus {
+    Unread,
+    Reading,
+    Read,
+}
+
+/// The state of an opened file that's shown when it's the active file.
+///
+#[derive(Clone, Default)]
+pub struct FileState {
+    /// The header of the file's data set, which leaves out its Pixel Data and other large values
+    /// that are only held in the job worker.
+    pub data_set: DataSet,
+
+    /// The data set held in the job worker, which jobs that use the data set run on. Edits
+    /// recorded in `history` are applied to it as they're made. This is `None` if the file hasn't
+    /// been read.
+    pub worker_data_set: Option<Rc<WorkerDataSet>>,
+
+    pub source_type: DataSetSourceType,
+    pub error_lines: Vec<String>,
+    pub history: History,
+    pub grid_expansion: GridExpansion,
+    pub view_mode: ViewMode,
+}
+
+impl FileState {
+    /// Reads a file's content in the given format in the job worker.
+    ///
+    pub async fn read(bytes: &[u8], source_type: DataSetSourceType) -> Self {
+        let (worker_data_set, result) = WorkerDataSet::read(bytes, source_type).await;
+
+        Self::new(result, worker_data_set, source_type)
+    }
+
+    /// Creates the state for a file from the result of reading it into the job worker. The view
+    /// state is left at its default, except that DICOMDIR files are shown as a directory.
+    ///
+    fn new(
+        result: Result<DataSet, (DataSet, Vec<String>)>,
+        worker_data_set: WorkerDataSet,
+        source_type: DataSetSourceType,
+    ) -> Self {
+        let (data_set, error_lines) = match result {
+            Ok(data_set) => (data_set, vec![]),
+            Err(e) => e,
+        };
+
+        let view_mode = if dicomdir::is_dicomdir(&data_set) {
+            ViewMode::Directory
+        } else {
+            ViewMode::default()
+        };
+
+        Self {
+            data_set,
+            worker_data_set: Some(Rc::new(worker_data_set)),
+            source_type,
+            error_lines,
+            view_mode,
+            ..Self::default()
+        }
+    }
+
+    fn failed(error: String) -> Self {
+        Self {
+            error_lines: vec![error],
+            ..Self::default()
+        }
+    }
+}
+
+/// The progress of reading a file in full.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReadProgress {
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+
+impl ReadProgress {
+    /// Returns the percentage of the file that has been read.
+    ///
+    pub fn percent(self) -> f64 {
+        if self.total_bytes == 0 {
+            100.0
+        } else {
+            self.bytes_read as f64 * 100.0 / self.total_bytes as f64
+        }
+    }
+}
+
+/// An update on a file that's being read in full.
+///
+pub enum ReadUpdate {
+    Progress(ReadProgress),
+
+    /// The file's data set up to its pixel data, which is available before the pixel data has
+    /// been read.
+    Header(DataSet),
+}
+
+/// The number of bytes read between updates on a file's read progress. Reading yields to the
+/// browser after each update so the page stays responsive while large files are read.
+///
+const READ_UPDATE_INTERVAL: u64 = 4 * 1024 * 1024;
+
+/// Reads a file in full into the job worker, detecting its format from its content. Returns the
+/// file's name with any `.gz` extension removed, its detected format, and its state.
+///
+/// The file is read in chunks, and gzip-compressed files are decompressed a chunk at a time as
+/// they're read. DICOM P10 files are passed to the job worker a chunk at a time, so that only the
+/// bytes needed to detect the format are held on to here, while other formats are collected and
+/// then read. Reading stops early if `is_cancelled` returns true, in which case the data set read
+/// so far is returned along with an error.
+///
+pub async fn read_file(
+    file_data: &FileData,
+    mut on_update: impl FnMut(ReadUpdate),
+    is_cancelled: impl Fn() -> bool,
+) -> (String, Option<SniffedFile>, FileState) {
+    let filename = file_data.name();
+    let total_bytes = file_data.size();
+
+    let mut stream = file_data.byte_stream();
+
+    // Bytes are collected until there are enough to detect the format. After that, the content of
+    // P10 files is passed to the worker a chunk at a time, and other formats keep being collected.
+    let mut bytes = vec![];
+    let mut sniffed_file = None::<SniffedFile>;
+    let mut content_decoder = None::<ContentDecoder>;
+    let mut content = vec![];
+    let mut p10_reader = None::<WorkerDataSet>;
+    let mut bytes_read = 0;
+    let mut next_update = READ_UPDATE_INTERVAL;
+
+    loop {
+        let (chunk, is_last) = match stream.next().await {
+            Some(Ok(chunk)) => (Vec::from(chunk), false),
+            Some(Err(_)) => {
+                return (
+                    filename,
+                    None,
+                    FileState::failed("Failed reading file".into()),
+                );
+            }
+            None => (vec![], true),
+        };
+
+        bytes_read += chunk.len() as u64;
+
+        let chunk = if sniffed_file.is_none() {
+            if bytes.is_empty() {
+                bytes = chunk;
+            } else {
+                bytes.extend_from_slice(&chunk);
+            }
+
+            if bytes.len() < source_type::SNIFF_LENGTH && !is_last {
+                continue;
+            }
+
+            match source_type::sniff(&bytes, &filename) {
+                Ok(sniffed) => {
+                    if sniffed.source_type == DataSetSourceType::P10 {
+                        p10_reader = Some(WorkerDataSet::read_p10());
+                    }
+
+                    content_decoder = Some(sniffed.content_decoder());
+                    sniffed_file = Some(sniffed);
+                }
+
+                Err(e) => return (filename, None, FileState::failed(e)),
+            }
+
+            // The bytes collected so far are the first ones to be decoded
+            std::mem::take(&mut bytes)
+        } else {
+            chunk
+        };
+
+        // The decoder is always created when the format is detected
+        let Some(decoder) = content_decoder.as_mut() else {
+            continue;
+        };
+
+        let decoded = decoder.decode(chunk).and_then(|mut decoded| {
+            if is_last && let Some(decoder) = content_decoder.take() {
+                decoded.extend(decoder.finish()?);
+            }
+
+            Ok(decoded)
+        });
+
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => return (filename, None, stop_reading(p10_reader, e).await),
+        };
+
+        match p10_reader.as_ref() {
+            Some(reader) if !decoded.is_empty() => match reader.read_p10_chunk(&decoded).await {
+                Ok(Some(header)) => on_update(ReadUpdate::Header(header)),
+                Ok(None) => (),
+
+                // Errors reading the file are reported when reading finishes, so this is only
+                // reached if the job worker failed
+                Err(JobError::Failed(e)) => return (filename, None, FileState::failed(e)),
+                Err(JobError::Cancelled) => (),
+            },
+            Some(_) => (),
+            None => content.extend_from_slice(&decoded),
+        }
+
+        if is_last {
+            break;
+        }
+
+        if bytes_read >= next_update {
+            next_update += READ_UPDATE_INTERVAL;
+
+            on_update(ReadUpdate::Progress(ReadProgress {
+                bytes_read,
+                total_bytes,
+            }));
+
+            gloo_timers::future::sleep(Duration::ZERO).await;
+        }
+
+        if is_cancelled() {
+            let error = format!(
+                "Reading was cancelled after {} of {}",
+                utils::format_size(bytes_read),
+                utils::format_size(total_bytes)
+            );
+
+            return (filename, None, stop_reading(p10_reader, error).await);
+        }
+    }
+
+    on_update(ReadUpdate::Progress(ReadProgress {
+        bytes_read,
+        total_bytes,
+    }));
+
+    // The format is always detected by the time the last chunk has been read
+    let Some(sniffed_file) = sniffed_file else {
+        return (
+            filename,
+            None,
+            FileState::failed("Failed reading file".into()),
+        );
+    };
+
+    let state = match p10_reader {
+        Some(reader) => {
+            let (worker_data_set, result) = reader.finish_read_p10().await;
+            FileState::new(result, worker_data_set, DataSetSourceType::P10)
+        }
+        None => FileState::read(&content, sniffed_file.source_type).await,
+    };
+
+    (sniffed_file.filename.clone(), Some(sniffed_file), state)
+}
+
+/// Returns the state of a file whose reading stopped before the end, which holds the data set read
+/// so far if it's a DICOM P10 file, along with the error.
+///
+async fn stop_reading(p10_reader: Option<WorkerDataSet>, error: String) -> FileState {
+    let mut state = match p10_reader {
+        Some(reader) => {
+            let (worker_data_set, result) = reader.finish_read_p10().await;
+            FileState::new(result, worker_data_set, DataSetSourceType::P10)
+        }
+        None => FileState::default(),
+    };
+
+    state.error_lines = vec![error];
+
+    state
+}
+
+/// Reads the content of a file that has already had its format detected, decompressing it if
+/// needed. The file is read and decompressed in chunks, yielding to the browser between them.
+///
+pub async fn read_file_content(
+    file_data: &FileData,
+    sniffed_file: &SniffedFile,
+) -> Result<Vec<u8>, String> {
+    let mut stream = file_data.byte_stream();
+    let mut decoder = sniffed_file.content_decoder();
+    let mut content = vec![];
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| "Failed reading file".to_string())?;
+        content.extend(decoder.decode(Vec::from(chunk))?);
+
+        gloo_timers::future::sleep(Duration::ZERO).await;
+    }
+
+    content.extend(decoder.finish()?);
+
+    Ok(content)
+}
+
+/// Shows the progress of reading the active file, with a button to cancel reading it.
+///
+#[component]
+pub fn ReadProgressBar(progress: Option<ReadProgress>, on_cancel: EventHandler<()>) -> Element {
+    rsx! {
+        div {
+            class: "read-progress",
+
+            if let Some(progress) = progress {
+                progress { max: 100, value: "{progress.percent():.1}" }
+                span {
+                    class: "read-progress-text",
+
+                    "{utils::format_size(progress.bytes_read)} of {utils::format_size(progress.total_bytes)}"
+                }
+            } else {
+                progress {}
+                span { class: "read-progress-text", "Reading…" }
+            }
+
+            button { onclick: move |_| on_cancel.call(()), "Cancel" }
+        }
+    }
+}
+
+/// Reads a file's data set without its pixel data.
+///
+/// DICOM P10 files are streamed to a header reader that stops at the root Pixel Data data element,
+/// so the rest of the file isn't read. Other formats, including gzip-compressed DICOM P10, can't
+/// stop reading at the pixel data, so they are read in full in the job worker and then have their
+/// pixel data removed.
+///
+pub async fn read_header(file_data: &FileData) -> Result<DataSet, String> {
+    let filename = file_data.name();
+    let mut stream = file_data.byte_stream();
+
+    let mut bytes = vec![];
+    let mut sniffed_file = None::<SniffedFile>;
+    let mut header_reader = None::<P10HeaderReader>;
+
+    loop {
+        let (chunk, is_last) = match stream.next().await {
+            Some(Ok(chunk)) => (Vec::from(chunk), false),
+            Some(Err(_)) => return Err("Failed reading file".into()),
+            None => (vec![], true),
+        };
+
+        let chunk = if sniffed_file.is_none() {
+            if bytes.is_empty() {
+                bytes = chunk;
+            } else {
+                bytes.extend_from_slice(&chunk);
+            }
+
+            if bytes.len() < source_type::SNIFF_LENGTH && !is_last {
+                continue;
+            }
+
+            let sniffed = source_type::sniff(&bytes, &filename)?;
+            if sniffed.source_type == DataSetSourceType::P10 && !sniffed.is_gzip {
+                header_reader = Some(P10HeaderReader::new());
+            }
+            sniffed_file = Some(sniffed);
+
+            if header_reader.is_some() {
+                std::mem::take(&mut bytes)
+            } else {
+                vec![]
+            }
+        } else if header_reader.is_none() {
+            bytes.extend_from_slice(&chunk);
+            vec![]
+        } else {
+            chunk
+        };
+
+        if let Some(reader) = header_reader.as_mut()
+            && let Some(header) = reader.write(chunk, is_last)?
+        {
+            return Ok(header);
+        }
+
+        if is_last {
+            break;
+        }
+    }
+
+    let Some(sniffed_file) = sniffed_file else {
+        return Err("Failed reading file".into());
+    };
+    let bytes = sniffed_file.content(bytes)?;
+
+    let mut data_set = jobs::read_data_set(&bytes, sniffed_file.source_type)
+        .await
+        .map_err(|(_, lines)| lines.join("\n"))?;
+    data_set.delete(dictionary::PIXEL_DATA.tag);
+
+    Ok(data_set)
+}
+
+/// The details of an opened file that are shown in the file list.
+///
+#[derive(Clone, PartialEq)]
+pub struct FileListEntry {
+    pub id: u64,
+    pub filename: String,
+    pub modality: Option<String>,
+    pub sop_class: Option<String>,
+    pub status: FileStatus,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileStatus {
+    Loading,
+    Loaded,
+    Failed,
+}
+
+impl FileListEntry {
+    /// Creates the file list entry for an opened file. The data set and errors of the active file
+    /// are passed in because its state is held in the app's signals rather than in the file.
+    ///
+    pub fn new(file: &OpenedFile, active_state: Option<(&DataSet, &[String])>) -> Self {
+        let (data_set, error_lines) =
+            active_state.unwrap_or((&file.state.data_set, file.state.error_lines.as_slice()));
+
+        // Until the file has been read in full its header is used
+        let empty_data_set = DataSet::new();
+        let data_set = match (&file.header, file.read_status) {
+            (_, ReadStatus::Read) => data_set,
+            (Some(Ok(header)), _) => header,
+            _ => &empty_data_set,
+        };
+
+        let status = match (&file.header, file.read_status) {
+            (_, ReadStatus::Reading) | (None, ReadStatus::Unread) => FileStatus::Loading,
+            (_, ReadStatus::Read) if !error_lines.is_empty() => FileStatus::Failed,
+            (Some(Err(_)), ReadStatus::Unread) => FileStatus::Failed,
+            _ => FileStatus::Loaded,
+        };
+
+        let sop_class = data_set
+            .get_string(dictionary::SOP_CLASS_UID.tag)
+            .ok()
+            .map(|uid| {
+                dictionary::uid_name(uid)
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|_| uid.to_string())
+            });
+
+        Self {
+            id: file.id,
+            filename: file.filename.clone(),
+            modality: data_set
+                .get_string(dictionary::MODALITY.tag)
+                .ok()
+                .map(|modality| modality.to_string()),
+            sop_class,
+            status,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileListTab {
+    Files,
+    Studies,
+}
+
+/// A sidebar that lists the opened files, either as a flat list or organized by patient, study,
+/// and series, and allows switching between them, closing them, and opening more.
+///
+#[component]
+pub fn FileList(
+    files: Signal<Vec<OpenedFile>>,
+    entries: Vec<FileListEntry>,
+    active_file_id: Option<u64>,
+    selected_node: Signal<Option<NodeKey>>,
+    on_select: EventHandler<u64>,
+    on_close: EventHandler<u64>,
+    on_open_files: EventHandler<Vec<FileData>>,
+) -> Element {
+    let mut tab = use_signal(|| FileListTab::Files);
+
+    let file_count = entries.len();
+
+    rsx! {
+        div {
+            class: "file-list",
+
+            div {
+                class: "file-list-header",
+
+                div {
+                    class: "file-list-tab",
+                    class: if tab() == FileListTab::Files { "selected" },
+                    onclick: move |_| tab.set(FileListTab::Files),
+
+                    "Files ({file_count})"
+                }
+                div {
+                    class: "file-list-tab",
+                    class: if tab() == FileListTab::Studies { "selected" },
+                    onclick: move |_| tab.set(FileListTab::Studies),
+
+                    "Studies"
+                }
+
+                label {
+                    class: "file-list-open",
+                    title: "Open more files",
+
+                    ui::FontAwesomeIcon { icon: "plus", style: "solid" }
+
+                    input {
+                        r#type: "file",
+                        multiple: true,
+                        onchange: move |event| on_open_files.call(event.files()),
+                    }
+                }
+            }
+
+            if tab() == FileListTab::Studies {
+                StudyBrowser { files, active_file_id, selected_node, on_select_file: on_select }
+            } else {
+                for entry in entries {
+                    div {
+                        key: "{entry.id}",
+                        class: "file-list-entry",
+                        class: if selected_node.read().is_none() && active_file_id == Some(entry.id) { "active" },
+                        class: if entry.status == FileStatus::Failed { "failed" },
+
+                        onclick: move |_| {
+                            selected_node.set(None);
+                            on_select.call(entry.id);
+                        },
+
+                        div {
+                            class: "file-list-entry-name",
+                            title: "{entry.filename}",
+
+                            match entry.status {
+                                FileStatus::Loading => rsx! {
+                                    ui::FontAwesomeIcon { icon: "spinner", style: "solid" }
+                                },
+                                FileStatus::Loaded => rsx! {
+                                    ui::FontAwesomeIcon { icon: "file", style: "regular" }
+                                },
+                                FileStatus::Failed => rsx! {
+                                    ui::FontAwesomeIcon { icon: "triangle-exclamation", style: "solid" }
+                                },
+                            }
+
+                            span { {entry.filename.clone()} }
+
+                            div {
+                                class: "close-icon",
+                                title: "Close",
+                                onclick: move |event| {
+                                    event.stop_propagation();
+                                    on_close.call(entry.id);
+                                },
+
+                                ui::FontAwesomeIcon { icon: "close", style: "solid" }
+                            }
+                        }
+
+                        div {
+                            class: "file-list-entry-details",
+
+                            if let Some(modality) = &entry.modality {
+                                span { class: "modality", {modality.clone()} }
+                            }
+                            if let Some(sop_class) = &entry.sop_class {
+                                span { title: "{sop_class}", {sop_class.clone()} }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
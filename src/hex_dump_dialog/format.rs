@@ -0,0 +1,303 @@
+//! Formatting of a binary value's bytes as lines of a hex dump, optionally interpreting them as
+//! numbers of a given type and byte order.
+
+/// The number of bytes shown on each line of the hex dump.
+///
+pub const BYTES_PER_LINE: usize = 16;
+
+/// The types the bytes of a value can be interpreted as.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Interpretation {
+    #[default]
+    Bytes,
+    U16,
+    I16,
+    F32,
+    F64,
+}
+
+impl Interpretation {
+    pub const ALL: [Self; 5] = [Self::Bytes, Self::U16, Self::I16, Self::F32, Self::F64];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bytes => "Bytes",
+            Self::U16 => "u16",
+            Self::I16 => "i16",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    /// The number of bytes taken up by a single value of this type.
+    ///
+    pub fn size(self) -> usize {
+        match self {
+            Self::Bytes => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Returns the interpretation that suits the given value representation, e.g. OW values are
+    /// shown as u16s.
+    ///
+    pub fn for_value_representation(vr: &str) -> Self {
+        match vr {
+            "OW" => Self::U16,
+            "OF" => Self::F32,
+            "OD" => Self::F64,
+            _ => Self::Bytes,
+        }
+    }
+}
+
+/// The byte order used when interpreting bytes as a type wider than a byte.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ByteOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    pub const ALL: [Self; 2] = [Self::LittleEndian, Self::BigEndian];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LittleEndian => "Little endian",
+            Self::BigEndian => "Big endian",
+        }
+    }
+}
+
+/// A single line of a hex dump.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexDumpLine {
+    pub offset: usize,
+
+    /// The line's bytes in hex, or the values they hold when interpreted as a wider type.
+    pub values: String,
+
+    /// The line's bytes as ASCII, with non-printable bytes shown as '.'.
+    pub ascii: String,
+}
+
+/// Formats the lines of a hex dump of the given bytes, which start at `offset` in the value. The
+/// offset must be a multiple of [`BYTES_PER_LINE`] so that values never span two lines.
+///
+pub fn lines(
+    bytes: &[u8],
+    offset: usize,
+    interpretation: Interpretation,
+    byte_order: ByteOrder,
+) -> Vec<HexDumpLine> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(index, line)| HexDumpLine {
+            offset: offset + index * BYTES_PER_LINE,
+            values: format_values(line, interpretation, byte_order),
+            ascii: line
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        char::from(*byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Formats the bytes of a single line. Trailing bytes that don't make up a whole value of the
+/// interpreted type are shown in hex.
+///
+fn format_values(line: &[u8], interpretation: Interpretation, byte_order: ByteOrder) -> String {
+    let size = interpretation.size();
+    let whole_values_length = line.len() / size * size;
+
+    let mut values = line[..whole_values_length]
+        .chunks_exact(size)
+        .map(|value| format_value(value, interpretation, byte_order))
+        .collect::<Vec<_>>();
+
+    values.extend(
+        line[whole_values_length..]
+            .iter()
+            .map(|byte| format!("{:02X}", byte)),
+    );
+
+    values.join(" ")
+}
+
+fn format_value(value: &[u8], interpretation: Interpretation, byte_order: ByteOrder) -> String {
+    macro_rules! read {
+        ($type:ty) => {{
+            let bytes = value.try_into().unwrap();
+            match byte_order {
+                ByteOrder::LittleEndian => <$type>::from_le_bytes(bytes),
+                ByteOrder::BigEndian => <$type>::from_be_bytes(bytes),
+            }
+        }};
+    }
+
+    match interpretation {
+        Interpretation::Bytes => format!("{:02X}", value[0]),
+        Interpretation::U16 => format!("{:>5}", read!(u16)),
+        Interpretation::I16 => format!("{:>6}", read!(i16)),
+        Interpretation::F32 => format!("{:>14}", read!(f32)),
+        Interpretation::F64 => format!("{:>24}", read!(f64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(bytes: &[u8], interpretation: Interpretation, byte_order: ByteOrder) -> String {
+        format_values(bytes, interpretation, byte_order)
+    }
+
+    #[test]
+    fn bytes_are_shown_in_hex() {
+        assert_eq!(
+            values(
+                &[0x00, 0x7F, 0xAB],
+                Interpretation::Bytes,
+                ByteOrder::BigEndian
+            ),
+            "00 7F AB"
+        );
+    }
+
+    #[test]
+    fn u16_values_in_both_byte_orders() {
+        let bytes = [0x01, 0x02, 0xFF, 0xFF];
+
+        assert_eq!(
+            values(&bytes, Interpretation::U16, ByteOrder::LittleEndian),
+            "  513 65535"
+        );
+        assert_eq!(
+            values(&bytes, Interpretation::U16, ByteOrder::BigEndian),
+            "  258 65535"
+        );
+    }
+
+    #[test]
+    fn i16_values_in_both_byte_orders() {
+        let bytes = [0xFE, 0xFF, 0x00, 0x80];
+
+        assert_eq!(
+            values(&bytes, Interpretation::I16, ByteOrder::LittleEndian),
+            "    -2 -32768"
+        );
+        assert_eq!(
+            values(&bytes, Interpretation::I16, ByteOrder::BigEndian),
+            "  -257    128"
+        );
+    }
+
+    #[test]
+    fn f32_values_in_both_byte_orders() {
+        assert_eq!(
+            values(
+                &1.5f32.to_le_bytes(),
+                Interpretation::F32,
+                ByteOrder::LittleEndian
+            ),
+            format!("{:>14}", "1.5")
+        );
+        assert_eq!(
+            values(
+                &(-0.25f32).to_be_bytes(),
+                Interpretation::F32,
+                ByteOrder::BigEndian
+            ),
+            format!("{:>14}", "-0.25")
+        );
+    }
+
+    #[test]
+    fn f64_values_in_both_byte_orders() {
+        assert_eq!(
+            values(
+                &1e100f64.to_le_bytes(),
+                Interpretation::F64,
+                ByteOrder::LittleEndian
+            ),
+            format!("{:>24}", 1e100f64)
+        );
+        assert_eq!(
+            values(
+                &(-2.5f64).to_be_bytes(),
+                Interpretation::F64,
+                ByteOrder::BigEndian
+            ),
+            format!("{:>24}", "-2.5")
+        );
+    }
+
+    #[test]
+    fn trailing_partial_values_are_shown_in_hex() {
+        assert_eq!(
+            values(
+                &[0x01, 0x00, 0x02, 0x00, 0xAB],
+                Interpretation::U16,
+                ByteOrder::LittleEndian
+            ),
+            "    1     2 AB"
+        );
+        assert_eq!(
+            values(
+                &[0x00, 0x00, 0x80, 0x3F, 0x01, 0x02, 0x03],
+                Interpretation::F32,
+                ByteOrder::LittleEndian
+            ),
+            format!("{:>14} 01 02 03", "1")
+        );
+        assert_eq!(
+            values(&[0xCD], Interpretation::F64, ByteOrder::LittleEndian),
+            "CD"
+        );
+    }
+
+    #[test]
+    fn lines_continue_the_offset_of_the_page() {
+        let bytes = (0..40).map(|i| b'A' + i).collect::<Vec<_>>();
+
+        let lines = lines(
+            &bytes,
+            3 * BYTES_PER_LINE,
+            Interpretation::Bytes,
+            ByteOrder::LittleEndian,
+        );
+
+        assert_eq!(
+            lines.iter().map(|line| line.offset).collect::<Vec<_>>(),
+            vec![48, 64, 80]
+        );
+        assert_eq!(lines[0].ascii, "ABCDEFGHIJKLMNOP");
+        assert_eq!(lines[2].ascii, "abcdefgh");
+        assert_eq!(lines[2].values, "61 62 63 64 65 66 67 68");
+    }
+
+    #[test]
+    fn non_printable_bytes_are_dots_in_ascii() {
+        let lines = lines(
+            &[b'a', b' ', 0x00, 0x7F, 0xE9],
+            0,
+            Interpretation::Bytes,
+            ByteOrder::LittleEndian,
+        );
+
+        assert_eq!(lines[0].ascii, "a ...");
+    }
+}
@@ -0,0 +1,252 @@
+mod format;
+
+use std::rc::Rc;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{
+    editing,
+    jobs::{JobError, WorkerDataSet},
+    ui::{self, FontAwesomeIcon},
+    utils,
+};
+use format::{BYTES_PER_LINE, ByteOrder, Interpretation};
+
+/// The number of bytes shown on each page of the hex dump. Large values are paged so that only a
+/// manageable number of lines are rendered at a time.
+///
+const PAGE_SIZE: usize = 256 * BYTES_PER_LINE;
+
+/// The value representations whose values are shown in the hex dump.
+///
+const BINARY_VALUE_REPRESENTATIONS: [&str; 7] = ["OB", "OD", "OF", "OL", "OV", "OW", "UN"];
+
+/// Returns whether a data element value can be viewed in the hex dump, which is the case for
+/// binary values that aren't encapsulated.
+///
+pub fn is_viewable(value: &DataElementValue) -> bool {
+    BINARY_VALUE_REPRESENTATIONS.contains(&value.value_representation().to_string().as_str())
+        && value.bytes().is_ok()
+}
+
+/// A dialog that shows the bytes of the data element value at the given path as a hex dump, and
+/// allows them to be downloaded. Values that were left out of the data set's header are read from
+/// the job worker when the dialog opens.
+///
+#[component]
+pub fn HexDumpDialog(
+    data_set: Signal<DataSet>,
+    worker_data_set: ReadSignal<Option<Rc<WorkerDataSet>>>,
+    path: DataSetPath,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut page = use_signal(|| 0usize);
+    let mut interpretation = use_signal(|| {
+        editing::get_value(&data_set.peek(), &path)
+            .map(|value| {
+                Interpretation::for_value_representation(&value.value_representation().to_string())
+            })
+            .unwrap_or_default()
+    });
+    let mut byte_order = use_signal(ByteOrder::default);
+
+    let omitted_value_path = path.clone();
+    let omitted_value = use_resource(move || {
+        let path = omitted_value_path.clone();
+
+        async move {
+            let worker_data_set = worker_data_set.peek().clone()?;
+            worker_data_set.omitted_value(&data_set.peek(), &path)?;
+
+            Some(worker_data_set.read_value(&path).await)
+        }
+    });
+
+    let Ok((_, tag)) = editing::split_data_element_path(&path) else {
+        return rsx! {};
+    };
+
+    let is_omitted = worker_data_set
+        .read()
+        .as_ref()
+        .is_some_and(|worker_data_set| {
+            worker_data_set
+                .omitted_value(&data_set.read(), &path)
+                .is_some()
+        });
+
+    let value = if is_omitted {
+        match &*omitted_value.read() {
+            Some(Some(Ok(value))) => Some(value.clone()),
+            Some(Some(Err(JobError::Failed(e)))) => {
+                return rsx! {
+                    ui::Dialog {
+                        title: dictionary::tag_name(tag, None),
+                        on_close,
+
+                        p { "Reading the value failed. {e}" }
+                    }
+                };
+            }
+            _ => {
+                return rsx! {
+                    ui::Dialog {
+                        title: dictionary::tag_name(tag, None),
+                        on_close,
+
+                        p { "Reading the value…" }
+                    }
+                };
+            }
+        }
+    } else {
+        editing::get_value(&data_set.read(), &path)
+    };
+
+    // The value may have been deleted or replaced by an undo while the dialog was open
+    let Some(value) = value.filter(is_viewable) else {
+        return rsx! {
+            ui::Dialog {
+                title: dictionary::tag_name(tag, None),
+                on_close,
+
+                p { "This data element no longer has a binary value." }
+            }
+        };
+    };
+
+    let bytes = value.bytes().map_or(&[][..], |bytes| &bytes[..]);
+
+    let page_count = bytes.len().div_ceil(PAGE_SIZE).max(1);
+    let page_index = page().min(page_count - 1);
+    let page_start = page_index * PAGE_SIZE;
+    let page_end = (page_start + PAGE_SIZE).min(bytes.len());
+
+    let lines = format::lines(
+        &bytes[page_start..page_end],
+        page_start,
+        interpretation(),
+        byte_order(),
+    );
+
+    let download = {
+        let value = value.clone();
+        move |_| {
+            let Ok(bytes) = value.bytes() else {
+                return;
+            };
+
+            let filename = format!("{:04X}{:04X}.bin", tag.group, tag.element);
+            let blob_parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&bytes[..]));
+
+            utils::download::trigger(blob_parts, &filename, "application/octet-stream").unwrap();
+        }
+    };
+
+    rsx! {
+        ui::Dialog {
+            title: dictionary::tag_name(tag, None),
+            on_close,
+
+            div {
+                class: "hex-dump-toolbar",
+
+                label {
+                    "Interpret as"
+
+                    select {
+                        onchange: move |event| {
+                            if let Some(option) = Interpretation::ALL
+                                .into_iter()
+                                .find(|option| option.name() == event.value())
+                            {
+                                interpretation.set(option);
+                            }
+                        },
+
+                        for option in Interpretation::ALL {
+                            option {
+                                value: option.name(),
+                                selected: option == interpretation(),
+
+                                {option.name()}
+                            }
+                        }
+                    }
+                }
+
+                select {
+                    disabled: interpretation() == Interpretation::Bytes,
+                    onchange: move |event| {
+                        if let Some(option) = ByteOrder::ALL
+                            .into_iter()
+                            .find(|option| option.name() == event.value())
+                        {
+                            byte_order.set(option);
+                        }
+                    },
+
+                    for option in ByteOrder::ALL {
+                        option {
+                            value: option.name(),
+                            selected: option == byte_order(),
+
+                            {option.name()}
+                        }
+                    }
+                }
+
+                div { class: "toolbar-spacer" }
+
+                if page_count > 1 {
+                    button {
+                        title: "Previous page",
+                        disabled: page_index == 0,
+                        onclick: move |_| page.set(page_index.saturating_sub(1)),
+
+                        FontAwesomeIcon { icon: "chevron-left", style: "solid" }
+                    }
+
+                    span { "Page {page_index + 1} of {page_count}" }
+
+                    button {
+                        title: "Next page",
+                        disabled: page_index + 1 == page_count,
+                        onclick: move |_| page.set(page_index + 1),
+
+                        FontAwesomeIcon { icon: "chevron-right", style: "solid" }
+                    }
+                }
+            }
+
+            div {
+                class: "hex-dump",
+
+                for line in lines {
+                    div { class: "offset", {format!("{:08X}", line.offset)} }
+                    div { class: "values", {line.values} }
+                    div { class: "ascii", {line.ascii} }
+                }
+            }
+
+            p {
+                class: "hint",
+
+                "{utils::format_size(bytes.len() as u64)} ({bytes.len()} bytes)"
+            }
+
+            div {
+                class: "dialog-buttons",
+
+                button { onclick: move |_| on_close.call(()), "Close" }
+                button {
+                    class: "primary",
+                    onclick: download,
+
+                    "Download bytes"
+                }
+            }
+        }
+    }
+}
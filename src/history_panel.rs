@@ -0,0 +1,120 @@
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{editing::History, ui};
+
+/// Undoes the most recent modification to the data set.
+///
+pub fn undo(mut data_set: Signal<DataSet>, mut history: Signal<History>) {
+    match history.write().undo(&mut data_set.write()) {
+        Ok(Some(description)) => ui::toasts::add_info(format!("Undid \"{}\"", description)),
+        Ok(None) => (),
+        Err(e) => ui::toasts::add_error(format!("Undo failed: {}", e)),
+    }
+}
+
+/// Redoes the most recently undone modification to the data set.
+///
+pub fn redo(mut data_set: Signal<DataSet>, mut history: Signal<History>) {
+    match history.write().redo(&mut data_set.write()) {
+        Ok(Some(description)) => ui::toasts::add_info(format!("Redid \"{}\"", description)),
+        Ok(None) => (),
+        Err(e) => ui::toasts::add_error(format!("Redo failed: {}", e)),
+    }
+}
+
+/// Undo and redo buttons, and a popup list of the modifications made to the data set. Clicking an
+/// entry in the list undoes or redoes up to and including that entry.
+///
+#[component]
+pub fn HistoryPanel(data_set: Signal<DataSet>, history: Signal<History>) -> Element {
+    let mut is_list_open = use_signal(|| false);
+
+    let undo_count = history.read().undo_entries().len();
+
+    // Undoes until there are the given number of entries left to undo
+    let mut undo_to = move |index: usize| {
+        while history.peek().undo_entries().len() > index {
+            if let Err(e) = history.write().undo(&mut data_set.write()) {
+                ui::toasts::add_error(format!("Undo failed: {}", e));
+                break;
+            }
+        }
+    };
+
+    // Redoes until there are the given number of entries left to redo
+    let mut redo_to = move |index: usize| {
+        while history.peek().redo_entries().len() > index {
+            if let Err(e) = history.write().redo(&mut data_set.write()) {
+                ui::toasts::add_error(format!("Redo failed: {}", e));
+                break;
+            }
+        }
+    };
+
+    rsx! {
+        div {
+            class: "history-panel",
+
+            button {
+                title: "Undo (Ctrl+Z)",
+                disabled: !history.read().can_undo(),
+                onclick: move |_| undo(data_set, history),
+
+                ui::FontAwesomeIcon { icon: "rotate-left", style: "solid" }
+            }
+            button {
+                title: "Redo (Ctrl+Shift+Z)",
+                disabled: !history.read().can_redo(),
+                onclick: move |_| redo(data_set, history),
+
+                ui::FontAwesomeIcon { icon: "rotate-right", style: "solid" }
+            }
+            button {
+                class: if is_list_open() { "selected" },
+                onclick: move |_| is_list_open.toggle(),
+
+                "History ({undo_count})"
+            }
+
+            if is_list_open() {
+                div {
+                    class: "history-list",
+
+                    if undo_count == 0 && !history.read().can_redo() {
+                        div { class: "history-empty", "No changes have been made" }
+                    }
+
+                    div {
+                        class: "history-entry",
+                        class: if undo_count == 0 { "current" },
+                        onclick: move |_| undo_to(0),
+
+                        i { "Original" }
+                    }
+
+                    for (index, entry) in history.read().undo_entries().iter().enumerate() {
+                        div {
+                            key: "undo-{index}",
+                            class: "history-entry",
+                            class: if index + 1 == undo_count { "current" },
+                            onclick: move |_| undo_to(index + 1),
+
+                            "{entry.description}"
+                        }
+                    }
+
+                    for (index, entry) in history.read().redo_entries().iter().enumerate().rev() {
+                        div {
+                            key: "redo-{index}",
+                            class: "history-entry undone",
+                            onclick: move |_| redo_to(index),
+
+                            "{entry.description}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
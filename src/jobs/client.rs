@@ -0,0 +1,357 @@
+//! Submits jobs from the main thread and routes the events they produce back to the code awaiting
+//! them. Jobs are run in a worker that loads this same WASM module. If the worker can't be
+//! started, jobs are run on the main thread instead so that everything still works, just without
+//! keeping the page responsive.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use futures_channel::mpsc;
+use futures_util::StreamExt;
+use wasm_bindgen::{JsCast, JsValue, closure::Closure, prelude::wasm_bindgen};
+
+use super::{
+    protocol::{self, Event, Job, JobId, Output, Request},
+    worker::JobRunner,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The URL of the JavaScript module that loaded this WASM module, which the worker imports to
+    /// load it again.
+    #[wasm_bindgen(thread_local_v2, js_namespace = ["import", "meta"], js_name = url)]
+    static GLUE_URL: String;
+}
+
+thread_local! {
+    static CLIENT: RefCell<Option<Client>> = const { RefCell::new(None) };
+}
+
+/// Where submitted jobs are sent.
+///
+enum Transport {
+    /// The worker is loading. Requests are queued until it reports that it's ready.
+    Starting {
+        worker: web_sys::Worker,
+        queued: Vec<(Request, js_sys::Array)>,
+    },
+
+    Worker(web_sys::Worker),
+
+    /// Jobs are run on the main thread because the worker couldn't be started or stopped working.
+    MainThread(JobRunner),
+}
+
+struct Client {
+    transport: Transport,
+    next_id: u64,
+    jobs: HashMap<JobId, PendingJob>,
+}
+
+/// A job whose result is still being awaited.
+///
+struct PendingJob {
+    chunks: js_sys::Array,
+    bytes_received: u64,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+}
+
+/// An update on a submitted job.
+///
+pub enum JobUpdate {
+    /// The total number of bytes of output received so far.
+    Progress(u64),
+
+    /// The job completed. Output that was streamed back in chunks is in the payload.
+    Done(Output, js_sys::Array),
+
+    Failed(String),
+}
+
+/// A job that has been submitted. The job is cancelled if this is dropped before it completes.
+///
+pub struct JobHandle {
+    job_id: JobId,
+    updates: mpsc::UnboundedReceiver<JobUpdate>,
+    is_complete: bool,
+}
+
+impl JobHandle {
+    /// Returns the job's next update. Returns `None` once the job has completed or been cancelled.
+    ///
+    pub async fn next_update(&mut self) -> Option<JobUpdate> {
+        let update = self.updates.next().await;
+
+        if !matches!(update, Some(JobUpdate::Progress(_))) {
+            self.is_complete = true;
+        }
+
+        update
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        if !self.is_complete {
+            cancel(self.job_id);
+        }
+    }
+}
+
+/// Submits a job, returning a handle for receiving its updates.
+///
+pub fn submit(job: Job, payload: js_sys::Array) -> JobHandle {
+    with_client(|client| {
+        let job_id = client.new_id();
+        let (updates, receiver) = mpsc::unbounded();
+
+        client.jobs.insert(
+            job_id,
+            PendingJob {
+                chunks: js_sys::Array::new(),
+                bytes_received: 0,
+                updates,
+            },
+        );
+        client.send(Request::Run { job_id, job }, payload);
+
+        JobHandle {
+            job_id,
+            updates: receiver,
+            is_complete: false,
+        }
+    })
+}
+
+/// Submits a job whose result isn't needed.
+///
+pub fn post(job: Job, payload: js_sys::Array) {
+    with_client(|client| {
+        let job_id = client.new_id();
+        client.send(Request::Run { job_id, job }, payload);
+    });
+}
+
+/// Returns a new ID that's unique among those of jobs and loaded data sets.
+///
+pub fn new_id() -> u64 {
+    with_client(Client::new_id)
+}
+
+/// Cancels a job. It's removed from the worker's queue if it hasn't started, and otherwise its
+/// result is discarded.
+///
+fn cancel(job_id: JobId) {
+    with_client(|client| {
+        if client.jobs.remove(&job_id).is_some() {
+            client.send(Request::Cancel { job_id }, js_sys::Array::new());
+        }
+    });
+}
+
+/// Calls the given function with the client, starting the worker the first time it's needed.
+///
+fn with_client<T>(f: impl FnOnce(&mut Client) -> T) -> T {
+    CLIENT.with(|client| {
+        let mut client = client.borrow_mut();
+
+        let client = client.get_or_insert_with(|| Client {
+            transport: match start_worker() {
+                Ok(worker) => Transport::Starting {
+                    worker,
+                    queued: vec![],
+                },
+                Err(_) => Transport::MainThread(start_main_thread_runner()),
+            },
+            next_id: 0,
+            jobs: HashMap::new(),
+        });
+
+        f(client)
+    })
+}
+
+impl Client {
+    fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn send(&mut self, request: Request, payload: js_sys::Array) {
+        let result = match &mut self.transport {
+            Transport::Starting { queued, .. } => {
+                queued.push((request, payload));
+                return;
+            }
+
+            Transport::Worker(worker) => post_request(worker, &request, &payload),
+
+            Transport::MainThread(runner) => {
+                runner.submit(request, payload);
+                return;
+            }
+        };
+
+        if let (Err(error), Request::Run { job_id, .. }) = (result, request) {
+            self.handle_event(Event::Failed { job_id, error }, js_sys::Array::new());
+        }
+    }
+
+    fn handle_event(&mut self, event: Event, payload: js_sys::Array) {
+        match event {
+            Event::Ready => {
+                let Transport::Starting { worker, queued } = &mut self.transport else {
+                    return;
+                };
+
+                let queued = std::mem::take(queued);
+                self.transport = Transport::Worker(worker.clone());
+
+                for (request, payload) in queued {
+                    self.send(request, payload);
+                }
+            }
+
+            Event::Chunk { job_id } => {
+                let Some(job) = self.jobs.get_mut(&job_id) else {
+                    return;
+                };
+
+                let chunk = payload.get(0).unchecked_into::<js_sys::Uint8Array>();
+                job.bytes_received += u64::from(chunk.length());
+                job.chunks.push(&chunk);
+
+                let _ = job
+                    .updates
+                    .unbounded_send(JobUpdate::Progress(job.bytes_received));
+            }
+
+            Event::Done { job_id, output } => {
+                if let Some(job) = self.jobs.remove(&job_id) {
+                    let payload = match output {
+                        Output::Empty => job.chunks,
+                        _ => payload,
+                    };
+
+                    let _ = job.updates.unbounded_send(JobUpdate::Done(output, payload));
+                }
+            }
+
+            Event::Failed { job_id, error } => {
+                if let Some(job) = self.jobs.remove(&job_id) {
+                    let _ = job.updates.unbounded_send(JobUpdate::Failed(error));
+                }
+            }
+        }
+    }
+
+    /// Switches to running jobs on the main thread after the worker failed. Requests that were
+    /// waiting for the worker to start are run on the main thread, while jobs the worker had
+    /// already been sent fail as their results won't arrive.
+    ///
+    fn handle_worker_error(&mut self, message: String) {
+        let (queued, was_running) = match &mut self.transport {
+            Transport::Starting { worker, queued } => {
+                worker.terminate();
+                (std::mem::take(queued), false)
+            }
+
+            Transport::Worker(worker) => {
+                worker.terminate();
+                (vec![], true)
+            }
+
+            Transport::MainThread(_) => return,
+        };
+
+        self.transport = Transport::MainThread(start_main_thread_runner());
+
+        if was_running {
+            let job_ids = self.jobs.keys().copied().collect::<Vec<_>>();
+            for job_id in job_ids {
+                self.handle_event(
+                    Event::Failed {
+                        job_id,
+                        error: format!("The job worker stopped. {}", message),
+                    },
+                    js_sys::Array::new(),
+                );
+            }
+        }
+
+        for (request, payload) in queued {
+            self.send(request, payload);
+        }
+    }
+}
+
+/// Starts the worker, which imports the same JavaScript module as the page and is then sent the
+/// compiled WASM module so that it doesn't need to be fetched and compiled again.
+///
+fn start_worker() -> Result<web_sys::Worker, JsValue> {
+    let glue_url = GLUE_URL.with(String::clone);
+    let glue_url = serde_json::to_string(&glue_url).map_err(|e| JsValue::from(e.to_string()))?;
+
+    let script = format!(
+        "import init from {glue_url};\n\
+         self.onmessage = (event) => {{\n\
+           self.onmessage = null;\n\
+           init({{ module_or_path: event.data }});\n\
+         }};\n"
+    );
+
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("text/javascript");
+
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(
+        &js_sys::Array::of1(&script.into()),
+        &options,
+    )?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let worker_options = web_sys::WorkerOptions::new();
+    worker_options.set_type(web_sys::WorkerType::Module);
+
+    // The script's URL isn't revoked as the worker may not have fetched it by the time this returns
+    let worker = web_sys::Worker::new_with_options(&url, &worker_options)?;
+
+    let on_message =
+        Closure::<dyn FnMut(web_sys::MessageEvent)>::new(|event: web_sys::MessageEvent| {
+            match protocol::decode_message::<Event>(&event.data()) {
+                Ok((event, payload)) => {
+                    with_client(|client| client.handle_event(event, payload));
+                }
+                Err(e) => with_client(|client| client.handle_worker_error(e)),
+            }
+        });
+    worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    let on_error = Closure::<dyn FnMut(web_sys::ErrorEvent)>::new(|event: web_sys::ErrorEvent| {
+        with_client(|client| client.handle_worker_error(event.message()));
+    });
+    worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    on_error.forget();
+
+    worker.post_message(&wasm_bindgen::module())?;
+
+    Ok(worker)
+}
+
+fn start_main_thread_runner() -> JobRunner {
+    JobRunner::start(|event, payload| {
+        with_client(|client| client.handle_event(event, payload));
+    })
+}
+
+fn post_request(
+    worker: &web_sys::Worker,
+    request: &Request,
+    payload: &js_sys::Array,
+) -> Result<(), String> {
+    let (value, transfer) = protocol::encode_message(request, payload)?;
+
+    worker
+        .post_message_with_transfer(&value, &transfer)
+        .map_err(|e| format!("Failed sending job to the worker: {:?}", e))
+}
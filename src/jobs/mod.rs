@@ -0,0 +1,480 @@
+//! Runs CPU-heavy work, i.e. reading files, rendering frames, and serializing data sets, in a Web
+//! Worker so that the page stays responsive. Work is submitted as jobs described by the typed
+//! messages in [`protocol`], and their output is streamed back to the main thread. Components start
+//! jobs and observe their progress through a [`JobState`] signal, and dropping the future of a job
+//! cancels it.
+//!
+//! Files are read into the worker, which keeps each file's data set so that jobs that use it, such
+//! as rendering frames and exporting, don't need it to be sent to them. Only the data set's header
+//! is returned to the main thread for display and editing, which leaves out its Pixel Data and
+//! other large values, and these are fetched with a job if they're needed. Edits are sent back to
+//! the worker as changes to individual data elements rather than as whole data sets. Data sets and
+//! values are sent between them in an encoding that's cheap to decode, see [`protocol::Encoder`].
+//!
+//! Long-running jobs yield between units of work, e.g. frames or data sets, so that they can be
+//! cancelled while they run. Jobs that parse a file in a single step, such as reading DICOM JSON,
+//! can't be interrupted, and their result is discarded when they're cancelled.
+
+mod client;
+mod protocol;
+mod worker;
+
+use std::collections::HashMap;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+use image::RgbImage;
+use js_sys::wasm_bindgen::JsCast;
+
+use crate::{
+    dicomdir::MediaLayout,
+    editing,
+    json_export_dialog::JsonExportOptions,
+    pixel_data_frame_view::VoiSelection,
+    raw_token_view::{P10Structure, ReadFailure},
+    source_type::DataSetSourceType,
+};
+use client::{JobHandle, JobUpdate};
+use protocol::{Edit, EncodedDataSet, Job, Output};
+pub use protocol::{OmittedValue, TranscodeTarget};
+pub use worker::{is_worker, run as run_worker};
+
+/// The state of a job, as shown by the component that started it.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum JobState {
+    #[default]
+    Idle,
+
+    /// The job is running, and has streamed back the given number of bytes of output so far.
+    Running { bytes_received: u64 },
+}
+
+impl JobState {
+    pub fn is_running(self) -> bool {
+        self != Self::Idle
+    }
+}
+
+/// Why a job didn't produce a result.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobError {
+    Cancelled,
+    Failed(String),
+}
+
+/// Reads the header of a data set in the given format without keeping it in the worker. This is
+/// used to read the headers of files in text formats. If reading fails then the lines of the error
+/// are returned, along with the partial data set read prior to the error.
+///
+pub async fn read_data_set(
+    bytes: &[u8],
+    source_type: DataSetSourceType,
+) -> Result<DataSet, (DataSet, Vec<String>)> {
+    let handle = client::submit(
+        Job::ReadDataSet {
+            key: None,
+            source_type,
+        },
+        js_sys::Array::of1(&protocol::bytes_to_js(bytes)),
+    );
+
+    read_result(wait(handle, None).await, None)
+}
+
+/// Reads the tokens of a DICOM P10 file along with their byte offsets.
+///
+pub async fn read_p10_structure(bytes: &[u8]) -> Result<P10Structure, JobError> {
+    let handle = client::submit(
+        Job::ReadP10Structure,
+        js_sys::Array::of1(&protocol::bytes_to_js(bytes)),
+    );
+
+    match wait(handle, None).await? {
+        (Output::P10Structure { structure }, _) => Ok(structure),
+        _ => Err(JobError::Failed(
+            "Unexpected output when reading P10 tokens".into(),
+        )),
+    }
+}
+
+/// A data set held in the worker, which jobs that use it run on without it being sent to them.
+/// The main thread keeps a copy of its header, and edits made to that are applied to the worker's
+/// copy with [`WorkerDataSet::apply_edits()`]. The worker's copy is discarded when this is dropped.
+///
+/// The header holds an empty placeholder in place of each value that was left out of it. Setting
+/// a placeholder again, e.g. when undoing its deletion, restores the worker's original value.
+///
+pub struct WorkerDataSet {
+    key: u64,
+    omitted_values: HashMap<DataElementTag, OmittedValue>,
+    read_failure: Option<ReadFailure>,
+}
+
+impl WorkerDataSet {
+    /// Reads a data set in the given format in the worker. Returns the worker's data set along
+    /// with the header of the data set read, or the partial header read and the lines of the error
+    /// if reading failed.
+    ///
+    pub async fn read(
+        bytes: &[u8],
+        source_type: DataSetSourceType,
+    ) -> (Self, Result<DataSet, (DataSet, Vec<String>)>) {
+        let mut worker_data_set = Self::new();
+
+        let handle = client::submit(
+            Job::ReadDataSet {
+                key: Some(worker_data_set.key),
+                source_type,
+            },
+            js_sys::Array::of1(&protocol::bytes_to_js(bytes)),
+        );
+
+        let result = read_result(wait(handle, None).await, Some(&mut worker_data_set));
+
+        (worker_data_set, result)
+    }
+
+    /// Starts reading a DICOM P10 file in the worker. Its bytes are passed to
+    /// [`WorkerDataSet::read_p10_chunk()`] as they're read, followed by a call to
+    /// [`WorkerDataSet::finish_read_p10()`].
+    ///
+    pub fn read_p10() -> Self {
+        Self::new()
+    }
+
+    /// Reads the next chunk of the DICOM P10 file. Returns the data set's header, i.e. everything
+    /// before its root Pixel Data data element, when this chunk completes it. Errors reading the
+    /// file are returned by [`WorkerDataSet::finish_read_p10()`].
+    ///
+    pub async fn read_p10_chunk(&self, chunk: &[u8]) -> Result<Option<DataSet>, JobError> {
+        let handle = client::submit(
+            Job::ReadP10Chunk { key: self.key },
+            js_sys::Array::of1(&protocol::bytes_to_js(chunk)),
+        );
+
+        match wait(handle, None).await? {
+            (Output::Empty, _) => Ok(None),
+            (Output::DataSet { data_set, .. }, payload) => {
+                decode_output_data_set(&data_set, &payload)
+                    .map(Some)
+                    .map_err(JobError::Failed)
+            }
+            _ => Err(JobError::Failed(
+                "Unexpected output when reading P10 file".into(),
+            )),
+        }
+    }
+
+    /// Finishes reading the DICOM P10 file, returning the worker's data set along with the header
+    /// of the data set read. If reading failed, or this is called before the last chunk was read,
+    /// then the partial header read is returned along with the lines of the error, and where
+    /// reading stopped in the file is available from [`WorkerDataSet::read_failure()`].
+    ///
+    pub async fn finish_read_p10(mut self) -> (Self, Result<DataSet, (DataSet, Vec<String>)>) {
+        let handle = client::submit(Job::FinishReadP10 { key: self.key }, js_sys::Array::new());
+
+        let result = read_result(wait(handle, None).await, Some(&mut self));
+
+        (self, result)
+    }
+
+    /// Returns where reading the DICOM P10 file stopped, if reading it failed with an error.
+    ///
+    pub fn read_failure(&self) -> Option<&ReadFailure> {
+        self.read_failure.as_ref()
+    }
+
+    /// Returns the size of the value at the given path if it was left out of the header, i.e. it's
+    /// in the root data set and the header has its placeholder.
+    ///
+    pub fn omitted_value(&self, header: &DataSet, path: &DataSetPath) -> Option<&OmittedValue> {
+        let [DataSetPathEntry::DataElement { tag }] = path.entries() else {
+            return None;
+        };
+
+        let value = header.get_value(*tag).ok()?;
+        let is_placeholder = value.bytes().is_ok_and(|bytes| bytes.is_empty())
+            || value
+                .encapsulated_pixel_data()
+                .is_ok_and(|items| items.is_empty());
+
+        self.omitted_values.get(tag).filter(|_| is_placeholder)
+    }
+
+    /// Reads the value of the data element at the given path. This is used to fetch values that
+    /// were left out of the header.
+    ///
+    pub async fn read_value(&self, path: &DataSetPath) -> Result<DataElementValue, JobError> {
+        let (_, tag) = editing::split_data_element_path(path).map_err(JobError::Failed)?;
+
+        let handle = client::submit(
+            Job::ReadValue {
+                key: self.key,
+                path: protocol::encode_path(path),
+            },
+            js_sys::Array::new(),
+        );
+
+        let (output, payload) = wait(handle, None).await?;
+
+        let (Output::Value { value }, Ok(bytes)) =
+            (output, payload.get(0).dyn_into::<js_sys::Uint8Array>())
+        else {
+            return Err(JobError::Failed(
+                "Unexpected output when reading value".into(),
+            ));
+        };
+
+        protocol::decode_value(tag, &value, &bytes).map_err(JobError::Failed)
+    }
+
+    /// Applies edits made to the main thread's copy of the data set to the worker's copy. Each
+    /// edit sets the value of the data element at a path, with `None` removing it. Jobs run in the
+    /// order they're submitted, so jobs submitted after this see the edits.
+    ///
+    pub fn apply_edits(&self, edits: &[(DataSetPath, Option<DataElementValue>)]) {
+        let mut encoder = protocol::Encoder::default();
+
+        let edits = edits
+            .iter()
+            .map(|(path, value)| Edit {
+                path: protocol::encode_path(path),
+                value: value.as_ref().map(|value| encoder.value(value)),
+            })
+            .collect();
+
+        client::post(
+            Job::ApplyEdits {
+                key: self.key,
+                edits,
+            },
+            js_sys::Array::of1(&encoder.finish()),
+        );
+    }
+
+    /// Renders a frame of this data set using the given VOI.
+    ///
+    pub async fn render_frame(
+        &self,
+        frame_index: usize,
+        voi_selection: Option<VoiSelection>,
+    ) -> Result<RgbImage, JobError> {
+        let handle = client::submit(
+            Job::RenderFrame {
+                key: self.key,
+                frame_index,
+                voi_selection,
+            },
+            js_sys::Array::new(),
+        );
+
+        let (output, payload) = wait(handle, None).await?;
+
+        let Output::Frame { width, height } = output else {
+            return Err(JobError::Failed("Unexpected output when rendering".into()));
+        };
+
+        let pixels = js_sys::Uint8Array::from(payload.get(0)).to_vec();
+
+        RgbImage::from_raw(width, height, pixels)
+            .ok_or_else(|| JobError::Failed("Rendered frame has the wrong size".into()))
+    }
+
+    /// Returns the stored values of the samples of the pixel at the given column and row of a frame
+    /// of this data set's native pixel data.
+    ///
+    pub async fn probe_pixel(
+        &self,
+        frame_index: usize,
+        column: usize,
+        row: usize,
+    ) -> Result<Vec<i64>, JobError> {
+        let handle = client::submit(
+            Job::ProbePixel {
+                key: self.key,
+                frame_index,
+                column,
+                row,
+            },
+            js_sys::Array::new(),
+        );
+
+        match wait(handle, None).await? {
+            (Output::StoredValues { values }, _) => Ok(values),
+            _ => Err(JobError::Failed(
+                "Unexpected output when probing pixel".into(),
+            )),
+        }
+    }
+
+    /// Exports this data set as DICOM Native XML, returning the output as blob parts.
+    ///
+    pub async fn export_xml(&self, state: Signal<JobState>) -> Result<js_sys::Array, JobError> {
+        let handle = client::submit(Job::ExportXml { key: self.key }, js_sys::Array::new());
+
+        Ok(wait(handle, Some(state)).await?.1)
+    }
+
+    /// Writes this data set as DICOM P10, transcoding it first if a transfer syntax is given.
+    /// Returns the output as blob parts.
+    ///
+    pub async fn write_p10(
+        &self,
+        transfer_syntax: Option<TranscodeTarget>,
+        state: Signal<JobState>,
+    ) -> Result<js_sys::Array, JobError> {
+        let handle = client::submit(
+            Job::WriteP10 {
+                key: self.key,
+                transfer_syntax,
+            },
+            js_sys::Array::new(),
+        );
+
+        Ok(wait(handle, Some(state)).await?.1)
+    }
+
+    fn new() -> Self {
+        Self {
+            key: client::new_id(),
+            omitted_values: HashMap::new(),
+            read_failure: None,
+        }
+    }
+}
+
+impl Drop for WorkerDataSet {
+    fn drop(&mut self) {
+        client::post(Job::UnloadDataSet { key: self.key }, js_sys::Array::new());
+    }
+}
+
+/// Exports data sets held in the worker as DICOM JSON, returning the output as blob parts.
+///
+pub async fn export_json(
+    data_sets: &[&WorkerDataSet],
+    options: JsonExportOptions,
+    state: Signal<JobState>,
+) -> Result<js_sys::Array, JobError> {
+    let handle = client::submit(
+        Job::ExportJson {
+            keys: data_sets.iter().map(|data_set| data_set.key).collect(),
+            options,
+        },
+        js_sys::Array::new(),
+    );
+
+    Ok(wait(handle, Some(state)).await?.1)
+}
+
+/// Writes a ZIP of a media set with a DICOMDIR in the worker. The data set of each file in the
+/// layout is given along with its file ID. Returns the output as blob parts.
+///
+pub async fn generate_media_set(
+    layout: &MediaLayout,
+    data_sets: &[(u64, &WorkerDataSet)],
+    state: Signal<JobState>,
+) -> Result<js_sys::Array, JobError> {
+    let handle = client::submit(
+        Job::GenerateMediaSet {
+            layout: layout.clone(),
+            data_sets: data_sets
+                .iter()
+                .map(|(file_id, data_set)| (*file_id, data_set.key))
+                .collect(),
+        },
+        js_sys::Array::new(),
+    );
+
+    Ok(wait(handle, Some(state)).await?.1)
+}
+
+/// Returns the header output by a job that reads a data set, along with any errors reading it. The
+/// values that were left out of the header and where reading stopped are recorded on the worker's
+/// data set if it was stored.
+///
+fn read_result(
+    result: Result<(Output, js_sys::Array), JobError>,
+    worker_data_set: Option<&mut WorkerDataSet>,
+) -> Result<DataSet, (DataSet, Vec<String>)> {
+    let failed = |error| Err((DataSet::new(), vec![error]));
+
+    let (output, payload) = match result {
+        Ok(output) => output,
+        Err(JobError::Cancelled) => return failed("Reading was cancelled".into()),
+        Err(JobError::Failed(e)) => return failed(e),
+    };
+
+    let Output::DataSet {
+        data_set,
+        error_lines,
+        read_failure,
+    } = output
+    else {
+        return failed("Unexpected output when reading data set".into());
+    };
+
+    let header = match decode_output_data_set(&data_set, &payload) {
+        Ok(header) => header,
+        Err(e) => return failed(e),
+    };
+
+    if let Some(worker_data_set) = worker_data_set {
+        worker_data_set.omitted_values = data_set.omitted_values().collect();
+        worker_data_set.read_failure = read_failure;
+    }
+
+    if error_lines.is_empty() {
+        Ok(header)
+    } else {
+        Err((header, error_lines))
+    }
+}
+
+fn decode_output_data_set(
+    data_set: &EncodedDataSet,
+    payload: &js_sys::Array,
+) -> Result<DataSet, String> {
+    let bytes = payload
+        .get(0)
+        .dyn_into::<js_sys::Uint8Array>()
+        .map_err(|_| "The job's output is missing the data set's values".to_string())?;
+
+    protocol::decode_data_set(data_set, &bytes)
+}
+
+/// Waits for a job to complete, updating its state signal as it progresses. The state is reset
+/// when the job completes, fails, or is cancelled by dropping this future.
+///
+async fn wait(
+    mut handle: JobHandle,
+    mut state: Option<Signal<JobState>>,
+) -> Result<(Output, js_sys::Array), JobError> {
+    if let Some(state) = state.as_mut() {
+        state.set(JobState::Running { bytes_received: 0 });
+    }
+
+    // The component that owns the state may have been unmounted if the job was cancelled
+    scopeguard::defer! {
+        if let Some(mut state) = state
+            && let Ok(mut state) = state.try_write()
+        {
+            *state = JobState::Idle;
+        }
+    }
+
+    loop {
+        match handle.next_update().await {
+            Some(JobUpdate::Progress(bytes_received)) => {
+                if let Some(mut state) = state {
+                    state.set(JobState::Running { bytes_received });
+                }
+            }
+            Some(JobUpdate::Done(output, payload)) => return Ok((output, payload)),
+            Some(JobUpdate::Failed(e)) => return Err(JobError::Failed(e)),
+            None => return Err(JobError::Cancelled),
+        }
+    }
+}
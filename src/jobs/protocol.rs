@@ -0,0 +1,578 @@
+//! The messages exchanged between the main thread and the job worker.
+//!
+//! Each message is serialized to JSON and posted together with a payload, which is an array of
+//! `Uint8Array`s holding bulk data such as file content, data set values, or rendered frames. The
+//! buffers of the payload are transferred to the receiving thread rather than copied, so they must
+//! never be views into WASM memory.
+
+use std::ops::Range;
+
+use dcmfx::core::*;
+use js_sys::wasm_bindgen::{JsCast, JsValue};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    dicomdir::MediaLayout,
+    editing,
+    json_export_dialog::JsonExportOptions,
+    pixel_data_frame_view::VoiSelection,
+    raw_token_view::{P10Structure, ReadFailure},
+    source_type::DataSetSourceType,
+};
+
+/// Uniquely identifies a job submitted by the main thread.
+///
+pub type JobId = u64;
+
+/// A message sent from the main thread to the worker.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Request {
+    Run {
+        job_id: JobId,
+        job: Job,
+    },
+
+    /// Stops the job if it hasn't started yet, and otherwise discards its result.
+    Cancel {
+        job_id: JobId,
+    },
+}
+
+/// The work a job does. Data sets that are read by jobs are stored in the worker under a key chosen
+/// by the main thread, so that later jobs can use them without them being sent again.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Job {
+    /// Reads a data set in the given format from the bytes in the payload, storing it under the
+    /// key if one is given. Outputs the data set read, which is partial if reading failed. When the
+    /// data set is stored, only its header is output, see [`Encoder::header()`].
+    ReadDataSet {
+        key: Option<u64>,
+        source_type: DataSetSourceType,
+    },
+
+    /// Reads the next chunk of a DICOM P10 file, which is the only item in the payload. Outputs the
+    /// data set's header, i.e. everything before its root Pixel Data data element, when this chunk
+    /// completes it, and otherwise has no output.
+    ReadP10Chunk { key: u64 },
+
+    /// Finishes reading the DICOM P10 file whose chunks were read by [`Job::ReadP10Chunk`], and
+    /// stores the data set read. Outputs the data set's header, see [`Encoder::header()`], which
+    /// is partial if reading failed or was stopped before the last chunk.
+    FinishReadP10 { key: u64 },
+
+    /// Reads the tokens of the DICOM P10 file in the payload along with their byte offsets.
+    ReadP10Structure,
+
+    /// Applies edits made on the main thread to a stored data set. Each edit sets the value of the
+    /// data element at a path, with `None` removing it. The bytes of the values are in the payload.
+    ApplyEdits { key: u64, edits: Vec<Edit> },
+
+    /// Discards a stored data set, or one that's still being read.
+    UnloadDataSet { key: u64 },
+
+    /// Outputs the value of the data element at a path in a stored data set. This is how values
+    /// that were left out of the header sent to the main thread are fetched when they're needed.
+    ReadValue {
+        key: u64,
+        path: Vec<EncodedPathEntry>,
+    },
+
+    /// Renders a frame of a stored data set. Outputs the frame's RGB pixels.
+    RenderFrame {
+        key: u64,
+        frame_index: usize,
+        voi_selection: Option<VoiSelection>,
+    },
+
+    /// Outputs the stored values of the samples of a pixel in a frame of a stored data set's native
+    /// pixel data.
+    ProbePixel {
+        key: u64,
+        frame_index: usize,
+        column: usize,
+        row: usize,
+    },
+
+    /// Exports stored data sets as DICOM JSON, streaming the output in chunks.
+    ExportJson {
+        keys: Vec<u64>,
+        options: JsonExportOptions,
+    },
+
+    /// Exports a stored data set as DICOM Native XML, streaming the output in chunks.
+    ExportXml { key: u64 },
+
+    /// Writes a ZIP of a media set with a DICOMDIR from stored data sets, streaming the output in
+    /// chunks. The data set of each file in the layout is given by its file ID and key.
+    GenerateMediaSet {
+        layout: MediaLayout,
+        data_sets: Vec<(u64, u64)>,
+    },
+
+    /// Writes a stored data set as DICOM P10, streaming the output in chunks. The data set is
+    /// first transcoded to the given transfer syntax if one is specified.
+    WriteP10 {
+        key: u64,
+        transfer_syntax: Option<TranscodeTarget>,
+    },
+}
+
+/// An edit to a stored data set. See [`Job::ApplyEdits`].
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Edit {
+    pub path: Vec<EncodedPathEntry>,
+    pub value: Option<EncodedValue>,
+}
+
+/// The transfer syntax a data set is transcoded to before it's written as DICOM P10.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TranscodeTarget {
+    pub uid: String,
+    pub jpeg_quality: u8,
+}
+
+/// A message sent from the worker to the main thread.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    /// The worker has started and is able to run jobs.
+    Ready,
+
+    /// A chunk of a job's output, which is the only item in the payload.
+    Chunk {
+        job_id: JobId,
+    },
+
+    Done {
+        job_id: JobId,
+        output: Output,
+    },
+    Failed {
+        job_id: JobId,
+        error: String,
+    },
+}
+
+/// The result of a job that completed, along with any data in the payload.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Output {
+    /// The job has no output, or its output was sent in chunks.
+    Empty,
+
+    /// A data set whose bytes are in the payload. Any errors encountered while reading it are
+    /// included, along with where reading stopped in the file if that's known.
+    DataSet {
+        data_set: EncodedDataSet,
+        error_lines: Vec<String>,
+        read_failure: Option<ReadFailure>,
+    },
+
+    /// A data element value whose bytes are in the payload.
+    Value { value: EncodedValue },
+
+    /// A rendered frame whose RGB pixels are in the payload.
+    Frame { width: u32, height: u32 },
+
+    /// The stored values of the samples of a probed pixel.
+    StoredValues { values: Vec<i64> },
+
+    /// The tokens of a DICOM P10 file and their offsets.
+    P10Structure { structure: P10Structure },
+}
+
+/// Creates the value posted for a message and its payload, and the list of buffers to transfer.
+///
+pub fn encode_message<T: Serialize>(
+    message: &T,
+    payload: &js_sys::Array,
+) -> Result<(JsValue, js_sys::Array), String> {
+    let json = serde_json::to_string(message).map_err(|e| e.to_string())?;
+
+    let value = js_sys::Object::new();
+    js_sys::Reflect::set(&value, &"message".into(), &json.into()).map_err(js_error)?;
+    js_sys::Reflect::set(&value, &"payload".into(), payload).map_err(js_error)?;
+
+    let transfer = payload
+        .iter()
+        .filter_map(|part| part.dyn_into::<js_sys::Uint8Array>().ok())
+        .map(|part| part.buffer())
+        .collect::<js_sys::Array>();
+
+    Ok((value.into(), transfer))
+}
+
+/// Reads a message and its payload from a posted value.
+///
+pub fn decode_message<T: DeserializeOwned>(value: &JsValue) -> Result<(T, js_sys::Array), String> {
+    let json = js_sys::Reflect::get(value, &"message".into())
+        .map_err(js_error)?
+        .as_string()
+        .ok_or_else(|| "Message is not a string".to_string())?;
+
+    let payload = js_sys::Reflect::get(value, &"payload".into())
+        .map_err(js_error)?
+        .dyn_into::<js_sys::Array>()
+        .map_err(|_| "Message payload is not an array".to_string())?;
+
+    let message = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    Ok((message, payload))
+}
+
+/// The size in bytes above which a value in the root data set is left out of the header that's
+/// sent to the main thread, see [`Encoder::header()`].
+///
+pub const MAX_HEADER_VALUE_SIZE: usize = 64 * 1024;
+
+/// A data set encoded so that it can be sent between the main thread and the worker, which is much
+/// faster to decode than DICOM P10. Its structure is sent in the message, and the bytes of its
+/// values are concatenated into a single buffer that's sent in the payload, see [`Encoder`].
+///
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EncodedDataSet(Vec<(u16, u16, EncodedValue)>);
+
+impl EncodedDataSet {
+    /// Returns the tags of the values in the root data set that were left out by
+    /// [`Encoder::header()`], along with the values' sizes.
+    ///
+    pub fn omitted_values(&self) -> impl Iterator<Item = (DataElementTag, OmittedValue)> + '_ {
+        self.0
+            .iter()
+            .filter_map(|(group, element, value)| match value {
+                EncodedValue::Omitted { omitted, .. } => {
+                    Some((DataElementTag::new(*group, *element), omitted.clone()))
+                }
+                _ => None,
+            })
+    }
+}
+
+/// A data element value encoded as part of an [`EncodedDataSet`]. Binary values refer to a range of
+/// the payload's buffer.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EncodedValue {
+    Binary {
+        vr: String,
+        bytes: Range<usize>,
+    },
+    EncapsulatedPixelData {
+        vr: String,
+        items: Vec<Range<usize>>,
+    },
+    Sequence {
+        items: Vec<EncodedDataSet>,
+    },
+
+    /// A value that was left out of a header, which is decoded as its placeholder, see
+    /// [`placeholder()`].
+    Omitted {
+        vr: String,
+        omitted: OmittedValue,
+    },
+}
+
+/// The size of a value that was left out of a header sent to the main thread.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OmittedValue {
+    pub length: usize,
+
+    /// The number of items when the value is encapsulated pixel data.
+    pub item_count: Option<usize>,
+}
+
+/// An entry of a [`DataSetPath`] encoded so it can be sent with an [`Edit`].
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EncodedPathEntry {
+    DataElement { group: u16, element: u16 },
+    SequenceItem { index: usize },
+}
+
+/// Encodes data sets and values, collecting the bytes of their values so they can be copied into
+/// a single buffer once everything has been encoded.
+///
+#[derive(Default)]
+pub struct Encoder<'a> {
+    parts: Vec<&'a [u8]>,
+    length: usize,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn data_set(&mut self, data_set: &'a DataSet) -> EncodedDataSet {
+        EncodedDataSet(
+            data_set
+                .iter()
+                .map(|(tag, value)| (tag.group, tag.element, self.value(value)))
+                .collect(),
+        )
+    }
+
+    /// Encodes the header of a data set to send to the main thread. This leaves out the values in
+    /// the root data set that are large, i.e. its Pixel Data and any other binary value larger than
+    /// [`MAX_HEADER_VALUE_SIZE`], so that they're only held in the worker. They're sent as
+    /// [`EncodedValue::Omitted`] so the main thread can show their sizes and fetch them if they're
+    /// needed.
+    ///
+    /// Values inside sequences are always sent, as the main thread can add and remove sequence
+    /// items, which would leave it unable to tell which value a placeholder stood for.
+    ///
+    pub fn header(&mut self, data_set: &'a DataSet) -> EncodedDataSet {
+        EncodedDataSet(
+            data_set
+                .iter()
+                .map(|(tag, value)| {
+                    let encoded = match omitted_value(*tag, value) {
+                        Some(omitted) => EncodedValue::Omitted {
+                            vr: value.value_representation().to_string(),
+                            omitted,
+                        },
+                        None => self.value(value),
+                    };
+
+                    (tag.group, tag.element, encoded)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn value(&mut self, value: &'a DataElementValue) -> EncodedValue {
+        let vr = value.value_representation().to_string();
+
+        if let Ok(items) = value.sequence_items() {
+            EncodedValue::Sequence {
+                items: items.iter().map(|item| self.data_set(item)).collect(),
+            }
+        } else if let Ok(items) = value.encapsulated_pixel_data() {
+            EncodedValue::EncapsulatedPixelData {
+                vr,
+                items: items.iter().map(|item| self.bytes(item)).collect(),
+            }
+        } else {
+            EncodedValue::Binary {
+                vr,
+                bytes: self.bytes(value.bytes().map_or(&[][..], |bytes| &bytes[..])),
+            }
+        }
+    }
+
+    fn bytes(&mut self, bytes: &'a [u8]) -> Range<usize> {
+        let range = self.length..self.length + bytes.len();
+
+        self.parts.push(bytes);
+        self.length += bytes.len();
+
+        range
+    }
+
+    /// Copies the bytes of everything encoded into a new `Uint8Array` that can be transferred.
+    ///
+    pub fn finish(self) -> js_sys::Uint8Array {
+        let array = js_sys::Uint8Array::new_with_length(self.length as u32);
+
+        let mut offset = 0;
+        for part in self.parts {
+            if !part.is_empty() {
+                array
+                    .subarray(offset as u32, (offset + part.len()) as u32)
+                    .copy_from(part);
+            }
+            offset += part.len();
+        }
+
+        array
+    }
+}
+
+/// Encodes the header of a data set, see [`Encoder::header()`], returning its structure and the
+/// buffer holding the bytes of its values.
+///
+pub fn encode_header(data_set: &DataSet) -> (EncodedDataSet, js_sys::Uint8Array) {
+    let mut encoder = Encoder::default();
+    let encoded = encoder.header(data_set);
+
+    (encoded, encoder.finish())
+}
+
+/// Returns the size of a value in the root data set if it's left out of headers, which is the
+/// case for the Pixel Data and other binary values larger than [`MAX_HEADER_VALUE_SIZE`].
+///
+pub fn omitted_value(tag: DataElementTag, value: &DataElementValue) -> Option<OmittedValue> {
+    let omitted = if let Ok(items) = value.encapsulated_pixel_data() {
+        OmittedValue {
+            length: items.iter().map(|item| item.len()).sum(),
+            item_count: Some(items.len()),
+        }
+    } else if let Ok(bytes) = value.bytes()
+        && !editing::value_text::is_string_vr(&value.value_representation().to_string())
+    {
+        OmittedValue {
+            length: bytes.len(),
+            item_count: None,
+        }
+    } else {
+        return None;
+    };
+
+    (tag == dictionary::PIXEL_DATA.tag || omitted.length > MAX_HEADER_VALUE_SIZE).then_some(omitted)
+}
+
+/// Returns the placeholder that the main thread holds in place of a value that was left out of a
+/// header, which is an empty value with the same VR.
+///
+pub fn placeholder(value: &DataElementValue) -> DataElementValue {
+    empty_value(
+        value.value_representation(),
+        value.encapsulated_pixel_data().is_ok(),
+    )
+}
+
+fn empty_value(vr: ValueRepresentation, is_encapsulated: bool) -> DataElementValue {
+    if is_encapsulated {
+        DataElementValue::new_encapsulated_pixel_data_unchecked(vr, vec![])
+    } else {
+        DataElementValue::new_binary_unchecked(vr, vec![].into())
+    }
+}
+
+/// Decodes a data set encoded by [`Encoder`] using the buffer of bytes it produced.
+///
+pub fn decode_data_set(
+    encoded: &EncodedDataSet,
+    bytes: &js_sys::Uint8Array,
+) -> Result<DataSet, String> {
+    let mut data_set = DataSet::new();
+
+    for (group, element, value) in encoded.0.iter() {
+        let tag = DataElementTag::new(*group, *element);
+        data_set.insert(tag, decode_value(tag, value, bytes)?);
+    }
+
+    Ok(data_set)
+}
+
+/// Decodes the value of the data element with the given tag that was encoded by [`Encoder`].
+///
+pub fn decode_value(
+    tag: DataElementTag,
+    encoded: &EncodedValue,
+    bytes: &js_sys::Uint8Array,
+) -> Result<DataElementValue, String> {
+    let value_representation = |vr: &str| {
+        ValueRepresentation::from_bytes(vr.as_bytes())
+            .map_err(|_| format!("Unknown value representation '{}'", vr))
+    };
+
+    let slice = |range: &Range<usize>| -> Result<RcByteSlice, String> {
+        if range.end > bytes.length() as usize {
+            return Err("Encoded value is out of range".into());
+        }
+
+        Ok(bytes
+            .subarray(range.start as u32, range.end as u32)
+            .to_vec()
+            .into())
+    };
+
+    // The values were valid when they were encoded, so they aren't validated again
+    match encoded {
+        EncodedValue::Binary { vr, bytes } => {
+            let vr = value_representation(vr)?;
+            let bytes = slice(bytes)?;
+
+            if dictionary::is_lut_descriptor_tag(tag) {
+                Ok(DataElementValue::new_lookup_table_descriptor_unchecked(
+                    vr, bytes,
+                ))
+            } else {
+                Ok(DataElementValue::new_binary_unchecked(vr, bytes))
+            }
+        }
+
+        EncodedValue::EncapsulatedPixelData { vr, items } => {
+            Ok(DataElementValue::new_encapsulated_pixel_data_unchecked(
+                value_representation(vr)?,
+                items.iter().map(slice).collect::<Result<Vec<_>, _>>()?,
+            ))
+        }
+
+        EncodedValue::Sequence { items } => Ok(DataElementValue::new_sequence(
+            items
+                .iter()
+                .map(|item| decode_data_set(item, bytes))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+
+        EncodedValue::Omitted { vr, omitted } => Ok(empty_value(
+            value_representation(vr)?,
+            omitted.item_count.is_some(),
+        )),
+    }
+}
+
+/// Encodes the entries of a data set path.
+///
+pub fn encode_path(path: &DataSetPath) -> Vec<EncodedPathEntry> {
+    path.entries()
+        .iter()
+        .map(|entry| match entry {
+            DataSetPathEntry::DataElement { tag } => EncodedPathEntry::DataElement {
+                group: tag.group,
+                element: tag.element,
+            },
+            DataSetPathEntry::SequenceItem { index } => {
+                EncodedPathEntry::SequenceItem { index: *index }
+            }
+        })
+        .collect()
+}
+
+/// Decodes a data set path encoded by [`encode_path()`].
+///
+pub fn decode_path(entries: &[EncodedPathEntry]) -> Result<DataSetPath, String> {
+    let entries = entries
+        .iter()
+        .map(|entry| match entry {
+            EncodedPathEntry::DataElement { group, element } => DataSetPathEntry::DataElement {
+                tag: DataElementTag::new(*group, *element),
+            },
+            EncodedPathEntry::SequenceItem { index } => {
+                DataSetPathEntry::SequenceItem { index: *index }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    editing::path_from_entries(&entries)
+}
+
+/// Copies bytes out of WASM memory into a new `Uint8Array` that can be transferred.
+///
+pub fn bytes_to_js(bytes: &[u8]) -> js_sys::Uint8Array {
+    let array = js_sys::Uint8Array::new_with_length(bytes.len() as u32);
+    array.copy_from(bytes);
+    array
+}
+
+/// Concatenates the parts of a payload into a single byte vector.
+///
+pub fn payload_bytes(payload: &js_sys::Array) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    for part in payload.iter() {
+        if let Ok(part) = part.dyn_into::<js_sys::Uint8Array>() {
+            bytes.extend_from_slice(&part.to_vec());
+        }
+    }
+
+    bytes
+}
+
+fn js_error(e: JsValue) -> String {
+    format!("{:?}", e)
+}
@@ -0,0 +1,616 @@
+//! Runs jobs one at a time in the order they're submitted. In the worker, requests arrive from the
+//! main thread and events are posted back to it. When a worker can't be started, the same runner
+//! is used on the main thread instead.
+
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    time::Duration,
+};
+
+use dcmfx::{
+    core::*,
+    pixel_data::{DataSetPixelDataExtensions, PixelDataFrame},
+};
+use futures_channel::mpsc;
+use futures_util::StreamExt;
+use js_sys::wasm_bindgen::{JsCast, closure::Closure};
+
+use super::protocol::{self, Event, Job, JobId, Output, Request};
+use crate::{
+    dicomdir, download_p10_dialog, editing, json_export_dialog, native_xml,
+    pixel_data_frame_view::{self, StoredValues},
+    raw_token_view::{self, ReadFailure},
+    source_type::P10StreamReader,
+};
+
+/// The size of the chunks that streamed output is sent back in.
+///
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Returns whether this is running in a dedicated worker rather than a page.
+///
+pub fn is_worker() -> bool {
+    js_sys::global()
+        .dyn_into::<web_sys::DedicatedWorkerGlobalScope>()
+        .is_ok()
+}
+
+/// Runs the worker's job loop, receiving requests from the main thread and posting events back
+/// to it. Called instead of launching the app when the WASM module is loaded in the worker.
+///
+pub fn run() {
+    let scope = js_sys::global().unchecked_into::<web_sys::DedicatedWorkerGlobalScope>();
+
+    let post_scope = scope.clone();
+    let runner = JobRunner::start(move |event, payload| {
+        if let Ok((value, transfer)) = protocol::encode_message(&event, &payload) {
+            let _ = post_scope.post_message_with_transfer(&value, &transfer);
+        }
+    });
+
+    // The main thread and the worker run the same build, so requests always decode
+    let on_message =
+        Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            if let Ok((request, payload)) = protocol::decode_message(&event.data()) {
+                runner.submit(request, payload);
+            }
+        });
+
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    if let Ok((value, transfer)) = protocol::encode_message(&Event::Ready, &js_sys::Array::new()) {
+        let _ = scope.post_message_with_transfer(&value, &transfer);
+    }
+}
+
+/// Queues submitted jobs and runs them in order, passing the events they produce to a callback.
+///
+pub struct JobRunner {
+    queue: mpsc::UnboundedSender<(JobId, Job, js_sys::Array)>,
+
+    /// The jobs that are queued or running. Requests to cancel any other job are ignored, e.g.
+    /// those sent when the handle of a job that already finished is dropped.
+    pending_jobs: Rc<RefCell<HashSet<JobId>>>,
+
+    cancelled_jobs: Rc<RefCell<HashSet<JobId>>>,
+}
+
+impl JobRunner {
+    /// Starts a job runner that passes each event and its payload to `post_event`.
+    ///
+    pub fn start(post_event: impl Fn(Event, js_sys::Array) + 'static) -> Self {
+        let (queue, mut receiver) = mpsc::unbounded::<(JobId, Job, js_sys::Array)>();
+        let pending_jobs = Rc::new(RefCell::new(HashSet::new()));
+        let cancelled_jobs = Rc::new(RefCell::new(HashSet::new()));
+
+        let pending = pending_jobs.clone();
+        let cancelled = cancelled_jobs.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut storage = Storage::default();
+
+            while let Some((job_id, job, payload)) = receiver.next().await {
+                if cancelled.borrow_mut().remove(&job_id) {
+                    pending.borrow_mut().remove(&job_id);
+                    continue;
+                }
+
+                let mut emit_chunk = |chunk: js_sys::Uint8Array| {
+                    post_event(Event::Chunk { job_id }, js_sys::Array::of1(&chunk));
+                };
+
+                let checkpoint = Checkpoint::new(job_id, cancelled.clone());
+
+                let result =
+                    execute(job, &payload, &mut storage, &mut emit_chunk, &checkpoint).await;
+
+                pending.borrow_mut().remove(&job_id);
+
+                // The result of a job that was cancelled while it ran is discarded
+                if !cancelled.borrow_mut().remove(&job_id) {
+                    match result {
+                        Ok((output, payload)) => {
+                            post_event(Event::Done { job_id, output }, payload)
+                        }
+                        Err(error) => {
+                            post_event(Event::Failed { job_id, error }, js_sys::Array::new())
+                        }
+                    }
+                }
+
+                // Yield between jobs so that requests to cancel queued jobs are received
+                gloo_timers::future::sleep(Duration::ZERO).await;
+            }
+        });
+
+        Self {
+            queue,
+            pending_jobs,
+            cancelled_jobs,
+        }
+    }
+
+    /// Queues a job to be run, or cancels one.
+    ///
+    pub fn submit(&self, request: Request, payload: js_sys::Array) {
+        match request {
+            Request::Run { job_id, job } => {
+                self.pending_jobs.borrow_mut().insert(job_id);
+                let _ = self.queue.unbounded_send((job_id, job, payload));
+            }
+
+            Request::Cancel { job_id } => {
+                if self.pending_jobs.borrow().contains(&job_id) {
+                    self.cancelled_jobs.borrow_mut().insert(job_id);
+                }
+            }
+        }
+    }
+}
+
+/// Lets a long-running job yield so that requests to cancel it are received, and stops it if it
+/// has been cancelled. Jobs call [`Checkpoint::check()`] between each unit of their work, e.g.
+/// each frame being transcoded, and it only yields once [`YIELD_INTERVAL_MS`] has passed since it
+/// last did, so that frequent checks don't slow the job down.
+///
+struct Checkpoint {
+    job_id: JobId,
+    cancelled_jobs: Rc<RefCell<HashSet<JobId>>>,
+    last_yield: Cell<f64>,
+}
+
+/// The minimum time between a running job yielding, in milliseconds.
+///
+const YIELD_INTERVAL_MS: f64 = 50.0;
+
+impl Checkpoint {
+    fn new(job_id: JobId, cancelled_jobs: Rc<RefCell<HashSet<JobId>>>) -> Self {
+        Self {
+            job_id,
+            cancelled_jobs,
+            last_yield: Cell::new(js_sys::Date::now()),
+        }
+    }
+
+    /// Yields if it's time to, and returns an error if the job has been cancelled.
+    ///
+    async fn check(&self) -> Result<(), String> {
+        if js_sys::Date::now() - self.last_yield.get() < YIELD_INTERVAL_MS {
+            return Ok(());
+        }
+
+        gloo_timers::future::sleep(Duration::ZERO).await;
+        self.last_yield.set(js_sys::Date::now());
+
+        if self.cancelled_jobs.borrow().contains(&self.job_id) {
+            return Err("The job was cancelled".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// The data sets stored in the worker, and the DICOM P10 files that are being read into it.
+///
+#[derive(Default)]
+struct Storage {
+    data_sets: HashMap<u64, StoredDataSet>,
+    p10_reads: HashMap<u64, P10Read>,
+}
+
+impl Storage {
+    fn get(&self, key: u64) -> Result<&StoredDataSet, String> {
+        self.data_sets
+            .get(&key)
+            .ok_or_else(|| "The data set isn't loaded in the job worker".to_string())
+    }
+
+    fn get_mut(&mut self, key: u64) -> Result<&mut StoredDataSet, String> {
+        self.data_sets
+            .get_mut(&key)
+            .ok_or_else(|| "The data set isn't loaded in the job worker".to_string())
+    }
+}
+
+/// A stored data set, along with its pixel data frames once one of them has been rendered, and the
+/// stored values of the last frame that was probed. These are discarded when the data set is
+/// edited.
+///
+/// The values that were left out of the header sent to the main thread are also kept, so that
+/// they can be restored when the main thread sets their placeholder again, e.g. when undoing their
+/// deletion.
+///
+struct StoredDataSet {
+    data_set: DataSet,
+    omitted_values: HashMap<DataElementTag, DataElementValue>,
+    frames: Option<Vec<PixelDataFrame>>,
+    stored_values: Option<(usize, Result<StoredValues, String>)>,
+}
+
+impl StoredDataSet {
+    fn new(data_set: DataSet) -> Self {
+        let omitted_values = data_set
+            .iter()
+            .filter(|(tag, value)| protocol::omitted_value(**tag, value).is_some())
+            .map(|(tag, value)| (*tag, value.clone()))
+            .collect();
+
+        Self {
+            data_set,
+            omitted_values,
+            frames: None,
+            stored_values: None,
+        }
+    }
+
+    /// Returns the value to set at a path for an edit made by the main thread. If the edit sets a
+    /// value in the root data set to the placeholder of a value that was left out of the header,
+    /// then the original value is returned in place of the placeholder.
+    ///
+    fn resolve_edit(&self, path: &DataSetPath, value: DataElementValue) -> DataElementValue {
+        if let [DataSetPathEntry::DataElement { tag }] = path.entries()
+            && let Some(original) = self.omitted_values.get(tag)
+            && value == protocol::placeholder(original)
+        {
+            return original.clone();
+        }
+
+        value
+    }
+}
+
+/// A DICOM P10 file being read in chunks, along with the lines of the error that stopped reading
+/// if there was one.
+///
+#[derive(Default)]
+struct P10Read {
+    reader: P10StreamReader,
+    error_lines: Vec<String>,
+}
+
+/// Runs a job, returning its output and the output's payload.
+///
+async fn execute(
+    job: Job,
+    payload: &js_sys::Array,
+    storage: &mut Storage,
+    emit_chunk: &mut dyn FnMut(js_sys::Uint8Array),
+    checkpoint: &Checkpoint,
+) -> Result<(Output, js_sys::Array), String> {
+    match job {
+        Job::ReadDataSet { key, source_type } => {
+            let (data_set, error_lines) = match source_type.read(&protocol::payload_bytes(payload))
+            {
+                Ok(data_set) => (data_set, vec![]),
+                Err(e) => e,
+            };
+
+            let output = header_output(&data_set, error_lines, None);
+
+            if let Some(key) = key {
+                storage.data_sets.insert(key, StoredDataSet::new(data_set));
+            }
+
+            Ok(output)
+        }
+
+        Job::ReadP10Chunk { key } => {
+            let read = storage.p10_reads.entry(key).or_default();
+
+            // Chunks after an error are ignored, and the error is reported when reading finishes
+            if !read.error_lines.is_empty() {
+                return Ok((Output::Empty, js_sys::Array::new()));
+            }
+
+            match read.reader.write(protocol::payload_bytes(payload), false) {
+                Ok(Some(header)) => Ok(header_output(&header, vec![], None)),
+                Ok(None) => Ok((Output::Empty, js_sys::Array::new())),
+                Err(lines) => {
+                    read.error_lines = lines;
+                    Ok((Output::Empty, js_sys::Array::new()))
+                }
+            }
+        }
+
+        Job::FinishReadP10 { key } => {
+            let P10Read {
+                mut reader,
+                mut error_lines,
+            } = storage.p10_reads.remove(&key).unwrap_or_default();
+
+            if error_lines.is_empty()
+                && let Err(lines) = reader.write(vec![], true)
+            {
+                error_lines = lines;
+            }
+
+            let read_failure = reader.read_failure().cloned();
+
+            let (data_set, error_lines) = match reader.finish(error_lines) {
+                Ok(data_set) => (data_set, vec![]),
+                Err(e) => e,
+            };
+
+            let output = header_output(&data_set, error_lines, read_failure);
+            storage.data_sets.insert(key, StoredDataSet::new(data_set));
+
+            Ok(output)
+        }
+
+        Job::ReadP10Structure => {
+            let structure = raw_token_view::read_p10_structure(&protocol::payload_bytes(payload));
+
+            Ok((Output::P10Structure { structure }, js_sys::Array::new()))
+        }
+
+        Job::ApplyEdits { key, edits } => {
+            let stored = storage.get_mut(key)?;
+
+            let bytes = payload
+                .get(0)
+                .dyn_into::<js_sys::Uint8Array>()
+                .map_err(|_| "The job's payload is missing the edited values".to_string())?;
+
+            for edit in edits {
+                let path = protocol::decode_path(&edit.path)?;
+
+                let value = match &edit.value {
+                    Some(value) => {
+                        let (_, tag) = editing::split_data_element_path(&path)?;
+                        let value = protocol::decode_value(tag, value, &bytes)?;
+
+                        Some(stored.resolve_edit(&path, value))
+                    }
+                    None => None,
+                };
+
+                editing::replace_value(&mut stored.data_set, &path, value)?;
+            }
+
+            stored.frames = None;
+            stored.stored_values = None;
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+
+        Job::UnloadDataSet { key } => {
+            storage.data_sets.remove(&key);
+            storage.p10_reads.remove(&key);
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+
+        Job::ReadValue { key, path } => {
+            let path = protocol::decode_path(&path)?;
+
+            let value = editing::get_value(&storage.get(key)?.data_set, &path)
+                .ok_or_else(|| "The data element no longer exists".to_string())?;
+
+            let mut encoder = protocol::Encoder::default();
+            let value = encoder.value(&value);
+
+            Ok((
+                Output::Value { value },
+                js_sys::Array::of1(&encoder.finish()),
+            ))
+        }
+
+        Job::RenderFrame {
+            key,
+            frame_index,
+            voi_selection,
+        } => {
+            let StoredDataSet {
+                data_set, frames, ..
+            } = storage.get_mut(key)?;
+
+            let frames =
+                frames.get_or_insert_with(|| data_set.get_pixel_data_frames().unwrap_or_default());
+
+            let Some(frame) = frames.get_mut(frame_index) else {
+                return Err(format!(
+                    "Pixel data frame index '{}' is out of range",
+                    frame_index
+                ));
+            };
+
+            let image = pixel_data_frame_view::render_frame(data_set, frame, voi_selection)?;
+
+            Ok((
+                Output::Frame {
+                    width: image.width(),
+                    height: image.height(),
+                },
+                js_sys::Array::of1(&protocol::bytes_to_js(image.as_raw())),
+            ))
+        }
+
+        Job::ProbePixel {
+            key,
+            frame_index,
+            column,
+            row,
+        } => {
+            let StoredDataSet {
+                data_set,
+                stored_values,
+                ..
+            } = storage.get_mut(key)?;
+
+            // The stored values of a frame are extracted the first time it's probed, and kept
+            // while pixels in it continue to be probed
+            let stored_values = match stored_values {
+                Some((index, values)) if *index == frame_index => values,
+                cached => {
+                    let values = StoredValues::from_data_set(data_set, frame_index);
+                    &mut cached.insert((frame_index, values)).1
+                }
+            };
+
+            let values = stored_values
+                .as_ref()
+                .map_err(Clone::clone)?
+                .get(column, row)
+                .ok_or_else(|| "Pixel is outside the stored values".to_string())?
+                .to_vec();
+
+            Ok((Output::StoredValues { values }, js_sys::Array::new()))
+        }
+
+        Job::ExportJson { keys, options } => {
+            let data_sets = keys
+                .iter()
+                .map(|key| storage.get(*key).map(|stored| &stored.data_set))
+                .collect::<Result<Vec<_>, String>>()?;
+
+            let mut writer = ChunkWriter::new(emit_chunk);
+            json_export_dialog::export_json(&data_sets, &options, &mut writer, &async || {
+                checkpoint.check().await
+            })
+            .await?;
+            writer.finish();
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+
+        Job::ExportXml { key } => {
+            let data_set = &storage.get(key)?.data_set;
+
+            let mut writer = ChunkWriter::new(emit_chunk);
+            native_xml::write_data_set(
+                data_set,
+                &mut |s| writer.write(s.as_bytes()),
+                &async || checkpoint.check().await,
+            )
+            .await?;
+            writer.finish();
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+
+        Job::GenerateMediaSet { layout, data_sets } => {
+            let data_sets = data_sets
+                .iter()
+                .map(|(file_id, key)| Ok((*file_id, &storage.get(*key)?.data_set)))
+                .collect::<Result<HashMap<_, _>, String>>()?;
+
+            let writer = dicomdir::write_media_set(
+                &layout,
+                &data_sets,
+                ChunkWriter::new(emit_chunk),
+                &async || checkpoint.check().await,
+            )
+            .await?;
+            writer.finish();
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+
+        Job::WriteP10 {
+            key,
+            transfer_syntax,
+        } => {
+            let data_set = &storage.get(key)?.data_set;
+
+            let data_set = match transfer_syntax {
+                Some(target) => {
+                    download_p10_dialog::transcode_to(
+                        data_set,
+                        &target.uid,
+                        target.jpeg_quality,
+                        &async || checkpoint.check().await,
+                    )
+                    .await?
+                }
+                None => Cow::Borrowed(data_set),
+            };
+
+            let mut writer = ChunkWriter::new(emit_chunk);
+            data_set
+                .write_p10_stream(&mut writer, None)
+                .map_err(|e| e.to_lines("writing P10 file").join(", "))?;
+            writer.finish();
+
+            Ok((Output::Empty, js_sys::Array::new()))
+        }
+    }
+}
+
+/// Returns the output for a job that reads a data set, which is only the data set's header, see
+/// [`protocol::Encoder::header()`].
+///
+fn header_output(
+    data_set: &DataSet,
+    error_lines: Vec<String>,
+    read_failure: Option<ReadFailure>,
+) -> (Output, js_sys::Array) {
+    let (data_set, bytes) = protocol::encode_header(data_set);
+
+    (
+        Output::DataSet {
+            data_set,
+            error_lines,
+            read_failure,
+        },
+        js_sys::Array::of1(&bytes),
+    )
+}
+
+/// Buffers written bytes and emits them in chunks of [`CHUNK_SIZE`] as they fill, so that output
+/// is sent back while it's still being written.
+///
+struct ChunkWriter<'a> {
+    buffer: Vec<u8>,
+    emit_chunk: &'a mut dyn FnMut(js_sys::Uint8Array),
+}
+
+impl<'a> ChunkWriter<'a> {
+    fn new(emit_chunk: &'a mut dyn FnMut(js_sys::Uint8Array)) -> Self {
+        Self {
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            emit_chunk,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut remaining = bytes;
+
+        while !remaining.is_empty() {
+            let to_write = remaining.len().min(CHUNK_SIZE - self.buffer.len());
+
+            self.buffer.extend_from_slice(&remaining[..to_write]);
+            remaining = &remaining[to_write..];
+
+            if self.buffer.len() == CHUNK_SIZE {
+                self.emit();
+            }
+        }
+    }
+
+    /// Emits any buffered bytes that haven't been sent yet.
+    ///
+    fn finish(mut self) {
+        if !self.buffer.is_empty() {
+            self.emit();
+        }
+    }
+
+    fn emit(&mut self) {
+        (self.emit_chunk)(protocol::bytes_to_js(&self.buffer));
+        self.buffer.clear();
+    }
+}
+
+impl dcmfx::p10::IoWrite for ChunkWriter<'_> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), dcmfx::p10::IoError> {
+        self.write(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), dcmfx::p10::IoError> {
+        Ok(())
+    }
+}
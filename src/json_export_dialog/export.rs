@@ -0,0 +1,383 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use dcmfx::{core::*, json::*, p10::IoWrite};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The options used when exporting data sets as DICOM JSON.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonExportOptions {
+    pub store_encapsulated_pixel_data: bool,
+    pub pretty_print: bool,
+
+    /// Binary values larger than this number of bytes are replaced with a `BulkDataURI`.
+    pub bulk_data_threshold: Option<usize>,
+
+    /// Whether to leave out the File Meta Information, i.e. data elements in group 0x0002.
+    pub exclude_file_meta_information: bool,
+
+    /// Whether to output a JSON array of instances, as returned by DICOMweb, rather than a single
+    /// JSON object.
+    pub as_array: bool,
+}
+
+impl Default for JsonExportOptions {
+    fn default() -> Self {
+        Self {
+            store_encapsulated_pixel_data: true,
+            pretty_print: true,
+            bulk_data_threshold: None,
+            exclude_file_meta_information: false,
+            as_array: false,
+        }
+    }
+}
+
+/// Exports the given data sets as DICOM JSON to the writer. Unless the output is a JSON array,
+/// there must be exactly one data set.
+///
+/// `checkpoint` is awaited before each data set is converted, and exporting stops with its error
+/// if it returns one.
+///
+pub async fn export(
+    data_sets: &[&DataSet],
+    options: &JsonExportOptions,
+    writer: &mut impl IoWrite,
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<(), String> {
+    if !options.as_array && data_sets.len() != 1 {
+        return Err("Exporting multiple data sets requires a JSON array".into());
+    }
+
+    // Data sets are only copied when their File Meta Information needs to be removed
+    let data_sets = data_sets
+        .iter()
+        .map(|data_set| {
+            if options.exclude_file_meta_information {
+                Cow::Owned(without_file_meta_information(data_set))
+            } else {
+                Cow::Borrowed(*data_set)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // When no changes to the JSON are needed, stream it straight from dcmfx
+    if !options.as_array && options.bulk_data_threshold.is_none() {
+        let config = DicomJsonConfig {
+            store_encapsulated_pixel_data: options.store_encapsulated_pixel_data,
+            pretty_print: options.pretty_print,
+        };
+
+        data_sets[0]
+            .to_json_stream(config, writer)
+            .map_err(|e| e.to_string())?;
+
+        return Ok(());
+    }
+
+    let mut instances = vec![];
+    for data_set in data_sets.iter() {
+        checkpoint().await?;
+
+        let config = DicomJsonConfig {
+            store_encapsulated_pixel_data: options.store_encapsulated_pixel_data,
+            pretty_print: false,
+        };
+
+        // Values over the bulk data threshold are removed before converting to JSON so they're
+        // never base64 encoded, and their data elements are then given a BulkDataURI
+        let mut bulk_data_uris = HashSet::new();
+        let data_set = match options.bulk_data_threshold {
+            Some(threshold) => {
+                let is_bulk_data = |value: &DataElementValue| {
+                    bulk_data_size(value, options.store_encapsulated_pixel_data)
+                        .is_some_and(|size| size > threshold)
+                };
+
+                Cow::Owned(strip_bulk_data(
+                    data_set,
+                    &is_bulk_data,
+                    "bulkdata",
+                    &mut bulk_data_uris,
+                ))
+            }
+            None => Cow::Borrowed(&**data_set),
+        };
+
+        let json = data_set.to_json(config).map_err(|e| e.to_string())?;
+        let mut value = serde_json::from_str::<Value>(&json).map_err(|e| e.to_string())?;
+
+        if let Value::Object(object) = &mut value {
+            insert_bulk_data_uris(object, &bulk_data_uris, "bulkdata");
+        }
+
+        instances.push(value);
+    }
+
+    let value = if options.as_array {
+        Value::Array(instances)
+    } else {
+        instances.remove(0)
+    };
+
+    let bytes = if options.pretty_print {
+        serde_json::to_vec_pretty(&value)
+    } else {
+        serde_json::to_vec(&value)
+    }
+    .map_err(|e| e.to_string())?;
+
+    writer
+        .write_all(&bytes)
+        .map_err(|e| format!("Failed writing JSON: {:?}", e))
+}
+
+/// Returns a copy of the data set without its File Meta Information.
+///
+fn without_file_meta_information(data_set: &DataSet) -> DataSet {
+    let mut data_set = data_set.clone();
+
+    let tags = data_set
+        .iter()
+        .map(|(tag, _)| *tag)
+        .filter(|tag| tag.group == 0x0002)
+        .collect::<Vec<_>>();
+
+    for tag in tags {
+        data_set.delete(tag);
+    }
+
+    data_set
+}
+
+/// Returns the size of a value that DICOM JSON stores as `InlineBinary`, or `None` if the value
+/// isn't binary.
+///
+fn bulk_data_size(value: &DataElementValue, store_encapsulated_pixel_data: bool) -> Option<usize> {
+    if let Ok(items) = value.encapsulated_pixel_data() {
+        return store_encapsulated_pixel_data.then(|| items.iter().map(|item| item.len()).sum());
+    }
+
+    match value.value_representation() {
+        ValueRepresentation::OtherByteString
+        | ValueRepresentation::OtherDoubleString
+        | ValueRepresentation::OtherFloatString
+        | ValueRepresentation::OtherLongString
+        | ValueRepresentation::OtherVeryLongString
+        | ValueRepresentation::OtherWordString
+        | ValueRepresentation::Unknown => value.bytes().ok().map(|bytes| bytes.len()),
+        _ => None,
+    }
+}
+
+/// Returns a copy of the data set with bulk data values replaced by empty values, recursing into
+/// sequences. The URI of each replaced value is added to `uris`, and is built from the path to the
+/// data element so that each one is unique.
+///
+fn strip_bulk_data(
+    data_set: &DataSet,
+    is_bulk_data: &impl Fn(&DataElementValue) -> bool,
+    uri_prefix: &str,
+    uris: &mut HashSet<String>,
+) -> DataSet {
+    let mut stripped = DataSet::new();
+
+    for (tag, value) in data_set.iter() {
+        let uri = format!("{}/{:04X}{:04X}", uri_prefix, tag.group, tag.element);
+
+        let value = if let Ok(items) = value.sequence_items() {
+            DataElementValue::new_sequence(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| {
+                        strip_bulk_data(item, is_bulk_data, &format!("{}/{}", uri, index), uris)
+                    })
+                    .collect(),
+            )
+        } else if is_bulk_data(value) {
+            uris.insert(uri);
+            DataElementValue::new_binary_unchecked(value.value_representation(), vec![].into())
+        } else {
+            value.clone()
+        };
+
+        stripped.insert(*tag, value);
+    }
+
+    stripped
+}
+
+/// Gives the data elements whose URIs are in `uris` a `BulkDataURI` in place of their value,
+/// recursing into sequences.
+///
+fn insert_bulk_data_uris(
+    data_set: &mut Map<String, Value>,
+    uris: &HashSet<String>,
+    uri_prefix: &str,
+) {
+    for (tag, element) in data_set.iter_mut() {
+        let Value::Object(element) = element else {
+            continue;
+        };
+
+        let uri = format!("{}/{}", uri_prefix, tag);
+
+        if uris.contains(&uri) {
+            element.remove("InlineBinary");
+            element.insert("BulkDataURI".into(), Value::String(uri));
+            continue;
+        }
+
+        if element.get("vr") == Some(&Value::String("SQ".into()))
+            && let Some(Value::Array(items)) = element.get_mut("Value")
+        {
+            for (index, item) in items.iter_mut().enumerate() {
+                if let Value::Object(item) = item {
+                    insert_bulk_data_uris(item, uris, &format!("{}/{}", uri, index));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        test_utils::{insert, test_data_set},
+        utils::BytesWriter,
+    };
+
+    fn export_to_json(
+        data_sets: &[&DataSet],
+        options: &JsonExportOptions,
+    ) -> Result<Value, String> {
+        let mut writer = BytesWriter::default();
+
+        export(data_sets, options, &mut writer, &async || Ok(()))
+            .now_or_never()
+            .unwrap()?;
+
+        Ok(serde_json::from_slice(&writer.into_bytes()).unwrap())
+    }
+
+    #[test]
+    fn exports_an_array_with_bulk_data_and_without_file_meta_information() {
+        let mut data_set = test_data_set();
+        insert(
+            &mut data_set,
+            dictionary::TRANSFER_SYNTAX_UID.tag,
+            "UI",
+            "1.2.840.10008.1.2.4.50",
+        );
+
+        let options = JsonExportOptions {
+            bulk_data_threshold: Some(2),
+            exclude_file_meta_information: true,
+            as_array: true,
+            ..JsonExportOptions::default()
+        };
+
+        let json = export_to_json(&[&data_set, &data_set], &options).unwrap();
+
+        let instances = json.as_array().unwrap();
+        assert_eq!(instances.len(), 2);
+
+        for instance in instances {
+            assert!(instance.get("00020010").is_none());
+            assert_eq!(instance["00080060"]["Value"], json!(["OT"]));
+            assert_eq!(
+                instance["7FE00010"]["BulkDataURI"],
+                json!("bulkdata/7FE00010")
+            );
+        }
+
+        // The data set itself isn't changed
+        assert!(
+            data_set
+                .get_value(dictionary::TRANSFER_SYNTAX_UID.tag)
+                .is_ok()
+        );
+    }
+
+    fn binary_value(length: usize) -> DataElementValue {
+        DataElementValue::new_binary(
+            ValueRepresentation::from_bytes(b"OB").unwrap(),
+            vec![0; length].into(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn values_over_the_threshold_are_replaced() {
+        let mut data_set = DataSet::new();
+        data_set.insert(DataElementTag::new(0x0009, 0x1010), binary_value(7));
+        data_set.insert(DataElementTag::new(0x0009, 0x1011), binary_value(6));
+
+        let options = JsonExportOptions {
+            bulk_data_threshold: Some(6),
+            ..JsonExportOptions::default()
+        };
+
+        let json = export_to_json(&[&data_set], &options).unwrap();
+
+        assert_eq!(
+            json["00091010"],
+            json!({ "vr": "OB", "BulkDataURI": "bulkdata/00091010" })
+        );
+        assert_eq!(
+            json["00091011"],
+            json!({ "vr": "OB", "InlineBinary": "AAAAAAAA" })
+        );
+    }
+
+    #[test]
+    fn values_in_sequences_are_replaced_with_unique_uris() {
+        let mut item = DataSet::new();
+        item.insert(DataElementTag::new(0x0009, 0x1010), binary_value(8));
+
+        let mut data_set = DataSet::new();
+        data_set.insert(
+            dictionary::REFERENCED_SERIES_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![item.clone(), item]),
+        );
+
+        let options = JsonExportOptions {
+            bulk_data_threshold: Some(4),
+            ..JsonExportOptions::default()
+        };
+
+        let json = export_to_json(&[&data_set], &options).unwrap();
+
+        let items = &json["00081115"]["Value"];
+        assert_eq!(
+            items[0]["00091010"]["BulkDataURI"],
+            json!("bulkdata/00081115/0/00091010")
+        );
+        assert_eq!(
+            items[1]["00091010"]["BulkDataURI"],
+            json!("bulkdata/00081115/1/00091010")
+        );
+        assert!(items[0]["00091010"].get("InlineBinary").is_none());
+    }
+
+    #[test]
+    fn exports_a_single_object() {
+        let json = export_to_json(&[&test_data_set()], &JsonExportOptions::default()).unwrap();
+
+        assert!(json.get("00080060").is_some());
+        assert!(json["7FE00010"].get("InlineBinary").is_some());
+    }
+
+    #[test]
+    fn multiple_data_sets_require_an_array() {
+        let data_set = test_data_set();
+
+        assert!(export_to_json(&[&data_set, &data_set], &JsonExportOptions::default()).is_err());
+    }
+}
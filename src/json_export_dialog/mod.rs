@@ -0,0 +1,231 @@
+mod export;
+
+use std::rc::Rc;
+
+use dioxus::prelude::*;
+
+use crate::{
+    file_list::{OpenedFile, ReadStatus},
+    jobs::{self, JobError, JobState, WorkerDataSet},
+    ui, utils,
+};
+pub use export::{JsonExportOptions, export as export_json};
+
+/// The bulk data size thresholds offered in the export dialog, in bytes.
+///
+const BULK_DATA_THRESHOLDS: [usize; 5] = [256, 1024, 16 * 1024, 256 * 1024, 1024 * 1024];
+
+/// A dialog for choosing the options used when downloading the data set as DICOM JSON. The data
+/// sets of the other opened files can be included when outputting a JSON array, in which case
+/// they're taken from the files when the download starts. Files that haven't been read in full
+/// are left out, and the user is told about them. The data sets are exported from their copies in
+/// the job worker.
+///
+#[component]
+pub fn JsonExportDialog(
+    worker_data_set: ReadSignal<Option<Rc<WorkerDataSet>>>,
+    files: Signal<Vec<OpenedFile>>,
+    active_file_id: Option<u64>,
+    filename: String,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut options = use_signal(JsonExportOptions::default);
+    let mut include_other_files = use_signal(|| false);
+    let export_state = use_signal(JobState::default);
+
+    let (other_file_count, unread_file_count) = {
+        let files = files.read();
+        let other_files = files
+            .iter()
+            .filter(|file| Some(file.id) != active_file_id)
+            .collect::<Vec<_>>();
+
+        let unread_file_count = other_files
+            .iter()
+            .filter(|file| !is_exportable(file))
+            .count();
+
+        (other_files.len(), unread_file_count)
+    };
+
+    // The export runs in the job worker, and is cancelled if the dialog is closed before it
+    // completes
+    let on_download = move |_| {
+        let Some(active_data_set) = worker_data_set.peek().clone() else {
+            ui::toasts::add_error("The file is still being read".into());
+            return;
+        };
+
+        let mut data_sets = vec![active_data_set];
+        if options.peek().as_array && include_other_files() {
+            let files = files.peek();
+            let (other_files, unread_files): (Vec<_>, Vec<_>) = files
+                .iter()
+                .filter(|file| Some(file.id) != active_file_id)
+                .partition(|file| is_exportable(file));
+
+            data_sets.extend(
+                other_files
+                    .iter()
+                    .filter_map(|file| file.state.worker_data_set.clone()),
+            );
+
+            if !unread_files.is_empty() {
+                ui::toasts::add_info(format!(
+                    "Excluded files that haven't been read: {}",
+                    unread_files
+                        .iter()
+                        .map(|file| file.filename.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+
+        let options = options.peek().clone();
+        let filename = filename.clone();
+
+        spawn(async move {
+            let data_sets = data_sets.iter().map(Rc::as_ref).collect::<Vec<_>>();
+
+            match jobs::export_json(&data_sets, options, export_state).await {
+                Ok(blob_parts) => {
+                    utils::download::trigger(blob_parts, &filename, "application/json").unwrap();
+
+                    ui::toasts::add_info("Generated DICOM JSON file for download".into());
+                    on_close.call(());
+                }
+                Err(JobError::Failed(e)) => ui::toasts::add_error(e),
+                Err(JobError::Cancelled) => (),
+            }
+        });
+    };
+
+    rsx! {
+        ui::Dialog {
+            title: "Download as .json",
+            on_close,
+
+            div {
+                class: "export-options",
+
+                label {
+                    input {
+                        r#type: "checkbox",
+                        checked: options.read().pretty_print,
+                        onchange: move |event| options.write().pretty_print = event.checked(),
+                    }
+                    "Pretty print"
+                }
+
+                label {
+                    input {
+                        r#type: "checkbox",
+                        checked: options.read().store_encapsulated_pixel_data,
+                        onchange: move |event| {
+                            options.write().store_encapsulated_pixel_data = event.checked()
+                        },
+                    }
+                    "Include encapsulated pixel data"
+                }
+
+                label {
+                    input {
+                        r#type: "checkbox",
+                        checked: options.read().exclude_file_meta_information,
+                        onchange: move |event| {
+                            options.write().exclude_file_meta_information = event.checked()
+                        },
+                    }
+                    "Exclude File Meta Information (group 0002)"
+                }
+
+                label {
+                    input {
+                        r#type: "checkbox",
+                        checked: options.read().as_array,
+                        onchange: move |event| options.write().as_array = event.checked(),
+                    }
+                    "Output a DICOMweb-style JSON array of instances"
+                }
+
+                if other_file_count > 0 {
+                    label {
+                        class: if !options.read().as_array { "disabled" },
+
+                        input {
+                            r#type: "checkbox",
+                            disabled: !options.read().as_array,
+                            checked: include_other_files(),
+                            onchange: move |event| include_other_files.set(event.checked()),
+                        }
+                        "Include the {other_file_count} other opened files in the array"
+                    }
+
+                    if unread_file_count > 0 {
+                        div {
+                            class: "hint",
+
+                            "{unread_file_count} of them haven't been read yet, and will be excluded. "
+                            "Select a file to read it."
+                        }
+                    }
+                }
+
+                label {
+                    "Replace binary values larger than"
+
+                    select {
+                        onchange: move |event| {
+                            options.write().bulk_data_threshold = event.value().parse().ok();
+                        },
+
+                        option {
+                            value: "",
+                            selected: options.read().bulk_data_threshold.is_none(),
+                            "No limit"
+                        }
+
+                        for threshold in BULK_DATA_THRESHOLDS {
+                            option {
+                                value: "{threshold}",
+                                selected: options.read().bulk_data_threshold == Some(threshold),
+
+                                {utils::format_size(threshold as u64)}
+                            }
+                        }
+                    }
+
+                    "with a BulkDataURI"
+                }
+            }
+
+            div {
+                class: "dialog-buttons",
+
+                button { onclick: move |_| on_close.call(()), "Cancel" }
+                button {
+                    class: "primary",
+                    disabled: export_state().is_running(),
+                    onclick: on_download,
+
+                    match export_state() {
+                        JobState::Running { bytes_received } => {
+                            rsx! { "Exporting… {utils::format_size(bytes_received)}" }
+                        }
+                        JobState::Idle => rsx! { "Download" },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether an opened file that isn't the active file has been read, so that its data set
+/// can be exported.
+///
+fn is_exportable(file: &OpenedFile) -> bool {
+    file.read_status == ReadStatus::Read
+        && file.state.worker_data_set.is_some()
+        && !file.state.data_set.is_empty()
+}
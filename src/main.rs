@@ -1,36 +1,70 @@
 #![allow(non_snake_case)]
 
-use dcmfx::{core::*, json::*, p10::*};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use dcmfx::core::*;
 use dioxus::{document::Title, prelude::*};
 use dioxus_elements::{FileData, HasFileData};
 
+mod anonymize_dialog;
 mod data_set_grid;
+mod dicomdir;
+mod download_p10_dialog;
 mod drop_area;
+mod editing;
+mod file_list;
+mod hex_dump_dialog;
+mod history_panel;
+mod jobs;
+mod json_export_dialog;
+mod native_xml;
 mod pixel_data_frame_view;
+mod raw_token_view;
+mod source_type;
+mod study_browser;
+#[cfg(test)]
+mod test_utils;
 mod ui;
 mod utils;
 
+use anonymize_dialog::*;
 use data_set_grid::*;
+use dicomdir::*;
+use download_p10_dialog::*;
 use drop_area::*;
+use editing::History;
+use file_list::*;
+use history_panel::*;
+use jobs::{JobError, JobState, WorkerDataSet};
+use json_export_dialog::*;
 use pixel_data_frame_view::*;
+use raw_token_view::*;
+use source_type::*;
+use study_browser::*;
 
 const LOGO_SVG: Asset = asset!("/assets/logo.svg");
 const MAIN_CSS: Asset = asset!("/assets/main.scss");
 
 fn main() {
-    launch(App);
-}
+    // The job worker loads this same WASM module, and runs jobs instead of the app
+    if jobs::is_worker() {
+        jobs::run_worker();
+        return;
+    }
 
-#[derive(Clone)]
-enum DataSetSourceType {
-    P10,
-    Json,
+    launch(App);
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, Default, PartialEq)]
 enum ViewMode {
+    #[default]
     DataSet,
     PixelData,
+    Directory,
+    Tokens,
 }
 
 #[component]
@@ -43,105 +77,505 @@ fn App() -> Element {
 
     let mut view_mode = use_signal(|| ViewMode::DataSet);
 
-    let mut clear_dicom = move || {
-        dicom_filename.set("".to_string());
-        data_set.set(DataSet::new());
-        error_lines.set(vec![]);
+    // The undo/redo history of modifications made to the data set
+    let mut history = use_signal(History::default);
+
+    // The copy of the data set held in the job worker, which jobs that use it run on
+    let mut worker_data_set = use_signal(|| None::<Rc<WorkerDataSet>>);
+
+    // Apply edits to the job worker's copy of the data set as they're made. This effect runs before
+    // those of child components, so jobs they start in response to an edit see it.
+    use_effect(move || {
+        if !history.read().has_edits() {
+            return;
+        }
+
+        let edits = history.write_silent().take_edits();
+        if let Some(worker_data_set) = &*worker_data_set.peek() {
+            worker_data_set.apply_edits(&edits);
+        }
+    });
+
+    let mut is_anonymize_dialog_open = use_signal(|| false);
+    let mut is_download_p10_dialog_open = use_signal(|| false);
+    let mut is_json_export_dialog_open = use_signal(|| false);
+    let mut is_generating_media_set = use_signal(|| false);
+    let media_set_state = use_signal(JobState::default);
+    let xml_export_state = use_signal(JobState::default);
+
+    // The grid's expansion state is held here so that it's preserved when switching views, and is
+    // remembered for each opened file so that it's restored if that file is opened again
+    let mut grid_expansion = use_signal(GridExpansion::default);
+    let mut grid_expansions = use_signal(HashMap::<String, GridExpansion>::new);
+
+    // The opened files, and the ID of the one being viewed. The active file's state is held in the
+    // signals above while it's being viewed, and is stored in its entry when switching files
+    let mut files = use_signal(Vec::<OpenedFile>::new);
+    let mut active_file_id = use_signal(|| None::<u64>);
+    let mut next_file_id = use_signal(|| 0u64);
+
+    // The progress of files that are being read in full, and the IDs of those whose reading has
+    // been cancelled
+    let mut read_progress = use_signal(HashMap::<u64, ReadProgress>::new);
+    let mut cancelled_reads = use_signal(HashSet::<u64>::new);
+
+    // The patient, study, or series selected in the study browser, whose shared attributes are
+    // shown in place of the active file
+    let selected_study_node = use_signal(|| None::<NodeKey>);
+
+    // When the active file is one of a series of single-frame images, the pixel data view shows
+    // the whole series as a stack of slices
+    let series_stack =
+        use_memo(move || active_file_id().and_then(|id| SeriesStack::for_file(&files.read(), id)));
+
+    // The P10 tokens of the active file and the offsets of its data elements, which are read from
+    // the file's bytes in the job worker only while they're being shown
+    let show_offsets = use_signal(|| false);
+    let mut jump_to_read_failure = use_signal(|| false);
+    let mut p10_structure = use_signal(|| None::<Result<P10Structure, String>>);
+    let mut p10_structure_task = use_signal(|| None::<Task>);
+
+    let p10_structure_file_id = use_memo(move || {
+        if !show_offsets() && view_mode() != ViewMode::Tokens {
+            return None;
+        }
+
+        let id = active_file_id()?;
+        let is_p10 = data_set_source_type() == DataSetSourceType::P10
+            && files
+                .read()
+                .iter()
+                .any(|file| file.id == id && file.sniffed_file.is_some());
+
+        is_p10.then_some(id)
+    });
+
+    use_effect(move || {
+        let id = p10_structure_file_id();
+
+        if let Some(task) = p10_structure_task.write().take() {
+            task.cancel();
+        }
+        p10_structure.set(None);
+
+        let Some((file_data, sniffed_file)) = id.and_then(|id| {
+            files
+                .peek()
+                .iter()
+                .find(|file| file.id == id)
+                .and_then(|file| Some((file.file_data.clone(), file.sniffed_file.clone()?)))
+        }) else {
+            return;
+        };
+
+        let task = spawn(async move {
+            let bytes = match file_list::read_file_content(&file_data, &sniffed_file).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    p10_structure.set(Some(Err(e)));
+                    return;
+                }
+            };
+
+            match jobs::read_p10_structure(&bytes).await {
+                Ok(structure) => p10_structure.set(Some(Ok(structure))),
+                Err(JobError::Failed(e)) => p10_structure.set(Some(Err(e))),
+                Err(JobError::Cancelled) => (),
+            }
+        });
+        p10_structure_task.set(Some(task));
+    });
+
+    let mut show_file_state = move |state: FileState| {
+        data_set.set(state.data_set);
+        worker_data_set.set(state.worker_data_set);
+        data_set_source_type.set(state.source_type);
+        error_lines.set(state.error_lines);
+        history.set(state.history);
+        grid_expansion.set(state.grid_expansion);
+        view_mode.set(state.view_mode);
     };
 
-    let mut on_select_input_file = move |file_data: Option<FileData>| {
-        dicom_filename.set("".to_string());
-        error_lines.set(vec![]);
+    let mut store_active_file = move || {
+        let Some(id) = active_file_id.take() else {
+            return;
+        };
+
+        let state = FileState {
+            data_set: data_set.take(),
+            worker_data_set: worker_data_set.take(),
+            source_type: data_set_source_type(),
+            error_lines: error_lines.take(),
+            history: history.take(),
+            grid_expansion: grid_expansion.take(),
+            view_mode: view_mode(),
+        };
 
-        spawn(async move {
-            let Some(file_data) = file_data else {
+        if let Some(file) = files.write().iter_mut().find(|file| file.id == id) {
+            file.state = state;
+        }
+    };
+
+    // Stores the state of a file that has finished being read in full, showing it if it's the
+    // active file
+    let mut set_file_state = move |id: u64,
+                                   filename: String,
+                                   sniffed_file: Option<SniffedFile>,
+                                   mut state: FileState| {
+        let mut files = files.write();
+        let Some(file) = files.iter_mut().find(|file| file.id == id) else {
+            return;
+        };
+
+        file.filename = filename;
+        file.sniffed_file = sniffed_file;
+        file.read_status = ReadStatus::Read;
+
+        let is_active = active_file_id() == Some(id);
+        let stored_expansion = grid_expansions.write().remove(&file.key);
+
+        // Keep any expansion made while the file's header was shown during reading
+        state.grid_expansion = if is_active && data_set.peek().size() > 0 {
+            grid_expansion.peek().clone()
+        } else {
+            stored_expansion.unwrap_or_default()
+        };
+
+        if is_active {
+            dicom_filename.set(file.filename.clone());
+            drop(files);
+            show_file_state(state);
+        } else {
+            file.state = state;
+        }
+    };
+
+    // Shows the header of a file that's still being read, so that its attributes can be viewed
+    // before its pixel data has loaded
+    let mut show_file_header = move |id: u64, header: DataSet| {
+        if active_file_id() == Some(id) {
+            data_set.set(header);
+        } else if let Some(file) = files.write().iter_mut().find(|file| file.id == id) {
+            file.state.data_set = header;
+        }
+    };
+
+    let mut activate_file = move |id: u64| {
+        if active_file_id() == Some(id) {
+            return;
+        }
+
+        store_active_file();
+
+        let (filename, state, file_to_read) = {
+            let mut files = files.write();
+            let Some(file) = files.iter_mut().find(|file| file.id == id) else {
                 return;
             };
 
-            let Ok(bytes) = file_data.read_bytes().await else {
-                return;
+            // Files are only read in full the first time they're viewed
+            let file_to_read = if file.read_status == ReadStatus::Unread {
+                file.read_status = ReadStatus::Reading;
+                Some(file.file_data.clone())
+            } else {
+                None
             };
 
-            dicom_filename.set(file_data.name());
+            (
+                file.filename.clone(),
+                std::mem::take(&mut file.state),
+                file_to_read,
+            )
+        };
 
-            // If the file has a .json extension then load it as a DICOM JSON file, otherwise load
-            // it as a DICOM P10
-            if dicom_filename().to_lowercase().ends_with(".json") {
-                let Ok(json) = std::str::from_utf8(&bytes) else {
-                    return;
-                };
+        dicom_filename.set(filename);
+        show_file_state(state);
+        active_file_id.set(Some(id));
+
+        if let Some(file_data) = file_to_read {
+            spawn(async move {
+                let (filename, sniffed_file, state) = file_list::read_file(
+                    &file_data,
+                    move |update| match update {
+                        ReadUpdate::Progress(progress) => {
+                            read_progress.write().insert(id, progress);
+                        }
+                        ReadUpdate::Header(header) => show_file_header(id, header),
+                    },
+                    move || cancelled_reads.peek().contains(&id),
+                )
+                .await;
 
-                match DataSet::from_json(json) {
-                    Ok(ds) => {
-                        data_set.set(ds);
-                        data_set_source_type.set(DataSetSourceType::Json);
-                    }
-                    Err(e) => error_lines.set(e.to_lines("")),
-                };
-            } else {
-                match dcmfx::p10::read_bytes(bytes.to_vec().into()) {
-                    Ok(ds) => {
-                        data_set.set(ds);
-                        data_set_source_type.set(DataSetSourceType::P10);
-                    }
-                    Err((e, mut data_set_builder)) => {
-                        data_set_builder.force_end();
-                        data_set.set(data_set_builder.final_data_set().unwrap());
-                        error_lines.set(e.to_lines("reading file"));
-                    }
-                };
-            }
-        });
+                read_progress.write().remove(&id);
+                cancelled_reads.write().remove(&id);
+
+                set_file_state(id, filename, sniffed_file, state);
+            });
+        }
     };
 
-    let on_download_p10 = move |_: MouseEvent| {
-        let filename = match data_set_source_type() {
-            DataSetSourceType::P10 => dicom_filename(),
-            DataSetSourceType::Json => {
-                format!("{}.dcm", &dicom_filename()[0..dicom_filename().len() - 5])
-            }
+    let mut close_file = move |id: u64| {
+        let is_active = active_file_id() == Some(id);
+        if is_active {
+            store_active_file();
+        }
+
+        let Some(index) = files.read().iter().position(|file| file.id == id) else {
+            return;
         };
 
-        let mut writer = utils::download::BlobPartWriter::new(1024 * 1024);
+        let file = files.write().remove(index);
+        if file.read_status == ReadStatus::Reading {
+            cancelled_reads.write().insert(id);
+        }
+
+        grid_expansions
+            .write()
+            .insert(file.key, file.state.grid_expansion);
+
+        if !is_active {
+            return;
+        }
 
-        match data_set().write_p10_stream(&mut writer, None) {
-            Ok(()) => {
-                utils::download::trigger(writer.into_js_array(), &filename, "application/json")
-                    .unwrap();
+        // Switch to the file that took the closed file's place in the list, or the one before it
+        let next_file_id = {
+            let files = files.read();
+            files
+                .get(index)
+                .or_else(|| files.last())
+                .map(|file| file.id)
+        };
 
-                ui::toasts::add_info("Generated DICOM P10 file for download".into());
+        match next_file_id {
+            Some(id) => activate_file(id),
+            None => {
+                dicom_filename.set("".to_string());
+                show_file_state(FileState::default());
             }
+        }
+    };
+
+    let mut read_active_file_as = move |source_type: DataSetSourceType| {
+        let Some(id) = active_file_id() else {
+            return;
+        };
+
+        let Some((file_data, sniffed_file)) = files
+            .read()
+            .iter()
+            .find(|file| file.id == id)
+            .and_then(|file| Some((file.file_data.clone(), file.sniffed_file.clone()?)))
+        else {
+            return;
+        };
 
-            Err(e) => {
-                ui::toasts::add_info(e.to_lines("writing P10 file").join(", "));
+        spawn(async move {
+            let file_state = match file_list::read_file_content(&file_data, &sniffed_file).await {
+                Ok(bytes) => FileState::read(&bytes, source_type).await,
+                Err(e) => FileState {
+                    error_lines: vec![e],
+                    ..FileState::default()
+                },
+            };
+
+            let state = FileState {
+                grid_expansion: grid_expansion(),
+                view_mode: view_mode(),
+                ..file_state
+            };
+
+            // Ignore the result if a different file was activated while it was being read
+            if active_file_id() == Some(id) {
+                show_file_state(state);
             }
+        });
+    };
+
+    let mut on_select_input_files = move |files_data: Vec<FileData>| {
+        if files_data.is_empty() {
+            return;
         }
+
+        let ids = files_data
+            .iter()
+            .map(|file_data| {
+                let id = next_file_id();
+                next_file_id += 1;
+
+                files.write().push(OpenedFile {
+                    id,
+                    filename: file_data.name(),
+                    key: format!("{}:{}", file_data.name(), file_data.size()),
+                    file_data: file_data.clone(),
+                    header: None,
+                    sniffed_file: None,
+                    read_status: ReadStatus::Unread,
+                    state: FileState::default(),
+                });
+
+                id
+            })
+            .collect::<Vec<_>>();
+
+        // When a DICOMDIR is opened along with the files it references, it's shown first so that
+        // those files can be navigated to from its directory
+        let first_id = files_data
+            .iter()
+            .position(|file_data| file_data.name().eq_ignore_ascii_case("DICOMDIR"))
+            .map_or(ids[0], |index| ids[index]);
+
+        activate_file(first_id);
+
+        // Read the header of every file so they can be shown in the file list and study browser
+        // without holding all of their pixel data in memory
+        spawn(async move {
+            for (id, file_data) in ids.into_iter().zip(files_data) {
+                let header = file_list::read_header(&file_data).await;
+
+                if let Some(file) = files.write().iter_mut().find(|file| file.id == id) {
+                    file.header = Some(header);
+                }
+            }
+        });
     };
 
-    let on_download_json = move |_: MouseEvent| {
+    let download_p10 = move |blob_parts: js_sys::Array| {
         let filename = match data_set_source_type() {
-            DataSetSourceType::P10 => format!("{}.json", dicom_filename()),
-            DataSetSourceType::Json => dicom_filename(),
+            DataSetSourceType::P10 => dicom_filename(),
+            DataSetSourceType::Json | DataSetSourceType::Xml => {
+                format!("{}.dcm", filename_stem(&dicom_filename()))
+            }
         };
 
-        let mut writer = utils::download::BlobPartWriter::new(1024 * 1024);
+        utils::download::trigger(blob_parts, &filename, "application/dicom").unwrap();
+
+        ui::toasts::add_info("Generated DICOM P10 file for download".into());
+    };
+
+    let json_filename = move || match data_set_source_type() {
+        DataSetSourceType::P10 => format!("{}.json", dicom_filename()),
+        DataSetSourceType::Json => dicom_filename(),
+        DataSetSourceType::Xml => format!("{}.json", filename_stem(&dicom_filename())),
+    };
+
+    let download_xml = move |_| {
+        let filename = match data_set_source_type() {
+            DataSetSourceType::P10 => format!("{}.xml", dicom_filename()),
+            DataSetSourceType::Json => format!("{}.xml", filename_stem(&dicom_filename())),
+            DataSetSourceType::Xml => dicom_filename(),
+        };
 
-        let config = DicomJsonConfig {
-            store_encapsulated_pixel_data: true,
-            pretty_print: true,
+        let Some(worker_data_set) = worker_data_set.peek().clone() else {
+            ui::toasts::add_error("The file is still being read".into());
+            return;
         };
 
-        match data_set().to_json_stream(config, &mut writer) {
-            Ok(()) => {
-                utils::download::trigger(writer.into_js_array(), &filename, "application/dicom")
-                    .unwrap();
+        spawn(async move {
+            match worker_data_set.export_xml(xml_export_state).await {
+                Ok(blob_parts) => {
+                    utils::download::trigger(blob_parts, &filename, "application/dicom+xml")
+                        .unwrap();
 
-                ui::toasts::add_info("Generated DICOM JSON file for download".into());
+                    ui::toasts::add_info("Generated DICOM Native XML file for download".into());
+                }
+                Err(JobError::Failed(e)) => ui::toasts::add_error(e),
+                Err(JobError::Cancelled) => (),
+            }
+        });
+    };
+
+    // Writes all the opened files into a ZIP of a media set that includes a DICOMDIR
+    let download_media_set = move |_| {
+        is_generating_media_set.set(true);
+
+        let active_file =
+            active_file_id().and_then(|id| Some((id, worker_data_set.peek().clone()?)));
+
+        spawn(async move {
+            scopeguard::defer! {
+                is_generating_media_set.set(false);
             }
-            Err(e) => ui::toasts::add_error(e.to_string()),
+
+            match generate_media_set(files, active_file, media_set_state).await {
+                Ok((blob_parts, file_count)) => {
+                    utils::download::trigger(blob_parts, "media.zip", "application/zip").unwrap();
+
+                    ui::toasts::add_info(format!(
+                        "Generated media set of {} files for download",
+                        file_count
+                    ));
+                }
+
+                Err(JobError::Failed(e)) => {
+                    ui::toasts::add_error(format!("Generating media set failed. {}", e))
+                }
+                Err(JobError::Cancelled) => (),
+            }
+        });
+    };
+
+    // Ctrl+Z undoes and Ctrl+Shift+Z or Ctrl+Y redoes, except when a text input has focus so that
+    // its own undo still works
+    let on_key_down = move |event: KeyboardEvent| {
+        let modifiers = event.modifiers();
+        if !modifiers.contains(Modifiers::CONTROL) && !modifiers.contains(Modifiers::META) {
+            return;
+        }
+
+        let is_input_focused = utils::document()
+            .active_element()
+            .is_some_and(|element| matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA"));
+        if is_input_focused {
+            return;
         }
+
+        let Key::Character(character) = event.key() else {
+            return;
+        };
+
+        match character.to_lowercase().as_str() {
+            "z" if modifiers.contains(Modifiers::SHIFT) => redo(data_set, history),
+            "z" => undo(data_set, history),
+            "y" => redo(data_set, history),
+            _ => return,
+        }
+
+        event.prevent_default();
     };
 
+    let file_list_entries = files
+        .read()
+        .iter()
+        .map(|file| {
+            if active_file_id() == Some(file.id) {
+                FileListEntry::new(file, Some((&data_set.read(), &error_lines.read())))
+            } else {
+                FileListEntry::new(file, None)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (active_file_reason, is_active_file_loading) = files
+        .read()
+        .iter()
+        .find(|file| Some(file.id) == active_file_id())
+        .map_or((None, false), |file| {
+            (
+                file.sniffed_file.as_ref().map(|f| f.reason.clone()),
+                file.read_status != ReadStatus::Read,
+            )
+        });
+
+    let active_read_progress =
+        active_file_id().and_then(|id| read_progress.read().get(&id).copied());
+
+    // Where reading the active file stopped, which the job worker records as it reads the file
+    let read_failure_description = worker_data_set
+        .read()
+        .as_ref()
+        .and_then(|worker_data_set| worker_data_set.read_failure())
+        .map(|failure| failure.description.clone());
+
     rsx! {
         document::Stylesheet { href: MAIN_CSS }
 
@@ -154,7 +588,9 @@ fn App() -> Element {
 
         section {
             class: "main",
+            tabindex: -1,
 
+            onkeydown: on_key_down,
             ondragover: move |event| {
                 event.prevent_default();
                 event.stop_propagation();
@@ -163,7 +599,7 @@ fn App() -> Element {
             ondragleave: move |_| is_file_dragged_over.set(false),
             ondrop: move |event| {
                 event.prevent_default();
-                on_select_input_file(event.files().into_iter().next());
+                on_select_input_files(event.files());
                 is_file_dragged_over.set(false);
             },
 
@@ -179,6 +615,16 @@ fn App() -> Element {
                         code { i { "\"{dicom_filename}\"" } }
                     }
                 }
+                if is_active_file_loading {
+                    ReadProgressBar {
+                        progress: active_read_progress,
+                        on_cancel: move |_| {
+                            if let Some(id) = active_file_id() {
+                                cancelled_reads.write().insert(id);
+                            }
+                        },
+                    }
+                }
                 a {
                     href: "https://github.com/dcmfx/dcmfx-playground",
                     target: "_blank",
@@ -196,6 +642,18 @@ fn App() -> Element {
                     div {
                         class: "file-details",
 
+                        if let Some(reason) = active_file_reason {
+                            div {
+                                class: "details-text",
+
+                                SourceTypeSelect {
+                                    source_type: data_set_source_type(),
+                                    reason,
+                                    on_change: move |source_type| read_active_file_as(source_type),
+                                }
+                            }
+                            div { class: "vertical-divider" }
+                        }
                         div {
                             class: "details-text",
                             class: if view_mode() == ViewMode::DataSet { "selected" },
@@ -211,10 +669,32 @@ fn App() -> Element {
                             onclick: move |_| view_mode.set(ViewMode::PixelData),
                             "Pixel data"
                         }
+                        if is_dicomdir(&data_set.read()) {
+                            div { class: "vertical-divider" }
+                            div {
+                                class: "details-text",
+                                class: if view_mode() == ViewMode::Directory { "selected" },
+
+                                onclick: move |_| view_mode.set(ViewMode::Directory),
+                                "Directory"
+                            }
+                        }
+                        div { class: "vertical-divider" }
+                        div {
+                            class: "details-text",
+                            class: if view_mode() == ViewMode::Tokens { "selected" },
+
+                            onclick: move |_| view_mode.set(ViewMode::Tokens),
+                            "Tokens"
+                        }
                         div { class: "vertical-divider" }
                         div {
                             class: "close-icon",
-                            onclick: move |_| clear_dicom(),
+                            onclick: move |_| {
+                                if let Some(id) = active_file_id() {
+                                    close_file(id);
+                                }
+                            },
 
                             ui::FontAwesomeIcon { icon: "close", style: "solid", size: "lg" }
                         }
@@ -234,16 +714,76 @@ fn App() -> Element {
                             pre { {line} }
                         }
                     }
+
+                    if let Some(description) = read_failure_description {
+                        br {}
+                        pre { {description} }
+                        button {
+                            class: "jump-to-error",
+                            onclick: move |_| {
+                                view_mode.set(ViewMode::DataSet);
+                                jump_to_read_failure.set(true);
+                            },
+
+                            "Jump to error"
+                        }
+                    }
+                }
+            }
+
+            if !file_list_entries.is_empty() {
+                FileList {
+                    files,
+                    entries: file_list_entries,
+                    active_file_id: active_file_id(),
+                    selected_node: selected_study_node,
+                    on_select: move |id| activate_file(id),
+                    on_close: move |id| close_file(id),
+                    on_open_files: move |files_data| on_select_input_files(files_data),
                 }
             }
 
-            if data_set().is_empty() && error_lines().is_empty() {
-                DropArea { is_file_dragged_over, on_select_input_file }
+            if files.read().is_empty() {
+                DropArea { is_file_dragged_over, on_select_input_files }
+            } else if let Some(node) = selected_study_node() {
+                SharedAttributes { files, node }
+            } else if is_active_file_loading && data_set.read().size() == 0 {
+                div { class: "loading-message", "Reading file…" }
             } else {
-                if view_mode() == ViewMode::DataSet {
-                    DataSetGrid { main_data_set: data_set }
-                } else {
-                    PixelDataFrameView { data_set, frame_index: 0 }
+                match view_mode() {
+                    ViewMode::PixelData => rsx! {
+                        PixelDataFrameView {
+                            data_set,
+                            worker_data_set,
+                            filename: dicom_filename,
+                            series_stack,
+                        }
+                    },
+                    ViewMode::Directory if is_dicomdir(&data_set.read()) => rsx! {
+                        DicomdirView {
+                            data_set,
+                            files,
+                            active_file_id: active_file_id(),
+                            on_select_file: move |id| activate_file(id),
+                        }
+                    },
+                    ViewMode::Tokens => rsx! {
+                        RawTokenView {
+                            structure: p10_structure,
+                            is_p10: p10_structure_file_id().is_some(),
+                        }
+                    },
+                    _ => rsx! {
+                        DataSetGrid {
+                            main_data_set: data_set,
+                            worker_data_set,
+                            expansion: grid_expansion,
+                            history,
+                            show_offsets,
+                            p10_structure,
+                            jump_to_read_failure,
+                        }
+                    },
                 }
             }
 
@@ -251,11 +791,76 @@ fn App() -> Element {
                 class: "bottom-toolbar",
                 class: if data_set().size() == 0 { "disabled" },
 
-                button { onclick: on_download_p10, "Download as .dcm" }
-                button { onclick: on_download_json, "Download as .json" }
+                HistoryPanel { data_set, history }
+
+                button { onclick: move |_| is_anonymize_dialog_open.set(true), "Anonymize…" }
+
+                button { onclick: move |_| is_download_p10_dialog_open.set(true), "Download as .dcm" }
+                button { onclick: move |_| is_json_export_dialog_open.set(true), "Download as .json" }
+                button {
+                    disabled: xml_export_state().is_running(),
+                    onclick: download_xml,
+
+                    match xml_export_state() {
+                        JobState::Running { bytes_received } => {
+                            rsx! { "Exporting… {utils::format_size(bytes_received)}" }
+                        }
+                        JobState::Idle => rsx! { "Download as .xml" },
+                    }
+                }
+
+                if files.read().len() > 1 {
+                    button {
+                        disabled: is_generating_media_set(),
+                        onclick: download_media_set,
+
+                        match media_set_state() {
+                            JobState::Running { bytes_received } => {
+                                rsx! { "Generating… {utils::format_size(bytes_received)}" }
+                            }
+                            JobState::Idle => rsx! { "Download media set with DICOMDIR" },
+                        }
+                    }
+                }
+            }
+
+            if is_anonymize_dialog_open() {
+                AnonymizeDialog {
+                    data_set,
+                    history,
+                    on_close: move |_| is_anonymize_dialog_open.set(false),
+                }
+            }
+
+            if is_download_p10_dialog_open() {
+                DownloadP10Dialog {
+                    data_set,
+                    worker_data_set,
+                    on_download: move |blob_parts| {
+                        download_p10(blob_parts);
+                        is_download_p10_dialog_open.set(false);
+                    },
+                    on_close: move |_| is_download_p10_dialog_open.set(false),
+                }
+            }
+
+            if is_json_export_dialog_open() {
+                JsonExportDialog {
+                    worker_data_set,
+                    files,
+                    active_file_id: active_file_id(),
+                    filename: json_filename(),
+                    on_close: move |_| is_json_export_dialog_open.set(false),
+                }
             }
 
             ui::ToastUi {}
         }
     }
 }
+
+/// Returns the filename with its extension removed.
+///
+fn filename_stem(filename: &str) -> &str {
+    filename.rsplit_once('.').map_or(filename, |(stem, _)| stem)
+}
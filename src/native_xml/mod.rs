@@ -0,0 +1,76 @@
+//! Reading and writing of data sets in the DICOM Native XML format defined in PS3.19 Annex A.
+
+mod read;
+mod write;
+
+pub use read::read_data_set;
+pub use write::write_data_set;
+
+/// The XML namespace of the Native DICOM Model.
+///
+const NAMESPACE: &str = "http://dicom.nema.org/PS3.19/models/NativeDICOM";
+
+/// The names of the XML elements that hold the three component groups of a person name.
+///
+const PERSON_NAME_GROUPS: [&str; 3] = ["Alphabetic", "Ideographic", "Phonetic"];
+
+/// The names of the XML elements that hold the five components of a person name component group.
+///
+const PERSON_NAME_COMPONENTS: [&str; 5] = [
+    "FamilyName",
+    "GivenName",
+    "MiddleName",
+    "NamePrefix",
+    "NameSuffix",
+];
+
+const ITEM_TAG: [u8; 4] = [0xFE, 0xFF, 0x00, 0xE0];
+const SEQUENCE_DELIMITATION_TAG: [u8; 4] = [0xFE, 0xFF, 0xDD, 0xE0];
+
+/// Converts the items of encapsulated pixel data into the bytes of its item sequence, which is how
+/// encapsulated pixel data is stored in an `InlineBinary`.
+///
+fn encapsulated_pixel_data_to_bytes(items: &[dcmfx::core::RcByteSlice]) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    for item in items {
+        bytes.extend_from_slice(&ITEM_TAG);
+        bytes.extend_from_slice(&(item.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(item);
+    }
+
+    bytes.extend_from_slice(&SEQUENCE_DELIMITATION_TAG);
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+
+    bytes
+}
+
+/// Parses the bytes of an encapsulated pixel data item sequence back into its items. Returns `None`
+/// if the bytes aren't an item sequence, in which case they're native pixel data.
+///
+fn bytes_to_encapsulated_pixel_data(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    if !bytes.starts_with(&ITEM_TAG) {
+        return None;
+    }
+
+    let mut items = vec![];
+
+    while bytes.len() >= 8 {
+        let tag = &bytes[0..4];
+        let length = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+
+        if tag == SEQUENCE_DELIMITATION_TAG {
+            return Some(items);
+        }
+
+        if tag != ITEM_TAG || bytes.len() < 8 + length {
+            return None;
+        }
+
+        items.push(bytes[8..8 + length].to_vec());
+        bytes = &bytes[8 + length..];
+    }
+
+    // The sequence delimitation item is optional
+    bytes.is_empty().then_some(items)
+}
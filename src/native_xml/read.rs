@@ -0,0 +1,442 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use dcmfx::core::*;
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+
+use super::{PERSON_NAME_COMPONENTS, PERSON_NAME_GROUPS, bytes_to_encapsulated_pixel_data};
+use crate::editing::value_text;
+
+/// Parses PS3.19 Native DICOM Model XML into a data set. If parsing fails then the lines of the
+/// error are returned.
+///
+/// Values referenced with `BulkData` can't be retrieved, so those data elements are given an empty
+/// value. This is reported as an error, which is returned along with the rest of the data set.
+///
+pub fn read_data_set(xml: &str) -> Result<DataSet, (DataSet, Vec<String>)> {
+    let mut bulk_data_tags = vec![];
+
+    let data_set = parse(xml, &mut bulk_data_tags).map_err(|e| (DataSet::new(), vec![e]))?;
+
+    if bulk_data_tags.is_empty() {
+        Ok(data_set)
+    } else {
+        let tags = bulk_data_tags
+            .iter()
+            .map(DataElementTag::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Err((
+            data_set,
+            vec![format!(
+                "Values that are only referenced as BulkData were left empty: {}",
+                tags
+            )],
+        ))
+    }
+}
+
+/// Parses the XML into a data set, adding the tags of data elements whose value is only referenced
+/// as `BulkData` to `bulk_data_tags`.
+///
+fn parse(xml: &str, bulk_data_tags: &mut Vec<DataElementTag>) -> Result<DataSet, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(false);
+
+    let mut parser = Parser {
+        data_sets: vec![],
+        attributes: vec![],
+        text: String::new(),
+        person_name: None,
+        person_name_group: None,
+        bulk_data_tags,
+    };
+
+    let mut is_root_found = false;
+
+    loop {
+        let position = reader.buffer_position();
+
+        let result = match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                if !is_root_found {
+                    check_root_element(&element)?;
+                    is_root_found = true;
+                    parser.data_sets.push(DataSet::new());
+                    continue;
+                }
+
+                parser.start_element(&element)
+            }
+
+            Ok(Event::Empty(element)) => {
+                if !is_root_found {
+                    check_root_element(&element)?;
+                    return Ok(DataSet::new());
+                }
+
+                parser
+                    .start_element(&element)
+                    .and_then(|_| parser.end_element(element.local_name().as_ref()))
+            }
+
+            Ok(Event::End(element)) => {
+                if parser.data_sets.len() == 1 && parser.attributes.is_empty() {
+                    break;
+                }
+
+                parser.end_element(element.local_name().as_ref())
+            }
+
+            Ok(Event::Text(text)) => text
+                .unescape()
+                .map(|text| parser.text.push_str(&text))
+                .map_err(|e| e.to_string()),
+
+            Ok(Event::CData(data)) => {
+                parser.text.push_str(&String::from_utf8_lossy(&data));
+                Ok(())
+            }
+
+            Ok(Event::Eof) => return Err("Unexpected end of XML".into()),
+
+            Ok(_) => Ok(()),
+
+            Err(e) => Err(e.to_string()),
+        };
+
+        result.map_err(|e| format!("Invalid XML at byte offset {}: {}", position, e))?;
+    }
+
+    parser
+        .data_sets
+        .pop()
+        .ok_or_else(|| "XML doesn't contain a NativeDicomModel element".to_string())
+}
+
+fn check_root_element(element: &BytesStart) -> Result<(), String> {
+    if element.local_name().as_ref() == b"NativeDicomModel" {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected a NativeDicomModel root element but found '{}'",
+            String::from_utf8_lossy(element.local_name().as_ref())
+        ))
+    }
+}
+
+/// A DICOM attribute whose content is being parsed.
+///
+struct Attribute {
+    tag: DataElementTag,
+    vr: String,
+    values: Vec<String>,
+    items: Vec<DataSet>,
+    inline_binary: Option<String>,
+    has_bulk_data: bool,
+}
+
+struct Parser<'a> {
+    /// The stack of data sets being built, starting with the root data set and followed by the
+    /// sequence items currently being parsed.
+    data_sets: Vec<DataSet>,
+
+    /// The stack of attributes being parsed, one for each data set after the root data set.
+    attributes: Vec<Attribute>,
+
+    /// The text content of the current element.
+    text: String,
+
+    /// The component groups of the person name currently being parsed.
+    person_name: Option<[Vec<String>; 3]>,
+    person_name_group: Option<usize>,
+
+    /// The tags of the data elements whose value is only referenced as `BulkData`.
+    bulk_data_tags: &'a mut Vec<DataElementTag>,
+}
+
+impl Parser<'_> {
+    fn start_element(&mut self, element: &BytesStart) -> Result<(), String> {
+        self.text.clear();
+
+        match element.local_name().as_ref() {
+            b"DicomAttribute" => {
+                let tag = value_text::parse_tag(&get_attribute(element, "tag")?)?;
+
+                // The VR is optional for attributes that have a single VR in the dictionary, but
+                // this isn't supported so it's required here
+                let vr = get_attribute(element, "vr")?;
+
+                self.attributes.push(Attribute {
+                    tag,
+                    vr,
+                    values: vec![],
+                    items: vec![],
+                    inline_binary: None,
+                    has_bulk_data: false,
+                });
+            }
+
+            b"Item" => self.data_sets.push(DataSet::new()),
+
+            b"BulkData" => self.current_attribute()?.has_bulk_data = true,
+
+            b"PersonName" => self.person_name = Some(Default::default()),
+
+            name => {
+                if let Some(index) = PERSON_NAME_GROUPS
+                    .iter()
+                    .position(|group| group.as_bytes() == name)
+                {
+                    self.person_name_group = Some(index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn end_element(&mut self, name: &[u8]) -> Result<(), String> {
+        let text = std::mem::take(&mut self.text);
+
+        match name {
+            b"DicomAttribute" => {
+                let attribute = self
+                    .attributes
+                    .pop()
+                    .ok_or("Unexpected end of DicomAttribute")?;
+
+                let value = attribute_value(attribute.tag, &attribute)?;
+
+                if attribute.has_bulk_data
+                    && attribute.inline_binary.is_none()
+                    && attribute.values.is_empty()
+                {
+                    self.bulk_data_tags.push(attribute.tag);
+                }
+
+                self.data_sets
+                    .last_mut()
+                    .ok_or("DicomAttribute is outside of a data set")?
+                    .insert(attribute.tag, value);
+            }
+
+            b"Item" => {
+                let item = self.data_sets.pop().ok_or("Unexpected end of Item")?;
+
+                self.attributes
+                    .last_mut()
+                    .ok_or("Item is outside of a DicomAttribute")?
+                    .items
+                    .push(item);
+            }
+
+            b"Value" => self.current_attribute()?.values.push(text),
+
+            b"InlineBinary" => self.current_attribute()?.inline_binary = Some(text),
+
+            b"PersonName" => {
+                let groups = self.person_name.take().unwrap_or_default();
+
+                let mut groups = groups
+                    .iter()
+                    .map(|components| trim_trailing_empty(components).join("^"))
+                    .collect::<Vec<_>>();
+                while groups.last().is_some_and(String::is_empty) {
+                    groups.pop();
+                }
+
+                self.current_attribute()?.values.push(groups.join("="));
+            }
+
+            name => {
+                if let Some(index) = PERSON_NAME_GROUPS
+                    .iter()
+                    .position(|group| group.as_bytes() == name)
+                    && self.person_name_group == Some(index)
+                {
+                    self.person_name_group = None;
+                } else if let Some(component_index) = PERSON_NAME_COMPONENTS
+                    .iter()
+                    .position(|component| component.as_bytes() == name)
+                    && let (Some(person_name), Some(group_index)) =
+                        (self.person_name.as_mut(), self.person_name_group)
+                {
+                    let components = &mut person_name[group_index];
+                    if components.len() <= component_index {
+                        components.resize(component_index + 1, String::new());
+                    }
+
+                    components[component_index] = text;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn current_attribute(&mut self) -> Result<&mut Attribute, String> {
+        self.attributes
+            .last_mut()
+            .ok_or_else(|| "Value is outside of a DicomAttribute".to_string())
+    }
+}
+
+fn get_attribute(element: &BytesStart, name: &str) -> Result<String, String> {
+    element
+        .try_get_attribute(name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("DicomAttribute is missing the '{}' attribute", name))?
+        .unescape_value()
+        .map(|value| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn trim_trailing_empty(components: &[String]) -> &[String] {
+    let length = components
+        .iter()
+        .rposition(|component| !component.is_empty())
+        .map_or(0, |index| index + 1);
+
+    &components[..length]
+}
+
+/// Converts the parsed content of a DICOM attribute into a data element value.
+///
+fn attribute_value(tag: DataElementTag, attribute: &Attribute) -> Result<DataElementValue, String> {
+    let vr = attribute.vr.as_str();
+
+    if vr == "SQ" {
+        return Ok(DataElementValue::new_sequence(attribute.items.clone()));
+    }
+
+    let value_representation = ValueRepresentation::from_bytes(vr.as_bytes())
+        .map_err(|_| format!("Unknown value representation '{}'", vr))?;
+
+    if let Some(inline_binary) = &attribute.inline_binary {
+        let bytes = BASE64
+            .decode(inline_binary.trim())
+            .map_err(|e| format!("Invalid InlineBinary for {}: {}", tag, e))?;
+
+        // Encapsulated pixel data is stored as its sequence of items
+        if tag == dictionary::PIXEL_DATA.tag
+            && let Some(items) = bytes_to_encapsulated_pixel_data(&bytes)
+        {
+            return DataElementValue::new_encapsulated_pixel_data(
+                value_representation,
+                items.into_iter().map(Into::into).collect(),
+            )
+            .map_err(|e| e.to_string());
+        }
+
+        return DataElementValue::new_binary(value_representation, bytes.into())
+            .map_err(|e| e.to_string());
+    }
+
+    // Attributes with no content, including those whose value is only available as BulkData, are
+    // given an empty value
+    if attribute.values.is_empty() {
+        return DataElementValue::new_binary(value_representation, vec![].into())
+            .map_err(|e| e.to_string());
+    }
+
+    value_text::text_to_value_unvalidated(vr, &attribute.values.join("\\"))
+        .map_err(|e| format!("Invalid value for {}: {}", tag, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use dcmfx::json::*;
+
+    use super::*;
+    use crate::{
+        source_type::DataSetSourceType,
+        test_utils::{insert, test_data_set, to_xml},
+        utils::BytesWriter,
+    };
+
+    #[test]
+    fn xml_round_trip() {
+        let data_set = test_data_set();
+
+        assert_eq!(read_data_set(&to_xml(&data_set)), Ok(data_set));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let config = || DicomJsonConfig {
+            store_encapsulated_pixel_data: true,
+            pretty_print: false,
+        };
+
+        // JSON to XML and back
+        let json = test_data_set().to_json(config()).unwrap();
+        let data_set = DataSet::from_json(&json).unwrap();
+        let read_data_set = read_data_set(&to_xml(&data_set)).unwrap();
+
+        assert_eq!(read_data_set, data_set);
+
+        // XML to JSON and back
+        assert_eq!(read_data_set.to_json(config()).unwrap(), json);
+    }
+
+    #[test]
+    fn p10_round_trip() {
+        let mut data_set = test_data_set();
+        insert(
+            &mut data_set,
+            dictionary::TRANSFER_SYNTAX_UID.tag,
+            "UI",
+            "1.2.840.10008.1.2.4.50",
+        );
+
+        let mut writer = BytesWriter::default();
+        data_set.write_p10_stream(&mut writer, None).unwrap();
+
+        // P10 to XML and back, which includes the File Meta Information added when writing
+        let data_set = DataSetSourceType::P10.read(&writer.into_bytes()).unwrap();
+        let read_data_set = read_data_set(&to_xml(&data_set)).unwrap();
+
+        assert_eq!(read_data_set, data_set);
+    }
+
+    #[test]
+    fn bulk_data_values_are_reported() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<NativeDicomModel xmlns="http://dicom.nema.org/PS3.19/models/NativeDICOM">
+  <DicomAttribute tag="00080060" vr="CS">
+    <Value number="1">OT</Value>
+  </DicomAttribute>
+  <DicomAttribute tag="7FE00010" vr="OB">
+    <BulkData uuid="pixel-data"/>
+  </DicomAttribute>
+</NativeDicomModel>
+"#;
+
+        let (data_set, error_lines) = read_data_set(xml).unwrap_err();
+
+        assert_eq!(data_set.get_string(dictionary::MODALITY.tag).unwrap(), "OT");
+        assert_eq!(
+            data_set
+                .get_value(dictionary::PIXEL_DATA.tag)
+                .unwrap()
+                .bytes()
+                .unwrap()
+                .len(),
+            0
+        );
+        assert_eq!(
+            error_lines,
+            vec!["Values that are only referenced as BulkData were left empty: (7FE0,0010)"]
+        );
+    }
+
+    #[test]
+    fn invalid_xml_is_rejected() {
+        assert!(read_data_set("<NativeDicomModel>").is_err());
+        assert!(read_data_set("<Other/>").is_err());
+        assert_eq!(read_data_set("<NativeDicomModel/>"), Ok(DataSet::new()));
+    }
+}
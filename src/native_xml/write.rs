@@ -0,0 +1,301 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use dcmfx::core::*;
+
+use super::{
+    NAMESPACE, PERSON_NAME_COMPONENTS, PERSON_NAME_GROUPS, encapsulated_pixel_data_to_bytes,
+};
+use crate::editing::value_text;
+
+/// Serializes a data set to PS3.19 Native DICOM Model XML, passing the output to the given
+/// function in chunks.
+///
+/// Binary values, including encapsulated pixel data, are always written in full as `InlineBinary`
+/// because there's no server that `BulkData` references could be retrieved from.
+///
+/// `checkpoint` is awaited before each data element in the root data set is written, and writing
+/// stops with its error if it returns one.
+///
+pub async fn write_data_set(
+    data_set: &DataSet,
+    output: &mut impl FnMut(&str),
+    checkpoint: &impl AsyncFn() -> Result<(), String>,
+) -> Result<(), String> {
+    output("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output(&format!(
+        "<NativeDicomModel xmlns=\"{}\" xml:space=\"preserve\">\n",
+        NAMESPACE
+    ));
+
+    for (tag, value) in data_set.iter() {
+        checkpoint().await?;
+        write_attribute(data_set, *tag, value, 1, output);
+    }
+
+    output("</NativeDicomModel>\n");
+
+    Ok(())
+}
+
+fn write_attributes(data_set: &DataSet, depth: usize, output: &mut impl FnMut(&str)) {
+    for (tag, value) in data_set.iter() {
+        write_attribute(data_set, *tag, value, depth, output);
+    }
+}
+
+/// Writes a data element of the given data set, which is needed to look up the private creator of
+/// private data elements.
+///
+fn write_attribute(
+    data_set: &DataSet,
+    tag: DataElementTag,
+    value: &DataElementValue,
+    depth: usize,
+    output: &mut impl FnMut(&str),
+) {
+    let indent = "  ".repeat(depth);
+
+    let vr = value.value_representation().to_string();
+
+    let private_creator = private_creator(data_set, tag)
+        .map(|private_creator| format!(" privateCreator=\"{}\"", escape(&private_creator)))
+        .unwrap_or_default();
+
+    output(&format!(
+        "{}<DicomAttribute tag=\"{:04X}{:04X}\" vr=\"{}\"{}>\n",
+        indent, tag.group, tag.element, vr, private_creator
+    ));
+
+    let child_indent = "  ".repeat(depth + 1);
+
+    if let Ok(items) = value.sequence_items() {
+        for (index, item) in items.iter().enumerate() {
+            output(&format!(
+                "{}<Item number=\"{}\">\n",
+                child_indent,
+                index + 1
+            ));
+            write_attributes(item, depth + 2, output);
+            output(&format!("{}</Item>\n", child_indent));
+        }
+    } else if let Ok(items) = value.encapsulated_pixel_data() {
+        output(&format!(
+            "{}<InlineBinary>{}</InlineBinary>\n",
+            child_indent,
+            BASE64.encode(encapsulated_pixel_data_to_bytes(items))
+        ));
+    } else if vr == "PN" {
+        let text = value_text::value_to_text(value).unwrap_or_default();
+        if !text.is_empty() {
+            for (index, name) in text.split('\\').enumerate() {
+                write_person_name(name, index + 1, depth + 1, output);
+            }
+        }
+    } else if let Some(text) = value_text::value_to_text(value)
+        && !text.is_empty()
+        && is_text_lossless(&vr, value)
+    {
+        let values = if matches!(vr.as_str(), "LT" | "ST" | "UR" | "UT") {
+            vec![text.as_str()]
+        } else {
+            text.split('\\').collect()
+        };
+
+        for (index, value) in values.into_iter().enumerate() {
+            // Attribute tags are written as eight hex digits, without the parentheses and
+            // comma used for display
+            let value = if vr == "AT" {
+                value.replace(['(', ')', ','], "")
+            } else {
+                value.to_string()
+            };
+
+            output(&format!(
+                "{}<Value number=\"{}\">{}</Value>\n",
+                child_indent,
+                index + 1,
+                escape(&value)
+            ));
+        }
+    } else if let Ok(bytes) = value.bytes()
+        && !bytes.is_empty()
+        && !value_text::is_string_vr(&vr)
+    {
+        // Binary values, and numeric values whose length isn't a whole number of values, are
+        // written as their bytes so that nothing is lost
+        output(&format!(
+            "{}<InlineBinary>{}</InlineBinary>\n",
+            child_indent,
+            BASE64.encode(bytes)
+        ));
+    }
+
+    output(&format!("{}</DicomAttribute>\n", indent));
+}
+
+/// Returns the private creator of a private data element, which is the value of the private creator
+/// data element that reserves its block.
+///
+fn private_creator(data_set: &DataSet, tag: DataElementTag) -> Option<String> {
+    if tag.group % 2 == 0 || tag.element < 0x1000 {
+        return None;
+    }
+
+    data_set
+        .get_string(DataElementTag::new(tag.group, tag.element >> 8))
+        .ok()
+        .map(|private_creator| private_creator.trim().to_string())
+}
+
+/// Returns whether a value's text holds all of its bytes. This isn't the case for numeric values
+/// whose length isn't a multiple of the size of a single value, as the trailing bytes are dropped.
+///
+fn is_text_lossless(vr: &str, value: &DataElementValue) -> bool {
+    match value_text::binary_value_size(vr) {
+        Some(size) => value.bytes().is_ok_and(|bytes| bytes.len() % size == 0),
+        None => true,
+    }
+}
+
+/// Writes a single person name value, splitting it into its component groups and components.
+///
+fn write_person_name(name: &str, number: usize, depth: usize, output: &mut impl FnMut(&str)) {
+    let indent = "  ".repeat(depth);
+
+    output(&format!("{}<PersonName number=\"{}\">\n", indent, number));
+
+    for (group, group_name) in name.split('=').zip(PERSON_NAME_GROUPS) {
+        if group.is_empty() {
+            continue;
+        }
+
+        output(&format!("{}  <{}>\n", indent, group_name));
+
+        for (component, component_name) in group.split('^').zip(PERSON_NAME_COMPONENTS) {
+            if !component.is_empty() {
+                output(&format!(
+                    "{}    <{}>{}</{}>\n",
+                    indent,
+                    component_name,
+                    escape(component),
+                    component_name
+                ));
+            }
+        }
+
+        output(&format!("{}  </{}>\n", indent, group_name));
+    }
+
+    output(&format!("{}</PersonName>\n", indent));
+}
+
+/// Escapes the characters that have special meaning in XML text and attribute values.
+///
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{insert, to_xml};
+
+    #[test]
+    fn root_element_has_the_namespace() {
+        let xml = to_xml(&DataSet::new());
+
+        assert!(xml.contains(
+            "<NativeDicomModel xmlns=\"http://dicom.nema.org/PS3.19/models/NativeDICOM\""
+        ));
+    }
+
+    #[test]
+    fn private_elements_have_their_private_creator() {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            DataElementTag::new(0x0009, 0x0010),
+            "LO",
+            "ACME & Co",
+        );
+        insert(
+            &mut data_set,
+            DataElementTag::new(0x0009, 0x1001),
+            "LO",
+            "Private",
+        );
+
+        let xml = to_xml(&data_set);
+
+        assert!(xml.contains(
+            "<DicomAttribute tag=\"00091001\" vr=\"LO\" privateCreator=\"ACME &amp; Co\">"
+        ));
+        assert!(xml.contains("<DicomAttribute tag=\"00090010\" vr=\"LO\">"));
+    }
+
+    #[test]
+    fn binary_values_are_written_inline() {
+        let mut data_set = DataSet::new();
+        data_set.insert(
+            dictionary::PIXEL_DATA.tag,
+            DataElementValue::new_encapsulated_pixel_data(
+                ValueRepresentation::from_bytes(b"OB").unwrap(),
+                vec![vec![].into(), vec![1, 2, 3, 4].into()],
+            )
+            .unwrap(),
+        );
+        data_set.insert(
+            dictionary::ENCAPSULATED_DOCUMENT.tag,
+            DataElementValue::new_binary(
+                ValueRepresentation::from_bytes(b"OB").unwrap(),
+                vec![5, 6].into(),
+            )
+            .unwrap(),
+        );
+
+        let xml = to_xml(&data_set);
+
+        let pixel_data = BASE64.encode([
+            0xFE, 0xFF, 0x00, 0xE0, 0, 0, 0, 0, 0xFE, 0xFF, 0x00, 0xE0, 4, 0, 0, 0, 1, 2, 3, 4,
+            0xFE, 0xFF, 0xDD, 0xE0, 0, 0, 0, 0,
+        ]);
+        assert!(xml.contains(&format!("<InlineBinary>{}</InlineBinary>", pixel_data)));
+        assert!(xml.contains(&format!(
+            "<InlineBinary>{}</InlineBinary>",
+            BASE64.encode([5, 6])
+        )));
+        assert!(!xml.contains("BulkData"));
+    }
+
+    #[test]
+    fn person_names_are_split_into_components() {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::PATIENT_NAME.tag,
+            "PN",
+            "Doe^Jane=Ideo^Graphic",
+        );
+
+        let xml = to_xml(&data_set);
+
+        assert!(xml.contains(
+            "<PersonName number=\"1\">\n      <Alphabetic>\n        \
+             <FamilyName>Doe</FamilyName>\n        <GivenName>Jane</GivenName>\n      \
+             </Alphabetic>\n      <Ideographic>\n        <FamilyName>Ideo</FamilyName>\n        \
+             <GivenName>Graphic</GivenName>\n      </Ideographic>\n    </PersonName>"
+        ));
+    }
+}
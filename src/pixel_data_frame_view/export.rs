@@ -0,0 +1,76 @@
+use image::{
+    ExtendedColorType, ImageEncoder, RgbImage,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+};
+
+/// The quality used when exporting frames as JPEG images.
+///
+const JPEG_QUALITY: u8 = 90;
+
+/// The image formats that rendered frames can be exported as.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageExportFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageExportFormat {
+    /// Returns the file extension for this image format.
+    ///
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    /// Returns the MIME type for this image format.
+    ///
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// Encodes a rendered frame in this image format.
+    ///
+    pub fn encode(&self, image: &RgbImage) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+
+        let result = match self {
+            Self::Png => PngEncoder::new(&mut bytes).write_image(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ExtendedColorType::Rgb8,
+            ),
+
+            Self::Jpeg => JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).write_image(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ExtendedColorType::Rgb8,
+            ),
+        };
+
+        result.map_err(|e| format!("Encoding frame failed. {}", e))?;
+
+        Ok(bytes)
+    }
+}
+
+/// Returns the name to use for an exported file, which is the input filename with its extension
+/// replaced by the given suffix.
+///
+pub fn export_filename(input_filename: &str, suffix: &str) -> String {
+    let stem = match input_filename.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => input_filename,
+    };
+
+    let stem = if stem.is_empty() { "frame" } else { stem };
+
+    format!("{}{}", stem, suffix)
+}
@@ -0,0 +1,57 @@
+use std::rc::Rc;
+
+use image::RgbImage;
+use indexmap::IndexMap;
+
+/// Stores rendered frames so that they don't need to be decoded and rendered again when they are
+/// next displayed, e.g. during cine playback. When the total size of the cached images exceeds the
+/// cache's capacity, the oldest rendered frames are evicted first.
+///
+pub struct FrameCache {
+    capacity: usize,
+    size: usize,
+    images: IndexMap<usize, Rc<RgbImage>>,
+}
+
+impl FrameCache {
+    /// Creates a new frame cache that holds up to the given number of bytes of rendered image data.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            images: IndexMap::new(),
+        }
+    }
+
+    /// Returns the cached rendered image for the given frame index, if present.
+    ///
+    pub fn get(&self, frame_index: usize) -> Option<Rc<RgbImage>> {
+        self.images.get(&frame_index).cloned()
+    }
+
+    /// Adds a rendered image for the given frame index to the cache, evicting older entries as
+    /// needed to stay within the cache's capacity.
+    ///
+    pub fn insert(&mut self, frame_index: usize, image: Rc<RgbImage>) {
+        if let Some(old_image) = self.images.shift_remove(&frame_index) {
+            self.size -= old_image.as_raw().len();
+        }
+
+        self.size += image.as_raw().len();
+        self.images.insert(frame_index, image);
+
+        while self.size > self.capacity && self.images.len() > 1 {
+            if let Some((_, evicted_image)) = self.images.shift_remove_index(0) {
+                self.size -= evicted_image.as_raw().len();
+            }
+        }
+    }
+
+    /// Removes all cached images.
+    ///
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.size = 0;
+    }
+}
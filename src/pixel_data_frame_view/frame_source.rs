@@ -0,0 +1,275 @@
+use std::{collections::HashMap, rc::Rc};
+
+use dcmfx::core::{DataSet, dictionary};
+use dioxus_elements::FileData;
+
+use crate::{file_list::OpenedFile, jobs::WorkerDataSet, study_browser};
+
+/// The maximum number of slices of a series stack whose data sets are held in memory at once.
+/// Slices furthest from the one being viewed are unloaded first.
+///
+const MAX_LOADED_SLICES: usize = 64;
+
+/// A stack made from the single-frame instances of a series, which is shown in the pixel data view
+/// as if its slices were the frames of one multi-frame instance.
+///
+#[derive(Clone)]
+pub struct SeriesStack {
+    pub slices: Vec<StackSlice>,
+
+    /// The index of the slice for the file being viewed.
+    pub active_index: usize,
+}
+
+#[derive(Clone)]
+pub struct StackSlice {
+    pub file_id: u64,
+    pub file_data: FileData,
+
+    /// A description of the slice's position, taken from its Slice Location or Image Position
+    /// (Patient).
+    pub position: Option<String>,
+}
+
+impl PartialEq for SeriesStack {
+    fn eq(&self, other: &Self) -> bool {
+        self.active_index == other.active_index
+            && self.slices.len() == other.slices.len()
+            && self
+                .slices
+                .iter()
+                .zip(other.slices.iter())
+                .all(|(a, b)| a.file_id == b.file_id && a.position == b.position)
+    }
+}
+
+impl SeriesStack {
+    /// Returns the stack for the series that contains the given file, if that series is made up of
+    /// more than one single-frame image.
+    ///
+    pub fn for_file(files: &[OpenedFile], file_id: u64) -> Option<Self> {
+        let file_ids = study_browser::series_stack_file_ids(files, file_id)?;
+
+        let slices = file_ids
+            .iter()
+            .filter_map(|id| files.iter().find(|file| file.id == *id))
+            .map(|file| StackSlice {
+                file_id: file.id,
+                file_data: file.file_data.clone(),
+                position: file
+                    .header
+                    .as_ref()
+                    .and_then(|header| header.as_ref().ok())
+                    .and_then(slice_position),
+            })
+            .collect::<Vec<_>>();
+
+        let active_index = slices.iter().position(|slice| slice.file_id == file_id)?;
+
+        Some(Self {
+            slices,
+            active_index,
+        })
+    }
+}
+
+fn slice_position(data_set: &DataSet) -> Option<String> {
+    if let Ok(slice_location) = data_set.get_float(dictionary::SLICE_LOCATION.tag) {
+        return Some(format!("Slice location {:.2} mm", slice_location));
+    }
+
+    match data_set.get_floats(dictionary::IMAGE_POSITION_PATIENT.tag) {
+        Ok(position) if position.len() == 3 => Some(format!(
+            "Position ({:.1}, {:.1}, {:.1}) mm",
+            position[0], position[1], position[2]
+        )),
+        _ => None,
+    }
+}
+
+/// A slice of a series stack whose data set is being loaded, or has been loaded. Loaded slices are
+/// read into the job worker, where their frame is rendered.
+///
+pub enum SliceContent {
+    Loading,
+    Loaded {
+        data_set: Rc<DataSet>,
+        worker_data_set: Rc<WorkerDataSet>,
+    },
+    Failed(String),
+}
+
+impl SliceContent {
+    /// Creates the content for a slice from the header of its data set and the job worker's copy
+    /// of it. Only the first frame of each slice is shown.
+    ///
+    pub fn new(data_set: Rc<DataSet>, worker_data_set: Option<Rc<WorkerDataSet>>) -> Self {
+        if !data_set.has(dictionary::PIXEL_DATA.tag) {
+            return Self::Failed("No pixel data found".into());
+        }
+
+        match worker_data_set {
+            Some(worker_data_set) => Self::Loaded {
+                data_set,
+                worker_data_set,
+            },
+            None => Self::Failed("The file is still being read".into()),
+        }
+    }
+}
+
+/// Where the frames shown in the pixel data view come from.
+///
+pub enum FrameSource {
+    /// The frames of the data set being viewed, which are rendered from its copy in the job worker.
+    DataSet {
+        frame_count: usize,
+        worker_data_set: Result<Rc<WorkerDataSet>, String>,
+    },
+
+    /// The first frame of each slice in a series stack. Slices are loaded when they're first
+    /// shown.
+    Stack {
+        stack: SeriesStack,
+        slices: HashMap<usize, SliceContent>,
+    },
+}
+
+impl FrameSource {
+    /// Creates the frame source for the data set being viewed, given its header and the job
+    /// worker's copy of it. The header only has a placeholder for the pixel data, so the number of
+    /// frames is taken from its Number of Frames. Returns `None` if the data set has no pixel data.
+    ///
+    pub fn for_data_set(
+        data_set: &DataSet,
+        worker_data_set: Option<Rc<WorkerDataSet>>,
+    ) -> Option<Self> {
+        if !data_set.has(dictionary::PIXEL_DATA.tag) {
+            return None;
+        }
+
+        let frame_count = data_set
+            .get_int::<u32>(dictionary::NUMBER_OF_FRAMES.tag)
+            .map_or(1, |frame_count| frame_count as usize);
+
+        Some(Self::DataSet {
+            frame_count,
+            worker_data_set: worker_data_set.ok_or_else(|| "The file is still being read".into()),
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        match self {
+            Self::DataSet { frame_count, .. } => *frame_count,
+            Self::Stack { stack, .. } => stack.slices.len(),
+        }
+    }
+
+    /// Returns the worker data set to render the given frame index from, and the index of the
+    /// frame in it. Returns `Ok(None)` when the slice for the frame hasn't been loaded yet.
+    ///
+    pub fn render_source(
+        &self,
+        frame_index: usize,
+    ) -> Result<Option<(Rc<WorkerDataSet>, usize)>, String> {
+        match self {
+            Self::DataSet {
+                frame_count,
+                worker_data_set,
+            } => {
+                if frame_index >= *frame_count {
+                    return Err(format!(
+                        "Pixel data frame index '{}' is out of range",
+                        frame_index
+                    ));
+                }
+
+                Ok(Some((worker_data_set.clone()?, frame_index)))
+            }
+
+            Self::Stack { slices, .. } => match slices.get(&frame_index) {
+                Some(SliceContent::Loaded {
+                    worker_data_set, ..
+                }) => Ok(Some((worker_data_set.clone(), 0))),
+                Some(SliceContent::Failed(e)) => Err(e.clone()),
+                Some(SliceContent::Loading) | None => Ok(None),
+            },
+        }
+    }
+
+    /// Returns the data set of the given frame, if it's loaded.
+    ///
+    pub fn data_set(&self, frame_index: usize) -> Option<Rc<DataSet>> {
+        match self {
+            Self::DataSet { .. } => None,
+            Self::Stack { slices, .. } => match slices.get(&frame_index) {
+                Some(SliceContent::Loaded { data_set, .. }) => Some(data_set.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the position readout of the given frame when it's a slice of a series stack.
+    ///
+    pub fn slice_position(&self, frame_index: usize) -> Option<String> {
+        match self {
+            Self::DataSet { .. } => None,
+            Self::Stack { stack, .. } => stack
+                .slices
+                .get(frame_index)
+                .and_then(|slice| slice.position.clone()),
+        }
+    }
+
+    /// Marks the given slice as loading and returns it, if it's a slice of a series stack that
+    /// hasn't started loading yet.
+    ///
+    pub fn start_loading_slice(&mut self, frame_index: usize) -> Option<StackSlice> {
+        let Self::Stack { stack, slices } = self else {
+            return None;
+        };
+
+        if slices.contains_key(&frame_index) {
+            return None;
+        }
+
+        let slice = stack.slices.get(frame_index)?.clone();
+        slices.insert(frame_index, SliceContent::Loading);
+
+        Some(slice)
+    }
+
+    /// Stores a slice that has finished loading, unloading the slices furthest from it if too many
+    /// are loaded. The active file's slice is never unloaded. Slices whose file is no longer at
+    /// the given index, e.g. because the stack changed while it was loading, are discarded.
+    ///
+    pub fn finish_loading_slice(
+        &mut self,
+        frame_index: usize,
+        file_id: u64,
+        content: SliceContent,
+    ) {
+        let Self::Stack { stack, slices } = self else {
+            return;
+        };
+
+        if stack.slices.get(frame_index).map(|slice| slice.file_id) != Some(file_id) {
+            return;
+        }
+
+        slices.insert(frame_index, content);
+
+        while slices.len() > MAX_LOADED_SLICES {
+            let furthest_index = slices
+                .keys()
+                .copied()
+                .filter(|index| *index != stack.active_index)
+                .max_by_key(|index| index.abs_diff(frame_index));
+
+            match furthest_index {
+                Some(index) => slices.remove(&index),
+                None => break,
+            };
+        }
+    }
+}
@@ -0,0 +1,1032 @@
+mod export;
+mod frame_cache;
+mod frame_source;
+mod pixel_probe;
+mod render;
+mod view_transform;
+mod voi;
+
+use std::{collections::HashMap, rc::Rc, time::Duration};
+
+use dcmfx::core::{DataSet, IodModule, dictionary};
+use dioxus::prelude::*;
+use dioxus_elements::input_data::MouseButton;
+use image::RgbImage;
+use js_sys::wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement};
+
+use crate::{
+    file_list,
+    jobs::{JobError, WorkerDataSet},
+    ui::{self, FontAwesomeIcon},
+    utils,
+};
+use export::ImageExportFormat;
+use frame_cache::FrameCache;
+pub use frame_source::SeriesStack;
+use frame_source::{FrameSource, SliceContent};
+pub use pixel_probe::StoredValues;
+use pixel_probe::{ModalityRescale, PixelProbe, PixelProbeOverlay};
+pub use render::render_frame;
+use view_transform::{ImagePlacement, ViewTransform, Viewport, ZoomMode};
+pub use voi::VoiSelection;
+use voi::Window;
+
+/// The maximum number of bytes of rendered frames to keep in the frame cache.
+///
+const FRAME_CACHE_CAPACITY: usize = 512 * 1024 * 1024;
+
+/// The cine frame rate used when the data set doesn't specify one.
+///
+const DEFAULT_FRAME_RATE: f64 = 10.0;
+
+/// Shows the frames of the data set's pixel data. When the data set is one of a series of
+/// single-frame images, the whole series is shown as a stack that can be scrolled through.
+///
+#[component]
+pub fn PixelDataFrameView(
+    data_set: Signal<DataSet>,
+    worker_data_set: ReadSignal<Option<Rc<WorkerDataSet>>>,
+    filename: ReadSignal<String>,
+    series_stack: ReadSignal<Option<SeriesStack>>,
+) -> Element {
+    let mut container_element = use_signal(|| None);
+    let mut canvas_element = use_signal(|| None);
+    let mut error_message = use_signal(|| None);
+
+    let mut frames = use_signal(|| None);
+    let mut frame_cache = use_signal(|| FrameCache::new(FRAME_CACHE_CAPACITY));
+    let mut frame_index = use_signal(|| 0);
+    let mut is_playing = use_signal(|| false);
+    let mut frame_rate = use_signal(|| DEFAULT_FRAME_RATE);
+
+    // The task that advances through the frames, which only exists while cine playback is active
+    let mut cine_task = use_signal(|| None::<Task>);
+
+    // Incremented whenever a slice of a series stack finishes loading so that it gets drawn
+    let mut slice_load_count = use_signal(|| 0usize);
+
+    // The task awaiting the frame being rendered in the job worker for display, and the errors of
+    // frames that failed to render, which are kept so that they aren't rendered again on redraw
+    let mut render_task = use_signal(|| None::<Task>);
+    let mut render_errors = use_signal(HashMap::<usize, String>::new);
+
+    let mut voi_selection = use_signal(|| None);
+    let mut voi_presets = use_signal(Vec::new);
+    let mut voi_drag = use_signal(|| None);
+
+    let mut view_transform = use_signal(ViewTransform::default);
+    let mut image_smoothing = use_signal(|| true);
+    let mut image_placement = use_signal(|| None::<ImagePlacement>);
+    let mut source_canvas = use_signal(|| None);
+    let mut pan_drag = use_signal(|| None);
+
+    // The pixel probe that's shown, and the task getting the stored values of the pixel being
+    // probed from the job worker
+    let mut probe = use_signal(|| None);
+    let mut probe_task = use_signal(|| None::<Task>);
+    let mut modality_rescale = use_signal(|| None);
+    let mut is_color = use_signal(|| false);
+
+    let mut is_exporting = use_signal(|| false);
+
+    // Reload the frames whenever the data set or series stack changes
+    use_effect(move || {
+        let data_set = data_set.read();
+        let worker_data_set = worker_data_set();
+
+        match series_stack() {
+            Some(stack) => {
+                let active_index = stack.active_index;
+
+                // The data set being viewed is used for its own slice so that edits to it are shown
+                let slices = HashMap::from([(
+                    active_index,
+                    SliceContent::new(Rc::new(data_set.clone()), worker_data_set),
+                )]);
+
+                frames.set(Some(FrameSource::Stack { stack, slices }));
+                frame_index.set(active_index);
+            }
+
+            None => {
+                frames.set(FrameSource::for_data_set(&data_set, worker_data_set));
+                frame_index.set(0);
+            }
+        }
+
+        let pending_render = render_task.write_silent().take();
+        if let Some(task) = pending_render {
+            task.cancel();
+        }
+        render_errors.write_silent().clear();
+        frame_cache.write().clear();
+        is_playing.set(false);
+        frame_rate.set(cine_frame_rate(&data_set).unwrap_or(DEFAULT_FRAME_RATE));
+        let presets = voi::presets(&data_set);
+
+        // Slices of a series stack share the window of the file being viewed so that they're all
+        // displayed consistently, rather than each using its own default window
+        voi_selection.set(if series_stack.peek().is_some() {
+            presets
+                .iter()
+                .map(|preset| preset.selection)
+                .find(|selection| matches!(selection, VoiSelection::Window(_)))
+        } else {
+            voi::default_selection(&data_set, &presets)
+        });
+        voi_presets.set(presets);
+        view_transform.set(ViewTransform::default());
+        let pending_probe = probe_task.write_silent().take();
+        if let Some(task) = pending_probe {
+            task.cancel();
+        }
+        probe.set(None);
+        modality_rescale.set(ModalityRescale::from_data_set(&data_set));
+        is_color.set(
+            !data_set
+                .get_string(dictionary::PHOTOMETRIC_INTERPRETATION.tag)
+                .unwrap_or_default()
+                .starts_with("MONOCHROME"),
+        );
+    });
+
+    // Changing the VOI invalidates all previously rendered frames, including one being rendered
+    let mut set_voi_selection = move |selection: Option<VoiSelection>| {
+        voi_selection.set(selection);
+
+        let pending_render = render_task.write_silent().take();
+        if let Some(task) = pending_render {
+            task.cancel();
+        }
+        render_errors.write_silent().clear();
+        frame_cache.write().clear();
+    };
+
+    let frame_count = frames.read().as_ref().map_or(0, FrameSource::frame_count);
+    let is_stack = matches!(*frames.read(), Some(FrameSource::Stack { .. }));
+    let slice_position = frames
+        .read()
+        .as_ref()
+        .and_then(|frames| frames.slice_position(frame_index()));
+
+    let mut step_frame = move |delta: isize| {
+        let frame_count = frames.peek().as_ref().map_or(0, FrameSource::frame_count);
+        if frame_count == 0 {
+            return;
+        }
+
+        let new_index = (*frame_index.peek() as isize + delta).rem_euclid(frame_count as isize);
+        frame_index.set(new_index as usize);
+    };
+
+    // Advance through the frames while cine playback is active. The task is started and cancelled
+    // as playback starts and stops, and reads the frame rate each time so that changes to it apply
+    // straight away.
+    use_effect(move || {
+        let is_playing = is_playing();
+
+        let previous_task = cine_task.write_silent().take();
+        if let Some(task) = previous_task {
+            task.cancel();
+        }
+
+        if is_playing {
+            let task = spawn(async move {
+                loop {
+                    step_frame(1);
+
+                    let delay = 1000.0 / frame_rate.peek().max(0.1);
+                    gloo_timers::future::sleep(Duration::from_millis(delay as u64)).await;
+                }
+            });
+
+            cine_task.set(Some(task));
+        }
+    });
+
+    // Loads the slice of a series stack for the given frame index if it hasn't been loaded yet
+    let load_slice = move |frame_index: usize| async move {
+        let Some(slice) = frames
+            .write_silent()
+            .as_mut()
+            .and_then(|frames| frames.start_loading_slice(frame_index))
+        else {
+            return;
+        };
+
+        let (_, _, state) = file_list::read_file(&slice.file_data, |_| (), || false).await;
+
+        let content = if state.error_lines.is_empty() {
+            SliceContent::new(Rc::new(state.data_set), state.worker_data_set)
+        } else {
+            SliceContent::Failed(state.error_lines.join(" "))
+        };
+
+        if let Some(frames) = frames.write_silent().as_mut() {
+            frames.finish_loading_slice(frame_index, slice.file_id, content);
+        }
+
+        slice_load_count += 1;
+    };
+
+    let mut show_error = move |e: String| {
+        if let Some(canvas) = canvas_element.peek().as_ref() {
+            utils::canvas::clear(canvas).unwrap();
+        }
+        *image_placement.write_silent() = None;
+
+        error_message.set(Some(e));
+    };
+
+    // Renders a frame in the job worker and adds it to the frame cache. Only one frame is rendered
+    // at a time, and when it completes the view is redrawn, which renders the current frame next if
+    // it has changed in the meantime. This means cine playback shows frames as fast as they can be
+    // rendered, rather than queueing up renders of frames that are no longer shown.
+    let mut request_render = move |index: usize| {
+        if render_task.peek().is_some() {
+            return;
+        }
+
+        let source = match &*frames.peek() {
+            Some(frames) => frames.render_source(index),
+            None => Err("No pixel data found".to_string()),
+        };
+
+        let (worker_data_set, data_set_frame_index) = match source {
+            Ok(Some(source)) => source,
+
+            // Keep showing the previous image while the slice loads
+            Ok(None) => {
+                spawn(load_slice(index));
+                return;
+            }
+
+            Err(e) => {
+                show_error(e);
+                return;
+            }
+        };
+
+        let voi_selection = *voi_selection.peek();
+
+        let task = spawn(async move {
+            let result = worker_data_set
+                .render_frame(data_set_frame_index, voi_selection)
+                .await;
+
+            match result {
+                Ok(image) => frame_cache.write_silent().insert(index, Rc::new(image)),
+                Err(JobError::Failed(e)) => {
+                    render_errors.write_silent().insert(index, e);
+                }
+                Err(JobError::Cancelled) => (),
+            }
+
+            render_task.set(None);
+        });
+
+        *render_task.write_silent() = Some(task);
+    };
+
+    let mut redraw = move || {
+        error_message.set(None);
+
+        // Subscribe to slices finishing loading and frames finishing rendering so the current
+        // frame is drawn once it's available
+        slice_load_count();
+        render_task.read();
+
+        let Some(container) = container_element() else {
+            return;
+        };
+
+        let Some(canvas) = canvas_element() else {
+            return;
+        };
+
+        let image = frame_cache.read().get(frame_index());
+        let Some(image) = image else {
+            let render_error = render_errors.peek().get(&frame_index()).cloned();
+            match render_error {
+                Some(e) => show_error(e),
+                None => request_render(frame_index()),
+            }
+            return;
+        };
+
+        // Reuse the source canvas if it already holds this image, e.g. when panning or zooming
+        let src_canvas = match &*source_canvas.peek() {
+            Some((cached_image, src_canvas)) if Rc::ptr_eq(cached_image, &image) => {
+                Some(src_canvas.clone())
+            }
+            _ => None,
+        };
+        let src_canvas = match src_canvas {
+            Some(src_canvas) => src_canvas,
+            None => {
+                let src_canvas = utils::canvas::from_rgb_image(&image).unwrap();
+                *source_canvas.write_silent() = Some((image.clone(), src_canvas.clone()));
+                src_canvas
+            }
+        };
+
+        let placement = draw_image_to_canvas(
+            &src_canvas,
+            &canvas,
+            &container,
+            view_transform(),
+            image_smoothing(),
+        )
+        .unwrap();
+
+        if *image_placement.peek() != Some(placement) {
+            image_placement.set(Some(placement));
+        }
+    };
+
+    use_effect(redraw);
+
+    let on_export_frame = move |format: ImageExportFormat| async move {
+        let image =
+            match get_rendered_frame(frames, frame_cache, frame_index(), voi_selection()).await {
+                Ok(Some(image)) => image,
+                Ok(None) => {
+                    ui::toasts::add_error("The slice hasn't finished loading".into());
+                    return;
+                }
+                Err(JobError::Failed(e)) => {
+                    ui::toasts::add_error(e);
+                    return;
+                }
+                Err(JobError::Cancelled) => return,
+            };
+
+        match format.encode(&image) {
+            Ok(bytes) => {
+                let frame_suffix = if frame_count > 1 {
+                    format!("_frame{}", frame_index() + 1)
+                } else {
+                    String::new()
+                };
+
+                let filename = export::export_filename(
+                    &filename(),
+                    &format!("{}.{}", frame_suffix, format.extension()),
+                );
+
+                let mut writer = utils::download::BlobPartWriter::new(1024 * 1024);
+                writer.write(&bytes);
+
+                utils::download::trigger(writer.into_js_array(), &filename, format.mime_type())
+                    .unwrap();
+
+                ui::toasts::add_info("Generated frame image for download".into());
+            }
+
+            Err(e) => ui::toasts::add_error(e),
+        }
+    };
+
+    let on_export_all_frames = move |format: ImageExportFormat| {
+        is_exporting.set(true);
+        is_playing.set(false);
+
+        spawn(async move {
+            scopeguard::defer! {
+                is_exporting.set(false);
+            }
+
+            let voi_selection = voi_selection();
+            let digits = frame_count.to_string().len();
+
+            let mut zip_writer =
+                utils::zip::ZipWriter::new(utils::download::BlobPartWriter::new(1024 * 1024));
+
+            for index in 0..frame_count {
+                // Slices of a series stack are loaded as they're exported
+                let image = loop {
+                    match get_rendered_frame(frames, frame_cache, index, voi_selection).await {
+                        Ok(Some(image)) => break Ok(image),
+                        Ok(None) => {
+                            load_slice(index).await;
+                            gloo_timers::future::sleep(Duration::from_millis(10)).await;
+                        }
+                        Err(JobError::Failed(e)) => break Err(e),
+                        Err(JobError::Cancelled) => return,
+                    }
+                };
+
+                let result = image
+                    .and_then(|image| format.encode(&image))
+                    .and_then(|bytes| {
+                        zip_writer.add_file(
+                            &format!("frame_{:0digits$}.{}", index + 1, format.extension()),
+                            &bytes,
+                        )
+                    });
+
+                if let Err(e) = result {
+                    ui::toasts::add_error(format!("Exporting frame {} failed. {}", index + 1, e));
+                    return;
+                }
+
+                // Yield so the page stays responsive while exporting
+                gloo_timers::future::sleep(Duration::ZERO).await;
+            }
+
+            match zip_writer.finish() {
+                Ok(writer) => {
+                    utils::download::trigger(
+                        writer.into_js_array(),
+                        &export::export_filename(&filename(), "_frames.zip"),
+                        "application/zip",
+                    )
+                    .unwrap();
+
+                    ui::toasts::add_info(format!(
+                        "Generated ZIP of {} frames for download",
+                        frame_count
+                    ));
+                }
+
+                Err(e) => ui::toasts::add_error(e),
+            }
+        });
+    };
+
+    let mut clear_probe = move || {
+        let pending_probe = probe_task.write_silent().take();
+        if let Some(task) = pending_probe {
+            task.cancel();
+        }
+
+        probe.set(None);
+    };
+
+    // Updates the pixel probe for the pixel at the given client position. The stored values are
+    // extracted from the pixel data in the job worker, and a probe that's still waiting on them is
+    // replaced when the pointer moves to another pixel.
+    let mut update_probe = move |client_x: f64, client_y: f64| {
+        let (Some(container), Some(placement)) =
+            (container_element.peek().clone(), *image_placement.peek())
+        else {
+            return;
+        };
+
+        // Map the position back through the view transform to find the image pixel
+        let rect = container.get_bounding_client_rect();
+        let (x, y) = placement.view_to_image(client_x - rect.left(), client_y - rect.top());
+        if x < 0.0 || y < 0.0 || x >= placement.image_width || y >= placement.image_height {
+            clear_probe();
+            return;
+        }
+
+        let (column, row) = (x as usize, y as usize);
+        let frame_index = *frame_index.peek();
+
+        // Slices of a series stack have their own data set, which may have its own rescale
+        let slice_modality_rescale = frames
+            .peek()
+            .as_ref()
+            .and_then(|frames| frames.data_set(frame_index))
+            .as_deref()
+            .and_then(ModalityRescale::from_data_set);
+        let modality_rescale = slice_modality_rescale.or_else(|| modality_rescale.peek().clone());
+
+        let rgb = if *is_color.peek() {
+            frame_cache.peek().get(frame_index).and_then(|image| {
+                image
+                    .get_pixel_checked(column as u32, row as u32)
+                    .map(|p| p.0)
+            })
+        } else {
+            None
+        };
+
+        let source = match &*frames.peek() {
+            Some(frames) => frames.render_source(frame_index),
+            None => Err("No pixel data found".to_string()),
+        };
+
+        let pending_probe = probe_task.write_silent().take();
+        if let Some(task) = pending_probe {
+            task.cancel();
+        }
+
+        let task = spawn(async move {
+            let stored_values = match source {
+                Ok(Some((worker_data_set, data_set_frame_index))) => {
+                    match worker_data_set
+                        .probe_pixel(data_set_frame_index, column, row)
+                        .await
+                    {
+                        Ok(values) => Ok(values),
+                        Err(JobError::Failed(e)) => Err(e),
+                        Err(JobError::Cancelled) => return,
+                    }
+                }
+                Ok(None) => Err("The slice hasn't finished loading".to_string()),
+                Err(e) => Err(e),
+            };
+
+            let modality_value = match (&stored_values, &modality_rescale) {
+                (Ok(values), Some(rescale)) if values.len() == 1 => {
+                    Some((rescale.apply(values[0]), rescale.rescale_type.clone()))
+                }
+                _ => None,
+            };
+
+            *probe_task.write_silent() = None;
+            probe.set(Some(PixelProbe {
+                column,
+                row,
+                stored_values,
+                modality_value,
+                rgb,
+            }));
+        });
+
+        *probe_task.write_silent() = Some(task);
+    };
+
+    rsx! {
+        div {
+            class: "pixel-data-view",
+
+            div {
+                class: "frame-view",
+
+                onmounted: move |ev| container_element.set(utils::get_element::<HtmlElement>(ev)),
+                onresize: move |_| redraw(),
+                oncontextmenu: move |event| event.prevent_default(),
+                onpointerdown: move |event| {
+                    let point = event.client_coordinates();
+
+                    if event.trigger_button() == Some(MouseButton::Primary) {
+                        pan_drag.set(Some((point.x, point.y, view_transform())));
+                        return;
+                    }
+
+                    if event.trigger_button() != Some(MouseButton::Secondary) {
+                        return;
+                    }
+
+                    // Start window/level adjustment from the current window
+                    let window = match voi_selection() {
+                        Some(VoiSelection::Window(window)) => window,
+                        _ => voi::initial_window(&data_set.read()),
+                    };
+
+                    voi_drag.set(Some((point.x, point.y, window)));
+                },
+                onpointermove: move |event| {
+                    let point = event.client_coordinates();
+                    update_probe(point.x, point.y);
+
+                    if let Some((start_x, start_y, start_transform)) = pan_drag() {
+                        if event.held_buttons().contains(MouseButton::Primary) {
+                            let point = event.client_coordinates();
+                            view_transform
+                                .set(start_transform.panned(point.x - start_x, point.y - start_y));
+                        } else {
+                            pan_drag.set(None);
+                        }
+                    }
+
+                    let Some((start_x, start_y, start_window)) = voi_drag() else {
+                        return;
+                    };
+
+                    if !event.held_buttons().contains(MouseButton::Secondary) {
+                        voi_drag.set(None);
+                        return;
+                    }
+
+                    // Horizontal movement adjusts the width and vertical movement adjusts the
+                    // center, scaled relative to the window's starting width
+                    let point = event.client_coordinates();
+                    let sensitivity = (start_window.width / 256.0).max(0.1);
+
+                    set_voi_selection(Some(VoiSelection::Window(start_window.adjusted(
+                        (point.y - start_y) * sensitivity,
+                        (point.x - start_x) * sensitivity,
+                    ))));
+                },
+                onpointerup: move |_| {
+                    voi_drag.set(None);
+                    pan_drag.set(None);
+                },
+                onpointerleave: move |_| clear_probe(),
+                onwheel: move |event| {
+                    let delta = event.delta().strip_units().y;
+                    if delta == 0.0 {
+                        return;
+                    }
+
+                    event.prevent_default();
+
+                    // The mouse wheel steps through frames of multi-frame data, and zooms
+                    // otherwise. Holding Ctrl or Cmd always zooms.
+                    let modifiers = event.modifiers();
+                    if frame_count > 1
+                        && !modifiers.contains(Modifiers::CONTROL)
+                        && !modifiers.contains(Modifiers::META)
+                    {
+                        step_frame(delta.signum() as isize);
+                        return;
+                    }
+
+                    let (Some(container), Some(placement)) =
+                        (container_element.peek().clone(), image_placement())
+                    else {
+                        return;
+                    };
+
+                    // Zoom about the pointer's position in the view, which is measured from the
+                    // container rather than from whichever child element is under the pointer
+                    let factor = if delta < 0.0 { 1.1 } else { 1.0 / 1.1 };
+                    let point = event.client_coordinates();
+                    let rect = container.get_bounding_client_rect();
+
+                    view_transform.set(ViewTransform::zoomed_at(
+                        &placement,
+                        factor,
+                        point.x - rect.left(),
+                        point.y - rect.top(),
+                    ));
+                },
+
+                canvas {
+                    onmounted: move |ev| canvas_element.set(utils::get_element::<HtmlCanvasElement>(ev)),
+                }
+
+                if let Some(probe) = probe() {
+                    PixelProbeOverlay { probe }
+                }
+
+                if let Some(error_message) = error_message() {
+                    div {
+                        class: "frame-view-error",
+
+                        b { "Error" }
+                        br {}
+                        br {}
+                        "{error_message}"
+                    }
+                }
+            }
+
+            if frame_count > 0 {
+                div {
+                    class: "frame-view-toolbar",
+
+                    VoiControls {
+                        voi_selection: voi_selection(),
+                        voi_presets,
+                        on_change: move |selection| set_voi_selection(selection),
+                    }
+
+                    div { class: "vertical-divider" }
+
+                    ZoomControls {
+                        view_transform,
+                        image_smoothing,
+                        zoom_percentage: image_placement().map(|p| p.zoom_percentage()),
+                    }
+
+                    div { class: "vertical-divider" }
+
+                    div {
+                        class: "frame-export",
+                        class: if is_exporting() { "disabled" },
+
+                        button {
+                            title: "Download the displayed frame as a PNG image",
+                            onclick: move |_| on_export_frame(ImageExportFormat::Png),
+                            "Frame as .png"
+                        }
+                        button {
+                            title: "Download the displayed frame as a JPEG image",
+                            onclick: move |_| on_export_frame(ImageExportFormat::Jpeg),
+                            "Frame as .jpg"
+                        }
+                        if frame_count > 1 {
+                            button {
+                                title: "Download all frames as PNG images in a ZIP file",
+                                onclick: move |_| on_export_all_frames(ImageExportFormat::Png),
+                                if is_exporting() { "Exporting…" } else { "All frames as .zip" }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if frame_count > 1 {
+                div {
+                    class: "frame-view-toolbar",
+
+                    button {
+                        title: if is_playing() { "Pause" } else { "Play" },
+                        onclick: move |_| is_playing.toggle(),
+
+                        FontAwesomeIcon { icon: if is_playing() { "pause" } else { "play" }, style: "solid" }
+                    }
+                    button {
+                        title: "Previous frame",
+                        onclick: move |_| step_frame(-1),
+
+                        FontAwesomeIcon { icon: "backward-step", style: "solid" }
+                    }
+                    button {
+                        title: "Next frame",
+                        onclick: move |_| step_frame(1),
+
+                        FontAwesomeIcon { icon: "forward-step", style: "solid" }
+                    }
+
+                    input {
+                        class: "frame-slider",
+                        r#type: "range",
+                        min: 0,
+                        max: frame_count - 1,
+                        value: frame_index(),
+                        oninput: move |event| {
+                            if let Ok(index) = event.value().parse::<usize>() {
+                                frame_index.set(index.min(frame_count - 1));
+                            }
+                        },
+                    }
+
+                    span {
+                        class: "frame-number",
+
+                        if is_stack {
+                            "Slice {frame_index() + 1} / {frame_count}"
+                        } else {
+                            "Frame {frame_index() + 1} / {frame_count}"
+                        }
+                    }
+
+                    if let Some(slice_position) = slice_position {
+                        span { class: "slice-position", "{slice_position}" }
+                    }
+
+                    label {
+                        "FPS"
+                        input {
+                            class: "frame-rate",
+                            r#type: "number",
+                            min: 0.1,
+                            max: 120,
+                            step: 1,
+                            value: "{frame_rate}",
+                            oninput: move |event| {
+                                if let Ok(rate) = event.value().parse::<f64>() && rate > 0.0 {
+                                    frame_rate.set(rate);
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the rendered image for the specified frame, taking it from the frame cache if present
+/// and otherwise rendering it in the job worker. Returns `None` if the frame is a slice of a series
+/// stack that hasn't been loaded yet.
+///
+async fn get_rendered_frame(
+    frames: Signal<Option<FrameSource>>,
+    frame_cache: Signal<FrameCache>,
+    frame_index: usize,
+    voi_selection: Option<VoiSelection>,
+) -> Result<Option<Rc<RgbImage>>, JobError> {
+    if let Some(image) = frame_cache.peek().get(frame_index) {
+        return Ok(Some(image));
+    }
+
+    let source = match &*frames.peek() {
+        Some(frames) => frames.render_source(frame_index),
+        None => Err("No pixel data found".to_string()),
+    };
+
+    let Some((worker_data_set, data_set_frame_index)) = source.map_err(JobError::Failed)? else {
+        return Ok(None);
+    };
+
+    let image = worker_data_set
+        .render_frame(data_set_frame_index, voi_selection)
+        .await?;
+
+    Ok(Some(Rc::new(image)))
+}
+
+/// Displays the VOI preset selector, the current window, and a button to reset the VOI back to the
+/// data set's default.
+///
+#[component]
+fn VoiControls(
+    voi_selection: Option<VoiSelection>,
+    voi_presets: Signal<Vec<voi::VoiPreset>>,
+    on_change: EventHandler<Option<VoiSelection>>,
+) -> Element {
+    let selected_preset = voi_presets
+        .read()
+        .iter()
+        .position(|preset| Some(preset.selection) == voi_selection);
+
+    let selected_value = match (voi_selection, selected_preset) {
+        (None, _) => "default".to_string(),
+        (Some(_), Some(index)) => index.to_string(),
+        (Some(_), None) => "custom".to_string(),
+    };
+
+    let current_window: Option<Window> = match voi_selection {
+        Some(VoiSelection::Window(window)) => Some(window),
+        Some(VoiSelection::Lut(_)) => None,
+        None => voi_presets
+            .read()
+            .iter()
+            .find_map(|preset| match preset.selection {
+                VoiSelection::Window(window) => Some(window),
+                VoiSelection::Lut(_) => None,
+            }),
+    };
+
+    rsx! {
+        label {
+            "VOI"
+
+            select {
+                value: "{selected_value}",
+                onchange: move |event| {
+                    let selection = event
+                        .value()
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| voi_presets.read().get(index).map(|preset| preset.selection));
+
+                    on_change.call(selection);
+                },
+
+                option { value: "default", selected: selected_value == "default", "Default" }
+
+                for (index, preset) in voi_presets.read().iter().enumerate() {
+                    option {
+                        value: "{index}",
+                        selected: selected_value == index.to_string(),
+
+                        "{preset.name}"
+                    }
+                }
+
+                if selected_value == "custom" {
+                    option { value: "custom", selected: true, "Custom" }
+                }
+            }
+        }
+
+        span {
+            class: "voi-window",
+
+            if let Some(window) = current_window {
+                {voi::window_description(window)}
+            } else if matches!(voi_selection, Some(VoiSelection::Lut(_))) {
+                "VOI LUT"
+            } else {
+                "Automatic"
+            }
+        }
+
+        button {
+            title: "Reset window/level",
+            disabled: voi_selection.is_none(),
+            onclick: move |_| on_change.call(None),
+
+            FontAwesomeIcon { icon: "rotate-left", style: "solid" }
+        }
+    }
+}
+
+/// Displays buttons for selecting the zoom mode, the current zoom, and the image smoothing toggle.
+///
+#[component]
+fn ZoomControls(
+    view_transform: Signal<ViewTransform>,
+    image_smoothing: Signal<bool>,
+    zoom_percentage: Option<f64>,
+) -> Element {
+    let zoom_mode = view_transform().zoom_mode;
+
+    rsx! {
+        for (mode, label, title) in [
+            (ZoomMode::Fit, "Fit", "Fit the image to the view"),
+            (ZoomMode::Actual, "1:1", "Show one image pixel per screen pixel"),
+            (ZoomMode::Fill, "Fill", "Fill the view with the image"),
+        ] {
+            button {
+                class: if zoom_mode == mode { "selected" },
+                title,
+                onclick: move |_| view_transform.set(ViewTransform::new(mode)),
+
+                "{label}"
+            }
+        }
+
+        if let Some(zoom_percentage) = zoom_percentage {
+            span {
+                class: "zoom-percentage",
+
+                "{zoom_percentage:.0}%"
+            }
+        }
+
+        label {
+            title: "Smooth the image when it is scaled, rather than showing its individual pixels",
+
+            input {
+                r#type: "checkbox",
+                checked: image_smoothing(),
+                onchange: move |event| image_smoothing.set(event.checked()),
+            }
+            "Smooth"
+        }
+
+        span {
+            class: "frame-view-hint",
+
+            "Drag to pan, Ctrl+scroll to zoom, right-drag to adjust window/level"
+        }
+    }
+}
+
+/// Returns the cine frame rate specified by the data set, taken from the Cine Rate data element if
+/// present, or otherwise derived from the Frame Time data element.
+///
+fn cine_frame_rate(data_set: &DataSet) -> Option<f64> {
+    if let Ok(cine_rate) = data_set.get_int::<i64>(dictionary::CINE_RATE.tag)
+        && cine_rate > 0
+    {
+        return Some(cine_rate as f64);
+    }
+
+    match data_set.get_float(dictionary::FRAME_TIME.tag) {
+        Ok(frame_time) if frame_time > 0.0 => Some(1000.0 / frame_time),
+        _ => None,
+    }
+}
+
+/// Draws the source canvas into the destination canvas using the given view transform. The
+/// destination canvas is sized to match its container at the device's pixel ratio so that it stays
+/// sharp on high-DPI displays. Returns where the image was placed in the view.
+///
+fn draw_image_to_canvas(
+    src_canvas: &HtmlCanvasElement,
+    dst_canvas: &HtmlCanvasElement,
+    canvas_container: &HtmlElement,
+    view_transform: ViewTransform,
+    image_smoothing: bool,
+) -> Result<ImagePlacement, JsValue> {
+    let rect = canvas_container.get_bounding_client_rect();
+    let viewport = Viewport {
+        width: rect.width(),
+        height: rect.height(),
+        device_pixel_ratio: web_sys::window().unwrap().device_pixel_ratio().max(1.0),
+    };
+
+    // Size the destination canvas in device pixels
+    dst_canvas.set_width((viewport.width * viewport.device_pixel_ratio).round() as u32);
+    dst_canvas.set_height((viewport.height * viewport.device_pixel_ratio).round() as u32);
+
+    // Get 2D rendering context for the destination canvas. Resizing the canvas resets its state, so
+    // the transform and smoothing are set every time.
+    let dst_context = dst_canvas
+        .get_context("2d")?
+        .unwrap()
+        .dyn_into::<CanvasRenderingContext2d>()?;
+
+    dst_context.set_transform(
+        viewport.device_pixel_ratio,
+        0.0,
+        0.0,
+        viewport.device_pixel_ratio,
+        0.0,
+        0.0,
+    )?;
+    dst_context.set_image_smoothing_enabled(image_smoothing);
+
+    let placement = view_transform.place_image(src_canvas.width(), src_canvas.height(), viewport);
+
+    dst_context.draw_image_with_html_canvas_element_and_dw_and_dh(
+        src_canvas,
+        placement.x,
+        placement.y,
+        placement.image_width * placement.scale,
+        placement.image_height * placement.scale,
+    )?;
+
+    Ok(placement)
+}
@@ -0,0 +1,432 @@
+use dcmfx::core::{DataElementTag, DataSet, dictionary};
+use dioxus::prelude::*;
+
+/// The stored values of a single frame of native pixel data, i.e. the pixel values prior to the
+/// application of any Modality LUT, VOI LUT, or color transform.
+///
+pub struct StoredValues {
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+    values: Vec<i64>,
+}
+
+impl StoredValues {
+    /// Extracts the stored values for the specified frame from a data set's native pixel data.
+    /// Encapsulated pixel data isn't supported.
+    ///
+    pub fn from_data_set(data_set: &DataSet, frame_index: usize) -> Result<Self, String> {
+        let get_u16 = |tag: DataElementTag| {
+            data_set
+                .get_int::<u16>(tag)
+                .map_err(|_| format!("{} is missing or invalid", dictionary::tag_name(tag, None)))
+        };
+
+        let width = usize::from(get_u16(dictionary::COLUMNS.tag)?);
+        let height = usize::from(get_u16(dictionary::ROWS.tag)?);
+        let samples_per_pixel = usize::from(get_u16(dictionary::SAMPLES_PER_PIXEL.tag)?).max(1);
+        let bits_allocated = usize::from(get_u16(dictionary::BITS_ALLOCATED.tag)?);
+        let bits_stored = usize::from(get_u16(dictionary::BITS_STORED.tag)?).clamp(1, 32);
+        let high_bit = data_set
+            .get_int::<u16>(dictionary::HIGH_BIT.tag)
+            .map_or(bits_stored - 1, usize::from);
+        let is_signed = get_u16(dictionary::PIXEL_REPRESENTATION.tag)? == 1;
+        let is_planar = samples_per_pixel > 1
+            && data_set
+                .get_int::<u16>(dictionary::PLANAR_CONFIGURATION.tag)
+                .unwrap_or(0)
+                == 1;
+
+        let photometric_interpretation = data_set
+            .get_string(dictionary::PHOTOMETRIC_INTERPRETATION.tag)
+            .unwrap_or_default();
+        if photometric_interpretation.trim().ends_with("_422") {
+            return Err("Stored values aren't available for subsampled pixel data".into());
+        }
+
+        let Ok(bytes) = data_set
+            .get_value(dictionary::PIXEL_DATA.tag)
+            .and_then(|value| value.bytes())
+        else {
+            return Err("Stored values aren't available for compressed pixel data".into());
+        };
+
+        let sample_count = width * height * samples_per_pixel;
+
+        let mut values = Vec::with_capacity(sample_count);
+
+        match bits_allocated {
+            1 => {
+                let first_bit = frame_index * sample_count;
+                if (first_bit + sample_count).div_ceil(8) > bytes.len() {
+                    return Err("Pixel data is too short".into());
+                }
+
+                for bit in first_bit..first_bit + sample_count {
+                    values.push(i64::from((bytes[bit / 8] >> (bit % 8)) & 1));
+                }
+            }
+
+            8 | 16 | 32 => {
+                let bytes_per_sample = bits_allocated / 8;
+                let frame_size = sample_count * bytes_per_sample;
+                let start = frame_index * frame_size;
+
+                let Some(frame_bytes) = bytes.get(start..start + frame_size) else {
+                    return Err("Pixel data is too short".into());
+                };
+
+                // The stored bits end at the high bit, and the bits outside of them may be used
+                // for other purposes so are masked out
+                let bits_stored = bits_stored.min(bits_allocated);
+                let shift = (high_bit + 1).saturating_sub(bits_stored);
+                if shift + bits_stored > bits_allocated {
+                    return Err(format!(
+                        "High Bit of {} isn't valid for Bits Stored of {}",
+                        high_bit, bits_stored
+                    ));
+                }
+
+                let mask = (1u64 << bits_stored) - 1;
+
+                for sample in frame_bytes.chunks_exact(bytes_per_sample) {
+                    let mut raw = 0u64;
+                    for (i, byte) in sample.iter().enumerate() {
+                        raw |= u64::from(*byte) << (i * 8);
+                    }
+
+                    // Extract the stored bits and sign extend from the top one
+                    let mut value = ((raw >> shift) & mask) as i64;
+                    if is_signed && value & (1 << (bits_stored - 1)) != 0 {
+                        value -= 1 << bits_stored;
+                    }
+
+                    values.push(value);
+                }
+            }
+
+            _ => {
+                return Err(format!(
+                    "Bits Allocated of {} isn't supported",
+                    bits_allocated
+                ));
+            }
+        }
+
+        // Convert planar data to be interleaved so that a pixel's samples are adjacent
+        if is_planar {
+            let plane_size = width * height;
+            values = (0..sample_count)
+                .map(|i| values[(i % samples_per_pixel) * plane_size + i / samples_per_pixel])
+                .collect();
+        }
+
+        Ok(Self {
+            width,
+            height,
+            samples_per_pixel,
+            values,
+        })
+    }
+
+    /// Returns the stored values of the samples for the pixel at the given column and row.
+    ///
+    pub fn get(&self, column: usize, row: usize) -> Option<&[i64]> {
+        if column >= self.width || row >= self.height {
+            return None;
+        }
+
+        let offset = (row * self.width + column) * self.samples_per_pixel;
+
+        self.values.get(offset..offset + self.samples_per_pixel)
+    }
+}
+
+/// The linear Modality LUT transform defined by a data set's Rescale Slope, Rescale Intercept and
+/// Rescale Type data elements.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModalityRescale {
+    pub slope: f64,
+    pub intercept: f64,
+    pub rescale_type: Option<String>,
+}
+
+impl ModalityRescale {
+    /// Reads the rescale from the data set. For enhanced multi-frame data sets where the rescale
+    /// isn't in the root data set, the Pixel Value Transformation Sequence in the Shared Functional
+    /// Groups Sequence is used. Returns `None` if no rescale is specified.
+    ///
+    pub fn from_data_set(data_set: &DataSet) -> Option<Self> {
+        let mut rescale = Self::from_data_set_root(data_set)
+            .or_else(|| Self::from_shared_functional_groups(data_set))?;
+
+        // CT rescales are in Hounsfield units when no Rescale Type is specified
+        if rescale.rescale_type.is_none()
+            && data_set
+                .get_string(dictionary::MODALITY.tag)
+                .is_ok_and(|modality| modality.trim() == "CT")
+        {
+            rescale.rescale_type = Some("HU".to_string());
+        }
+
+        Some(rescale)
+    }
+
+    fn from_shared_functional_groups(data_set: &DataSet) -> Option<Self> {
+        let shared_functional_groups = data_set
+            .get_value(dictionary::SHARED_FUNCTIONAL_GROUPS_SEQUENCE.tag)
+            .and_then(|value| value.sequence_items())
+            .ok()?;
+
+        let pixel_value_transformations = shared_functional_groups
+            .first()?
+            .get_value(dictionary::PIXEL_VALUE_TRANSFORMATION_SEQUENCE.tag)
+            .and_then(|value| value.sequence_items())
+            .ok()?;
+
+        Self::from_data_set_root(pixel_value_transformations.first()?)
+    }
+
+    fn from_data_set_root(data_set: &DataSet) -> Option<Self> {
+        let slope = data_set.get_float(dictionary::RESCALE_SLOPE.tag).ok()?;
+        let intercept = data_set.get_float(dictionary::RESCALE_INTERCEPT.tag).ok()?;
+
+        let rescale_type = data_set
+            .get_string(dictionary::RESCALE_TYPE.tag)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        Some(Self {
+            slope,
+            intercept,
+            rescale_type,
+        })
+    }
+
+    /// Applies this rescale to a stored value.
+    ///
+    pub fn apply(&self, stored_value: i64) -> f64 {
+        stored_value as f64 * self.slope + self.intercept
+    }
+}
+
+/// The details of the pixel under the pointer in the frame view.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelProbe {
+    pub column: usize,
+    pub row: usize,
+    pub stored_values: Result<Vec<i64>, String>,
+    pub modality_value: Option<(f64, Option<String>)>,
+    pub rgb: Option<[u8; 3]>,
+}
+
+/// Displays a pixel probe as an overlay on top of the frame view.
+///
+#[component]
+pub fn PixelProbeOverlay(probe: PixelProbe) -> Element {
+    rsx! {
+        div {
+            class: "pixel-probe",
+
+            span { "X {probe.column}  Y {probe.row}" }
+
+            match &probe.stored_values {
+                Ok(stored_values) => rsx! {
+                    span {
+                        "Stored "
+                        {stored_values.iter().map(i64::to_string).collect::<Vec<_>>().join(", ")}
+                    }
+                },
+                Err(e) => rsx! { span { class: "unavailable", "{e}" } },
+            }
+
+            if let Some((value, rescale_type)) = &probe.modality_value {
+                span {
+                    "Value {value:.2}"
+                    if let Some(rescale_type) = rescale_type {
+                        " {rescale_type}"
+                    }
+                }
+            }
+
+            if let Some([r, g, b]) = probe.rgb {
+                span { "RGB {r}, {g}, {b}" }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dcmfx::core::{DataElementValue, ValueRepresentation};
+
+    use super::*;
+    use crate::test_utils::insert;
+
+    /// Returns a data set with a single row of native pixel data. `bits` is Bits Allocated, Bits
+    /// Stored, and High Bit.
+    ///
+    fn pixel_data_set(
+        columns: usize,
+        samples_per_pixel: usize,
+        bits: (u16, u16, u16),
+        is_signed: bool,
+        pixel_data: Vec<u8>,
+    ) -> DataSet {
+        let mut data_set = DataSet::new();
+        for (tag, value) in [
+            (dictionary::ROWS.tag, 1),
+            (dictionary::COLUMNS.tag, columns as u16),
+            (dictionary::SAMPLES_PER_PIXEL.tag, samples_per_pixel as u16),
+            (dictionary::BITS_ALLOCATED.tag, bits.0),
+            (dictionary::BITS_STORED.tag, bits.1),
+            (dictionary::HIGH_BIT.tag, bits.2),
+            (dictionary::PIXEL_REPRESENTATION.tag, u16::from(is_signed)),
+        ] {
+            insert(&mut data_set, tag, "US", &value.to_string());
+        }
+
+        let photometric_interpretation = if samples_per_pixel == 3 {
+            "RGB"
+        } else {
+            "MONOCHROME2"
+        };
+        insert(
+            &mut data_set,
+            dictionary::PHOTOMETRIC_INTERPRETATION.tag,
+            "CS",
+            photometric_interpretation,
+        );
+
+        let vr = if bits.0 > 8 {
+            ValueRepresentation::OtherWordString
+        } else {
+            ValueRepresentation::OtherByteString
+        };
+        data_set.insert(
+            dictionary::PIXEL_DATA.tag,
+            DataElementValue::new_binary(vr, pixel_data.into()).unwrap(),
+        );
+
+        data_set
+    }
+
+    fn u16_bytes(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn row_values(stored_values: &StoredValues, columns: usize) -> Vec<Vec<i64>> {
+        (0..columns)
+            .map(|column| stored_values.get(column, 0).unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn signed_values_are_sign_extended() {
+        let pixel_data = u16_bytes(&[(-5i16) as u16, 300, i16::MIN as u16]);
+        let data_set = pixel_data_set(3, 1, (16, 16, 15), true, pixel_data);
+
+        let stored_values = StoredValues::from_data_set(&data_set, 0).unwrap();
+
+        assert_eq!(
+            row_values(&stored_values, 3),
+            vec![vec![-5], vec![300], vec![-32768]]
+        );
+    }
+
+    #[test]
+    fn bits_outside_of_the_stored_bits_are_masked_out() {
+        // 12 bits stored in the low bits, with overlay bits set above them
+        let pixel_data = u16_bytes(&[0xF123, 0x0FFF]);
+        let data_set = pixel_data_set(2, 1, (16, 12, 11), false, pixel_data.clone());
+
+        let stored_values = StoredValues::from_data_set(&data_set, 0).unwrap();
+        assert_eq!(
+            row_values(&stored_values, 2),
+            vec![vec![0x123], vec![0xFFF]]
+        );
+
+        // The same bits when signed
+        let data_set = pixel_data_set(2, 1, (16, 12, 11), true, pixel_data);
+
+        let stored_values = StoredValues::from_data_set(&data_set, 0).unwrap();
+        assert_eq!(row_values(&stored_values, 2), vec![vec![0x123], vec![-1]]);
+    }
+
+    #[test]
+    fn stored_bits_are_taken_from_below_the_high_bit() {
+        let pixel_data = u16_bytes(&[0xABCF, 0x8000]);
+        let data_set = pixel_data_set(2, 1, (16, 12, 15), true, pixel_data);
+
+        let stored_values = StoredValues::from_data_set(&data_set, 0).unwrap();
+
+        assert_eq!(
+            row_values(&stored_values, 2),
+            vec![vec![0xABC - 0x1000], vec![-2048]]
+        );
+    }
+
+    #[test]
+    fn invalid_high_bit_is_an_error() {
+        let data_set = pixel_data_set(1, 1, (16, 12, 16), false, u16_bytes(&[0]));
+
+        assert!(StoredValues::from_data_set(&data_set, 0).is_err());
+    }
+
+    #[test]
+    fn planar_values_are_interleaved() {
+        let pixel_data = vec![10, 20, 30, 40, 50, 60];
+
+        let mut data_set = pixel_data_set(2, 3, (8, 8, 7), false, pixel_data);
+        insert(
+            &mut data_set,
+            dictionary::PLANAR_CONFIGURATION.tag,
+            "US",
+            "1",
+        );
+
+        let stored_values = StoredValues::from_data_set(&data_set, 0).unwrap();
+
+        assert_eq!(
+            row_values(&stored_values, 2),
+            vec![vec![10, 30, 50], vec![20, 40, 60]]
+        );
+    }
+
+    #[test]
+    fn values_are_read_from_the_requested_frame() {
+        let pixel_data = u16_bytes(&[1, 2, 3, 4]);
+        let data_set = pixel_data_set(2, 1, (16, 16, 15), false, pixel_data);
+
+        let stored_values = StoredValues::from_data_set(&data_set, 1).unwrap();
+        assert_eq!(row_values(&stored_values, 2), vec![vec![3], vec![4]]);
+        assert_eq!(stored_values.get(2, 0), None);
+        assert_eq!(stored_values.get(0, 1), None);
+
+        assert!(StoredValues::from_data_set(&data_set, 2).is_err());
+    }
+
+    #[test]
+    fn ct_rescale_defaults_to_hounsfield_units() {
+        let mut data_set = DataSet::new();
+        insert(&mut data_set, dictionary::MODALITY.tag, "CS", "CT");
+        insert(&mut data_set, dictionary::RESCALE_SLOPE.tag, "DS", "2");
+        insert(
+            &mut data_set,
+            dictionary::RESCALE_INTERCEPT.tag,
+            "DS",
+            "-1024",
+        );
+
+        let rescale = ModalityRescale::from_data_set(&data_set).unwrap();
+
+        assert_eq!(rescale.rescale_type.as_deref(), Some("HU"));
+        assert_eq!(rescale.apply(100), -824.0);
+    }
+}
@@ -0,0 +1,36 @@
+use dcmfx::{
+    core::DataSet,
+    pixel_data::{PixelDataFrame, PixelDataRenderer},
+};
+use image::RgbImage;
+
+use super::voi::{self, VoiSelection};
+
+/// Renders a frame of the data set's pixel data to an RGB image. This is run by the job worker.
+///
+pub fn render_frame(
+    data_set: &DataSet,
+    frame: &mut PixelDataFrame,
+    voi_selection: Option<VoiSelection>,
+) -> Result<RgbImage, String> {
+    // When a VOI LUT is selected the renderer is created from a data set that has that VOI LUT as
+    // its only VOI transform, otherwise a selected window is passed as a VOI override
+    let voi_lut_data_set = match voi_selection {
+        Some(VoiSelection::Lut(index)) => voi::data_set_with_voi_lut(data_set, index),
+        _ => None,
+    };
+    let voi_override = match voi_selection {
+        Some(VoiSelection::Window(window)) => Some(window.to_voi_window()),
+        _ => None,
+    };
+
+    let Ok(pixel_data_renderer) =
+        PixelDataRenderer::from_data_set(voi_lut_data_set.as_ref().unwrap_or(data_set))
+    else {
+        return Err("Pixel data renderer creation failed".into());
+    };
+
+    pixel_data_renderer
+        .render_frame(frame, voi_override.as_ref())
+        .map_err(|e| format!("Frame rendering failed. {}", e))
+}
@@ -0,0 +1,225 @@
+/// The smallest and largest zoom allowed, in CSS pixels per image pixel.
+///
+const MIN_SCALE: f64 = 0.02;
+const MAX_SCALE: f64 = 64.0;
+
+/// How a frame's image is scaled to the frame view.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZoomMode {
+    /// Scale the image so that it fits entirely within the view.
+    Fit,
+
+    /// Scale the image so that it fills the whole view, cropping it as needed.
+    Fill,
+
+    /// Display one image pixel per device pixel.
+    Actual,
+
+    /// A custom zoom set by the user, in CSS pixels per image pixel.
+    Custom(f64),
+}
+
+/// The view transform for the frame view, which is a zoom mode combined with a pan offset in CSS
+/// pixels. The pan offset is relative to the image being centered in the view, which means the
+/// transform remains valid when the size of the view changes.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewTransform {
+    pub zoom_mode: ZoomMode,
+    pub pan_x: f64,
+    pub pan_y: f64,
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self::new(ZoomMode::Fit)
+    }
+}
+
+impl ViewTransform {
+    /// Creates a new view transform with the given zoom mode and no panning.
+    ///
+    pub fn new(zoom_mode: ZoomMode) -> Self {
+        Self {
+            zoom_mode,
+            pan_x: 0.0,
+            pan_y: 0.0,
+        }
+    }
+
+    /// Returns where an image of the given size is placed in a view of the given size when this
+    /// transform is applied.
+    ///
+    pub fn place_image(
+        &self,
+        image_width: u32,
+        image_height: u32,
+        viewport: Viewport,
+    ) -> ImagePlacement {
+        let image_width = f64::from(image_width.max(1));
+        let image_height = f64::from(image_height.max(1));
+
+        let fit_scale_x = viewport.width / image_width;
+        let fit_scale_y = viewport.height / image_height;
+
+        let scale = match self.zoom_mode {
+            ZoomMode::Fit => fit_scale_x.min(fit_scale_y),
+            ZoomMode::Fill => fit_scale_x.max(fit_scale_y),
+            ZoomMode::Actual => 1.0 / viewport.device_pixel_ratio,
+            ZoomMode::Custom(scale) => scale,
+        };
+
+        ImagePlacement {
+            x: (viewport.width - image_width * scale) * 0.5 + self.pan_x,
+            y: (viewport.height - image_height * scale) * 0.5 + self.pan_y,
+            scale,
+            image_width,
+            image_height,
+            viewport,
+        }
+    }
+
+    /// Returns this transform after panning by the given number of CSS pixels.
+    ///
+    pub fn panned(self, dx: f64, dy: f64) -> Self {
+        Self {
+            pan_x: self.pan_x + dx,
+            pan_y: self.pan_y + dy,
+            ..self
+        }
+    }
+
+    /// Returns this transform after multiplying the zoom by the given factor, keeping the image
+    /// point under the given view position in place.
+    ///
+    pub fn zoomed_at(placement: &ImagePlacement, factor: f64, view_x: f64, view_y: f64) -> Self {
+        let scale = (placement.scale * factor).clamp(MIN_SCALE, MAX_SCALE);
+
+        let (image_x, image_y) = placement.view_to_image(view_x, view_y);
+        let x = view_x - image_x * scale;
+        let y = view_y - image_y * scale;
+
+        Self {
+            zoom_mode: ZoomMode::Custom(scale),
+            pan_x: x - (placement.viewport.width - placement.image_width * scale) * 0.5,
+            pan_y: y - (placement.viewport.height - placement.image_height * scale) * 0.5,
+        }
+    }
+}
+
+/// The size of the frame view in CSS pixels, and the number of device pixels per CSS pixel.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub width: f64,
+    pub height: f64,
+    pub device_pixel_ratio: f64,
+}
+
+/// The position and scale of an image drawn into the frame view, all in CSS pixels.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImagePlacement {
+    pub x: f64,
+    pub y: f64,
+    pub scale: f64,
+    pub image_width: f64,
+    pub image_height: f64,
+    pub viewport: Viewport,
+}
+
+impl ImagePlacement {
+    /// Converts a position in the view to a position in the image. The result is in image pixels
+    /// and may lie outside the image.
+    ///
+    pub fn view_to_image(&self, view_x: f64, view_y: f64) -> (f64, f64) {
+        (
+            (view_x - self.x) / self.scale,
+            (view_y - self.y) / self.scale,
+        )
+    }
+
+    /// Returns the zoom as a percentage of device pixels per image pixel, i.e. 100% means that one
+    /// image pixel covers exactly one device pixel.
+    ///
+    pub fn zoom_percentage(&self) -> f64 {
+        self.scale * self.viewport.device_pixel_ratio * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Viewport = Viewport {
+        width: 800.0,
+        height: 400.0,
+        device_pixel_ratio: 2.0,
+    };
+
+    #[test]
+    fn zoom_modes_scale_the_image() {
+        let scale = |zoom_mode| {
+            ViewTransform::new(zoom_mode)
+                .place_image(200, 100, VIEWPORT)
+                .scale
+        };
+
+        assert_eq!(scale(ZoomMode::Fit), 4.0);
+        assert_eq!(scale(ZoomMode::Actual), 0.5);
+        assert_eq!(scale(ZoomMode::Custom(3.0)), 3.0);
+
+        // Fill covers the view in both directions
+        let placement = ViewTransform::new(ZoomMode::Fill).place_image(100, 100, VIEWPORT);
+        assert_eq!(placement.scale, 8.0);
+        assert_eq!((placement.x, placement.y), (0.0, -200.0));
+    }
+
+    #[test]
+    fn images_are_centered_then_panned() {
+        let transform = ViewTransform::new(ZoomMode::Custom(1.0));
+
+        let placement = transform.place_image(100, 50, VIEWPORT);
+        assert_eq!((placement.x, placement.y), (350.0, 175.0));
+
+        let placement = transform
+            .panned(10.0, -5.0)
+            .panned(5.0, 0.0)
+            .place_image(100, 50, VIEWPORT);
+        assert_eq!((placement.x, placement.y), (365.0, 170.0));
+    }
+
+    #[test]
+    fn view_positions_convert_to_image_positions() {
+        let placement = ViewTransform::new(ZoomMode::Custom(2.0)).place_image(100, 50, VIEWPORT);
+
+        assert_eq!(placement.view_to_image(300.0, 150.0), (0.0, 0.0));
+        assert_eq!(placement.view_to_image(400.0, 200.0), (50.0, 25.0));
+        assert_eq!(placement.view_to_image(290.0, 150.0), (-5.0, 0.0));
+        assert_eq!(placement.zoom_percentage(), 400.0);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_pointer_in_place() {
+        let placement = ViewTransform::default().place_image(200, 100, VIEWPORT);
+        let point = placement.view_to_image(500.0, 300.0);
+
+        let zoomed = ViewTransform::zoomed_at(&placement, 2.0, 500.0, 300.0);
+        assert_eq!(zoomed.zoom_mode, ZoomMode::Custom(8.0));
+
+        let zoomed_placement = zoomed.place_image(200, 100, VIEWPORT);
+        assert_eq!(zoomed_placement.view_to_image(500.0, 300.0), point);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let placement = ViewTransform::default().place_image(200, 100, VIEWPORT);
+
+        let zoomed = ViewTransform::zoomed_at(&placement, 100.0, 0.0, 0.0);
+        assert_eq!(zoomed.zoom_mode, ZoomMode::Custom(MAX_SCALE));
+
+        let zoomed = ViewTransform::zoomed_at(&placement, 0.0001, 0.0, 0.0);
+        assert_eq!(zoomed.zoom_mode, ZoomMode::Custom(MIN_SCALE));
+    }
+}
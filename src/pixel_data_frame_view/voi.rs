@@ -0,0 +1,419 @@
+use dcmfx::{
+    core::{DataSet, dictionary},
+    pixel_data::iods::voi_lut_module::{VoiLutFunction, VoiWindow},
+};
+use serde::{Deserialize, Serialize};
+
+/// A VOI window defined by a center and width, as used by the Window Center and Window Width data
+/// elements.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub center: f64,
+    pub width: f64,
+}
+
+impl Window {
+    /// Returns this window in the form accepted by the pixel data renderer as a VOI override.
+    ///
+    pub fn to_voi_window(self) -> VoiWindow {
+        VoiWindow::new(
+            self.center as f32,
+            self.width as f32,
+            String::new(),
+            VoiLutFunction::Linear,
+        )
+    }
+
+    /// Returns this window after adjusting it by the given amounts. The width is clamped so that it
+    /// stays at least one.
+    ///
+    pub fn adjusted(self, center_delta: f64, width_delta: f64) -> Self {
+        Self {
+            center: self.center + center_delta,
+            width: (self.width + width_delta).max(1.0),
+        }
+    }
+}
+
+/// The VOI transform that is applied when rendering frames.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VoiSelection {
+    /// A window, either one of the data set's presets or one set interactively.
+    Window(Window),
+
+    /// The item at the given index in the VOI LUT Sequence.
+    Lut(usize),
+}
+
+/// A VOI preset stored in a data set that can be selected by the user.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoiPreset {
+    pub name: String,
+    pub selection: VoiSelection,
+}
+
+/// Returns the VOI presets defined in the data set, which are its windows specified by the Window
+/// Center and Window Width data elements, followed by the items in its VOI LUT Sequence.
+///
+/// The windows are taken from the first frame's Frame VOI LUT Sequence in the Per-frame or Shared
+/// Functional Groups Sequence if present, and otherwise from the root data set.
+///
+pub fn presets(data_set: &DataSet) -> Vec<VoiPreset> {
+    let mut presets = vec![];
+
+    let window_data_set = functional_group_windows(data_set).unwrap_or(data_set);
+
+    let centers = window_data_set
+        .get_floats(dictionary::WINDOW_CENTER.tag)
+        .unwrap_or_default();
+    let widths = window_data_set
+        .get_floats(dictionary::WINDOW_WIDTH.tag)
+        .unwrap_or_default();
+    let explanations = window_data_set
+        .get_strings(dictionary::WINDOW_CENTER_WIDTH_EXPLANATION.tag)
+        .unwrap_or_default();
+
+    for (i, (center, width)) in centers.iter().zip(widths.iter()).enumerate() {
+        let window = Window {
+            center: *center,
+            width: *width,
+        };
+
+        let name = match explanations.get(i).map(|s| s.trim()) {
+            Some(explanation) if !explanation.is_empty() => {
+                format!("{} ({})", explanation, window_description(window))
+            }
+            _ => window_description(window),
+        };
+
+        presets.push(VoiPreset {
+            name,
+            selection: VoiSelection::Window(window),
+        });
+    }
+
+    if let Ok(items) = data_set
+        .get_value(dictionary::VOI_LUT_SEQUENCE.tag)
+        .and_then(|value| value.sequence_items())
+    {
+        for (i, item) in items.iter().enumerate() {
+            let name = match item.get_string(dictionary::LUT_EXPLANATION.tag) {
+                Ok(explanation) if !explanation.trim().is_empty() => {
+                    format!("VOI LUT: {}", explanation.trim())
+                }
+                _ => format!("VOI LUT {}", i + 1),
+            };
+
+            presets.push(VoiPreset {
+                name,
+                selection: VoiSelection::Lut(i),
+            });
+        }
+    }
+
+    presets
+}
+
+/// Returns the VOI transform to select when a data set is first displayed. Enhanced multi-frame
+/// data sets specify their windows in functional groups, which aren't used by default when
+/// rendering, so their first window is selected explicitly. Otherwise `None` is returned and the
+/// data set's own default VOI transform is used.
+///
+pub fn default_selection(data_set: &DataSet, presets: &[VoiPreset]) -> Option<VoiSelection> {
+    functional_group_windows(data_set)?;
+
+    presets
+        .iter()
+        .map(|preset| preset.selection)
+        .find(|selection| matches!(selection, VoiSelection::Window(_)))
+}
+
+/// Returns the item of the first frame's Frame VOI LUT Sequence that specifies a window, looking
+/// in the Per-frame Functional Groups Sequence first and then in the Shared Functional Groups
+/// Sequence.
+///
+fn functional_group_windows(data_set: &DataSet) -> Option<&DataSet> {
+    [
+        dictionary::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE.tag,
+        dictionary::SHARED_FUNCTIONAL_GROUPS_SEQUENCE.tag,
+    ]
+    .into_iter()
+    .find_map(|tag| {
+        let functional_groups = data_set
+            .get_value(tag)
+            .and_then(|value| value.sequence_items())
+            .ok()?;
+
+        let frame_voi_luts = functional_groups
+            .first()?
+            .get_value(dictionary::FRAME_VOI_LUT_SEQUENCE.tag)
+            .and_then(|value| value.sequence_items())
+            .ok()?;
+
+        frame_voi_luts
+            .first()
+            .filter(|item| item.has(dictionary::WINDOW_CENTER.tag))
+    })
+}
+
+/// Returns the window that interactive adjustment starts from when no window is currently
+/// selected. This is the data set's first window if it has one, and otherwise is a window that
+/// covers the full range of stored values after the modality rescale is applied.
+///
+pub fn initial_window(data_set: &DataSet) -> Window {
+    for preset in presets(data_set) {
+        if let VoiSelection::Window(window) = preset.selection {
+            return window;
+        }
+    }
+
+    let bits_stored = data_set
+        .get_int::<u16>(dictionary::BITS_STORED.tag)
+        .unwrap_or(8)
+        .clamp(1, 32);
+    let is_signed = data_set
+        .get_int::<u16>(dictionary::PIXEL_REPRESENTATION.tag)
+        .unwrap_or(0)
+        == 1;
+
+    let (min, max) = if is_signed {
+        let half = 2f64.powi(bits_stored as i32 - 1);
+        (-half, half - 1.0)
+    } else {
+        (0.0, 2f64.powi(bits_stored as i32) - 1.0)
+    };
+
+    let slope = data_set
+        .get_float(dictionary::RESCALE_SLOPE.tag)
+        .unwrap_or(1.0);
+    let intercept = data_set
+        .get_float(dictionary::RESCALE_INTERCEPT.tag)
+        .unwrap_or(0.0);
+
+    let (min, max) = (min * slope + intercept, max * slope + intercept);
+
+    Window {
+        center: (min + max) * 0.5,
+        width: (max - min).abs().max(1.0),
+    }
+}
+
+/// Returns a copy of the data set that has the specified item of its VOI LUT Sequence as its only
+/// VOI transform, which causes that VOI LUT to be used when rendering frames.
+///
+pub fn data_set_with_voi_lut(data_set: &DataSet, lut_index: usize) -> Option<DataSet> {
+    let items = data_set
+        .get_value(dictionary::VOI_LUT_SEQUENCE.tag)
+        .and_then(|value| value.sequence_items())
+        .ok()?;
+
+    let item = items.get(lut_index)?.clone();
+
+    let mut data_set = data_set.clone();
+    data_set.delete(dictionary::WINDOW_CENTER.tag);
+    data_set.delete(dictionary::WINDOW_WIDTH.tag);
+    data_set.delete(dictionary::WINDOW_CENTER_WIDTH_EXPLANATION.tag);
+    data_set.insert(
+        dictionary::VOI_LUT_SEQUENCE.tag,
+        dcmfx::core::DataElementValue::new_sequence(vec![item]),
+    );
+
+    Some(data_set)
+}
+
+/// Returns a short human-readable description of a window's center and width.
+///
+pub fn window_description(window: Window) -> String {
+    format!(
+        "C {} / W {}",
+        format_number(window.center),
+        format_number(window.width)
+    )
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dcmfx::core::DataElementValue;
+
+    use super::*;
+    use crate::test_utils::insert;
+
+    fn window(center: f64, width: f64) -> Window {
+        Window { center, width }
+    }
+
+    fn lut_item(explanation: &str) -> DataSet {
+        let mut item = DataSet::new();
+        insert(
+            &mut item,
+            dictionary::LUT_EXPLANATION.tag,
+            "LO",
+            explanation,
+        );
+        item
+    }
+
+    fn windowed_data_set() -> DataSet {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::WINDOW_CENTER.tag,
+            "DS",
+            "40\\300",
+        );
+        insert(
+            &mut data_set,
+            dictionary::WINDOW_WIDTH.tag,
+            "DS",
+            "400\\1500.5",
+        );
+        insert(
+            &mut data_set,
+            dictionary::WINDOW_CENTER_WIDTH_EXPLANATION.tag,
+            "LO",
+            "SOFT TISSUE\\",
+        );
+        data_set.insert(
+            dictionary::VOI_LUT_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![lut_item("LUNG"), lut_item("")]),
+        );
+
+        data_set
+    }
+
+    #[test]
+    fn presets_are_windows_then_luts() {
+        let presets = presets(&windowed_data_set());
+
+        assert_eq!(
+            presets,
+            vec![
+                VoiPreset {
+                    name: "SOFT TISSUE (C 40 / W 400)".into(),
+                    selection: VoiSelection::Window(window(40.0, 400.0)),
+                },
+                VoiPreset {
+                    name: "C 300 / W 1500.5".into(),
+                    selection: VoiSelection::Window(window(300.0, 1500.5)),
+                },
+                VoiPreset {
+                    name: "VOI LUT: LUNG".into(),
+                    selection: VoiSelection::Lut(0),
+                },
+                VoiPreset {
+                    name: "VOI LUT 2".into(),
+                    selection: VoiSelection::Lut(1),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn functional_group_windows_are_selected_by_default() {
+        let mut frame_voi_lut = DataSet::new();
+        insert(
+            &mut frame_voi_lut,
+            dictionary::WINDOW_CENTER.tag,
+            "DS",
+            "50",
+        );
+        insert(
+            &mut frame_voi_lut,
+            dictionary::WINDOW_WIDTH.tag,
+            "DS",
+            "350",
+        );
+
+        let mut functional_groups = DataSet::new();
+        functional_groups.insert(
+            dictionary::FRAME_VOI_LUT_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![frame_voi_lut]),
+        );
+
+        let mut data_set = windowed_data_set();
+        data_set.insert(
+            dictionary::SHARED_FUNCTIONAL_GROUPS_SEQUENCE.tag,
+            DataElementValue::new_sequence(vec![functional_groups]),
+        );
+
+        let presets = presets(&data_set);
+        assert_eq!(
+            presets[0].selection,
+            VoiSelection::Window(window(50.0, 350.0))
+        );
+        assert_eq!(
+            default_selection(&data_set, &presets),
+            Some(VoiSelection::Window(window(50.0, 350.0)))
+        );
+
+        // Windows in the root data set are rendered by default without being selected
+        let data_set = windowed_data_set();
+        assert_eq!(
+            default_selection(&data_set, &super::presets(&data_set)),
+            None
+        );
+    }
+
+    #[test]
+    fn initial_window_covers_the_rescaled_range() {
+        let mut data_set = DataSet::new();
+        insert(&mut data_set, dictionary::BITS_STORED.tag, "US", "12");
+        insert(
+            &mut data_set,
+            dictionary::PIXEL_REPRESENTATION.tag,
+            "US",
+            "1",
+        );
+        insert(&mut data_set, dictionary::RESCALE_SLOPE.tag, "DS", "2");
+        insert(
+            &mut data_set,
+            dictionary::RESCALE_INTERCEPT.tag,
+            "DS",
+            "-1024",
+        );
+
+        // Stored values from -2048 to 2047 are rescaled to -5120 to 3070
+        assert_eq!(initial_window(&data_set), window(-1025.0, 8190.0));
+
+        // The first window is used when there is one
+        assert_eq!(initial_window(&windowed_data_set()), window(40.0, 400.0));
+    }
+
+    #[test]
+    fn adjusted_windows_keep_a_width_of_at_least_one() {
+        assert_eq!(
+            window(40.0, 400.0).adjusted(10.0, -100.0),
+            window(50.0, 300.0)
+        );
+        assert_eq!(window(40.0, 400.0).adjusted(0.0, -500.0), window(40.0, 1.0));
+    }
+
+    #[test]
+    fn voi_lut_replaces_the_other_voi_transforms() {
+        let data_set = data_set_with_voi_lut(&windowed_data_set(), 1).unwrap();
+
+        assert!(!data_set.has(dictionary::WINDOW_CENTER.tag));
+        assert!(!data_set.has(dictionary::WINDOW_WIDTH.tag));
+        assert_eq!(
+            data_set
+                .get_value(dictionary::VOI_LUT_SEQUENCE.tag)
+                .and_then(|value| value.sequence_items())
+                .unwrap()
+                .to_vec(),
+            vec![lut_item("")]
+        );
+
+        assert_eq!(data_set_with_voi_lut(&windowed_data_set(), 2), None);
+    }
+}
@@ -0,0 +1,165 @@
+mod structure;
+
+use dioxus::prelude::*;
+use web_sys::HtmlElement;
+
+use crate::utils;
+pub use structure::{
+    ElementOffset, OffsetTracker, P10Structure, RawTokenKind, ReadFailure,
+    read as read_p10_structure,
+};
+
+/// The height in pixels of each row in the token list. This must match the height set in the
+/// stylesheet because only the rows that are in view are rendered.
+///
+const ROW_HEIGHT: f64 = 22.0;
+
+/// The number of extra rows rendered above and below the visible rows.
+///
+const OVERSCAN_ROWS: usize = 10;
+
+/// Formats the location of a data element in its file as its offset in hex followed by the length
+/// of its header.
+///
+pub fn format_offset(offset: &ElementOffset) -> String {
+    format!("{:08X} +{}", offset.offset, offset.header_length)
+}
+
+/// Lists the tokens of a DICOM P10 file with the byte offset and length of each one, so that
+/// malformed structures in the file can be located. `structure` is `None` while the tokens are
+/// being read.
+///
+#[component]
+pub fn RawTokenView(
+    structure: Signal<Option<Result<P10Structure, String>>>,
+    is_p10: bool,
+) -> Element {
+    let mut list_element = use_signal(|| None::<HtmlElement>);
+    let mut scroll_top = use_signal(|| 0.0);
+    let mut viewport_height = use_signal(|| 0.0);
+
+    let mut update_scroll_state = move || {
+        if let Some(element) = list_element.peek().as_ref() {
+            scroll_top.set(f64::from(element.scroll_top()));
+            viewport_height.set(f64::from(element.client_height()));
+        }
+    };
+
+    if !is_p10 {
+        return rsx! {
+            div {
+                class: "raw-token-view",
+
+                div {
+                    class: "raw-token-message",
+                    "Raw tokens are only available for files read as DICOM P10"
+                }
+            }
+        };
+    }
+
+    let structure = structure.read();
+    let structure = match &*structure {
+        Some(Ok(structure)) => structure,
+        Some(Err(e)) => {
+            return rsx! {
+                div {
+                    class: "raw-token-view",
+                    div { class: "raw-token-message", "Reading tokens failed. {e}" }
+                }
+            };
+        }
+        None => {
+            return rsx! {
+                div {
+                    class: "raw-token-view",
+                    div { class: "raw-token-message", "Reading tokens…" }
+                }
+            };
+        }
+    };
+
+    let row_count = structure.tokens.len();
+    let first_row = (((scroll_top() - ROW_HEIGHT) / ROW_HEIGHT).max(0.0) as usize)
+        .saturating_sub(OVERSCAN_ROWS)
+        .min(row_count);
+    let last_row =
+        (first_row + (viewport_height() / ROW_HEIGHT).ceil() as usize + OVERSCAN_ROWS * 2)
+            .min(row_count);
+
+    rsx! {
+        div {
+            class: "raw-token-view",
+
+            if let Some(failure) = &structure.read_failure {
+                div {
+                    class: "raw-token-error",
+
+                    pre { {failure.description.clone()} }
+                    for line in failure.error.lines() {
+                        pre { {line} }
+                    }
+                }
+            }
+
+            if let Some(error) = &structure.offsets_error {
+                div { class: "raw-token-message", "Offsets are incomplete. {error}" }
+            }
+
+            div {
+                class: "raw-token-list",
+
+                onmounted: move |ev| {
+                    list_element.set(utils::get_element::<HtmlElement>(ev));
+                    update_scroll_state();
+                },
+                onscroll: move |_| update_scroll_state(),
+                onresize: move |_| update_scroll_state(),
+
+                div {
+                    class: "raw-token-row header",
+
+                    div { "Offset" }
+                    div { "Length" }
+                    div { "Token" }
+                    div { "Description" }
+                }
+
+                div {
+                    class: "raw-token-spacer",
+                    height: format!("{}px", first_row as f64 * ROW_HEIGHT),
+                }
+
+                for token in structure.tokens[first_row..last_row].iter() {
+                    div {
+                        class: "raw-token-row",
+                        class: if token.kind == RawTokenKind::ValueBytes { "value-bytes" },
+
+                        div {
+                            title: token.offset.map(|offset| offset.to_string()),
+
+                            {
+                                token
+                                    .offset
+                                    .map(|offset| format!("{:08X}", offset))
+                                    .unwrap_or_else(|| "?".into())
+                            }
+                        }
+                        div { "{token.length}" }
+                        div { {token.kind.name()} }
+                        div {
+                            padding_left: format!("{}em", token.depth),
+
+                            {token.description.clone()}
+                        }
+                    }
+                }
+
+                div {
+                    class: "raw-token-spacer",
+                    height: format!("{}px", (row_count - last_row) as f64 * ROW_HEIGHT),
+                }
+            }
+        }
+    }
+}
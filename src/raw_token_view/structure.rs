@@ -0,0 +1,1347 @@
+//! Reading of the token stream of a DICOM P10 file along with the byte offset of each token, which
+//! is used to pinpoint malformed structures in a file. The offsets are worked out by following the
+//! file's raw bytes alongside the tokens read from them, because the tokens themselves don't say
+//! how many bytes they took up, e.g. delimiters are emitted for defined-length sequences too.
+
+use std::collections::HashMap;
+
+use dcmfx::{
+    core::*,
+    p10::{P10ReadContext, P10Token},
+};
+use serde::{Deserialize, Serialize};
+
+/// The tokens of a DICOM P10 file and the offsets of its data elements.
+///
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct P10Structure {
+    pub tokens: Vec<RawToken>,
+
+    /// The location of each data element, sequence, and sequence item in the file, keyed by the
+    /// string form of its path. Encapsulated pixel data items are keyed the same way as their rows
+    /// in the data set grid.
+    pub offsets: HashMap<String, ElementOffset>,
+
+    /// Where reading stopped if the file couldn't be read in full.
+    pub read_failure: Option<ReadFailure>,
+
+    /// Why offsets aren't known for some of the tokens, if that's the case. Once the raw bytes
+    /// stop matching the tokens read from them, no further offsets are reported.
+    pub offsets_error: Option<String>,
+}
+
+/// A single token read from a DICOM P10 file.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RawToken {
+    pub kind: RawTokenKind,
+
+    /// The offset of the token's first byte in the file, if known.
+    pub offset: Option<u64>,
+
+    /// The number of bytes in the file that the token was read from.
+    pub length: u64,
+
+    /// The nesting depth of the token in sequences and sequence items.
+    pub depth: usize,
+
+    pub description: String,
+}
+
+/// The kinds of token in a DICOM P10 file. Headers of File Meta Information data elements are
+/// listed as their own tokens even though they're read as part of the File Meta Information.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RawTokenKind {
+    Preamble,
+    FileMetaInformation,
+    DataElementHeader,
+    ValueBytes,
+    SequenceStart,
+    SequenceDelimiter,
+    ItemStart,
+    ItemDelimiter,
+    PixelDataItem,
+    End,
+}
+
+impl RawTokenKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Preamble => "Preamble",
+            Self::FileMetaInformation => "File Meta Information",
+            Self::DataElementHeader => "Header",
+            Self::ValueBytes => "Value bytes",
+            Self::SequenceStart => "Sequence start",
+            Self::SequenceDelimiter => "Sequence delimiter",
+            Self::ItemStart => "Item start",
+            Self::ItemDelimiter => "Item delimiter",
+            Self::PixelDataItem => "Pixel data item",
+            Self::End => "End",
+        }
+    }
+}
+
+/// Where reading a DICOM P10 file stopped because of an error.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReadFailure {
+    pub error: String,
+
+    /// A description of where reading stopped, including the offset in the file if it's known.
+    pub description: String,
+
+    /// The paths of the sequences and sequence items that reading stopped inside of, outermost
+    /// first. These are incomplete in the data set that was read.
+    pub open_paths: Vec<String>,
+
+    /// The path of the data element whose value was being read when reading stopped, if any.
+    pub partial_element_path: Option<String>,
+}
+
+impl ReadFailure {
+    /// Returns whether the data element, sequence, or sequence item with the given path is
+    /// incomplete because reading stopped inside it.
+    ///
+    pub fn is_partial(&self, path: &str) -> bool {
+        self.open_paths.iter().any(|open_path| open_path == path)
+            || self.partial_element_path.as_deref() == Some(path)
+    }
+}
+
+/// The location of a data element, sequence, or sequence item in a DICOM P10 file.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElementOffset {
+    pub offset: u64,
+    pub header_length: u32,
+}
+
+/// Reads the tokens of a DICOM P10 file. Reading stops at the first error, which is recorded
+/// along with the tokens read prior to it. This is run by the job worker.
+///
+pub fn read(bytes: &[u8]) -> P10Structure {
+    let mut tracker = OffsetTracker::new(true);
+    tracker.write_bytes(bytes);
+
+    let mut context = P10ReadContext::new(None);
+
+    if let Err(e) = context.write_bytes(bytes.to_vec().into(), true) {
+        tracker.fail(e.to_lines("reading file").join("\n"));
+        return tracker.structure;
+    }
+
+    loop {
+        let tokens = match context.read_tokens() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                tracker.fail(e.to_lines("reading file").join("\n"));
+                break;
+            }
+        };
+
+        if tokens.is_empty() {
+            break;
+        }
+
+        for token in tokens {
+            let is_end = matches!(token, P10Token::End);
+
+            tracker.add_token(&token);
+
+            if is_end {
+                return tracker.structure;
+            }
+        }
+    }
+
+    tracker.structure
+}
+
+/// The UIDs of the transfer syntaxes that deflate the whole data set, for which the raw bytes can't
+/// be followed.
+///
+const DEFLATED_TRANSFER_SYNTAX_UIDS: [&str; 2] =
+    ["1.2.840.10008.1.2.1.99", "1.2.840.10008.1.2.4.95"];
+
+const IMPLICIT_VR_LITTLE_ENDIAN_UID: &str = "1.2.840.10008.1.2";
+const EXPLICIT_VR_BIG_ENDIAN_UID: &str = "1.2.840.10008.1.2.2";
+
+/// The value representations that have a 12-byte header, rather than an 8-byte one, when
+/// encoded with an explicit VR.
+///
+const LONG_HEADER_VALUE_REPRESENTATIONS: [&[u8; 2]; 13] = [
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT", b"UV",
+];
+
+const ITEM_TAG: DataElementTag = DataElementTag {
+    group: 0xFFFE,
+    element: 0xE000,
+};
+const ITEM_DELIMITATION_TAG: DataElementTag = DataElementTag {
+    group: 0xFFFE,
+    element: 0xE00D,
+};
+const SEQUENCE_DELIMITATION_TAG: DataElementTag = DataElementTag {
+    group: 0xFFFE,
+    element: 0xE0DD,
+};
+
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+
+#[derive(Clone, Copy)]
+struct Encoding {
+    explicit_vr: bool,
+    big_endian: bool,
+}
+
+/// A data element or sequence item header read directly from the file's bytes.
+///
+struct RawHeader {
+    tag: DataElementTag,
+    length: u32,
+    header_length: u32,
+}
+
+/// A sequence or sequence item that tokens are currently being read from.
+///
+struct Container {
+    path: DataSetPath,
+
+    /// The offset of the end of the container, if it has a defined length.
+    end: Option<u64>,
+
+    item_count: usize,
+}
+
+/// Follows the raw bytes of a DICOM P10 file alongside the tokens read from them, working out the
+/// offset of each token and where reading stopped if it fails.
+///
+/// When reading a file as it's streamed in, tokens and offsets aren't recorded, and only where
+/// reading stopped is tracked. The bytes prior to the next token are discarded as they're passed
+/// over, so the file isn't held in memory.
+///
+pub struct OffsetTracker {
+    /// Whether to record the tokens and the offsets of data elements.
+    record_tokens: bool,
+
+    structure: P10Structure,
+
+    /// The bytes of the file that may still be read, which start at `bytes_start` in the file, and
+    /// the total number of bytes written so far.
+    bytes: Vec<u8>,
+    bytes_start: u64,
+    bytes_written: u64,
+
+    /// The length of the preamble and "DICM" prefix, which is found when the first token is added.
+    prefix_length: Option<u64>,
+
+    /// The offset of the next token, which is `None` once offsets are no longer known.
+    offset: Option<u64>,
+    encoding: Encoding,
+    containers: Vec<Container>,
+
+    /// The offset of the value whose bytes are being read, and how many of its bytes have been
+    /// read so far.
+    value: (u64, u64),
+
+    /// The data element whose value is being read, which is recorded in case reading fails before
+    /// its value is complete.
+    pending_element: Option<PendingElement>,
+}
+
+struct PendingElement {
+    path: String,
+    tag: DataElementTag,
+    length: u32,
+}
+
+impl OffsetTracker {
+    pub fn new(record_tokens: bool) -> Self {
+        Self {
+            record_tokens,
+            structure: P10Structure::default(),
+            bytes: vec![],
+            bytes_start: 0,
+            bytes_written: 0,
+            prefix_length: None,
+            offset: Some(0),
+            encoding: Encoding {
+                explicit_vr: false,
+                big_endian: false,
+            },
+            containers: vec![],
+            value: (0, 0),
+            pending_element: None,
+        }
+    }
+
+    /// Returns where reading stopped, if [`OffsetTracker::fail()`] has been called.
+    ///
+    pub fn read_failure(&self) -> Option<&ReadFailure> {
+        self.structure.read_failure.as_ref()
+    }
+
+    /// Passes the next bytes of the file, which must be done before adding the tokens read from
+    /// them.
+    ///
+    pub fn write_bytes(&mut self, chunk: &[u8]) {
+        let chunk_start = self.bytes_written;
+        self.bytes_written += chunk.len() as u64;
+
+        // Headers are only read at or after the offset of the next token, so the bytes before it
+        // are discarded. Once offsets are no longer known no more headers are read.
+        let keep_from = self
+            .offset
+            .unwrap_or(self.bytes_written)
+            .max(self.bytes_start);
+
+        let discard = (keep_from - self.bytes_start).min(self.bytes.len() as u64);
+        self.bytes.drain(..discard as usize);
+        self.bytes_start += discard;
+
+        let skip = keep_from
+            .saturating_sub(chunk_start)
+            .min(chunk.len() as u64);
+        if self.bytes.is_empty() {
+            self.bytes_start = chunk_start + skip;
+        }
+        self.bytes.extend_from_slice(&chunk[skip as usize..]);
+    }
+
+    pub fn add_token(&mut self, token: &P10Token) {
+        // The preamble and prefix are found from the start of the file, which is still held
+        // because no bytes are discarded before the first token
+        if self.prefix_length.is_none() {
+            let length = prefix_length(&self.bytes);
+            self.prefix_length = Some(length);
+            self.offset = Some(length);
+        }
+
+        let depth = self.containers.len();
+
+        if !matches!(token, P10Token::DataElementValueBytes { .. }) {
+            self.pending_element = None;
+        }
+
+        match token {
+            P10Token::FilePreambleAndDICMPrefix { .. } => {
+                let length = self.prefix_length.unwrap_or_default();
+                self.push(
+                    RawTokenKind::Preamble,
+                    Some(0),
+                    length,
+                    0,
+                    "128-byte preamble and DICM prefix".into(),
+                );
+            }
+
+            P10Token::FileMetaInformation { data_set } => self.add_file_meta_information(data_set),
+
+            P10Token::DataElementHeader {
+                tag, length, path, ..
+            } => {
+                self.pending_element = Some(PendingElement {
+                    path: path.to_string(),
+                    tag: *tag,
+                    length: *length,
+                });
+                self.value.1 = 0;
+
+                let Some((offset, header)) = self.read_header(*tag) else {
+                    return self.push(
+                        RawTokenKind::DataElementHeader,
+                        None,
+                        0,
+                        depth,
+                        dictionary::tag_name(*tag, None),
+                    );
+                };
+
+                self.record(path.to_string(), offset, header.header_length);
+                self.value = (offset + u64::from(header.header_length), 0);
+
+                let description = format!(
+                    "{}, length {}",
+                    dictionary::tag_name(*tag, None),
+                    header.length
+                );
+                self.push(
+                    RawTokenKind::DataElementHeader,
+                    Some(offset),
+                    u64::from(header.header_length),
+                    depth,
+                    description,
+                );
+
+                if header.length == UNDEFINED_LENGTH {
+                    self.lose(format!(
+                        "{} has an undefined length but isn't a sequence",
+                        tag
+                    ));
+                } else {
+                    self.offset =
+                        Some(offset + u64::from(header.header_length) + u64::from(header.length));
+                }
+            }
+
+            P10Token::DataElementValueBytes {
+                tag,
+                data,
+                bytes_remaining,
+                ..
+            } => {
+                let (value_offset, bytes_read) = self.value;
+                let offset = self.offset.map(|_| value_offset + bytes_read);
+                self.value.1 += data.len() as u64;
+
+                if *bytes_remaining == 0 {
+                    self.pending_element = None;
+                }
+
+                let description =
+                    format!("{}, {} bytes", dictionary::tag_name(*tag, None), data.len());
+                self.push(
+                    RawTokenKind::ValueBytes,
+                    offset,
+                    data.len() as u64,
+                    depth,
+                    description,
+                );
+            }
+
+            P10Token::SequenceStart { tag, path, .. } => {
+                let header = self.read_header(*tag);
+
+                let end = match &header {
+                    Some((offset, header)) => {
+                        self.record(path.to_string(), *offset, header.header_length);
+                        self.offset = Some(offset + u64::from(header.header_length));
+
+                        (header.length != UNDEFINED_LENGTH).then(|| {
+                            offset + u64::from(header.header_length) + u64::from(header.length)
+                        })
+                    }
+                    None => None,
+                };
+
+                self.containers.push(Container {
+                    path: path.clone(),
+                    end,
+                    item_count: 0,
+                });
+
+                let description = match &header {
+                    Some((_, header)) => format!(
+                        "{}, {}",
+                        dictionary::tag_name(*tag, None),
+                        describe_length(header.length)
+                    ),
+                    None => dictionary::tag_name(*tag, None),
+                };
+                self.push(
+                    RawTokenKind::SequenceStart,
+                    header.as_ref().map(|(offset, _)| *offset),
+                    header.map_or(0, |(_, header)| u64::from(header.header_length)),
+                    depth,
+                    description,
+                );
+            }
+
+            P10Token::SequenceItemStart { index, .. } => {
+                let header = self.read_header(ITEM_TAG);
+
+                let mut item_path = self
+                    .containers
+                    .last()
+                    .map(|container| container.path.clone())
+                    .unwrap_or_else(DataSetPath::new);
+                let _ = item_path.add_sequence_item(*index);
+
+                let end = match &header {
+                    Some((offset, header)) => {
+                        self.record(item_path.to_string(), *offset, header.header_length);
+                        self.offset = Some(offset + u64::from(header.header_length));
+
+                        (header.length != UNDEFINED_LENGTH).then(|| {
+                            offset + u64::from(header.header_length) + u64::from(header.length)
+                        })
+                    }
+                    None => None,
+                };
+
+                let description = match &header {
+                    Some((_, header)) => {
+                        format!("Item {}, {}", index + 1, describe_length(header.length))
+                    }
+                    None => format!("Item {}", index + 1),
+                };
+                self.push(
+                    RawTokenKind::ItemStart,
+                    header.as_ref().map(|(offset, _)| *offset),
+                    header.map_or(0, |(_, header)| u64::from(header.header_length)),
+                    depth,
+                    description,
+                );
+
+                self.containers.push(Container {
+                    path: item_path,
+                    end,
+                    item_count: 0,
+                });
+            }
+
+            P10Token::PixelDataItem { .. } => {
+                let header = self.read_header(ITEM_TAG);
+
+                let Some(container) = self.containers.last_mut() else {
+                    return;
+                };
+
+                let key = format!("{}/{}", container.path, container.item_count);
+                let index = container.item_count;
+                container.item_count += 1;
+
+                let Some((offset, header)) = header else {
+                    return self.push(
+                        RawTokenKind::PixelDataItem,
+                        None,
+                        0,
+                        depth,
+                        format!("Item {}", index),
+                    );
+                };
+
+                self.record(key, offset, header.header_length);
+
+                let length = u64::from(header.header_length) + u64::from(header.length);
+                self.offset = Some(offset + length);
+
+                let description = format!("Item {}, length {}", index, header.length);
+                self.push(
+                    RawTokenKind::PixelDataItem,
+                    Some(offset),
+                    length,
+                    depth,
+                    description,
+                );
+            }
+
+            P10Token::SequenceItemDelimiter { .. } => self.add_delimiter(
+                RawTokenKind::ItemDelimiter,
+                ITEM_DELIMITATION_TAG,
+                "Item end",
+            ),
+
+            P10Token::SequenceDelimiter { .. } => self.add_delimiter(
+                RawTokenKind::SequenceDelimiter,
+                SEQUENCE_DELIMITATION_TAG,
+                "Sequence end",
+            ),
+
+            P10Token::End => {
+                if let Some(offset) = self.offset
+                    && offset < self.bytes_written
+                {
+                    self.lose(format!(
+                        "Reading ended at offset {} but the file has {} bytes",
+                        offset, self.bytes_written
+                    ));
+                }
+
+                self.push(RawTokenKind::End, self.offset, 0, 0, "End of file".into());
+            }
+        }
+    }
+
+    /// Adds the File Meta Information token. Its data elements are listed individually, and are
+    /// always encoded in explicit VR little endian.
+    ///
+    fn add_file_meta_information(&mut self, data_set: &DataSet) {
+        let start = self.prefix_length.unwrap_or_default();
+        let mut offset = start;
+
+        let encoding = Encoding {
+            explicit_vr: true,
+            big_endian: false,
+        };
+
+        let mut elements = vec![];
+        while let Some(header) = self.read_raw_header(offset, encoding)
+            && header.tag.group == 0x0002
+            && header.length != UNDEFINED_LENGTH
+        {
+            let next_offset = offset + u64::from(header.header_length) + u64::from(header.length);
+
+            elements.push((offset, header));
+            offset = next_offset;
+        }
+
+        self.push(
+            RawTokenKind::FileMetaInformation,
+            Some(start),
+            offset - start,
+            0,
+            format!("{} data elements", elements.len()),
+        );
+
+        for (element_offset, header) in elements {
+            let mut path = DataSetPath::new();
+            let _ = path.add_data_element(header.tag);
+            self.record(path.to_string(), element_offset, header.header_length);
+
+            let description = format!(
+                "{}, length {}",
+                dictionary::tag_name(header.tag, None),
+                header.length
+            );
+            self.push(
+                RawTokenKind::DataElementHeader,
+                Some(element_offset),
+                u64::from(header.header_length),
+                1,
+                description,
+            );
+        }
+
+        self.offset = Some(offset);
+
+        let transfer_syntax_uid = data_set
+            .get_string(dictionary::TRANSFER_SYNTAX_UID.tag)
+            .unwrap_or(IMPLICIT_VR_LITTLE_ENDIAN_UID)
+            .trim_end_matches('\0')
+            .trim();
+
+        self.encoding = Encoding {
+            explicit_vr: transfer_syntax_uid != IMPLICIT_VR_LITTLE_ENDIAN_UID,
+            big_endian: transfer_syntax_uid == EXPLICIT_VR_BIG_ENDIAN_UID,
+        };
+
+        if DEFLATED_TRANSFER_SYNTAX_UIDS.contains(&transfer_syntax_uid) {
+            self.lose(
+                "The data set is deflated, so offsets past the File Meta Information aren't known"
+                    .into(),
+            );
+        }
+    }
+
+    /// Adds an item or sequence delimiter. Delimiters are only present in the file when their item
+    /// or sequence has an undefined length.
+    ///
+    fn add_delimiter(&mut self, kind: RawTokenKind, tag: DataElementTag, name: &str) {
+        let container = self.containers.pop();
+        let depth = self.containers.len();
+
+        match container.and_then(|container| container.end) {
+            Some(end) => {
+                if self.offset.is_some_and(|offset| offset != end) {
+                    self.lose(format!(
+                        "Content ended at offset {} but its length says it ends at offset {}",
+                        self.offset.unwrap_or_default(),
+                        end
+                    ));
+                } else {
+                    self.offset = self.offset.map(|_| end);
+                }
+
+                self.push(
+                    kind,
+                    self.offset,
+                    0,
+                    depth,
+                    format!("{} (defined length)", name),
+                );
+            }
+
+            None => {
+                let header = self.read_header(tag);
+                if let Some((offset, header)) = &header {
+                    self.offset = Some(offset + u64::from(header.header_length));
+                }
+
+                self.push(
+                    kind,
+                    header.as_ref().map(|(offset, _)| *offset),
+                    header.map_or(0, |(_, header)| u64::from(header.header_length)),
+                    depth,
+                    name.into(),
+                );
+            }
+        }
+    }
+
+    /// Records that reading failed with the given error at the current position.
+    ///
+    pub fn fail(&mut self, error: String) {
+        let mut description = "Reading stopped".to_string();
+
+        // When reading stopped inside a value, the offset is how far into the value it got
+        let offset = match &self.pending_element {
+            Some(_) => self.offset.map(|_| self.value.0 + self.value.1),
+            None => self.offset,
+        };
+        if let Some(offset) = offset {
+            description.push_str(&format!(" at offset {:08X}", offset));
+        }
+
+        if let Some(element) = &self.pending_element {
+            description.push_str(&format!(
+                " in the value of {}, after {} of {} bytes",
+                dictionary::tag_name(element.tag, None),
+                self.value.1,
+                element.length
+            ));
+        }
+
+        self.structure.read_failure = Some(ReadFailure {
+            error,
+            description,
+            open_paths: self
+                .containers
+                .iter()
+                .map(|container| container.path.to_string())
+                .collect(),
+            partial_element_path: self
+                .pending_element
+                .as_ref()
+                .map(|element| element.path.clone()),
+        });
+    }
+
+    /// Reads the header at the current offset, checking that it has the expected tag. Returns
+    /// `None` if offsets aren't known, or if the header doesn't match in which case offsets are no
+    /// longer known.
+    ///
+    fn read_header(&mut self, expected_tag: DataElementTag) -> Option<(u64, RawHeader)> {
+        let offset = self.offset?;
+
+        match self.read_raw_header(offset, self.encoding) {
+            Some(header) if header.tag == expected_tag => Some((offset, header)),
+
+            Some(header) => {
+                self.lose(format!(
+                    "Expected {} at offset {} but found {}",
+                    expected_tag, offset, header.tag
+                ));
+                None
+            }
+
+            None => {
+                self.lose(format!(
+                    "Expected {} at offset {} but the file ended",
+                    expected_tag, offset
+                ));
+                None
+            }
+        }
+    }
+
+    /// Reads the raw header at the given offset in the file, if its bytes are held.
+    ///
+    fn read_raw_header(&self, offset: u64, encoding: Encoding) -> Option<RawHeader> {
+        read_raw_header(&self.bytes, offset.checked_sub(self.bytes_start)?, encoding)
+    }
+
+    fn record(&mut self, key: String, offset: u64, header_length: u32) {
+        if !self.record_tokens {
+            return;
+        }
+
+        self.structure.offsets.insert(
+            key,
+            ElementOffset {
+                offset,
+                header_length,
+            },
+        );
+    }
+
+    fn push(
+        &mut self,
+        kind: RawTokenKind,
+        offset: Option<u64>,
+        length: u64,
+        depth: usize,
+        description: String,
+    ) {
+        if !self.record_tokens {
+            return;
+        }
+
+        self.structure.tokens.push(RawToken {
+            kind,
+            offset,
+            length,
+            depth,
+            description,
+        });
+    }
+
+    /// Stops tracking offsets, recording why. This happens when the raw bytes don't match the
+    /// tokens, e.g. when a value with an unknown VR holds a sequence in a different encoding.
+    ///
+    fn lose(&mut self, reason: String) {
+        self.offset = None;
+
+        if self.structure.offsets_error.is_none() {
+            self.structure.offsets_error = Some(reason);
+        }
+    }
+}
+
+/// Returns the length of the preamble and "DICM" prefix at the start of the file, which is zero
+/// if they're absent.
+///
+fn prefix_length(bytes: &[u8]) -> u64 {
+    if bytes.get(128..132) == Some(b"DICM") {
+        132
+    } else if bytes.starts_with(b"DICM") {
+        4
+    } else {
+        0
+    }
+}
+
+fn read_raw_header(bytes: &[u8], offset: u64, encoding: Encoding) -> Option<RawHeader> {
+    let offset = usize::try_from(offset).ok()?;
+    let header = bytes.get(offset..offset.checked_add(8)?)?;
+
+    let read_u16 = |b: &[u8]| {
+        let b = [b[0], b[1]];
+        if encoding.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    };
+    let read_u32 = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if encoding.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+
+    let tag = DataElementTag {
+        group: read_u16(&header[0..2]),
+        element: read_u16(&header[2..4]),
+    };
+
+    // Item and delimiter headers never have a VR
+    if !encoding.explicit_vr || tag.group == 0xFFFE {
+        return Some(RawHeader {
+            tag,
+            length: read_u32(&header[4..8]),
+            header_length: 8,
+        });
+    }
+
+    if LONG_HEADER_VALUE_REPRESENTATIONS.contains(&&[header[4], header[5]]) {
+        let length = bytes.get(offset + 8..offset + 12)?;
+
+        Some(RawHeader {
+            tag,
+            length: read_u32(length),
+            header_length: 12,
+        })
+    } else {
+        Some(RawHeader {
+            tag,
+            length: u32::from(read_u16(&header[6..8])),
+            header_length: 8,
+        })
+    }
+}
+
+fn describe_length(length: u32) -> String {
+    if length == UNDEFINED_LENGTH {
+        "undefined length".into()
+    } else {
+        format!("length {}", length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::source_type::P10StreamReader;
+
+    const EXPLICIT_VR_LITTLE_ENDIAN: Encoding = Encoding {
+        explicit_vr: true,
+        big_endian: false,
+    };
+    const IMPLICIT_VR_LITTLE_ENDIAN: Encoding = Encoding {
+        explicit_vr: false,
+        big_endian: false,
+    };
+    const EXPLICIT_VR_BIG_ENDIAN: Encoding = Encoding {
+        explicit_vr: true,
+        big_endian: true,
+    };
+
+    /// The offset of the data set in files made by [`p10_file()`] with an explicit VR little
+    /// endian transfer syntax, after the preamble, prefix, and two File Meta Information data
+    /// elements.
+    const DATA_SET_OFFSET: u64 = 132 + 12 + 28;
+
+    fn header(encoding: Encoding, tag: DataElementTag, vr: &[u8; 2], length: u32) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if encoding.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if encoding.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+
+        let mut bytes = [u16_bytes(tag.group), u16_bytes(tag.element)].concat();
+
+        if !encoding.explicit_vr || tag.group == 0xFFFE {
+            bytes.extend(u32_bytes(length));
+        } else if LONG_HEADER_VALUE_REPRESENTATIONS.contains(&vr) {
+            bytes.extend(vr);
+            bytes.extend([0, 0]);
+            bytes.extend(u32_bytes(length));
+        } else {
+            bytes.extend(vr);
+            bytes.extend(u16_bytes(length as u16));
+        }
+
+        bytes
+    }
+
+    fn item_header(encoding: Encoding, tag: DataElementTag, length: u32) -> Vec<u8> {
+        header(encoding, tag, b"  ", length)
+    }
+
+    fn element(encoding: Encoding, tag: DataElementTag, vr: &[u8; 2], value: &[u8]) -> Vec<u8> {
+        [
+            header(encoding, tag, vr, value.len() as u32),
+            value.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// Returns a DICOM P10 file with the given transfer syntax and data set bytes. The File Meta
+    /// Information holds its group length and the Transfer Syntax UID.
+    ///
+    fn p10_file(transfer_syntax_uid: &str, data_set: &[u8]) -> Vec<u8> {
+        let mut uid = transfer_syntax_uid.as_bytes().to_vec();
+        if uid.len() % 2 == 1 {
+            uid.push(0);
+        }
+
+        let transfer_syntax = element(
+            EXPLICIT_VR_LITTLE_ENDIAN,
+            dictionary::TRANSFER_SYNTAX_UID.tag,
+            b"UI",
+            &uid,
+        );
+        let group_length = element(
+            EXPLICIT_VR_LITTLE_ENDIAN,
+            DataElementTag::new(0x0002, 0x0000),
+            b"UL",
+            &(transfer_syntax.len() as u32).to_le_bytes(),
+        );
+
+        [
+            vec![0; 128],
+            b"DICM".to_vec(),
+            group_length,
+            transfer_syntax,
+            data_set.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// Returns a data set holding a short string, a person name, and a value with a 12-byte
+    /// header when the VR is explicit.
+    ///
+    fn simple_data_set(encoding: Encoding) -> Vec<u8> {
+        [
+            element(encoding, dictionary::MODALITY.tag, b"CS", b"CT"),
+            element(encoding, dictionary::PATIENT_NAME.tag, b"PN", b"Doe^Jane"),
+            element(
+                encoding,
+                dictionary::ENCAPSULATED_DOCUMENT.tag,
+                b"OB",
+                &[1, 2, 3, 4],
+            ),
+        ]
+        .concat()
+    }
+
+    fn path(tag: DataElementTag) -> DataSetPath {
+        let mut path = DataSetPath::new();
+        path.add_data_element(tag).unwrap();
+        path
+    }
+
+    fn child(parent: &DataSetPath, item_index: usize, tag: DataElementTag) -> DataSetPath {
+        let mut path = parent.clone();
+        path.add_sequence_item(item_index).unwrap();
+        path.add_data_element(tag).unwrap();
+        path
+    }
+
+    fn item(parent: &DataSetPath, item_index: usize) -> DataSetPath {
+        let mut path = parent.clone();
+        path.add_sequence_item(item_index).unwrap();
+        path
+    }
+
+    fn offset(structure: &P10Structure, key: &str) -> Option<(u64, u32)> {
+        structure
+            .offsets
+            .get(key)
+            .map(|offset| (offset.offset, offset.header_length))
+    }
+
+    fn token_offsets(structure: &P10Structure, kind: RawTokenKind) -> Vec<(Option<u64>, u64)> {
+        structure
+            .tokens
+            .iter()
+            .filter(|token| token.kind == kind)
+            .map(|token| (token.offset, token.length))
+            .collect()
+    }
+
+    /// Checks the offsets in a file made from [`simple_data_set()`] whose data set starts at the
+    /// given offset.
+    ///
+    fn assert_simple_offsets(structure: &P10Structure, start: u64, long_header_length: u32) {
+        assert_eq!(structure.read_failure, None);
+        assert_eq!(structure.offsets_error, None);
+
+        assert_eq!(
+            offset(
+                structure,
+                &path(DataElementTag::new(0x0002, 0x0000)).to_string()
+            ),
+            Some((132, 8))
+        );
+        assert_eq!(
+            offset(
+                structure,
+                &path(dictionary::TRANSFER_SYNTAX_UID.tag).to_string()
+            ),
+            Some((144, 8))
+        );
+
+        assert_eq!(
+            offset(structure, &path(dictionary::MODALITY.tag).to_string()),
+            Some((start, 8))
+        );
+        assert_eq!(
+            offset(structure, &path(dictionary::PATIENT_NAME.tag).to_string()),
+            Some((start + 10, 8))
+        );
+        assert_eq!(
+            offset(
+                structure,
+                &path(dictionary::ENCAPSULATED_DOCUMENT.tag).to_string()
+            ),
+            Some((start + 26, long_header_length))
+        );
+
+        let end = start + 26 + u64::from(long_header_length) + 4;
+        assert_eq!(
+            token_offsets(structure, RawTokenKind::End),
+            vec![(Some(end), 0)]
+        );
+    }
+
+    #[test]
+    fn offsets_in_explicit_vr_little_endian() {
+        let bytes = p10_file(
+            "1.2.840.10008.1.2.1",
+            &simple_data_set(EXPLICIT_VR_LITTLE_ENDIAN),
+        );
+
+        let structure = read(&bytes);
+
+        assert_simple_offsets(&structure, DATA_SET_OFFSET, 12);
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::Preamble),
+            vec![(Some(0), 132)]
+        );
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::FileMetaInformation),
+            vec![(Some(132), 40)]
+        );
+    }
+
+    #[test]
+    fn offsets_in_implicit_vr_little_endian() {
+        let bytes = p10_file(
+            "1.2.840.10008.1.2",
+            &simple_data_set(IMPLICIT_VR_LITTLE_ENDIAN),
+        );
+
+        // The Transfer Syntax UID is two bytes shorter, and all headers are 8 bytes
+        assert_simple_offsets(&read(&bytes), DATA_SET_OFFSET - 2, 8);
+    }
+
+    #[test]
+    fn offsets_in_explicit_vr_big_endian() {
+        let bytes = p10_file(
+            "1.2.840.10008.1.2.2",
+            &simple_data_set(EXPLICIT_VR_BIG_ENDIAN),
+        );
+
+        assert_simple_offsets(&read(&bytes), DATA_SET_OFFSET, 12);
+    }
+
+    #[test]
+    fn offsets_in_undefined_length_sequences_and_items() {
+        let le = EXPLICIT_VR_LITTLE_ENDIAN;
+        let series_instance_uid =
+            element(le, dictionary::SERIES_INSTANCE_UID.tag, b"UI", b"1.2.3\0");
+
+        let data_set = [
+            header(
+                le,
+                dictionary::REFERENCED_SERIES_SEQUENCE.tag,
+                b"SQ",
+                UNDEFINED_LENGTH,
+            ),
+            item_header(le, ITEM_TAG, UNDEFINED_LENGTH),
+            series_instance_uid.clone(),
+            item_header(le, ITEM_DELIMITATION_TAG, 0),
+            item_header(le, ITEM_TAG, series_instance_uid.len() as u32),
+            series_instance_uid,
+            item_header(le, SEQUENCE_DELIMITATION_TAG, 0),
+            element(le, dictionary::PATIENT_NAME.tag, b"PN", b"Doe^Jane"),
+        ]
+        .concat();
+
+        let structure = read(&p10_file("1.2.840.10008.1.2.1", &data_set));
+
+        assert_eq!(structure.read_failure, None);
+        assert_eq!(structure.offsets_error, None);
+
+        let start = DATA_SET_OFFSET;
+        let sequence = path(dictionary::REFERENCED_SERIES_SEQUENCE.tag);
+
+        assert_eq!(offset(&structure, &sequence.to_string()), Some((start, 12)));
+        assert_eq!(
+            offset(&structure, &item(&sequence, 0).to_string()),
+            Some((start + 12, 8))
+        );
+        assert_eq!(
+            offset(
+                &structure,
+                &child(&sequence, 0, dictionary::SERIES_INSTANCE_UID.tag).to_string()
+            ),
+            Some((start + 20, 8))
+        );
+        assert_eq!(
+            offset(&structure, &item(&sequence, 1).to_string()),
+            Some((start + 42, 8))
+        );
+        assert_eq!(
+            offset(
+                &structure,
+                &child(&sequence, 1, dictionary::SERIES_INSTANCE_UID.tag).to_string()
+            ),
+            Some((start + 50, 8))
+        );
+        assert_eq!(
+            offset(&structure, &path(dictionary::PATIENT_NAME.tag).to_string()),
+            Some((start + 72, 8))
+        );
+
+        // The defined-length item's delimiter isn't in the file, so it's placed at the item's end
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::ItemDelimiter),
+            vec![(Some(start + 34), 8), (Some(start + 64), 0)]
+        );
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::SequenceDelimiter),
+            vec![(Some(start + 64), 8)]
+        );
+    }
+
+    #[test]
+    fn offsets_in_encapsulated_pixel_data() {
+        let le = EXPLICIT_VR_LITTLE_ENDIAN;
+
+        let data_set = [
+            header(le, dictionary::PIXEL_DATA.tag, b"OB", UNDEFINED_LENGTH),
+            item_header(le, ITEM_TAG, 0),
+            item_header(le, ITEM_TAG, 4),
+            vec![1, 2, 3, 4],
+            item_header(le, SEQUENCE_DELIMITATION_TAG, 0),
+        ]
+        .concat();
+
+        let structure = read(&p10_file("1.2.840.10008.1.2.4.50", &data_set));
+
+        assert_eq!(structure.read_failure, None);
+        assert_eq!(structure.offsets_error, None);
+
+        // The JPEG Baseline Transfer Syntax UID is two bytes longer
+        let start = DATA_SET_OFFSET + 2;
+        let pixel_data = path(dictionary::PIXEL_DATA.tag).to_string();
+
+        assert_eq!(offset(&structure, &pixel_data), Some((start, 12)));
+        assert_eq!(
+            offset(&structure, &format!("{}/0", pixel_data)),
+            Some((start + 12, 8))
+        );
+        assert_eq!(
+            offset(&structure, &format!("{}/1", pixel_data)),
+            Some((start + 20, 8))
+        );
+
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::PixelDataItem),
+            vec![(Some(start + 12), 8), (Some(start + 20), 12)]
+        );
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::SequenceDelimiter),
+            vec![(Some(start + 32), 8)]
+        );
+        assert_eq!(
+            token_offsets(&structure, RawTokenKind::End),
+            vec![(Some(start + 40), 0)]
+        );
+    }
+
+    #[test]
+    fn offsets_are_not_known_in_deflated_data_sets() {
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(&simple_data_set(EXPLICIT_VR_LITTLE_ENDIAN))
+            .unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let structure = read(&p10_file("1.2.840.10008.1.2.1.99", &deflated));
+
+        assert_eq!(structure.read_failure, None);
+        assert!(structure.offsets_error.is_some());
+
+        // Offsets in the File Meta Information are still known
+        assert_eq!(
+            offset(
+                &structure,
+                &path(dictionary::TRANSFER_SYNTAX_UID.tag).to_string()
+            ),
+            Some((144, 8))
+        );
+        assert_eq!(
+            offset(&structure, &path(dictionary::MODALITY.tag).to_string()),
+            None
+        );
+
+        let header_offsets = structure
+            .tokens
+            .iter()
+            .filter(|token| token.kind == RawTokenKind::DataElementHeader && token.depth == 0)
+            .map(|token| token.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(header_offsets, vec![None, None, None]);
+    }
+
+    /// Returns a file that ends inside an item of an undefined-length sequence, after the given
+    /// bytes of the item's content.
+    ///
+    fn truncated_file(item_content: &[u8]) -> Vec<u8> {
+        let le = EXPLICIT_VR_LITTLE_ENDIAN;
+
+        let data_set = [
+            header(
+                le,
+                dictionary::REFERENCED_SERIES_SEQUENCE.tag,
+                b"SQ",
+                UNDEFINED_LENGTH,
+            ),
+            item_header(le, ITEM_TAG, UNDEFINED_LENGTH),
+            item_content.to_vec(),
+        ]
+        .concat();
+
+        p10_file("1.2.840.10008.1.2.1", &data_set)
+    }
+
+    #[test]
+    fn read_failure_inside_a_header() {
+        let structure = read(&truncated_file(&[0x08, 0x00, 0x60, 0x00]));
+
+        let sequence = path(dictionary::REFERENCED_SERIES_SEQUENCE.tag);
+        let read_failure = structure.read_failure.unwrap();
+
+        assert_eq!(
+            read_failure.open_paths,
+            vec![sequence.to_string(), item(&sequence, 0).to_string()]
+        );
+        assert_eq!(read_failure.partial_element_path, None);
+        assert_eq!(
+            read_failure.description,
+            format!("Reading stopped at offset {:08X}", DATA_SET_OFFSET + 20)
+        );
+    }
+
+    #[test]
+    fn read_failure_inside_a_value() {
+        let le = EXPLICIT_VR_LITTLE_ENDIAN;
+        let content = [
+            header(le, dictionary::ENCAPSULATED_DOCUMENT.tag, b"OB", 16),
+            vec![1, 2, 3, 4],
+        ]
+        .concat();
+
+        let structure = read(&truncated_file(&content));
+
+        let sequence = path(dictionary::REFERENCED_SERIES_SEQUENCE.tag);
+        let read_failure = structure.read_failure.unwrap();
+
+        assert_eq!(
+            read_failure.open_paths,
+            vec![sequence.to_string(), item(&sequence, 0).to_string()]
+        );
+        assert_eq!(
+            read_failure.partial_element_path,
+            Some(child(&sequence, 0, dictionary::ENCAPSULATED_DOCUMENT.tag).to_string())
+        );
+        assert!(read_failure.is_partial(&sequence.to_string()));
+        assert!(read_failure.description.contains("in the value of"));
+        assert!(read_failure.description.ends_with("of 16 bytes"));
+    }
+
+    #[test]
+    fn read_failure_is_the_same_when_streamed() {
+        let bytes = truncated_file(&[0x08, 0x00, 0x60, 0x00]);
+
+        let mut reader = P10StreamReader::new();
+        let mut chunks = bytes.chunks(5).peekable();
+        while let Some(chunk) = chunks.next() {
+            if reader
+                .write(chunk.to_vec(), chunks.peek().is_none())
+                .is_err()
+            {
+                break;
+            }
+        }
+
+        let streamed = reader.read_failure().unwrap();
+        let read_failure = read(&bytes).read_failure.unwrap();
+
+        assert_eq!(streamed.description, read_failure.description);
+        assert_eq!(streamed.open_paths, read_failure.open_paths);
+        assert_eq!(
+            streamed.partial_element_path,
+            read_failure.partial_element_path
+        );
+    }
+}
@@ -0,0 +1,647 @@
+//! Detection of the format of an opened file from its content, so that files with a missing or
+//! misleading extension are still read correctly, and reading of data sets in each format.
+
+use std::io::{Read, Write};
+
+use dcmfx::{
+    core::*,
+    json::*,
+    p10::{DataSetBuilder, P10ReadContext, P10Token},
+};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    native_xml,
+    raw_token_view::{OffsetTracker, ReadFailure},
+};
+
+/// The formats an opened file can be read as.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum DataSetSourceType {
+    #[default]
+    P10,
+    Json,
+    Xml,
+}
+
+impl DataSetSourceType {
+    pub const ALL: [Self; 3] = [Self::P10, Self::Json, Self::Xml];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::P10 => "DICOM P10",
+            Self::Json => "DICOM JSON",
+            Self::Xml => "DICOM Native XML",
+        }
+    }
+
+    /// Reads a data set from the given bytes in this format. If reading fails then the lines of
+    /// the error are returned, along with the partial data set read prior to the error where
+    /// possible.
+    ///
+    pub fn read(self, bytes: &[u8]) -> Result<DataSet, (DataSet, Vec<String>)> {
+        match self {
+            Self::P10 => dcmfx::p10::read_bytes(bytes.to_vec().into()).map_err(
+                |(e, mut data_set_builder)| {
+                    data_set_builder.force_end();
+
+                    (
+                        data_set_builder.final_data_set().unwrap(),
+                        e.to_lines("reading file"),
+                    )
+                },
+            ),
+
+            Self::Json => {
+                let json = std::str::from_utf8(bytes).map_err(not_utf8_error)?;
+                DataSet::from_json(json).map_err(|e| (DataSet::new(), e.to_lines("")))
+            }
+
+            Self::Xml => {
+                let xml = std::str::from_utf8(bytes).map_err(not_utf8_error)?;
+                native_xml::read_data_set(xml)
+            }
+        }
+    }
+}
+
+/// Reads the header of a DICOM P10 data set, i.e. everything before its root Pixel Data data
+/// element, from bytes that are passed to it in chunks as they're read from a file. This is used to
+/// read the attributes of many files at once without reading or holding on to their pixel data.
+///
+pub struct P10HeaderReader {
+    context: P10ReadContext,
+    builder: DataSetBuilder,
+}
+
+impl Default for P10HeaderReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl P10HeaderReader {
+    pub fn new() -> Self {
+        Self {
+            context: P10ReadContext::new(None),
+            builder: DataSetBuilder::new(),
+        }
+    }
+
+    /// Reads the next chunk of bytes. Returns the header once the root Pixel Data data element or
+    /// the end of the data set is reached, after which no more chunks need to be read.
+    ///
+    pub fn write(&mut self, chunk: Vec<u8>, is_last: bool) -> Result<Option<DataSet>, String> {
+        self.context
+            .write_bytes(chunk.into(), is_last)
+            .map_err(|e| e.to_lines("reading file").join("\n"))?;
+
+        loop {
+            let tokens = self
+                .context
+                .read_tokens()
+                .map_err(|e| e.to_lines("reading file").join("\n"))?;
+
+            if tokens.is_empty() {
+                break;
+            }
+
+            for token in tokens {
+                if is_root_pixel_data(&token) {
+                    self.builder.force_end();
+                    return self.final_data_set().map(Some);
+                }
+
+                self.builder
+                    .add_token(&token)
+                    .map_err(|e| e.to_lines("reading file").join("\n"))?;
+
+                if self.builder.is_complete() {
+                    return self.final_data_set().map(Some);
+                }
+            }
+        }
+
+        if is_last {
+            self.builder.force_end();
+            return self.final_data_set().map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn final_data_set(&mut self) -> Result<DataSet, String> {
+        std::mem::replace(&mut self.builder, DataSetBuilder::new())
+            .final_data_set()
+            .map_err(|_| "Failed building data set".to_string())
+    }
+}
+
+/// Reads a DICOM P10 data set from bytes that are passed to it in chunks as they're read from a
+/// file. The data set's header, i.e. everything before its root Pixel Data data element, is made
+/// available as soon as it has been read so it can be shown while the pixel data is still loading.
+///
+/// Where reading stopped is tracked as the file is read, so that it can be shown if reading fails
+/// without reading the file again.
+///
+pub struct P10StreamReader {
+    context: P10ReadContext,
+    builder: DataSetBuilder,
+    header_builder: Option<DataSetBuilder>,
+    offset_tracker: OffsetTracker,
+}
+
+impl Default for P10StreamReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl P10StreamReader {
+    pub fn new() -> Self {
+        Self {
+            context: P10ReadContext::new(None),
+            builder: DataSetBuilder::new(),
+            header_builder: Some(DataSetBuilder::new()),
+            offset_tracker: OffsetTracker::new(false),
+        }
+    }
+
+    /// Reads the next chunk of bytes. Returns the data set's header if it was completed by this
+    /// chunk. On error the lines of the error are returned, after which no more chunks can be read.
+    ///
+    pub fn write(&mut self, chunk: Vec<u8>, is_last: bool) -> Result<Option<DataSet>, Vec<String>> {
+        self.offset_tracker.write_bytes(&chunk);
+
+        self.read_tokens(chunk, is_last).inspect_err(|lines| {
+            self.offset_tracker.fail(lines.join("\n"));
+        })
+    }
+
+    fn read_tokens(
+        &mut self,
+        chunk: Vec<u8>,
+        is_last: bool,
+    ) -> Result<Option<DataSet>, Vec<String>> {
+        self.context
+            .write_bytes(chunk.into(), is_last)
+            .map_err(|e| e.to_lines("reading file"))?;
+
+        let mut header = None;
+
+        loop {
+            let tokens = self
+                .context
+                .read_tokens()
+                .map_err(|e| e.to_lines("reading file"))?;
+
+            if tokens.is_empty() {
+                return Ok(header);
+            }
+
+            for token in tokens {
+                self.offset_tracker.add_token(&token);
+
+                if let Some(header_builder) = self.header_builder.as_mut() {
+                    if is_root_pixel_data(&token) {
+                        let mut header_builder = self.header_builder.take().unwrap();
+                        header_builder.force_end();
+                        header = header_builder.final_data_set().ok();
+                    } else {
+                        header_builder
+                            .add_token(&token)
+                            .map_err(|e| e.to_lines("reading file"))?;
+                    }
+                }
+
+                self.builder
+                    .add_token(&token)
+                    .map_err(|e| e.to_lines("reading file"))?;
+            }
+        }
+    }
+
+    /// Returns where reading stopped if it failed with an error.
+    ///
+    pub fn read_failure(&self) -> Option<&ReadFailure> {
+        self.offset_tracker.read_failure()
+    }
+
+    /// Returns the data set that has been read. If it's incomplete then the given error lines are
+    /// returned along with the partial data set read so far.
+    ///
+    pub fn finish(mut self, error_lines: Vec<String>) -> Result<DataSet, (DataSet, Vec<String>)> {
+        let is_complete = self.builder.is_complete();
+        if !is_complete {
+            self.builder.force_end();
+        }
+
+        let data_set = self.builder.final_data_set().unwrap_or_default();
+
+        if !error_lines.is_empty() {
+            Err((data_set, error_lines))
+        } else if !is_complete {
+            Err((
+                data_set,
+                vec!["Error reading file: the data set is incomplete".into()],
+            ))
+        } else {
+            Ok(data_set)
+        }
+    }
+}
+
+/// Returns whether a token starts the root Pixel Data data element.
+///
+fn is_root_pixel_data(token: &P10Token) -> bool {
+    match token {
+        P10Token::DataElementHeader { tag, path, .. }
+        | P10Token::SequenceStart { tag, path, .. } => {
+            *tag == dictionary::PIXEL_DATA.tag && path.entries().len() == 1
+        }
+        _ => false,
+    }
+}
+
+fn not_utf8_error(e: std::str::Utf8Error) -> (DataSet, Vec<String>) {
+    (
+        DataSet::new(),
+        vec![format!("File is not valid UTF-8: {}", e)],
+    )
+}
+
+/// The number of bytes at the start of a file that its format is detected from.
+///
+pub const SNIFF_LENGTH: usize = 4 * 1024;
+
+/// The result of detecting the format of a file from its content.
+///
+#[derive(Clone)]
+pub struct SniffedFile {
+    pub source_type: DataSetSourceType,
+
+    /// A description of what in the content led to the format being chosen, which is shown to the
+    /// user.
+    pub reason: String,
+
+    /// The file's name, with any `.gz` extension removed if it's gzip-compressed.
+    pub filename: String,
+
+    /// Whether the file's content is gzip-compressed.
+    pub is_gzip: bool,
+}
+
+impl SniffedFile {
+    /// Returns the content of the file given its bytes, decompressing them if the file is
+    /// gzip-compressed.
+    ///
+    pub fn content(&self, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        let mut decoder = self.content_decoder();
+
+        let mut content = decoder.decode(bytes)?;
+        content.extend(decoder.finish()?);
+
+        Ok(content)
+    }
+
+    /// Returns a decoder that turns the file's bytes into its content a chunk at a time.
+    ///
+    pub fn content_decoder(&self) -> ContentDecoder {
+        ContentDecoder(self.is_gzip.then(|| flate2::write::GzDecoder::new(vec![])))
+    }
+}
+
+/// Turns the bytes of a file into its content a chunk at a time as they're read, decompressing them
+/// if the file is gzip-compressed. This avoids holding the whole compressed file in memory and
+/// lets the caller yield between chunks.
+///
+pub struct ContentDecoder(Option<flate2::write::GzDecoder<Vec<u8>>>);
+
+impl ContentDecoder {
+    /// Returns the content for the next chunk of the file's bytes.
+    ///
+    pub fn decode(&mut self, chunk: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some(decoder) = self.0.as_mut() else {
+            return Ok(chunk);
+        };
+
+        decoder
+            .write_all(&chunk)
+            .map_err(|e| format!("Failed decompressing gzip file: {}", e))?;
+
+        Ok(std::mem::take(decoder.get_mut()))
+    }
+
+    /// Returns the rest of the content once all of the file's bytes have been decoded, and checks
+    /// that compressed content wasn't truncated.
+    ///
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        match self.0 {
+            Some(decoder) => decoder
+                .finish()
+                .map_err(|e| format!("Failed decompressing gzip file: {}", e)),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Detects the format of a file from the bytes at its start, of which there should be at least
+/// [`SNIFF_LENGTH`] unless the file is shorter. The filename's extension is only used as a fallback
+/// when the content isn't recognized.
+///
+pub fn sniff(prefix: &[u8], filename: &str) -> Result<SniffedFile, String> {
+    if prefix.starts_with(&[0x1F, 0x8B]) {
+        // Only the start of the compressed content is available, so it's decompressed until the
+        // compressed bytes run out, which is reported as an error that's expected here
+        let mut decompressed = vec![];
+        let _ = flate2::read::GzDecoder::new(prefix)
+            .take(SNIFF_LENGTH as u64)
+            .read_to_end(&mut decompressed);
+
+        if decompressed.is_empty() && prefix.len() >= SNIFF_LENGTH {
+            return Err("Failed decompressing gzip file".into());
+        }
+
+        let filename = filename.strip_suffix(".gz").unwrap_or(filename);
+
+        let mut sniffed_file = sniff(&decompressed, filename)?;
+        sniffed_file.reason = format!("gzip-compressed, {}", sniffed_file.reason);
+        sniffed_file.is_gzip = true;
+
+        return Ok(sniffed_file);
+    }
+
+    if prefix.starts_with(b"PK\x03\x04") || prefix.starts_with(b"PK\x05\x06") {
+        return Err("This is a ZIP archive. Extract the files in it and open them instead.".into());
+    }
+
+    let (source_type, reason) = sniff_uncompressed(prefix, filename);
+
+    Ok(SniffedFile {
+        source_type,
+        reason,
+        filename: filename.to_string(),
+        is_gzip: false,
+    })
+}
+
+fn sniff_uncompressed(bytes: &[u8], filename: &str) -> (DataSetSourceType, String) {
+    if bytes.get(128..132) == Some(b"DICM") {
+        return (
+            DataSetSourceType::P10,
+            "DICM prefix after the 128-byte preamble".into(),
+        );
+    }
+
+    if bytes.starts_with(b"DICM") {
+        return (
+            DataSetSourceType::P10,
+            "DICM prefix without a preamble".into(),
+        );
+    }
+
+    // Text formats are recognized by their first non-whitespace character, ignoring any UTF-8 BOM
+    let text = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match text.iter().find(|c| !c.is_ascii_whitespace()) {
+        Some(b'{') | Some(b'[') => {
+            return (
+                DataSetSourceType::Json,
+                "content starts with a JSON object or array".into(),
+            );
+        }
+        Some(b'<') => {
+            return (
+                DataSetSourceType::Xml,
+                "content starts with an XML tag".into(),
+            );
+        }
+        _ => (),
+    }
+
+    // A data set with no preamble or DICM prefix starts with a little endian data element tag,
+    // usually in the File Meta Information group or the identifying group
+    if bytes.len() >= 8 {
+        let group = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if group == 0x0002 || group == 0x0008 {
+            return (
+                DataSetSourceType::P10,
+                format!(
+                    "data set without a preamble, starting in group {:04X}",
+                    group
+                ),
+            );
+        }
+    }
+
+    let lowercase_filename = filename.to_lowercase();
+    if lowercase_filename.ends_with(".json") {
+        (
+            DataSetSourceType::Json,
+            "unrecognized content, .json extension".into(),
+        )
+    } else if lowercase_filename.ends_with(".xml") {
+        (
+            DataSetSourceType::Xml,
+            "unrecognized content, .xml extension".into(),
+        )
+    } else {
+        (DataSetSourceType::P10, "unrecognized content".into())
+    }
+}
+
+/// Shows the format an opened file was read as, and why, and allows it to be read as a different
+/// format instead.
+///
+#[component]
+pub fn SourceTypeSelect(
+    source_type: DataSetSourceType,
+    reason: String,
+    on_change: EventHandler<DataSetSourceType>,
+) -> Element {
+    rsx! {
+        label {
+            class: "source-type-select",
+
+            "Read as"
+
+            select {
+                onchange: move |event| {
+                    if let Some(source_type) = DataSetSourceType::ALL
+                        .into_iter()
+                        .find(|source_type| source_type.name() == event.value())
+                    {
+                        on_change.call(source_type);
+                    }
+                },
+
+                for option_type in DataSetSourceType::ALL {
+                    option {
+                        value: option_type.name(),
+                        selected: option_type == source_type,
+                        {option_type.name()}
+                    }
+                }
+            }
+
+            span { class: "source-type-reason", "Detected: {reason}" }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn sniff_type(bytes: &[u8], filename: &str) -> DataSetSourceType {
+        sniff(bytes, filename).unwrap().source_type
+    }
+
+    #[test]
+    fn detects_p10_from_its_prefix() {
+        let mut bytes = vec![0; 128];
+        bytes.extend_from_slice(b"DICM");
+
+        let sniffed_file = sniff(&bytes, "file.json").unwrap();
+        assert_eq!(sniffed_file.source_type, DataSetSourceType::P10);
+        assert_eq!(
+            sniffed_file.reason,
+            "DICM prefix after the 128-byte preamble"
+        );
+        assert_eq!(sniffed_file.filename, "file.json");
+        assert!(!sniffed_file.is_gzip);
+
+        assert_eq!(
+            sniff_type(b"DICM\x02\x00", "file.xml"),
+            DataSetSourceType::P10
+        );
+    }
+
+    #[test]
+    fn detects_p10_without_a_preamble() {
+        assert_eq!(
+            sniff_type(&[0x02, 0x00, 0x00, 0x00, b'U', b'L', 4, 0], "file"),
+            DataSetSourceType::P10
+        );
+        assert_eq!(
+            sniff_type(&[0x08, 0x00, 0x05, 0x00, b'C', b'S', 10, 0], "file.json"),
+            DataSetSourceType::P10
+        );
+    }
+
+    #[test]
+    fn detects_text_formats_from_their_first_character() {
+        assert_eq!(
+            sniff_type(b"  {\"00080060\"", "file.dcm"),
+            DataSetSourceType::Json
+        );
+        assert_eq!(sniff_type(b"\n[{}]", "file"), DataSetSourceType::Json);
+        assert_eq!(
+            sniff_type(b"\xEF\xBB\xBF<?xml", "file.json"),
+            DataSetSourceType::Xml
+        );
+        assert_eq!(
+            sniff_type(b"\t<NativeDicomModel>", "file"),
+            DataSetSourceType::Xml
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_extension() {
+        assert_eq!(sniff_type(b"", "file.JSON"), DataSetSourceType::Json);
+        assert_eq!(sniff_type(b"text", "file.xml"), DataSetSourceType::Xml);
+        assert_eq!(sniff_type(b"text", "file.dcm"), DataSetSourceType::P10);
+
+        assert_eq!(
+            sniff(b"text", "file.xml").unwrap().reason,
+            "unrecognized content, .xml extension"
+        );
+    }
+
+    #[test]
+    fn detects_gzip_compressed_content() {
+        let sniffed_file = sniff(&gzip(b"{\"00080060\": {}}"), "file.json.gz").unwrap();
+
+        assert_eq!(sniffed_file.source_type, DataSetSourceType::Json);
+        assert_eq!(
+            sniffed_file.reason,
+            "gzip-compressed, content starts with a JSON object or array"
+        );
+        assert_eq!(sniffed_file.filename, "file.json");
+        assert!(sniffed_file.is_gzip);
+    }
+
+    #[test]
+    fn detects_gzip_compressed_content_from_its_start() {
+        // Only the start of the compressed bytes is available when sniffing, so the content is
+        // made incompressible to make it longer than that once compressed
+        let mut content = vec![0; 128];
+        content.extend_from_slice(b"DICM");
+
+        let mut state = 0x2545_F491u32;
+        for _ in 0..16 * 1024 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            content.extend_from_slice(&state.to_le_bytes());
+        }
+
+        let compressed = gzip(&content);
+        assert!(compressed.len() > SNIFF_LENGTH);
+
+        let sniffed_file = sniff(&compressed[..SNIFF_LENGTH], "image.dcm.gz").unwrap();
+        assert_eq!(sniffed_file.source_type, DataSetSourceType::P10);
+        assert!(sniffed_file.is_gzip);
+
+        assert_eq!(sniffed_file.content(compressed), Ok(content));
+    }
+
+    #[test]
+    fn gzip_content_is_decoded_a_chunk_at_a_time() {
+        let content = (0..64 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let compressed = gzip(&content);
+
+        let sniffed_file = sniff(&compressed, "file.dcm.gz").unwrap();
+        let mut decoder = sniffed_file.content_decoder();
+
+        let mut decoded = vec![];
+        for chunk in compressed.chunks(100) {
+            decoded.extend(decoder.decode(chunk.to_vec()).unwrap());
+        }
+        decoded.extend(decoder.finish().unwrap());
+
+        assert_eq!(decoded, content);
+    }
+
+    #[test]
+    fn rejects_zip_archives() {
+        assert!(sniff(b"PK\x03\x04rest", "files.zip").is_err());
+        assert!(sniff(b"PK\x05\x06rest", "files.zip").is_err());
+    }
+
+    #[test]
+    fn content_is_returned_unchanged_when_not_compressed() {
+        let sniffed_file = sniff(b"{}", "file.json").unwrap();
+
+        assert_eq!(sniffed_file.content(b"{}".to_vec()), Ok(b"{}".to_vec()));
+    }
+
+    #[test]
+    fn invalid_gzip_content_is_an_error() {
+        let mut compressed = gzip(b"{}");
+        compressed.truncate(12);
+
+        let sniffed_file = sniff(&compressed, "file.json.gz").unwrap();
+
+        assert!(sniffed_file.content(compressed).is_err());
+    }
+}
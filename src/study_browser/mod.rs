@@ -0,0 +1,212 @@
+mod tree;
+
+use std::collections::HashSet;
+
+use dcmfx::core::*;
+use dioxus::prelude::*;
+
+use crate::{file_list::OpenedFile, ui};
+use tree::BrowserRowKind;
+pub use tree::{NodeKey, build as build_study_tree};
+
+/// Shows the opened files organized into a Patient → Study → Series → Instance tree. Selecting a
+/// patient, study, or series selects that node so its shared attributes can be shown, and
+/// selecting an instance opens its file.
+///
+#[component]
+pub fn StudyBrowser(
+    files: Signal<Vec<OpenedFile>>,
+    active_file_id: Option<u64>,
+    selected_node: Signal<Option<NodeKey>>,
+    on_select_file: EventHandler<u64>,
+) -> Element {
+    let patients = use_memo(move || tree::build(&files.read()));
+    let mut collapsed = use_signal(HashSet::<NodeKey>::new);
+
+    let rows = tree::flatten(&patients.read(), &collapsed.read());
+
+    rsx! {
+        div {
+            class: "study-browser",
+
+            if rows.is_empty() {
+                div { class: "study-browser-empty", "No DICOM instances have been read yet." }
+            }
+
+            for row in rows {
+                match row.kind {
+                    BrowserRowKind::Node { key, is_expanded } => {
+                        let is_selected = selected_node.read().as_ref() == Some(&key);
+                        let toggle_key = key.clone();
+
+                        rsx! {
+                            div {
+                                class: "study-browser-row",
+                                class: if is_selected { "selected" },
+                                padding_left: "calc(0.5em + {row.depth}em)",
+
+                                onclick: move |_| selected_node.set(Some(key.clone())),
+
+                                span {
+                                    class: "expand-icon",
+                                    onclick: move |event| {
+                                        event.stop_propagation();
+
+                                        let mut collapsed = collapsed.write();
+                                        if !collapsed.remove(&toggle_key) {
+                                            collapsed.insert(toggle_key.clone());
+                                        }
+                                    },
+
+                                    ui::FontAwesomeIcon {
+                                        icon: if is_expanded { "chevron-down" } else { "chevron-right" },
+                                        style: "solid",
+                                    }
+                                }
+
+                                span { class: "label", title: "{row.label}", {row.label.clone()} }
+                                span { class: "details", {row.details.clone()} }
+                            }
+                        }
+                    }
+
+                    BrowserRowKind::Instance { file_id } => rsx! {
+                        div {
+                            class: "study-browser-row",
+                            class: if selected_node.read().is_none() && active_file_id == Some(file_id) { "selected" },
+                            padding_left: "calc(0.5em + {row.depth}em)",
+
+                            onclick: move |_| {
+                                selected_node.set(None);
+                                on_select_file.call(file_id);
+                            },
+
+                            span { class: "expand-icon" }
+                            span { class: "label", title: "{row.label}", {row.label.clone()} }
+                            span { class: "details", {row.details.clone()} }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Shows the attributes that have the same value in all instances under the selected node of the
+/// study browser.
+///
+#[component]
+pub fn SharedAttributes(files: Signal<Vec<OpenedFile>>, node: ReadSignal<NodeKey>) -> Element {
+    let attributes = use_memo(move || {
+        let files = files.read();
+        let patients = tree::build(&files);
+        let file_ids = tree::node_file_ids(&patients, &node.read());
+
+        let data_sets = files
+            .iter()
+            .filter(|file| file_ids.contains(&file.id))
+            .filter_map(|file| file.header.as_ref().and_then(|header| header.as_ref().ok()))
+            .collect::<Vec<_>>();
+
+        (file_ids.len(), tree::shared_attributes(&data_sets))
+    });
+
+    let attributes = attributes.read();
+    let (instance_count, data_set) = &*attributes;
+
+    rsx! {
+        div {
+            class: "shared-attributes",
+
+            div {
+                class: "shared-attributes-header",
+
+                if *instance_count == 1 {
+                    "Attributes of 1 instance"
+                } else {
+                    "Attributes shared by {instance_count} instances"
+                }
+            }
+
+            div {
+                class: "shared-attributes-grid",
+
+                for (tag, value) in data_set.iter() {
+                    div {
+                        key: "{tag}",
+                        class: "shared-attribute",
+
+                        span { class: "tag", {tag.to_string()} }
+                        span { class: "name", {data_set.tag_name(*tag)} }
+                        span { class: "vr", {value.value_representation().to_string()} }
+                        span { class: "value", {shared_value_text(*tag, value)} }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the IDs of the files in the same series as the given file, in slice order, if that
+/// series is made up of more than one single-frame image.
+///
+pub fn series_stack_file_ids(files: &[OpenedFile], file_id: u64) -> Option<Vec<u64>> {
+    let patients = tree::build(files);
+
+    let series = patients
+        .iter()
+        .flat_map(|patient| patient.studies.iter())
+        .flat_map(|study| study.series.iter())
+        .find(|series| {
+            series
+                .instances
+                .iter()
+                .any(|instance| instance.file_id == file_id)
+        })?;
+
+    if series.instances.len() < 2 {
+        return None;
+    }
+
+    let is_single_frame_image = |file_id: u64| {
+        files
+            .iter()
+            .find(|file| file.id == file_id)
+            .and_then(|file| file.header.as_ref())
+            .and_then(|header| header.as_ref().ok())
+            .is_some_and(|header| {
+                header.has(dictionary::ROWS.tag)
+                    && header
+                        .get_int::<i64>(dictionary::NUMBER_OF_FRAMES.tag)
+                        .unwrap_or(1)
+                        <= 1
+            })
+    };
+
+    if !series
+        .instances
+        .iter()
+        .all(|instance| is_single_frame_image(instance.file_id))
+    {
+        return None;
+    }
+
+    Some(
+        series
+            .instances
+            .iter()
+            .map(|instance| instance.file_id)
+            .collect(),
+    )
+}
+
+fn shared_value_text(tag: DataElementTag, value: &DataElementValue) -> String {
+    match value.sequence_items() {
+        Ok(items) => format!(
+            "{} item{}",
+            items.len(),
+            if items.len() == 1 { "" } else { "s" }
+        ),
+        Err(_) => value.to_string(tag, 1000),
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::HashSet;
+
+use dcmfx::core::*;
+
+use crate::file_list::OpenedFile;
+
+/// Identifies a patient, study, or series in the study tree. Studies and series include the keys
+/// of their parents because files with missing UIDs are grouped under an empty UID.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum NodeKey {
+    Patient(String),
+    Study(String, String),
+    Series(String, String, String),
+}
+
+pub struct Patient {
+    pub id: String,
+    pub name: String,
+    pub studies: Vec<Study>,
+}
+
+pub struct Study {
+    pub uid: String,
+    pub date: String,
+    pub description: String,
+    pub series: Vec<Series>,
+}
+
+pub struct Series {
+    pub uid: String,
+    pub number: Option<i64>,
+    pub modality: String,
+    pub description: String,
+    pub instances: Vec<Instance>,
+}
+
+pub struct Instance {
+    pub file_id: u64,
+    pub filename: String,
+    pub number: Option<i64>,
+
+    /// The distance of the instance's image plane along its normal, calculated from its Image
+    /// Position (Patient) and Image Orientation (Patient).
+    pub position: Option<f64>,
+}
+
+/// Organizes the opened files into a Patient → Study → Series → Instance tree using the data sets
+/// read from their headers. Files whose header hasn't been read, or failed to read, are left out.
+///
+pub fn build(files: &[OpenedFile]) -> Vec<Patient> {
+    let mut patients: Vec<Patient> = vec![];
+
+    for file in files {
+        let Some(Ok(header)) = &file.header else {
+            continue;
+        };
+
+        let patient_id = get_string(header, dictionary::PATIENT_ID.tag);
+        let patient = match patients.iter().position(|p| p.id == patient_id) {
+            Some(index) => &mut patients[index],
+            None => {
+                patients.push(Patient {
+                    id: patient_id,
+                    name: get_string(header, dictionary::PATIENT_NAME.tag),
+                    studies: vec![],
+                });
+                patients.last_mut().unwrap()
+            }
+        };
+
+        let study_uid = get_string(header, dictionary::STUDY_INSTANCE_UID.tag);
+        let study = match patient.studies.iter().position(|s| s.uid == study_uid) {
+            Some(index) => &mut patient.studies[index],
+            None => {
+                patient.studies.push(Study {
+                    uid: study_uid,
+                    date: get_string(header, dictionary::STUDY_DATE.tag),
+                    description: get_string(header, dictionary::STUDY_DESCRIPTION.tag),
+                    series: vec![],
+                });
+                patient.studies.last_mut().unwrap()
+            }
+        };
+
+        let series_uid = get_string(header, dictionary::SERIES_INSTANCE_UID.tag);
+        let series = match study.series.iter().position(|s| s.uid == series_uid) {
+            Some(index) => &mut study.series[index],
+            None => {
+                study.series.push(Series {
+                    uid: series_uid,
+                    number: header.get_int::<i64>(dictionary::SERIES_NUMBER.tag).ok(),
+                    modality: get_string(header, dictionary::MODALITY.tag),
+                    description: get_string(header, dictionary::SERIES_DESCRIPTION.tag),
+                    instances: vec![],
+                });
+                study.series.last_mut().unwrap()
+            }
+        };
+
+        series.instances.push(Instance {
+            file_id: file.id,
+            filename: file.filename.clone(),
+            number: header.get_int::<i64>(dictionary::INSTANCE_NUMBER.tag).ok(),
+            position: image_plane_position(header),
+        });
+    }
+
+    patients.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    for patient in patients.iter_mut() {
+        patient.studies.sort_by(|a, b| a.date.cmp(&b.date));
+
+        for study in patient.studies.iter_mut() {
+            sort_series(&mut study.series);
+
+            for series in study.series.iter_mut() {
+                sort_instances(&mut series.instances);
+            }
+        }
+    }
+
+    patients
+}
+
+/// Sorts series by Series Number, with series that don't have one last.
+///
+fn sort_series(series: &mut [Series]) {
+    series.sort_by_key(|series| (series.number.is_none(), series.number));
+}
+
+/// Sorts instances by their position along the normal of their image plane when all of them have
+/// one, and otherwise by Instance Number.
+///
+fn sort_instances(instances: &mut [Instance]) {
+    if instances.iter().all(|instance| instance.position.is_some()) {
+        instances.sort_by(|a, b| a.position.unwrap().total_cmp(&b.position.unwrap()));
+    } else {
+        instances.sort_by(|a, b| {
+            a.number
+                .cmp(&b.number)
+                .then_with(|| a.filename.cmp(&b.filename))
+        });
+    }
+}
+
+fn image_plane_position(data_set: &DataSet) -> Option<f64> {
+    let position = data_set
+        .get_floats(dictionary::IMAGE_POSITION_PATIENT.tag)
+        .ok()
+        .filter(|position| position.len() == 3)?;
+
+    // Without an orientation, assume an axial image plane
+    let Some(orientation) = data_set
+        .get_floats(dictionary::IMAGE_ORIENTATION_PATIENT.tag)
+        .ok()
+        .filter(|orientation| orientation.len() == 6)
+    else {
+        return Some(position[2]);
+    };
+
+    let normal = [
+        orientation[1] * orientation[5] - orientation[2] * orientation[4],
+        orientation[2] * orientation[3] - orientation[0] * orientation[5],
+        orientation[0] * orientation[4] - orientation[1] * orientation[3],
+    ];
+
+    Some(position[0] * normal[0] + position[1] * normal[1] + position[2] * normal[2])
+}
+
+fn get_string(data_set: &DataSet, tag: DataElementTag) -> String {
+    data_set
+        .get_string(tag)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the IDs of the files under the given node.
+///
+pub fn node_file_ids(patients: &[Patient], key: &NodeKey) -> Vec<u64> {
+    let mut file_ids = vec![];
+
+    for patient in patients {
+        for study in patient.studies.iter() {
+            for series in study.series.iter() {
+                let is_in_node = match key {
+                    NodeKey::Patient(patient_id) => *patient_id == patient.id,
+                    NodeKey::Study(patient_id, study_uid) => {
+                        *patient_id == patient.id && *study_uid == study.uid
+                    }
+                    NodeKey::Series(patient_id, study_uid, series_uid) => {
+                        *patient_id == patient.id
+                            && *study_uid == study.uid
+                            && *series_uid == series.uid
+                    }
+                };
+
+                if is_in_node {
+                    file_ids.extend(series.instances.iter().map(|instance| instance.file_id));
+                }
+            }
+        }
+    }
+
+    file_ids
+}
+
+/// Returns the data elements that have the same value in all of the given data sets. Sequences are
+/// included only if they're identical in all the data sets.
+///
+pub fn shared_attributes(data_sets: &[&DataSet]) -> DataSet {
+    let mut shared = DataSet::new();
+
+    let Some((first, rest)) = data_sets.split_first() else {
+        return shared;
+    };
+
+    for (tag, value) in first.iter() {
+        if rest
+            .iter()
+            .all(|data_set| data_set.get_value(*tag).is_ok_and(|other| other == value))
+        {
+            shared.insert(*tag, value.clone());
+        }
+    }
+
+    shared
+}
+
+/// A row in the flattened study tree that's shown in the study browser.
+///
+#[derive(Clone, PartialEq)]
+pub struct BrowserRow {
+    pub depth: usize,
+    pub kind: BrowserRowKind,
+    pub label: String,
+    pub details: String,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum BrowserRowKind {
+    Node { key: NodeKey, is_expanded: bool },
+    Instance { file_id: u64 },
+}
+
+/// Flattens the study tree into the rows that are shown, leaving out the children of collapsed
+/// nodes.
+///
+pub fn flatten(patients: &[Patient], collapsed: &HashSet<NodeKey>) -> Vec<BrowserRow> {
+    let mut rows = vec![];
+
+    for patient in patients {
+        let patient_key = NodeKey::Patient(patient.id.clone());
+        let is_expanded = !collapsed.contains(&patient_key);
+
+        rows.push(BrowserRow {
+            depth: 0,
+            kind: BrowserRowKind::Node {
+                key: patient_key,
+                is_expanded,
+            },
+            label: or_unknown(&patient.name, "Unnamed patient"),
+            details: patient.id.clone(),
+        });
+
+        if !is_expanded {
+            continue;
+        }
+
+        for study in patient.studies.iter() {
+            let study_key = NodeKey::Study(patient.id.clone(), study.uid.clone());
+            let is_expanded = !collapsed.contains(&study_key);
+
+            rows.push(BrowserRow {
+                depth: 1,
+                kind: BrowserRowKind::Node {
+                    key: study_key,
+                    is_expanded,
+                },
+                label: or_unknown(&study.description, "Study"),
+                details: study.date.clone(),
+            });
+
+            if !is_expanded {
+                continue;
+            }
+
+            for series in study.series.iter() {
+                let series_key =
+                    NodeKey::Series(patient.id.clone(), study.uid.clone(), series.uid.clone());
+                let is_expanded = !collapsed.contains(&series_key);
+
+                rows.push(BrowserRow {
+                    depth: 2,
+                    kind: BrowserRowKind::Node {
+                        key: series_key,
+                        is_expanded,
+                    },
+                    label: or_unknown(&series.description, "Series"),
+                    details: format!(
+                        "{} · {} instance{}",
+                        or_unknown(&series.modality, "??"),
+                        series.instances.len(),
+                        if series.instances.len() == 1 { "" } else { "s" }
+                    ),
+                });
+
+                if !is_expanded {
+                    continue;
+                }
+
+                for instance in series.instances.iter() {
+                    rows.push(BrowserRow {
+                        depth: 3,
+                        kind: BrowserRowKind::Instance {
+                            file_id: instance.file_id,
+                        },
+                        label: instance.filename.clone(),
+                        details: instance
+                            .number
+                            .map(|number| format!("#{}", number))
+                            .unwrap_or_default(),
+                    });
+                }
+            }
+        }
+    }
+
+    rows
+}
+
+fn or_unknown(value: &str, default: &str) -> String {
+    if value.is_empty() {
+        default.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::insert;
+
+    fn series(uid: &str, number: Option<i64>) -> Series {
+        Series {
+            uid: uid.into(),
+            number,
+            modality: String::new(),
+            description: String::new(),
+            instances: vec![],
+        }
+    }
+
+    fn instance(filename: &str, number: Option<i64>, position: Option<f64>) -> Instance {
+        Instance {
+            file_id: 0,
+            filename: filename.into(),
+            number,
+            position,
+        }
+    }
+
+    fn filenames(instances: &[Instance]) -> Vec<&str> {
+        instances
+            .iter()
+            .map(|instance| instance.filename.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn series_without_a_number_are_sorted_last() {
+        let mut all_series = vec![
+            series("a", None),
+            series("b", Some(3)),
+            series("c", Some(-1)),
+            series("d", None),
+            series("e", Some(1)),
+        ];
+
+        sort_series(&mut all_series);
+
+        assert_eq!(
+            all_series
+                .iter()
+                .map(|series| series.uid.as_str())
+                .collect::<Vec<_>>(),
+            vec!["c", "e", "b", "a", "d"]
+        );
+    }
+
+    #[test]
+    fn instances_are_sorted_by_position_when_all_have_one() {
+        let mut instances = vec![
+            instance("a", Some(1), Some(10.0)),
+            instance("b", Some(2), Some(-2.5)),
+            instance("c", Some(3), Some(0.0)),
+        ];
+
+        sort_instances(&mut instances);
+
+        assert_eq!(filenames(&instances), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn instances_are_sorted_by_number_when_a_position_is_missing() {
+        let mut instances = vec![
+            instance("d", Some(2), Some(10.0)),
+            instance("c", Some(2), None),
+            instance("a", Some(1), Some(-2.5)),
+            instance("b", Some(0), Some(0.0)),
+        ];
+
+        sort_instances(&mut instances);
+
+        // Instances with the same number are sorted by filename
+        assert_eq!(filenames(&instances), vec!["b", "a", "c", "d"]);
+    }
+
+    #[test]
+    fn axial_position_is_used_without_an_orientation() {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::IMAGE_POSITION_PATIENT.tag,
+            "DS",
+            "1\\2\\-30.5",
+        );
+
+        assert_eq!(image_plane_position(&data_set), Some(-30.5));
+    }
+
+    #[test]
+    fn position_is_along_the_image_plane_normal() {
+        let mut data_set = DataSet::new();
+        insert(
+            &mut data_set,
+            dictionary::IMAGE_POSITION_PATIENT.tag,
+            "DS",
+            "12.5\\3\\4",
+        );
+
+        // A sagittal plane, whose normal points along the negative x axis
+        insert(
+            &mut data_set,
+            dictionary::IMAGE_ORIENTATION_PATIENT.tag,
+            "DS",
+            "0\\1\\0\\0\\0\\-1",
+        );
+
+        assert_eq!(image_plane_position(&data_set), Some(-12.5));
+    }
+
+    #[test]
+    fn invalid_positions_are_ignored() {
+        let mut data_set = DataSet::new();
+        assert_eq!(image_plane_position(&data_set), None);
+
+        insert(
+            &mut data_set,
+            dictionary::IMAGE_POSITION_PATIENT.tag,
+            "DS",
+            "1\\2",
+        );
+        assert_eq!(image_plane_position(&data_set), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! Helpers shared by the unit tests of different modules.
+
+use dcmfx::core::*;
+use futures_util::FutureExt;
+
+use crate::{editing::value_text, native_xml};
+
+/// Inserts a value given as text into a data set, without validating the text.
+///
+pub fn insert(data_set: &mut DataSet, tag: DataElementTag, vr: &str, text: &str) {
+    data_set.insert(
+        tag,
+        value_text::text_to_value_unvalidated(vr, text).unwrap(),
+    );
+}
+
+/// Returns a data set as native DICOM model XML.
+///
+pub fn to_xml(data_set: &DataSet) -> String {
+    let mut xml = String::new();
+
+    native_xml::write_data_set(data_set, &mut |s| xml.push_str(s), &async || Ok(()))
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+
+    xml
+}
+
+/// Returns a data set with values of most VRs, a sequence with two items, private data elements,
+/// and encapsulated pixel data.
+///
+pub fn test_data_set() -> DataSet {
+    let mut head = DataSet::new();
+    insert(&mut head, dictionary::CODE_VALUE.tag, "SH", "T-D1100");
+    insert(&mut head, dictionary::CODE_MEANING.tag, "LO", "Head & neck");
+
+    let mut chest = DataSet::new();
+    insert(&mut chest, dictionary::CODE_VALUE.tag, "SH", "T-D3000");
+    insert(&mut chest, dictionary::CODE_MEANING.tag, "LO", "Chest");
+
+    let mut data_set = DataSet::new();
+    insert(
+        &mut data_set,
+        dictionary::SOP_CLASS_UID.tag,
+        "UI",
+        "1.2.840.10008.5.1.4.1.1.7",
+    );
+    insert(
+        &mut data_set,
+        dictionary::SOP_INSTANCE_UID.tag,
+        "UI",
+        "1.2.3.4",
+    );
+    insert(&mut data_set, dictionary::MODALITY.tag, "CS", "OT");
+    insert(
+        &mut data_set,
+        dictionary::PATIENT_NAME.tag,
+        "PN",
+        "Doe^Jane=Ideo^Graphic",
+    );
+    insert(
+        &mut data_set,
+        dictionary::PATIENT_COMMENTS.tag,
+        "LT",
+        "A <comment>\\ with a backslash",
+    );
+    insert(
+        &mut data_set,
+        dictionary::IMAGE_TYPE.tag,
+        "CS",
+        "ORIGINAL\\PRIMARY",
+    );
+    insert(&mut data_set, dictionary::SLICE_THICKNESS.tag, "DS", "1.5");
+    insert(&mut data_set, dictionary::ROWS.tag, "US", "2");
+    insert(&mut data_set, dictionary::COLUMNS.tag, "US", "2");
+    insert(
+        &mut data_set,
+        dictionary::FRAME_INCREMENT_POINTER.tag,
+        "AT",
+        "(0018,1063)",
+    );
+    insert(
+        &mut data_set,
+        DataElementTag::new(0x0009, 0x0010),
+        "LO",
+        "ACME",
+    );
+    insert(
+        &mut data_set,
+        DataElementTag::new(0x0009, 0x1001),
+        "FL",
+        "0.5\\-2",
+    );
+    data_set.insert(
+        dictionary::ANATOMIC_REGION_SEQUENCE.tag,
+        DataElementValue::new_sequence(vec![head, chest]),
+    );
+    data_set.insert(
+        dictionary::PIXEL_DATA.tag,
+        DataElementValue::new_encapsulated_pixel_data(
+            ValueRepresentation::from_bytes(b"OB").unwrap(),
+            vec![vec![].into(), vec![1, 2, 3, 4].into()],
+        )
+        .unwrap(),
+    );
+
+    data_set
+}
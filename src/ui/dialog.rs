@@ -0,0 +1,57 @@
+use dioxus::prelude::*;
+
+use super::FontAwesomeIcon;
+
+/// A modal dialog shown over the rest of the app. Clicking the backdrop, the close button, or
+/// pressing Escape closes the dialog.
+///
+#[component]
+pub fn Dialog(
+    #[props(into)] title: String,
+    on_close: EventHandler<()>,
+    children: Element,
+) -> Element {
+    rsx! {
+        div {
+            class: "dialog-backdrop",
+
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "dialog",
+                tabindex: -1,
+
+                onmounted: move |event| async move {
+                    let _ = event.set_focus(true).await;
+                },
+                onclick: move |event| event.stop_propagation(),
+                onkeydown: move |event| {
+                    if event.key() == Key::Escape {
+                        on_close.call(());
+                    }
+
+                    // Stop keyboard shortcuts from acting on what's behind the dialog
+                    event.stop_propagation();
+                },
+
+                div {
+                    class: "dialog-header",
+
+                    h2 { {title} }
+                    div {
+                        class: "close-icon",
+                        onclick: move |_| on_close.call(()),
+
+                        FontAwesomeIcon { icon: "close", style: "solid", size: "lg" }
+                    }
+                }
+
+                div {
+                    class: "dialog-content",
+
+                    {children}
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
+pub mod dialog;
 pub mod font_awesome_icon;
 pub mod toasts;
 
+pub use dialog::Dialog;
 pub use font_awesome_icon::FontAwesomeIcon;
 pub use toasts::ToastUi;
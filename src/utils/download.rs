@@ -60,23 +60,20 @@ impl BlobPartWriter {
         }
     }
 
-    /// Consumes this writer and returns its internal JavaScript array.
+    /// Consumes this writer and returns its internal JavaScript array. Any buffered bytes that
+    /// haven't yet been flushed are included.
     ///
-    pub fn into_js_array(self) -> js_sys::Array {
-        self.js_array
-    }
-
-    fn flush_buffer(&mut self) {
-        let uint8_array = js_sys::Uint8Array::new_with_length(self.buffer.len() as u32);
-        uint8_array.copy_from(&self.buffer);
-        self.js_array.push(&uint8_array);
+    pub fn into_js_array(mut self) -> js_sys::Array {
+        if !self.buffer.is_empty() {
+            self.flush_buffer();
+        }
 
-        self.buffer.clear();
+        self.js_array
     }
-}
 
-impl dcmfx::p10::IoWrite for BlobPartWriter {
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), dcmfx::p10::IoError> {
+    /// Writes the given bytes. Writing to a blob part writer can't fail.
+    ///
+    pub fn write(&mut self, buf: &[u8]) {
         let mut remaining = buf;
 
         while !remaining.is_empty() {
@@ -90,7 +87,20 @@ impl dcmfx::p10::IoWrite for BlobPartWriter {
                 self.flush_buffer();
             }
         }
+    }
+
+    fn flush_buffer(&mut self) {
+        let uint8_array = js_sys::Uint8Array::new_with_length(self.buffer.len() as u32);
+        uint8_array.copy_from(&self.buffer);
+        self.js_array.push(&uint8_array);
 
+        self.buffer.clear();
+    }
+}
+
+impl dcmfx::p10::IoWrite for BlobPartWriter {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), dcmfx::p10::IoError> {
+        self.write(buf);
         Ok(())
     }
 
@@ -4,6 +4,7 @@ use js_sys::wasm_bindgen::JsCast;
 
 pub mod canvas;
 pub mod download;
+pub mod zip;
 
 /// Returns the global document object.
 ///
@@ -20,3 +21,53 @@ where
 {
     ev.as_web_event().dyn_into::<T>().ok()
 }
+
+/// Returns a new random UID in the "2.25" UUID-derived root.
+///
+pub fn random_uid() -> String {
+    let mut uuid = 0u128;
+    for _ in 0..4 {
+        uuid = (uuid << 32) | (js_sys::Math::random() * 4294967296.0) as u128;
+    }
+
+    // Set the version 4 and variant bits as for a random UUID
+    uuid = (uuid & !(0xF << 76)) | (0x4 << 76);
+    uuid = (uuid & !(0x3 << 62)) | (0x2 << 62);
+
+    format!("2.25.{}", uuid)
+}
+
+/// Formats a number of bytes for display using the largest whole binary unit.
+///
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{} MiB", bytes / (1024 * 1024))
+    } else if bytes >= 1024 {
+        format!("{} KiB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Collects written bytes in memory. This is for output that's needed in full before it can be
+/// used, as most output is instead streamed to where it's going.
+///
+#[derive(Default)]
+pub struct BytesWriter(Vec<u8>);
+
+impl BytesWriter {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl dcmfx::p10::IoWrite for BytesWriter {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), dcmfx::p10::IoError> {
+        self.0.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), dcmfx::p10::IoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use dcmfx::p10::{IoError, IoWrite};
+
+/// Writes a ZIP archive of uncompressed files. Each file is written as soon as it's added so that
+/// only the archive's central directory is held in memory.
+///
+/// A file's content can also be streamed into the archive by starting it with
+/// [`ZipWriter::start_file()`] and then writing to the ZIP writer. Such files are followed by a
+/// data descriptor that holds their CRC-32 and size, because these aren't known when their header
+/// is written.
+///
+pub struct ZipWriter<W: IoWrite> {
+    writer: W,
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    current_file: Option<CurrentFile>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+/// A file whose content is being streamed into the archive.
+///
+struct CurrentFile {
+    name: String,
+    offset: u32,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+struct ZipEntry {
+    name: String,
+    flags: u16,
+    crc32: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// The general purpose flags of every file, which have bit 11 set to indicate that the file's name
+/// is UTF-8.
+///
+const UTF8_FLAG: u16 = 0x0800;
+
+/// The general purpose flag that indicates a file's CRC-32 and size are in a data descriptor
+/// following its content.
+///
+const DATA_DESCRIPTOR_FLAG: u16 = 0x0008;
+
+impl<W: IoWrite> ZipWriter<W> {
+    /// Creates a new ZIP writer that writes to the given writer.
+    ///
+    pub fn new(writer: W) -> Self {
+        let (dos_time, dos_date) = current_dos_time_and_date();
+
+        Self {
+            writer,
+            offset: 0,
+            entries: vec![],
+            current_file: None,
+            dos_time,
+            dos_date,
+        }
+    }
+
+    /// Adds a file with the given path and content to the archive. Paths use `/` as the separator.
+    ///
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.finish_file()?;
+
+        let (Ok(size), Ok(offset)) = (u32::try_from(data.len()), u32::try_from(self.offset)) else {
+            return Err(size_error());
+        };
+
+        let crc32 = crc32fast::hash(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.write_common_header_fields(&mut header, name, UTF8_FLAG, crc32, size);
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(data)?;
+
+        self.entries.push(ZipEntry {
+            name: name.to_string(),
+            flags: UTF8_FLAG,
+            crc32,
+            size,
+            offset,
+        });
+
+        Ok(())
+    }
+
+    /// Starts a file with the given path whose content is then written to this ZIP writer. The
+    /// file ends when the next one is added or the archive is finished.
+    ///
+    pub fn start_file(&mut self, name: &str) -> Result<(), String> {
+        self.finish_file()?;
+
+        let Ok(offset) = u32::try_from(self.offset) else {
+            return Err(size_error());
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.write_common_header_fields(&mut header, name, UTF8_FLAG | DATA_DESCRIPTOR_FLAG, 0, 0);
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+
+        self.current_file = Some(CurrentFile {
+            name: name.to_string(),
+            offset,
+            hasher: crc32fast::Hasher::new(),
+            size: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Ends the file being streamed into the archive, if there is one, by writing its data
+    /// descriptor.
+    ///
+    fn finish_file(&mut self) -> Result<(), String> {
+        let Some(file) = self.current_file.take() else {
+            return Ok(());
+        };
+
+        let Ok(size) = u32::try_from(file.size) else {
+            return Err(size_error());
+        };
+        let crc32 = file.hasher.finalize();
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(&0x08074b50u32.to_le_bytes());
+        descriptor.extend_from_slice(&crc32.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+
+        self.write(&descriptor)?;
+
+        self.entries.push(ZipEntry {
+            name: file.name,
+            flags: UTF8_FLAG | DATA_DESCRIPTOR_FLAG,
+            crc32,
+            size,
+            offset: file.offset,
+        });
+
+        Ok(())
+    }
+
+    /// Writes the archive's central directory and returns the underlying writer.
+    ///
+    pub fn finish(mut self) -> Result<W, String> {
+        self.finish_file()?;
+
+        let Ok(central_directory_offset) = u32::try_from(self.offset) else {
+            return Err(size_error());
+        };
+
+        let Ok(entry_count) = u16::try_from(self.entries.len()) else {
+            return Err("ZIP archive exceeds the maximum of 65,535 files".into());
+        };
+
+        let entries = std::mem::take(&mut self.entries);
+        for entry in entries.iter() {
+            let mut header = Vec::with_capacity(46 + entry.name.len());
+            header.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            header.extend_from_slice(&20u16.to_le_bytes());
+            self.write_common_header_fields(
+                &mut header,
+                &entry.name,
+                entry.flags,
+                entry.crc32,
+                entry.size,
+            );
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u32.to_le_bytes());
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+
+            self.write(&header)?;
+        }
+
+        let central_directory_size = (self.offset - u64::from(central_directory_offset)) as u32;
+
+        let mut end_record = Vec::with_capacity(22);
+        end_record.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end_record.extend_from_slice(&0u16.to_le_bytes());
+        end_record.extend_from_slice(&0u16.to_le_bytes());
+        end_record.extend_from_slice(&entry_count.to_le_bytes());
+        end_record.extend_from_slice(&entry_count.to_le_bytes());
+        end_record.extend_from_slice(&central_directory_size.to_le_bytes());
+        end_record.extend_from_slice(&central_directory_offset.to_le_bytes());
+        end_record.extend_from_slice(&0u16.to_le_bytes());
+
+        self.write(&end_record)?;
+
+        Ok(self.writer)
+    }
+
+    /// Appends the fields shared by local file headers and central directory headers, from
+    /// "version needed to extract" through to "extra field length".
+    ///
+    fn write_common_header_fields(
+        &self,
+        header: &mut Vec<u8>,
+        name: &str,
+        flags: u16,
+        crc32: u32,
+        size: u32,
+    ) {
+        // Version needed to extract, then the general purpose flags, then the compression method,
+        // which is always stored
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+
+        header.extend_from_slice(&self.dos_time.to_le_bytes());
+        header.extend_from_slice(&self.dos_date.to_le_bytes());
+        header.extend_from_slice(&crc32.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|_| "Failed writing ZIP archive".to_string())?;
+        self.offset += bytes.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// Writes content to the file started by [`ZipWriter::start_file()`].
+///
+impl<W: IoWrite> IoWrite for ZipWriter<W> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), IoError> {
+        if let Some(file) = self.current_file.as_mut() {
+            file.hasher.update(buf);
+            file.size += buf.len() as u64;
+        }
+
+        self.writer.write_all(buf)?;
+        self.offset += buf.len() as u64;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+}
+
+fn size_error() -> String {
+    "ZIP archive exceeds the maximum size of 4 GiB".into()
+}
+
+/// Returns the current local time and date in MS-DOS format, as used for ZIP modification times.
+///
+fn current_dos_time_and_date() -> (u16, u16) {
+    let now = js_sys::Date::new_0();
+
+    let time = (now.get_hours() << 11) | (now.get_minutes() << 5) | (now.get_seconds() / 2);
+    let date = ((now.get_full_year().saturating_sub(1980)) << 9)
+        | ((now.get_month() + 1) << 5)
+        | now.get_date();
+
+    (time as u16, date as u16)
+}